@@ -0,0 +1,253 @@
+// The conversions are in the code #[pyfunction] and #[pymethods] expand to.
+#![allow(clippy::useless_conversion)]
+
+use athletic::capture::{self, Capture, ControlRequest, Origin};
+use athletic::device;
+use athletic::pixel_format::RgbFormat;
+use athletic::spec::IndexKind;
+use nokhwa::{native_api_backend, query, utils::ControlValueSetter};
+use numpy::{ndarray::Array3, IntoPyArray, PyArray3};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+fn runtime_error(why: impl ToString) -> PyErr {
+    PyRuntimeError::new_err(why.to_string())
+}
+
+/// A camera as listed by `list_devices`.
+#[pyclass(frozen, module = "athletic")]
+struct Device {
+    /// What to pass to `CaptureSession`.
+    #[pyo3(get)]
+    index: String,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    description: String,
+}
+
+#[pymethods]
+impl Device {
+    fn __repr__(&self) -> String {
+        format!("Device(index={:?}, name={:?})", self.index, self.name)
+    }
+}
+
+/// Lists the cameras on this machine.
+#[pyfunction]
+fn list_devices() -> PyResult<Vec<Device>> {
+    let backend = native_api_backend().ok_or_else(|| runtime_error("no camera backend"))?;
+    let devices = query(backend).map_err(runtime_error)?;
+    Ok(devices
+        .iter()
+        .map(|info| Device {
+            index: info.index().to_string(),
+            name: info.human_name(),
+            description: info.description().to_string(),
+        })
+        .collect())
+}
+
+/// A decoded frame.
+#[pyclass(frozen, module = "athletic")]
+struct Frame {
+    /// Counts every frame the camera delivered, so gaps are dropped frames.
+    #[pyo3(get)]
+    sequence: u64,
+    /// Seconds since the first frame of the session.
+    #[pyo3(get)]
+    pts: f64,
+    array: Py<PyArray3<u8>>,
+}
+
+#[pymethods]
+impl Frame {
+    /// The pixels as a height × width × 3 RGB array of uint8.
+    #[getter]
+    fn array(&self, py: Python<'_>) -> Py<PyArray3<u8>> {
+        self.array.clone_ref(py)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Frame(sequence={}, pts={:.3})", self.sequence, self.pts)
+    }
+}
+
+#[derive(FromPyObject)]
+enum DeviceArg {
+    Index(u32),
+    Name(String),
+}
+
+/// A camera streaming on its own thread. Iterating over a session yields
+/// frames until it is closed or the camera stops.
+#[pyclass(module = "athletic")]
+struct CaptureSession {
+    capture: Option<Capture>,
+}
+
+impl CaptureSession {
+    fn capture(&self) -> PyResult<&Capture> {
+        self.capture
+            .as_ref()
+            .ok_or_else(|| PyValueError::new_err("the session is closed"))
+    }
+
+    /// Waits for the next frame, or None once the camera has stopped.
+    fn next_frame(&self, py: Python<'_>) -> PyResult<Option<Frame>> {
+        let capture = self.capture()?;
+        let decoded = py.allow_threads(|| {
+            let Ok(frame) = capture.frames.recv() else {
+                return Ok(None);
+            };
+            let image = frame
+                .buffer
+                .decode_image::<RgbFormat>()
+                .map_err(runtime_error)?;
+            Ok::<_, PyErr>(Some((frame, image)))
+        })?;
+        let Some((frame, image)) = decoded else {
+            return Ok(None);
+        };
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let array =
+            Array3::from_shape_vec((height, width, 3), image.into_raw()).map_err(runtime_error)?;
+        Ok(Some(Frame {
+            sequence: frame.sequence,
+            pts: frame.pts.as_secs_f64(),
+            array: array.into_pyarray_bound(py).unbind(),
+        }))
+    }
+}
+
+#[pymethods]
+impl CaptureSession {
+    /// Opens `device`, defaulting to the first camera. It's an index, or a
+    /// name as the command line takes one: part of a camera's name, a
+    /// device path such as /dev/v4l/by-id/..., or "test-pattern".
+    #[new]
+    #[pyo3(signature = (device = None))]
+    fn new(py: Python<'_>, device: Option<DeviceArg>) -> PyResult<Self> {
+        let device = device.map(|device| match device {
+            DeviceArg::Index(index) => IndexKind::Index(index),
+            DeviceArg::Name(name) => IndexKind::String(name),
+        });
+        let capture = py
+            .allow_threads(|| {
+                let index = device::resolve(device.as_ref())?;
+                capture::spawn_capture(index)
+            })
+            .map_err(runtime_error)?;
+        Ok(CaptureSession {
+            capture: Some(capture),
+        })
+    }
+
+    #[getter]
+    fn name(&self) -> PyResult<String> {
+        Ok(self.capture()?.info.human_name())
+    }
+
+    #[getter]
+    fn width(&self) -> PyResult<u32> {
+        Ok(self.capture()?.format.width())
+    }
+
+    #[getter]
+    fn height(&self) -> PyResult<u32> {
+        Ok(self.capture()?.format.height())
+    }
+
+    #[getter]
+    fn frame_rate(&self) -> PyResult<u32> {
+        Ok(self.capture()?.format.frame_rate())
+    }
+
+    /// The format the camera sends, such as "MJPEG" or "YUYV".
+    #[getter]
+    fn frame_format(&self) -> PyResult<String> {
+        Ok(self.capture()?.format.format().to_string())
+    }
+
+    /// Names of the controls the camera supports.
+    #[getter]
+    fn controls(&self) -> PyResult<Vec<String>> {
+        Ok(self
+            .capture()?
+            .supported_controls
+            .iter()
+            .map(ToString::to_string)
+            .collect())
+    }
+
+    /// Waits for the next frame.
+    fn read(&self, py: Python<'_>) -> PyResult<Frame> {
+        self.next_frame(py)?
+            .ok_or_else(|| runtime_error("the camera has stopped"))
+    }
+
+    /// Sets an integer control, named as in `controls`, to `value`.
+    fn set_control(&self, py: Python<'_>, name: &str, value: i64) -> PyResult<()> {
+        let id = capture::known_control(name)
+            .ok_or_else(|| PyValueError::new_err(format!("unknown control: {name}")))?;
+        let capture = self.capture()?;
+        py.allow_threads(|| {
+            let (reply, answer) = flume::bounded(1);
+            capture
+                .controls
+                .send(ControlRequest::Set(
+                    id,
+                    ControlValueSetter::Integer(value),
+                    Origin::Remote,
+                    reply,
+                ))
+                .map_err(|_| runtime_error("the camera has stopped"))?;
+            answer
+                .recv()
+                .map_err(|_| runtime_error("the camera has stopped"))?
+                .map_err(runtime_error)?;
+            Ok(())
+        })
+    }
+
+    /// Stops the camera. Reading from a closed session raises ValueError.
+    fn close(&mut self) {
+        self.capture = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _type: &Bound<'_, PyAny>,
+        _value: &Bound<'_, PyAny>,
+        _traceback: &Bound<'_, PyAny>,
+    ) {
+        self.close();
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Frame>> {
+        if self.capture.is_none() {
+            return Ok(None);
+        }
+        self.next_frame(py)
+    }
+}
+
+/// Camera capture from athletic: device listing, format negotiation and
+/// frames as NumPy arrays.
+#[pymodule]
+#[pyo3(name = "athletic")]
+fn athletic_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Device>()?;
+    module.add_class::<Frame>()?;
+    module.add_class::<CaptureSession>()?;
+    module.add_function(wrap_pyfunction!(list_devices, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+"""Checks the bindings against the test pattern, which every machine has.
+
+Run after `maturin develop -m athletic-py/Cargo.toml`:
+
+    python -m unittest discover athletic-py/tests
+"""
+
+import unittest
+
+import athletic
+
+
+class OpeningByName(unittest.TestCase):
+    def test_the_test_pattern_opens_by_name(self):
+        with athletic.CaptureSession("test-pattern") as session:
+            self.assertEqual(session.name, "athletic test pattern")
+            self.assertEqual((session.width, session.height), (640, 480))
+            frame = session.read()
+            self.assertEqual(frame.sequence, 0)
+
+    def test_part_of_a_camera_name_opens_it(self):
+        devices = athletic.list_devices()
+        if not devices:
+            self.skipTest("no cameras connected")
+        name = devices[0].name
+        with athletic.CaptureSession(name[: max(len(name) // 2, 1)]) as session:
+            self.assertEqual(session.name, name)
+
+    def test_a_name_no_camera_has_is_an_error(self):
+        with self.assertRaisesRegex(RuntimeError, "no camera's name contains"):
+            athletic.CaptureSession("no such camera")
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,21 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the vendored protoc so building doesn't need one installed.
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::compile_protos("proto/athletic.proto")?;
+
+    #[cfg(feature = "capi")]
+    {
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        println!("cargo:rerun-if-env-changed=ATHLETIC_UPDATE_HEADER");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR")?;
+        let header = cbindgen::generate(&crate_dir)?;
+        // The checked-in header is only rewritten when asked, so a build
+        // leaves the source tree alone and works on read-only sources.
+        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+        header.write_to_file(out_dir.join("athletic.h"));
+        if std::env::var_os("ATHLETIC_UPDATE_HEADER").is_some() {
+            header.write_to_file(std::path::Path::new(&crate_dir).join("include/athletic.h"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+//! Recordings as they might be found on disk: cut short, corrupted or not
+//! Matroska at all, read back as `verify`, `decrypt` and `benchmark` do.
+
+#![no_main]
+
+use athletic::mkv::{self, Block};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut bytes = 0;
+    let _ = mkv::read_blocks(data, |block| {
+        match block {
+            Block::Video(_, frame) | Block::Audio(_, frame) => bytes += frame.len(),
+        }
+        Ok(())
+    });
+    assert!(bytes <= data.len());
+});
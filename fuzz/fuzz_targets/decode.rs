@@ -0,0 +1,30 @@
+//! Frames as a camera might send them: any format, any resolution up to
+//! 256x256 and any bytes, packed and decoded both ways athletic does.
+
+#![no_main]
+
+use athletic::layout;
+use athletic::pixel_format::{RgbAFormat, RgbFormat};
+use libfuzzer_sys::fuzz_target;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+
+const FORMATS: [FrameFormat; 5] = [
+    FrameFormat::MJPEG,
+    FrameFormat::YUYV,
+    FrameFormat::NV12,
+    FrameFormat::GRAY,
+    FrameFormat::RAWRGB,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let [format, width, height, frame @ ..] = data else {
+        return;
+    };
+    let format = FORMATS[*format as usize % FORMATS.len()];
+    let resolution = Resolution::new(*width as u32 + 1, *height as u32 + 1);
+    let buffer = layout::pack(Buffer::new(resolution, frame, format));
+    let _ = buffer.decode_image::<RgbFormat>();
+    let mut rgba = vec![0; resolution.width() as usize * resolution.height() as usize * 4];
+    let _ = buffer.decode_image_to_buffer::<RgbAFormat>(&mut rgba);
+});
@@ -0,0 +1,29 @@
+//! The strings the command line takes for devices, resolutions, formats,
+//! durations, masks and upscaling, including that what parses prints back
+//! the same.
+
+#![no_main]
+
+use athletic::mask::Mask;
+use athletic::spec::{parse_camera_format, parse_duration, parse_resolution, IndexKind};
+use athletic::upscale::Upscale;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(s) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(resolution) = parse_resolution(s) {
+        assert_eq!(
+            parse_resolution(&resolution.to_string()).unwrap(),
+            resolution
+        );
+    }
+    let _ = parse_camera_format(s);
+    let _ = parse_duration(s);
+    let _ = s.parse::<IndexKind>();
+    let _ = s.parse::<Mask>();
+    if let Ok(upscale) = s.parse::<Upscale>() {
+        let _ = upscale.size(3840, 2160);
+    }
+});
@@ -0,0 +1,215 @@
+/* Generated by cbindgen from src/capi.rs; do not edit. */
+
+#ifndef ATHLETIC_H
+#define ATHLETIC_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// How many frames may wait for a slow consumer before new ones get dropped.
+#define QUEUE_DEPTH 4
+
+// Size of the header written by [`SinkFrame::header`].
+#define FRAME_HEADER_SIZE 40
+
+// The controls `athletic_set_control` can change, matching what
+// `athletic list-properties` prints.
+typedef enum AthleticControl {
+  ATHLETIC_CONTROL_BRIGHTNESS,
+  ATHLETIC_CONTROL_CONTRAST,
+  ATHLETIC_CONTROL_HUE,
+  ATHLETIC_CONTROL_SATURATION,
+  ATHLETIC_CONTROL_SHARPNESS,
+  ATHLETIC_CONTROL_GAMMA,
+  ATHLETIC_CONTROL_WHITE_BALANCE,
+  ATHLETIC_CONTROL_BACKLIGHT_COMP,
+  ATHLETIC_CONTROL_GAIN,
+  ATHLETIC_CONTROL_PAN,
+  ATHLETIC_CONTROL_TILT,
+  ATHLETIC_CONTROL_ZOOM,
+  ATHLETIC_CONTROL_EXPOSURE,
+  ATHLETIC_CONTROL_IRIS,
+  ATHLETIC_CONTROL_FOCUS,
+} AthleticControl;
+
+// An open camera.
+typedef struct AthleticCamera AthleticCamera;
+
+// A [`Buffer`] stamped at the moment it entered the pipeline.
+//
+// `sequence` counts every frame the camera delivered, including the ones
+// dropped because a consumer fell behind, so gaps in it are real gaps in
+// the recording. `pts` is measured from the first frame of the session on
+// a monotonic clock and should be used instead of assuming a constant
+// frame interval.
+typedef struct Frame Frame;
+
+typedef struct AthleticDevice {
+  // What to pass to `athletic_open`.
+  uint32_t index;
+  char *name;
+  char *description;
+} AthleticDevice;
+
+// A decoded frame handed to a frame callback. The pixels are RGBA and
+// only valid during the callback.
+typedef struct AthleticFrame {
+  const uint8_t *data;
+  uintptr_t length;
+  uint32_t width;
+  uint32_t height;
+  // Bytes per row.
+  uint32_t stride;
+  // Counts every frame the camera delivered, so gaps are dropped frames.
+  uint64_t sequence;
+  // Nanoseconds since the first frame.
+  uint64_t pts_ns;
+} AthleticFrame;
+
+// Called on a thread of athletic's for every frame until the camera is
+// closed or another callback replaces it. Frames that arrive while the
+// callback is still running are dropped.
+typedef void (*AthleticFrameCallback)(const struct AthleticFrame *frame, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Why the last failing call on this thread failed, or NULL if none has.
+// The string stays valid until the next failing call on the same thread.
+const char *athletic_last_error(void);
+
+// Lists the cameras into a new array of `*count` devices, to be freed
+// with `athletic_free_devices`.
+//
+// # Safety
+//
+// `devices` and `count` must be valid for writes.
+int athletic_list_devices(struct AthleticDevice **devices, uintptr_t *count);
+
+// Frees a list returned by `athletic_list_devices`.
+//
+// # Safety
+//
+// `devices` and `count` must come from one call to
+// `athletic_list_devices`, and not have been freed already.
+void athletic_free_devices(struct AthleticDevice *devices, uintptr_t count);
+
+// Opens the camera at `index` and starts streaming from it. Returns NULL
+// on failure.
+struct AthleticCamera *athletic_open(uint32_t index);
+
+// Stops the camera and frees it. Waits for a running frame callback to
+// return first, unless called from that callback.
+//
+// # Safety
+//
+// `camera` must come from `athletic_open` and not have been closed already.
+void athletic_close(struct AthleticCamera *camera);
+
+// Calls `callback` with every frame from `camera`, replacing any previous
+// callback. A NULL callback stops the calls. This may be called from
+// the frame callback.
+//
+// # Safety
+//
+// `camera` must be open. `callback` must be safe to call from another
+// thread with `user_data`.
+int athletic_set_frame_callback(struct AthleticCamera *camera,
+                                AthleticFrameCallback callback,
+                                void *user_data);
+
+// Sets an integer control, such as brightness, to `value`. Fails if the
+// camera hasn't answered within five seconds.
+//
+// # Safety
+//
+// `camera` must be open.
+int athletic_set_control(struct AthleticCamera *camera,
+                         enum AthleticControl control,
+                         int64_t value);
+
+extern Context *rs2_create_context(int api_version, Error **error);
+
+extern void rs2_delete_context(Context *context);
+
+extern Pipeline *rs2_create_pipeline(Context *context, Error **error);
+
+extern void rs2_delete_pipeline(Pipeline *pipeline);
+
+extern Config *rs2_create_config(Error **error);
+
+extern void rs2_delete_config(Config *config);
+
+extern void rs2_config_enable_stream(Config *config,
+                                     int stream,
+                                     int index,
+                                     int width,
+                                     int height,
+                                     int format,
+                                     int framerate,
+                                     Error **error);
+
+extern PipelineProfile *rs2_pipeline_start_with_config(Pipeline *pipeline,
+                                                       Config *config,
+                                                       Error **error);
+
+extern void rs2_delete_pipeline_profile(PipelineProfile *profile);
+
+extern void rs2_pipeline_stop(Pipeline *pipeline, Error **error);
+
+extern struct Frame *rs2_pipeline_wait_for_frames(Pipeline *pipeline,
+                                                  unsigned int timeout_ms,
+                                                  Error **error);
+
+extern ProcessingBlock *rs2_create_align(int align_to, Error **error);
+
+extern void rs2_delete_processing_block(ProcessingBlock *block);
+
+extern FrameQueue *rs2_create_frame_queue(int capacity, Error **error);
+
+extern void rs2_delete_frame_queue(FrameQueue *queue);
+
+extern void rs2_start_processing_queue(ProcessingBlock *block, FrameQueue *queue, Error **error);
+
+extern void rs2_process_frame(ProcessingBlock *block, struct Frame *frame, Error **error);
+
+extern struct Frame *rs2_wait_for_frame(FrameQueue *queue, unsigned int timeout_ms, Error **error);
+
+extern int rs2_embedded_frames_count(struct Frame *composite, Error **error);
+
+extern struct Frame *rs2_extract_frame(struct Frame *composite, int index, Error **error);
+
+extern const StreamProfile *rs2_get_frame_stream_profile(const struct Frame *frame, Error **error);
+
+extern void rs2_get_stream_profile_data(const StreamProfile *profile,
+                                        int *stream,
+                                        int *format,
+                                        int *index,
+                                        int *unique_id,
+                                        int *framerate,
+                                        Error **error);
+
+extern const void *rs2_get_frame_data(const struct Frame *frame, Error **error);
+
+extern int rs2_get_frame_width(const struct Frame *frame, Error **error);
+
+extern int rs2_get_frame_height(const struct Frame *frame, Error **error);
+
+extern int rs2_get_frame_stride_in_bytes(const struct Frame *frame, Error **error);
+
+extern float rs2_depth_frame_get_units(const struct Frame *frame, Error **error);
+
+extern void rs2_release_frame(struct Frame *frame);
+
+extern const char *rs2_get_error_message(const Error *error);
+
+extern void rs2_free_error(Error *error);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ATHLETIC_H */
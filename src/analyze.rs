@@ -0,0 +1,111 @@
+//! `analyze`, for measuring what's wrong with a camera's picture.
+
+use crate::capture::{self, Capture};
+use crate::defects::{self, Kind, Levels, PixelMap};
+use crate::pixel_format::RgbFormat;
+use crate::IndexKind;
+use clap::Subcommand;
+use color_eyre::Report;
+use nokhwa::utils::CameraIndex;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+/// How many of the pixels found are listed; the map has them all.
+const LISTED: usize = 50;
+
+#[derive(Subcommand, Clone)]
+pub enum Action {
+    /// Find the camera's hot, dead and stuck pixels, from frames taken with
+    /// the lens covered and then of something evenly lit.
+    DeadPixels {
+        device: Option<IndexKind>,
+        /// How many frames to average in the dark, and then in the light.
+        #[arg(long, default_value_t = 16, value_name = "COUNT")]
+        frames: u32,
+        /// How far a pixel has to stray from those around it to count, in
+        /// levels of 255.
+        #[arg(long, default_value_t = 48.0, value_name = "LEVELS")]
+        threshold: f32,
+        /// Write the pixels found to this file, for --pixel-map to fill
+        /// them in.
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Averages `frames` frames from the camera at `index` in the dark and as
+/// many in the light, and lists the pixels that stray by `threshold` from
+/// those around them, writing them to `output` if given.
+pub fn dead_pixels(
+    index: CameraIndex,
+    frames: u32,
+    threshold: f32,
+    output: Option<&Path>,
+) -> Result<(), Report> {
+    let capture = capture::spawn_capture(index)?;
+    println!(
+        "Looking for {}'s dead pixels, in {}",
+        capture.info.human_name(),
+        capture.format
+    );
+    let dark = average(&capture, "Cover the lens", frames)?;
+    let bright = average(
+        &capture,
+        "Point the camera at something evenly and brightly lit, as a white wall",
+        frames,
+    )?;
+    if bright.mean() < dark.mean() + threshold {
+        eprintln!(
+            "The lit frames are hardly brighter than the dark ones, so dead and stuck pixels may go unfound"
+        );
+    }
+    let found = defects::find(&dark, &bright, threshold)?;
+    let count = |kind| found.iter().filter(|defect| defect.kind == kind).count();
+    println!(
+        "Found {} pixels: {} hot, {} dead and {} stuck",
+        found.len(),
+        count(Kind::Hot),
+        count(Kind::Dead),
+        count(Kind::Stuck)
+    );
+    for defect in found.iter().take(LISTED) {
+        println!("  {:>5},{:<5} {}", defect.x, defect.y, defect.kind);
+    }
+    if found.len() > LISTED {
+        println!("  and {} more", found.len() - LISTED);
+    }
+    if let Some(path) = output {
+        PixelMap {
+            width: dark.width,
+            height: dark.height,
+            defects: found,
+        }
+        .save(path)?;
+        println!(
+            "Wrote them to {}; --pixel-map {0} fills them in",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// The average of `frames` frames, taken once whoever's at the terminal
+/// has done as `ask` says.
+fn average(capture: &Capture, ask: &str, frames: u32) -> Result<Levels, Report> {
+    if std::io::stdin().is_terminal() {
+        print!("{ask}, then press Enter ");
+        std::io::stdout().flush()?;
+        std::io::stdin().read_line(&mut String::new())?;
+    }
+    // Frames queued while waiting are from before.
+    capture.frames.drain();
+    let mut levels: Option<Levels> = None;
+    for _ in 0..frames {
+        let frame = capture.frames.recv()?;
+        let image = frame.buffer.decode_image::<RgbFormat>()?;
+        levels
+            .get_or_insert_with(|| Levels::new(image.width(), image.height()))
+            .add(image.as_raw())?;
+    }
+    levels.ok_or_else(|| Report::msg("--frames has to be at least 1"))
+}
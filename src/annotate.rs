@@ -0,0 +1,161 @@
+use ggez::{
+    glam::Vec2,
+    graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Rect, Text},
+    Context, GameError,
+};
+
+const COLOR: Color = Color::RED;
+/// Line width and text size in image pixels, so they scale with the frame.
+const WIDTH: f32 = 4.0;
+const TEXT_SIZE: f32 = 32.0;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Tool {
+    Pen,
+    Rectangle,
+    Arrow,
+    Text,
+}
+
+enum Shape {
+    Stroke(Vec<Vec2>),
+    Rectangle(Vec2, Vec2),
+    Arrow(Vec2, Vec2),
+    Text(Vec2, String),
+}
+
+/// Shapes drawn over the preview, kept in image coordinates so they stay
+/// on the same part of the scene when the window is resized.
+pub struct Annotations {
+    pub enabled: bool,
+    pub tool: Tool,
+    shapes: Vec<Shape>,
+    /// The shape under the mouse or keyboard right now.
+    current: Option<Shape>,
+}
+
+impl Annotations {
+    pub fn new() -> Self {
+        Annotations {
+            enabled: false,
+            tool: Tool::Pen,
+            shapes: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Whether keystrokes should go to a text annotation being typed.
+    pub fn typing(&self) -> bool {
+        matches!(self.current, Some(Shape::Text(..)))
+    }
+
+    pub fn press(&mut self, at: Vec2) {
+        if !self.enabled {
+            return;
+        }
+        self.finish();
+        self.current = Some(match self.tool {
+            Tool::Pen => Shape::Stroke(vec![at]),
+            Tool::Rectangle => Shape::Rectangle(at, at),
+            Tool::Arrow => Shape::Arrow(at, at),
+            Tool::Text => Shape::Text(at, String::new()),
+        });
+    }
+
+    pub fn drag(&mut self, to: Vec2) {
+        match &mut self.current {
+            Some(Shape::Stroke(points)) => points.push(to),
+            Some(Shape::Rectangle(_, end)) | Some(Shape::Arrow(_, end)) => *end = to,
+            _ => {}
+        }
+    }
+
+    pub fn release(&mut self) {
+        if !self.typing() {
+            self.finish();
+        }
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if let Some(Shape::Text(_, text)) = &mut self.current {
+            if !c.is_control() {
+                text.push(c);
+            }
+        }
+    }
+
+    /// Deletes the last typed character, or else the last shape.
+    pub fn undo(&mut self) {
+        match &mut self.current {
+            Some(Shape::Text(_, text)) => {
+                text.pop();
+            }
+            _ => {
+                self.shapes.pop();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+        self.current = None;
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(shape) = self.current.take() {
+            self.shapes.push(shape);
+        }
+    }
+
+    /// Draws the annotations for an image placed at `offset` and scaled by `scale`.
+    pub fn draw(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        offset: [f32; 2],
+        scale: f32,
+    ) -> Result<(), GameError> {
+        let mut mesh = MeshBuilder::new();
+        let mut empty = true;
+        for shape in self.shapes.iter().chain(&self.current) {
+            match shape {
+                Shape::Stroke(points) if points.len() > 1 => {
+                    mesh.line(points, WIDTH, COLOR)?;
+                }
+                Shape::Rectangle(start, end) if start != end => {
+                    let rect = Rect::new(
+                        start.x.min(end.x),
+                        start.y.min(end.y),
+                        (end.x - start.x).abs(),
+                        (end.y - start.y).abs(),
+                    );
+                    mesh.rectangle(DrawMode::stroke(WIDTH), rect, COLOR)?;
+                }
+                Shape::Arrow(start, end) if start != end => {
+                    let back = (*start - *end).normalize() * WIDTH * 5.0;
+                    let side = back.perp() * 0.5;
+                    mesh.line(&[*start, *end], WIDTH, COLOR)?;
+                    mesh.line(
+                        &[*end + back + side, *end, *end + back - side],
+                        WIDTH,
+                        COLOR,
+                    )?;
+                }
+                Shape::Text(at, text) => {
+                    let mut text = Text::new(text.as_str());
+                    text.set_scale(TEXT_SIZE * scale);
+                    let dest = Vec2::from(offset) + *at * scale;
+                    canvas.draw(&text, DrawParam::new().dest(dest).color(COLOR));
+                    continue;
+                }
+                _ => continue,
+            }
+            empty = false;
+        }
+        if !empty {
+            let mesh = Mesh::from_data(ctx, mesh.build());
+            canvas.draw(&mesh, DrawParam::new().dest(offset).scale([scale, scale]));
+        }
+        Ok(())
+    }
+}
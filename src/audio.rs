@@ -0,0 +1,245 @@
+use clap::Args;
+use color_eyre::Report;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Sample, SampleFormat, Stream, StreamConfig};
+use flume::{Receiver, Sender};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Quietest level the meter shows; anything below reads as this.
+pub const FLOOR_DB: f32 = -60.0;
+
+/// Which microphone to watch and what counts as silence.
+#[derive(Args, Clone, Default)]
+pub struct AudioOptions {
+    /// Watch the level of a microphone, the default one unless named.
+    /// `record` also records it, unless given --no-audio; everywhere else
+    /// it's only metered.
+    #[arg(long, value_name = "DEVICE", num_args = 0..=1, default_missing_value = "default")]
+    pub audio: Option<String>,
+    /// Level, in dBFS, below which the microphone counts as silent.
+    #[arg(long, default_value_t = -50.0, allow_negative_numbers = true)]
+    pub silence_threshold: f32,
+}
+
+/// State shared with the audio callback.
+struct Level {
+    /// Level of the last block of samples, in dBFS, as `f32` bits.
+    db: AtomicU32,
+    /// Milliseconds from `start` to the last block above the threshold.
+    heard: AtomicU64,
+    start: Instant,
+    threshold: f32,
+}
+
+impl Level {
+    fn update<T: Sample>(&self, samples: &[T]) {
+        if samples.is_empty() {
+            return;
+        }
+        let power = samples
+            .iter()
+            .map(|sample| sample.to_f32().powi(2))
+            .sum::<f32>()
+            / samples.len() as f32;
+        let db = (10.0 * power.log10()).max(FLOOR_DB);
+        self.db.store(db.to_bits(), Ordering::Relaxed);
+        if db >= self.threshold {
+            let heard = self.start.elapsed().as_millis() as u64;
+            self.heard.store(heard, Ordering::Relaxed);
+        }
+    }
+
+    /// Meters `samples` and, when recording, passes them on as 16-bit.
+    fn heard<T: Sample>(&self, samples: &[T], tap: &Option<Sender<Vec<i16>>>) {
+        self.update(samples);
+        if let Some(tap) = tap {
+            let _ = tap.send(samples.iter().map(Sample::to_i16).collect());
+        }
+    }
+}
+
+/// The microphone's samples, interleaved, in blocks as the audio callback
+/// got them.
+pub struct Tap {
+    pub samples: Receiver<Vec<i16>>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// A live level meter on a microphone.
+pub struct Meter {
+    level: Arc<Level>,
+    /// Kept for as long as the meter is, since dropping it stops the audio.
+    _stream: Stream,
+}
+
+impl Meter {
+    /// Starts metering the microphone named in `options`, if any.
+    pub fn open(options: &AudioOptions) -> Result<Option<Self>, Report> {
+        Ok(Self::start(options, None)?.map(|(meter, _)| meter))
+    }
+
+    /// Like [`Meter::open`], but also hands every sample to the returned
+    /// [`Tap`] to be recorded.
+    pub fn open_recording(options: &AudioOptions) -> Result<Option<(Self, Tap)>, Report> {
+        let (sender, samples) = flume::unbounded();
+        Ok(Self::start(options, Some(sender))?.map(|(meter, config)| {
+            let tap = Tap {
+                samples,
+                sample_rate: config.sample_rate.0,
+                channels: config.channels,
+            };
+            (meter, tap)
+        }))
+    }
+
+    fn start(
+        options: &AudioOptions,
+        tap: Option<Sender<Vec<i16>>>,
+    ) -> Result<Option<(Self, StreamConfig)>, Report> {
+        let Some(name) = &options.audio else {
+            return Ok(None);
+        };
+        let device = input_device(name)?;
+        let supported = device.default_input_config()?;
+        let config: StreamConfig = supported.config();
+
+        let level = Arc::new(Level {
+            db: AtomicU32::new(FLOOR_DB.to_bits()),
+            heard: AtomicU64::new(0),
+            start: Instant::now(),
+            threshold: options.silence_threshold,
+        });
+        let recording = tap.is_some();
+        let error = |why| eprintln!("audio stream failed: {why}");
+        let stream = {
+            let level = level.clone();
+            match supported.sample_format() {
+                SampleFormat::F32 => device.build_input_stream(
+                    &config,
+                    move |samples: &[f32], _: &_| level.heard(samples, &tap),
+                    error,
+                ),
+                SampleFormat::I16 => device.build_input_stream(
+                    &config,
+                    move |samples: &[i16], _: &_| level.heard(samples, &tap),
+                    error,
+                ),
+                SampleFormat::U16 => device.build_input_stream(
+                    &config,
+                    move |samples: &[u16], _: &_| level.heard(samples, &tap),
+                    error,
+                ),
+            }?
+        };
+        stream.play()?;
+        println!(
+            "{} audio from {}",
+            if recording { "Recording" } else { "Metering" },
+            device.name().unwrap_or_else(|_| name.clone())
+        );
+        let meter = Meter {
+            level,
+            _stream: stream,
+        };
+        Ok(Some((meter, config)))
+    }
+
+    /// The current level in dBFS, no lower than [`FLOOR_DB`].
+    pub fn db(&self) -> f32 {
+        f32::from_bits(self.level.db.load(Ordering::Relaxed))
+    }
+
+    /// How long the level has stayed below the silence threshold.
+    pub fn silent_for(&self) -> Duration {
+        let heard = Duration::from_millis(self.level.heard.load(Ordering::Relaxed));
+        self.level.start.elapsed().saturating_sub(heard)
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.db() < self.level.threshold
+    }
+}
+
+/// Plays a tone of `frequency` hertz for `length` on the default speakers,
+/// in the background. Failing to is only reported, since a beep is never
+/// worth stopping for.
+pub fn beep(frequency: f32, length: Duration) {
+    std::thread::spawn(move || {
+        if let Err(why) = play_tone(frequency, length) {
+            eprintln!("failed to beep: {why}");
+        }
+    });
+}
+
+fn play_tone(frequency: f32, length: Duration) -> Result<(), Report> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or_else(|| Report::msg("no speakers"))?;
+    let supported = device.default_output_config()?;
+    let config: StreamConfig = supported.config();
+    let (rate, channels) = (config.sample_rate.0 as f32, config.channels as usize);
+    let mut phase = 0.0f32;
+    // Quiet enough not to startle, and the same on every channel.
+    let mut next = move || {
+        phase = (phase + frequency / rate).fract();
+        (phase * std::f32::consts::TAU).sin() * 0.2
+    };
+    let error = |why| eprintln!("audio stream failed: {why}");
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => device.build_output_stream(
+            &config,
+            move |out: &mut [f32], _: &_| fill(out, channels, &mut next),
+            error,
+        ),
+        SampleFormat::I16 => device.build_output_stream(
+            &config,
+            move |out: &mut [i16], _: &_| fill(out, channels, &mut next),
+            error,
+        ),
+        SampleFormat::U16 => device.build_output_stream(
+            &config,
+            move |out: &mut [u16], _: &_| fill(out, channels, &mut next),
+            error,
+        ),
+    }?;
+    stream.play()?;
+    std::thread::sleep(length);
+    Ok(())
+}
+
+fn fill<T: Sample>(out: &mut [T], channels: usize, next: &mut impl FnMut() -> f32) {
+    for frame in out.chunks_mut(channels) {
+        let value = T::from(&next());
+        frame.fill(value);
+    }
+}
+
+/// The name and format of the microphone `options` asks for, found without
+/// starting to listen to it.
+pub fn describe(options: &AudioOptions) -> Result<Option<String>, Report> {
+    let Some(name) = &options.audio else {
+        return Ok(None);
+    };
+    let device = input_device(name)?;
+    let config = device.default_input_config()?;
+    Ok(Some(format!(
+        "{}, {} Hz, {} channels",
+        device.name().unwrap_or_else(|_| name.clone()),
+        config.sample_rate().0,
+        config.channels()
+    )))
+}
+
+fn input_device(name: &str) -> Result<cpal::Device, Report> {
+    let host = cpal::default_host();
+    if name == "default" {
+        host.default_input_device()
+    } else {
+        host.input_devices()?
+            .find(|device| device.name().is_ok_and(|found| found == name))
+    }
+    .ok_or_else(|| Report::msg(format!("no microphone called {name}")))
+}
@@ -0,0 +1,70 @@
+use clap::Args;
+use color_eyre::Report;
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Colour of the card sent when no --away-card is given.
+const PLAIN_CARD: Rgba<u8> = Rgba([32, 32, 32, 255]);
+
+/// What goes out instead of the camera while outgoing frames are blanked.
+#[derive(Args, Clone, Default)]
+pub struct AwayOptions {
+    /// Image to send instead of the camera while outgoing frames are
+    /// blanked; a plain dark frame if not given.
+    #[arg(long, value_name = "IMAGE")]
+    pub away_card: Option<PathBuf>,
+}
+
+/// Replaces outgoing frames with a card while switched on, so the camera
+/// can be blanked without covering the lens.
+pub struct Away {
+    image: Option<RgbaImage>,
+    /// `image` scaled to the size of the last frame it replaced.
+    scaled: Option<RgbaImage>,
+    switch: Arc<AtomicBool>,
+}
+
+impl Away {
+    pub fn open(options: &AwayOptions) -> Result<Self, Report> {
+        let image = match &options.away_card {
+            Some(path) => Some(image::open(path)?.to_rgba8()),
+            None => None,
+        };
+        Ok(Away {
+            image,
+            scaled: None,
+            switch: Arc::default(),
+        })
+    }
+
+    /// The switch turning the card on and off, for sharing with whatever
+    /// else may toggle it.
+    pub fn switch(&self) -> Arc<AtomicBool> {
+        self.switch.clone()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.switch.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle(&self) {
+        self.switch.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// The card at `width` by `height`.
+    pub fn card(&mut self, width: u32, height: u32) -> &RgbaImage {
+        let stale = self
+            .scaled
+            .as_ref()
+            .is_none_or(|card| card.dimensions() != (width, height));
+        if stale {
+            self.scaled = Some(match &self.image {
+                Some(image) => image::imageops::resize(image, width, height, FilterType::Triangle),
+                None => RgbaImage::from_pixel(width, height, PLAIN_CARD),
+            });
+        }
+        self.scaled.as_ref().unwrap()
+    }
+}
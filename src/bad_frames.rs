@@ -0,0 +1,39 @@
+use crate::bus::{self, Event};
+use crate::capture::Frame;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// Keeps count of frames that failed to decode, and optionally keeps their
+/// payloads for a closer look.
+pub struct BadFrames {
+    dump_to: Option<PathBuf>,
+    pub count: u64,
+}
+
+impl BadFrames {
+    pub fn new(dump_to: Option<PathBuf>) -> Self {
+        BadFrames { dump_to, count: 0 }
+    }
+
+    /// Records that `frame` couldn't be decoded because of `why`.
+    pub fn record(&mut self, frame: &Frame, why: impl Display) {
+        self.count += 1;
+        let message = format!("skipping corrupt frame {}: {why}", frame.sequence);
+        eprintln!("{message}");
+        bus::publish(Event::Error { message });
+        let Some(dir) = &self.dump_to else {
+            return;
+        };
+        let format = frame.buffer.source_frame_format().to_string();
+        let path = dir.join(format!(
+            "frame-{}.{}",
+            frame.sequence,
+            format.to_lowercase()
+        ));
+        let written = std::fs::create_dir_all(dir)
+            .and_then(|()| std::fs::write(&path, frame.buffer.buffer()));
+        if let Err(why) = written {
+            eprintln!("failed to save corrupt frame to {}: {why}", path.display());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! `athletic run`: a script of athletic's commands, run one after another
+//! in one process as at the prompt, for a procedure that has to go the
+//! same way every time.
+//!
+//! Each line of a script is a command as it would follow `athletic`, or
+//! one of:
+//!
+//! - `let NAME = VALUE`, which `$NAME` or `${NAME}` stands for in the lines
+//!   after it, unless `athletic run` was given `NAME=VALUE` itself;
+//! - `wait DURATION`, as `wait 2s`;
+//! - `echo TEXT`, which prints the text.
+//!
+//! `#` starts a comment and `$$` is a dollar sign. The whole script is
+//! checked before any of it runs, and it stops at the first command that
+//! fails.
+
+use crate::{parse_duration, repl, Cli, Commands};
+use clap::Parser;
+use color_eyre::Report;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+enum Step {
+    Wait(Duration),
+    Echo(String),
+    /// A command, in the options it's run with, and as it's written.
+    Run {
+        cli: Box<Cli>,
+        words: Vec<String>,
+    },
+}
+
+/// A step, with its line in the script and the line as it's run.
+struct Line {
+    number: usize,
+    shown: String,
+    step: Step,
+}
+
+/// Parses a variable given on the command line, as `camera=0`.
+pub fn parse_var(s: &str) -> Result<(String, String), Report> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| Report::msg(format!("expected NAME=VALUE, not {s}")))?;
+    if !is_name(name) {
+        return Err(Report::msg(format!("{name} can't be a variable's name")));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Runs the script at `path` with `vars` and the options `session` gives
+/// `athletic run`, returning the code of the command it stopped at, or 0
+/// if it got to the end.
+pub fn run(session: &Cli, path: &Path, vars: &[(String, String)]) -> i32 {
+    let mut own = vec![OsString::from("run"), path.into()];
+    own.extend(
+        vars.iter()
+            .map(|(name, value)| format!("{name}={value}").into()),
+    );
+    let options = repl::options(&own);
+    let lines = match load(path, vars, &options) {
+        Ok(lines) => lines,
+        Err(why) => {
+            eprintln!("{why}");
+            return 1;
+        }
+    };
+    for Line {
+        number,
+        shown,
+        step,
+    } in lines
+    {
+        let code = match step {
+            Step::Wait(duration) => {
+                println!("+ {shown}");
+                if !session.dry_run {
+                    std::thread::sleep(duration);
+                }
+                0
+            }
+            Step::Echo(text) => {
+                println!("{text}");
+                0
+            }
+            Step::Run { cli, words }
+                if cli.command.as_ref().is_some_and(Commands::opens_window) && !cli.dry_run =>
+            {
+                println!("+ {shown}");
+                // A window ends the process it's in when it closes.
+                match std::env::current_exe()
+                    .and_then(|athletic| Command::new(athletic).args(&options).args(words).status())
+                {
+                    Ok(status) => status.code().unwrap_or(1),
+                    Err(why) => {
+                        eprintln!("can't start athletic for the window: {why}");
+                        1
+                    }
+                }
+            }
+            Step::Run { mut cli, .. } => {
+                println!("+ {shown}");
+                // Load checked it has one.
+                let cmd = cli.command.take().unwrap();
+                repl::attempt(&cli, cmd)
+            }
+        };
+        if code != 0 {
+            eprintln!(
+                "{}:{number}: exited with {code}, so the script stops here",
+                path.display()
+            );
+            return code;
+        }
+    }
+    0
+}
+
+/// Reads the script at `path` and checks each of its lines, with `vars`
+/// and `options` given to `athletic run`.
+fn load(path: &Path, vars: &[(String, String)], options: &[OsString]) -> Result<Vec<Line>, Report> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|why| Report::msg(format!("can't read {}: {why}", path.display())))?;
+    let given: HashSet<_> = vars.iter().map(|(name, _)| name.clone()).collect();
+    let mut values: HashMap<_, _> = vars.iter().cloned().collect();
+    let mut lines = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let at = |why: String| Report::msg(format!("{}:{number}: {why}", path.display()));
+        let words = shlex::split(line).ok_or_else(|| at("a quote isn't closed".into()))?;
+        let words = words
+            .iter()
+            .map(|word| substitute(word, &values))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(at)?;
+        let shown = words.join(" ");
+        let step = match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [] => continue,
+            ["let", name, "=", ..] if is_name(name) => {
+                if !given.contains(name) {
+                    values.insert(name.to_string(), words[3..].join(" "));
+                }
+                continue;
+            }
+            ["let", ..] => return Err(at("expected let NAME = VALUE".into())),
+            ["wait", duration] => {
+                Step::Wait(parse_duration(duration).map_err(|why| at(why.to_string()))?)
+            }
+            ["wait", ..] => return Err(at("expected wait DURATION, as wait 2s".into())),
+            ["echo", ..] => Step::Echo(words[1..].join(" ")),
+            _ => {
+                let own = std::iter::once("athletic".to_string()).chain(words.iter().cloned());
+                let cli = Cli::try_parse_from(own).map_err(|why| at(why.render().to_string()))?;
+                if let Some(option) = repl::whole_session(&cli) {
+                    return Err(at(format!(
+                        "{option} holds for the whole script; give it to athletic run"
+                    )));
+                }
+                let args = std::iter::once(OsString::from("athletic"))
+                    .chain(options.iter().cloned())
+                    .chain(words.iter().map(OsString::from));
+                let cli = Cli::try_parse_from(args).map_err(|why| at(why.render().to_string()))?;
+                match cli.command {
+                    None => return Err(at("expected a command".into())),
+                    Some(Commands::Repl | Commands::Run { .. }) => {
+                        return Err(at("a script can't run repl or another script".into()))
+                    }
+                    Some(_) => Step::Run {
+                        cli: Box::new(cli),
+                        words,
+                    },
+                }
+            }
+        };
+        lines.push(Line {
+            number,
+            shown,
+            step,
+        });
+    }
+    Ok(lines)
+}
+
+/// Whether `name` can name a variable: letters, digits and underscores,
+/// not starting with a digit.
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `word` with the values of the variables it names, or why not.
+fn substitute(word: &str, values: &HashMap<String, String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = word;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => braced
+                .split_once('}')
+                .ok_or_else(|| format!("a ${{ isn't closed in {word}"))?,
+            None => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        let value = values
+            .get(name)
+            .ok_or_else(|| format!("no variable called {name} has been let"))?;
+        out.push_str(value);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
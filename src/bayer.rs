@@ -0,0 +1,253 @@
+use crate::capture::Frame;
+use crate::mono::Depth;
+use clap::Args;
+use color_eyre::Report;
+use std::str::FromStr;
+
+/// How raw sensor data is turned into colour.
+///
+/// nokhwa has no Bayer formats of its own, so raw Bayer modes arrive as
+/// grayscale frames and the colour filter layout has to be given here.
+#[derive(Args, Clone, Default)]
+pub struct BayerOptions {
+    /// Treat grayscale frames as raw Bayer data laid out as RGGB, BGGR,
+    /// GRBG or GBRG.
+    #[arg(long)]
+    pub bayer: Option<Pattern>,
+    /// How to fill in the colours a Bayer pixel didn't see: nearest,
+    /// bilinear or malvar.
+    #[arg(long, default_value = "bilinear")]
+    pub demosaic: Demosaic,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+/// The colour filter over each pixel of a 2×2 cell, row by row.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Pattern(pub [Channel; 4]);
+
+impl Pattern {
+    /// The channel of the pixel at `(x, y)`.
+    pub fn at(&self, x: usize, y: usize) -> Channel {
+        self.0[(y % 2) * 2 + x % 2]
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let channels: Vec<Channel> = s
+            .chars()
+            .map(|c| match c.to_ascii_uppercase() {
+                'R' => Ok(Channel::Red),
+                'G' => Ok(Channel::Green),
+                'B' => Ok(Channel::Blue),
+                _ => Err(Report::msg(format!("unknown Bayer pattern: {s}"))),
+            })
+            .collect::<Result<_, _>>()?;
+        let count = |channel| channels.iter().filter(|&&c| c == channel).count();
+        // The two greens sit on one diagonal of the cell, red and blue on the other.
+        match channels[..] {
+            [a, b, c, d]
+                if count(Channel::Red) == 1 && count(Channel::Blue) == 1 && (a == d || b == c) =>
+            {
+                Ok(Pattern([a, b, c, d]))
+            }
+            _ => Err(Report::msg(format!("unknown Bayer pattern: {s}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Demosaic {
+    /// Every pixel of a 2×2 cell gets the cell's colour. Fast and blocky.
+    Nearest,
+    /// Missing colours are averaged from the nearest pixels that saw them.
+    #[default]
+    Bilinear,
+    /// Malvar, He and Cutler's gradient-corrected interpolation, sharper
+    /// than bilinear for little extra cost.
+    Malvar,
+}
+
+impl FromStr for Demosaic {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" | "Nearest" | "NEAREST" => Ok(Demosaic::Nearest),
+            "bilinear" | "Bilinear" | "BILINEAR" => Ok(Demosaic::Bilinear),
+            "malvar" | "Malvar" | "MALVAR" | "mhc" => Ok(Demosaic::Malvar),
+            _ => Err(Report::msg(format!("unknown demosaic algorithm: {s}"))),
+        }
+    }
+}
+
+/// Where the wanted colour sits relative to a pixel that didn't see it.
+#[derive(Copy, Clone)]
+enum Case {
+    /// Green at a red or blue pixel.
+    Green,
+    /// Red or blue at a green pixel that has it to its left and right.
+    Row,
+    /// Red or blue at a green pixel that has it above and below.
+    Column,
+    /// Red at a blue pixel, or blue at a red one.
+    Diagonal,
+}
+
+/// A filter as `(dx, dy, weight)` taps, whose weights sum to one.
+type Kernel = &'static [(isize, isize, f32)];
+
+fn kernel(demosaic: Demosaic, case: Case) -> Kernel {
+    match (demosaic, case) {
+        (Demosaic::Malvar, Case::Green) => &[
+            (0, 0, 0.5),
+            (-1, 0, 0.25),
+            (1, 0, 0.25),
+            (0, -1, 0.25),
+            (0, 1, 0.25),
+            (-2, 0, -0.125),
+            (2, 0, -0.125),
+            (0, -2, -0.125),
+            (0, 2, -0.125),
+        ],
+        (Demosaic::Malvar, Case::Row) => &[
+            (0, 0, 0.625),
+            (-1, 0, 0.5),
+            (1, 0, 0.5),
+            (-1, -1, -0.125),
+            (1, -1, -0.125),
+            (-1, 1, -0.125),
+            (1, 1, -0.125),
+            (-2, 0, -0.125),
+            (2, 0, -0.125),
+            (0, -2, 0.0625),
+            (0, 2, 0.0625),
+        ],
+        (Demosaic::Malvar, Case::Column) => &[
+            (0, 0, 0.625),
+            (0, -1, 0.5),
+            (0, 1, 0.5),
+            (-1, -1, -0.125),
+            (1, -1, -0.125),
+            (-1, 1, -0.125),
+            (1, 1, -0.125),
+            (0, -2, -0.125),
+            (0, 2, -0.125),
+            (-2, 0, 0.0625),
+            (2, 0, 0.0625),
+        ],
+        (Demosaic::Malvar, Case::Diagonal) => &[
+            (0, 0, 0.75),
+            (-1, -1, 0.25),
+            (1, -1, 0.25),
+            (-1, 1, 0.25),
+            (1, 1, 0.25),
+            (-2, 0, -0.1875),
+            (2, 0, -0.1875),
+            (0, -2, -0.1875),
+            (0, 2, -0.1875),
+        ],
+        (_, Case::Green) => &[(-1, 0, 0.25), (1, 0, 0.25), (0, -1, 0.25), (0, 1, 0.25)],
+        (_, Case::Row) => &[(-1, 0, 0.5), (1, 0, 0.5)],
+        (_, Case::Column) => &[(0, -1, 0.5), (0, 1, 0.5)],
+        (_, Case::Diagonal) => &[(-1, -1, 0.25), (1, -1, 0.25), (-1, 1, 0.25), (1, 1, 0.25)],
+    }
+}
+
+/// Reflects a coordinate that ran off the edge back inside, keeping it on
+/// the same colour of the pattern.
+fn reflect(at: isize, size: usize) -> usize {
+    let last = size as isize - 1;
+    let at = if at < 0 { -at } else { at };
+    let at = if at > last { 2 * last - at } else { at };
+    at.clamp(0, last) as usize
+}
+
+/// Raw samples of a Bayer frame, scaled to 0..=255.
+struct Mosaic<'a> {
+    data: &'a [u8],
+    depth: Depth,
+    width: usize,
+    height: usize,
+}
+
+impl Mosaic<'_> {
+    fn sample(&self, x: isize, y: isize) -> f32 {
+        let at = reflect(y, self.height) * self.width + reflect(x, self.width);
+        match self.depth {
+            Depth::Eight => self.data[at] as f32,
+            Depth::Sixteen => {
+                u16::from_le_bytes([self.data[at * 2], self.data[at * 2 + 1]]) as f32 / 257.0
+            }
+        }
+    }
+}
+
+/// Demosaics a grayscale frame holding raw Bayer data into RGBA, writing
+/// four bytes for every pixel of `rgba`.
+pub fn demosaic(
+    frame: &Frame,
+    depth: Depth,
+    pattern: Pattern,
+    demosaic: Demosaic,
+    rgba: &mut [u8],
+) {
+    let resolution = frame.buffer.resolution();
+    let mosaic = Mosaic {
+        data: frame.buffer.buffer(),
+        depth,
+        width: resolution.width() as usize,
+        height: resolution.height() as usize,
+    };
+    let needed = mosaic.width * mosaic.height * if depth == Depth::Sixteen { 2 } else { 1 };
+    if mosaic.data.len() < needed {
+        return;
+    }
+
+    for (n, pixel) in rgba
+        .chunks_exact_mut(4)
+        .take(mosaic.width * mosaic.height)
+        .enumerate()
+    {
+        let (x, y) = (n % mosaic.width, n / mosaic.width);
+        let here = pattern.at(x, y);
+        let channels = [Channel::Red, Channel::Green, Channel::Blue].map(|wanted| {
+            let (x, y) = (x as isize, y as isize);
+            if demosaic == Demosaic::Nearest {
+                // Take the wanted colour from this pixel's 2×2 cell, averaging
+                // the two greens.
+                let (cell_x, cell_y) = (x & !1, y & !1);
+                let taps: Vec<f32> = (0..4)
+                    .filter(|&k| pattern.0[k] == wanted)
+                    .map(|k| mosaic.sample(cell_x + (k % 2) as isize, cell_y + (k / 2) as isize))
+                    .collect();
+                return taps.iter().sum::<f32>() / taps.len() as f32;
+            }
+            let case = if wanted == here {
+                return mosaic.sample(x, y);
+            } else if wanted == Channel::Green {
+                Case::Green
+            } else if here != Channel::Green {
+                Case::Diagonal
+            } else if pattern.at(x as usize + 1, y as usize) == wanted {
+                Case::Row
+            } else {
+                Case::Column
+            };
+            kernel(demosaic, case)
+                .iter()
+                .map(|&(dx, dy, weight)| mosaic.sample(x + dx, y + dy) * weight)
+                .sum()
+        });
+        let [r, g, b] = channels.map(|value| value.round().clamp(0.0, 255.0) as u8);
+        pixel.copy_from_slice(&[r, g, b, 255]);
+    }
+}
@@ -0,0 +1,247 @@
+//! `benchmark`: how fast the pieces of the pipeline run on this machine.
+
+use crate::layout::FrameLayout;
+use crate::mkv::{self, Block};
+use crate::pixel_format::{RgbAFormat, RgbFormat};
+use clap::{Args, Subcommand};
+use color_eyre::Report;
+use image::codecs::jpeg::JpegEncoder;
+use image::RgbImage;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Frames read from a recording at most.
+const RECORDED_FRAMES: usize = 100;
+
+#[derive(Subcommand, Clone)]
+pub enum Suite {
+    /// Decode YUYV, NV12 and MJPEG frames with each decoder athletic can
+    /// use, and print how many frames a second each manages.
+    Decoders(DecoderOptions),
+}
+
+#[derive(Args, Clone)]
+pub struct DecoderOptions {
+    /// How long to keep decoding with each decoder, format and resolution.
+    #[arg(long, default_value = "1s", value_parser = crate::parse_duration)]
+    pub time: Duration,
+    /// Resolutions to make test frames at, separated by commas.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "640x480,1280x720,1920x1080",
+        value_parser = crate::parse_resolution
+    )]
+    pub resolutions: Vec<Resolution>,
+    /// Also decode the MJPEG frames of this recording, at its resolution.
+    #[arg(long, value_name = "FILE")]
+    pub recording: Option<PathBuf>,
+}
+
+/// Frames to decode: the same picture, or a recording's frames, in one
+/// format at one resolution.
+struct Samples {
+    source: &'static str,
+    format: FrameFormat,
+    resolution: Resolution,
+    buffers: Vec<Buffer>,
+}
+
+/// A way of decoding frames, and which formats it takes.
+struct Decoder {
+    name: &'static str,
+    formats: &'static [FrameFormat],
+    decode: fn(&Buffer, &mut Vec<u8>) -> Result<(), Report>,
+}
+
+const DECODERS: &[Decoder] = &[
+    // What athletic decodes with: jpeg-decoder for MJPEG and its own YUV
+    // conversion.
+    Decoder {
+        name: "athletic",
+        formats: &[FrameFormat::YUYV, FrameFormat::NV12, FrameFormat::MJPEG],
+        decode: |buffer, _| {
+            buffer.decode_image::<RgbFormat>()?;
+            Ok(())
+        },
+    },
+    // What the preview window does, into a buffer kept between frames.
+    Decoder {
+        name: "athletic, RGBA into a buffer",
+        formats: &[FrameFormat::YUYV, FrameFormat::NV12, FrameFormat::MJPEG],
+        decode: |buffer, out| {
+            out.resize(
+                FrameLayout::decoded::<RgbAFormat>(buffer.resolution()).bytes(),
+                0,
+            );
+            buffer.decode_image_to_buffer::<RgbAFormat>(out)?;
+            Ok(())
+        },
+    },
+    Decoder {
+        name: "jpeg-decoder",
+        formats: &[FrameFormat::MJPEG],
+        decode: |buffer, _| {
+            jpeg_decoder::Decoder::new(buffer.buffer()).decode()?;
+            Ok(())
+        },
+    },
+];
+
+pub fn run(suite: &Suite) -> Result<(), Report> {
+    match suite {
+        Suite::Decoders(options) => decoders(options),
+    }
+}
+
+fn decoders(options: &DecoderOptions) -> Result<(), Report> {
+    let mut samples = Vec::new();
+    for &resolution in &options.resolutions {
+        if resolution.width() == 0 || resolution.height() == 0 {
+            return Err(Report::msg(format!("can't make {resolution} test frames")));
+        }
+        let image = pattern(resolution.width(), resolution.height());
+        for format in [FrameFormat::YUYV, FrameFormat::NV12, FrameFormat::MJPEG] {
+            samples.push(Samples {
+                source: "test frame",
+                format,
+                resolution,
+                buffers: vec![Buffer::new(resolution, &encode(&image, format)?, format)],
+            });
+        }
+    }
+    if let Some(path) = &options.recording {
+        samples.push(recorded(path)?);
+    }
+
+    println!(
+        "{:<8} {:<11} {:<12} {:<28} {:>9} {:>12}",
+        "Format", "Resolution", "Frames", "Decoder", "Frames/s", "Megapixels/s"
+    );
+    for samples in &samples {
+        let megapixels =
+            samples.resolution.width() as f64 * samples.resolution.height() as f64 / 1e6;
+        for decoder in DECODERS {
+            if !decoder.formats.contains(&samples.format) {
+                continue;
+            }
+            let rate = match measure(decoder, &samples.buffers, options.time) {
+                Ok(rate) => format!("{rate:>9.1} {:>12.1}", rate * megapixels),
+                Err(why) => format!("failed: {why}"),
+            };
+            println!(
+                "{:<8} {:<11} {:<12} {:<28} {rate}",
+                samples.format.to_string(),
+                samples.resolution.to_string(),
+                samples.source,
+                decoder.name,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Frames a second `decoder` gets through `buffers`, going round them for
+/// `time`.
+fn measure(decoder: &Decoder, buffers: &[Buffer], time: Duration) -> Result<f64, Report> {
+    let mut out = Vec::new();
+    // One round first, so nothing is timed that only happens once.
+    for buffer in buffers {
+        (decoder.decode)(buffer, &mut out)?;
+    }
+    let start = Instant::now();
+    let mut frames = 0u64;
+    while start.elapsed() < time {
+        for buffer in buffers {
+            (decoder.decode)(buffer, &mut out)?;
+            frames += 1;
+        }
+    }
+    Ok(frames as f64 / start.elapsed().as_secs_f64())
+}
+
+/// Up to `RECORDED_FRAMES` of the recording at `path`.
+fn recorded(path: &Path) -> Result<Samples, Report> {
+    let mut jpegs = Vec::new();
+    let input = BufReader::new(File::open(path)?);
+    mkv::read_blocks(input, |block| {
+        if let Block::Video(_, data) = block {
+            if jpegs.len() < RECORDED_FRAMES {
+                jpegs.push(data.to_vec());
+            }
+        }
+        Ok(())
+    })?;
+    let first = jpegs
+        .first()
+        .ok_or_else(|| Report::msg(format!("{} has no video frames", path.display())))?;
+    let mut decoder = jpeg_decoder::Decoder::new(first.as_slice());
+    decoder.read_info()?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| Report::msg("the first frame isn't a JPEG"))?;
+    let resolution = Resolution::new(info.width as u32, info.height as u32);
+    Ok(Samples {
+        source: "recorded",
+        format: FrameFormat::MJPEG,
+        resolution,
+        buffers: jpegs
+            .iter()
+            .map(|jpeg| Buffer::new(resolution, jpeg, FrameFormat::MJPEG))
+            .collect(),
+    })
+}
+
+/// A picture with smooth gradients and sharp edges, so JPEG has about as
+/// much to do as with a camera's.
+fn pattern(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        let (u, v) = (x * 255 / width.max(1), y * 255 / height.max(1));
+        let edge = if (x / 16 + y / 16) % 2 == 0 { 40 } else { 0 };
+        image::Rgb([u as u8, v as u8, ((u ^ v) as u8).saturating_add(edge)])
+    })
+}
+
+/// `image` in `format`, with BT.601 video-range YUV.
+fn encode(image: &RgbImage, format: FrameFormat) -> Result<Vec<u8>, Report> {
+    let (width, height) = image.dimensions();
+    let yuv = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        (
+            (16.0 + 0.257 * r + 0.504 * g + 0.098 * b) as u8,
+            (128.0 - 0.148 * r - 0.291 * g + 0.439 * b) as u8,
+            (128.0 + 0.439 * r - 0.368 * g - 0.071 * b) as u8,
+        )
+    };
+    let mut out = Vec::new();
+    match format {
+        FrameFormat::YUYV => {
+            for y in 0..height {
+                for x in (0..width).step_by(2) {
+                    let (y0, u, v) = yuv(x, y);
+                    let (y1, _, _) = yuv(x + 1, y);
+                    out.extend_from_slice(&[y0, u, y1, v]);
+                }
+            }
+        }
+        FrameFormat::NV12 => {
+            for y in 0..height {
+                out.extend((0..width).map(|x| yuv(x, y).0));
+            }
+            for y in (0..height).step_by(2) {
+                for x in (0..width).step_by(2) {
+                    let (_, u, v) = yuv(x, y);
+                    out.extend_from_slice(&[u, v]);
+                }
+            }
+        }
+        FrameFormat::MJPEG => JpegEncoder::new_with_quality(&mut out, 85).encode_image(image)?,
+        _ => return Err(Report::msg(format!("can't make {format} test frames"))),
+    }
+    Ok(out)
+}
@@ -0,0 +1,157 @@
+use crate::mask::{self, Mask};
+use crate::pixel_format::RgbAFormat;
+use crate::timer::{self, TimerOptions};
+use crate::{capture, defects, font, summary};
+use clap::Args;
+use color_eyre::Report;
+use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+use nokhwa::utils::CameraIndex;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Countdown before each shot when --timer isn't given.
+const DEFAULT_TIMER: Duration = Duration::from_secs(3);
+
+/// What `booth` shoots and how the strip looks.
+#[derive(Args, Clone)]
+pub struct BoothOptions {
+    /// Where to save the strip.
+    #[arg(short, long, default_value = "booth.png")]
+    pub output: PathBuf,
+    /// How many shots the strip holds.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub shots: u32,
+    /// Width of each shot on the strip, in pixels.
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u32).range(64..))]
+    pub width: u32,
+    /// Colour behind the shots, as #RRGGBB.
+    #[arg(long, default_value = "#ffffff")]
+    pub background: Colour,
+    /// Image drawn behind the shots instead of --background, stretched to
+    /// the strip.
+    #[arg(long, value_name = "IMAGE")]
+    pub template: Option<PathBuf>,
+    /// Text printed along the bottom of the strip.
+    #[arg(long)]
+    pub caption: Option<String>,
+    /// Send the strip to the default printer once it's saved.
+    #[arg(long)]
+    pub print: bool,
+    /// Countdown before each shot, 3s unless given.
+    #[command(flatten)]
+    pub timer: TimerOptions,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Colour(pub [u8; 3]);
+
+impl FromStr for Colour {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Report::msg(format!("expected a colour as #RRGGBB, got: {s}"));
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| invalid());
+        Ok(Colour([channel(0)?, channel(2)?, channel(4)?]))
+    }
+}
+
+/// Takes `options.shots` shots, each after a countdown, lays them out one
+/// above the other on a strip and saves it to `options.output`.
+pub fn booth(index: CameraIndex, options: &BoothOptions, masks: &[Mask]) -> Result<(), Report> {
+    let capture = capture::spawn_capture(index)?;
+    summary::watch(&capture);
+    let timer = TimerOptions {
+        timer: Some(options.timer.timer.unwrap_or(DEFAULT_TIMER)),
+        beep: options.timer.beep,
+    };
+    let mut shots = Vec::new();
+    for shot in 1..=options.shots {
+        println!("Shot {shot} of {}", options.shots);
+        timer::wait(&timer);
+        // Frames queued during the countdown are from before it ended.
+        capture.frames.drain();
+        let frame = capture.frames.recv()?;
+        let mut image = frame.buffer.decode_image::<RgbAFormat>()?;
+        let (width, height) = image.dimensions();
+        defects::correct(&mut image, width, height, 4);
+        mask::apply(masks, &mut image, width, height, 4);
+        shots.push(image);
+    }
+
+    let strip = compose(&shots, options)?;
+    strip.save(&options.output)?;
+    println!("Saved strip to {}", options.output.display());
+    if options.print {
+        print(&options.output)?;
+        println!("Sent {} to the printer", options.output.display());
+    }
+    Ok(())
+}
+
+/// Lays `shots` out in a column with a margin around each, and the caption
+/// under them.
+fn compose(shots: &[RgbaImage], options: &BoothOptions) -> Result<RgbaImage, Report> {
+    let width = options.width;
+    let margin = width / 16;
+    let (shot_width, shot_height) = shots[0].dimensions();
+    let height = (width as u64 * shot_height as u64 / shot_width.max(1) as u64) as u32;
+    let scale = (width / 120).max(1);
+    let caption_height = match options.caption {
+        Some(_) => font::GLYPH_HEIGHT * scale + margin,
+        None => 0,
+    };
+    let strip_width = width + 2 * margin;
+    let strip_height = margin + shots.len() as u32 * (height + margin) + caption_height;
+
+    let Colour([r, g, b]) = options.background;
+    let mut strip = match &options.template {
+        Some(template) => image::open(template)?
+            .resize_exact(strip_width, strip_height, FilterType::Triangle)
+            .to_rgba8(),
+        None => RgbaImage::from_pixel(strip_width, strip_height, Rgba([r, g, b, 255])),
+    };
+    for (n, shot) in shots.iter().enumerate() {
+        let shot = imageops::resize(shot, width, height, FilterType::Triangle);
+        let y = margin + n as u32 * (height + margin);
+        imageops::overlay(&mut strip, &shot, margin as i64, y as i64);
+    }
+    if let Some(caption) = &options.caption {
+        // Dark text on a light background and the other way round.
+        let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        let colour = if luma >= 128 { [0, 0, 0] } else { [255; 3] };
+        let x = strip_width.saturating_sub(font::width(caption, scale)) / 2;
+        let y = strip_height - caption_height;
+        font::draw(
+            &mut strip,
+            (strip_width, strip_height),
+            4,
+            (x, y),
+            scale,
+            caption,
+            colour,
+        );
+    }
+    Ok(strip)
+}
+
+/// Hands `path` to the system's print command.
+fn print(path: &Path) -> Result<(), Report> {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("mspaint");
+        command.arg("/p");
+        command
+    } else {
+        Command::new("lp")
+    };
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(Report::msg(format!("printing failed: {status}")));
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use clap::Args;
+use image::{DynamicImage, GrayImage};
+use std::path::{Path, PathBuf};
+
+/// Taking several frames in a row and keeping the best of them.
+#[derive(Args, Clone, Default)]
+pub struct BurstOptions {
+    /// Take this many frames in a row, saved as OUTPUT-1.png and onwards.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..), conflicts_with_all = ["stack", "save_raw"])]
+    pub burst: Option<u32>,
+    /// Only keep the sharpest frame of the burst, or this many of the
+    /// sharpest, judged by how much detail they have.
+    #[arg(long, value_name = "COUNT", num_args = 0..=1, default_missing_value = "1", requires = "burst")]
+    pub pick_sharpest: Option<usize>,
+}
+
+/// How sharp `image` is, as the variance of its Laplacian: in focus and
+/// still, edges are crisp and the Laplacian swings widely; blurred, it
+/// stays close to zero.
+pub fn sharpness(image: &GrayImage) -> f64 {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let at = |x: u32, y: u32| image.get_pixel(x, y)[0] as f64;
+    let (mut sum, mut squares, mut count) = (0.0, 0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian =
+                at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            sum += laplacian;
+            squares += laplacian * laplacian;
+            count += 1.0;
+        }
+    }
+    let mean = sum / count;
+    squares / count - mean * mean
+}
+
+/// The `keep` sharpest of `images`, sharpest first.
+pub fn pick_sharpest(images: Vec<DynamicImage>, keep: usize) -> Vec<DynamicImage> {
+    let mut scored: Vec<(f64, usize, DynamicImage)> = images
+        .into_iter()
+        .enumerate()
+        .map(|(n, image)| (sharpness(&image.to_luma8()), n, image))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (rank, (score, n, _)) in scored.iter().enumerate() {
+        let kept = if rank < keep { ", kept" } else { "" };
+        println!("Frame {}: sharpness {score:.1}{kept}", n + 1);
+    }
+    scored.truncate(keep);
+    scored.into_iter().map(|(_, _, image)| image).collect()
+}
+
+/// Where the `n`th of `count` images goes when `path` is asked for: `path`
+/// itself for a single image, otherwise numbered from 1 before the
+/// extension.
+pub fn numbered(path: &Path, n: usize, count: usize) -> PathBuf {
+    if count == 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{}.{}", n + 1, extension.to_string_lossy()),
+        None => format!("{stem}-{}", n + 1),
+    };
+    path.with_file_name(name)
+}
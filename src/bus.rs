@@ -0,0 +1,363 @@
+//! The events athletic reports, and the publishers that pass them on.
+//!
+//! Events are published once to the bus, and everything that wants them
+//! subscribes: WebSocket clients and scripts in `serve`, and whatever was
+//! asked for with `--publish`. See docs/events.md.
+
+use clap::Args;
+use color_eyre::Report;
+use flume::{Receiver, Sender, TrySendError};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Where athletic is found on the session bus, serving `daemon` and
+/// sending the `dbus` publisher's signals.
+pub const BUS_NAME: &str = "org.athletic.Camera";
+pub const OBJECT_PATH: &str = "/org/athletic/Camera";
+pub const INTERFACE: &str = "org.athletic.Camera1";
+
+/// How many events may wait for a slow subscriber before new ones get
+/// dropped for it.
+const QUEUE_DEPTH: usize = 64;
+const DEFAULT_MQTT_TOPIC: &str = "athletic/events";
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Arc<Event>>>> = Mutex::new(Vec::new());
+
+/// Something that happened, serialized as a JSON object with its kind in
+/// `type`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    /// Motion started or stopped.
+    Motion { active: bool, score: f64 },
+    /// What `serve` sent over the last second.
+    Stats {
+        frames: u64,
+        synthetic: u64,
+        dropped: u64,
+        corrupt: u64,
+        fps: f64,
+        clients: usize,
+        pts: f64,
+        /// Bytes the frames in the pipeline hold.
+        memory: u64,
+    },
+    /// A recording started, or finished with `frames` frames.
+    Recording {
+        active: bool,
+        path: PathBuf,
+        frames: u64,
+    },
+    /// How a recording is going, about once a second.
+    Progress {
+        elapsed: f64,
+        frames: u64,
+        bytes: u64,
+        bitrate: f64,
+        bytes_per_hour: f64,
+        free: Option<u64>,
+    },
+    /// The camera stopped sending frames, usually because it was unplugged.
+    CameraLost { camera: String },
+    /// The camera gave no frame in time, and `recovery` is being tried
+    /// for the `attempt`th time since its last one.
+    CameraStalled {
+        camera: String,
+        attempt: u32,
+        recovery: String,
+    },
+    /// A stalled camera sends frames again, after `attempts` tries.
+    CameraRecovered { camera: String, attempts: u32 },
+    /// Something went wrong that athletic carried on past.
+    Error { message: String },
+}
+
+impl Event {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("events always serialize")
+    }
+}
+
+/// Hands `event` to every subscriber, forgetting the ones that went away.
+pub fn publish(event: Event) {
+    let event = Arc::new(event);
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .retain(|subscriber| match subscriber.try_send(event.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+}
+
+/// Every event published from now on.
+pub fn subscribe() -> Receiver<Arc<Event>> {
+    let (sender, receiver) = flume::bounded(QUEUE_DEPTH);
+    SUBSCRIBERS.lock().unwrap().push(sender);
+    receiver
+}
+
+/// Where else events go.
+#[derive(Args, Clone, Default)]
+pub struct PublishOptions {
+    /// Also send every event to `stdout` as a JSON line, to
+    /// `webhook:http://HOST[:PORT]/PATH` as a POST, to
+    /// `mqtt:HOST[:PORT][/TOPIC]` (athletic/events by default), or to
+    /// `dbus` as an Event signal. Repeatable.
+    #[arg(long = "publish", value_name = "PUBLISHER")]
+    pub publishers: Vec<Publisher>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Publisher {
+    Stdout,
+    /// Plain HTTP only; there's no TLS to speak HTTPS with.
+    Webhook {
+        host: String,
+        port: u16,
+        path: String,
+    },
+    /// MQTT 3.1.1 at QoS 0.
+    Mqtt {
+        host: String,
+        port: u16,
+        topic: String,
+    },
+    /// An `Event` signal on the session bus, from the daemon's object.
+    Dbus,
+}
+
+impl FromStr for Publisher {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, target) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "stdout" | "STDOUT" => Ok(Publisher::Stdout),
+            "dbus" | "DBUS" | "d-bus" => Ok(Publisher::Dbus),
+            "webhook" | "WEBHOOK" => {
+                let rest = target.strip_prefix("http://").ok_or_else(|| {
+                    Report::msg(format!("webhooks have to be http:// URLs, not {target}"))
+                })?;
+                let (authority, path) = match rest.find('/') {
+                    Some(at) => rest.split_at(at),
+                    None => (rest, "/"),
+                };
+                let (host, port) = host_port(authority, 80)?;
+                Ok(Publisher::Webhook {
+                    host,
+                    port,
+                    path: path.to_string(),
+                })
+            }
+            "mqtt" | "MQTT" => {
+                let (authority, topic) = target.split_once('/').unwrap_or((target, ""));
+                let (host, port) = host_port(authority, 1883)?;
+                let topic = match topic {
+                    "" => DEFAULT_MQTT_TOPIC,
+                    topic => topic,
+                };
+                Ok(Publisher::Mqtt {
+                    host,
+                    port,
+                    topic: topic.to_string(),
+                })
+            }
+            _ => Err(Report::msg(format!("unknown publisher: {s}"))),
+        }
+    }
+}
+
+impl Display for Publisher {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Publisher::Stdout => write!(f, "standard output"),
+            Publisher::Webhook { host, port, path } => write!(f, "http://{host}:{port}{path}"),
+            Publisher::Mqtt { host, port, topic } => write!(f, "{topic} on MQTT at {host}:{port}"),
+            Publisher::Dbus => write!(f, "the session bus"),
+        }
+    }
+}
+
+fn host_port(authority: &str, default: u16) -> Result<(String, u16), Report> {
+    if authority.is_empty() {
+        return Err(Report::msg("expected a host"));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Ok((
+            host.to_string(),
+            port.parse()
+                .map_err(|_| Report::msg(format!("invalid port: {port}")))?,
+        )),
+        None => Ok((authority.to_string(), default)),
+    }
+}
+
+/// Starts a thread per publisher in `options`, each subscribed to the bus.
+pub fn start(options: &PublishOptions) -> Result<(), Report> {
+    for publisher in &options.publishers {
+        let events = subscribe();
+        let publisher = publisher.clone();
+        match publisher {
+            Publisher::Stdout => {
+                std::thread::spawn(move || {
+                    for event in events.iter() {
+                        println!("{}", event.to_json());
+                    }
+                });
+            }
+            Publisher::Webhook { host, port, path } => {
+                std::thread::spawn(move || {
+                    for event in events.iter() {
+                        if let Err(why) = post(&host, port, &path, &event.to_json()) {
+                            eprintln!("webhook http://{host}:{port}{path} failed: {why}");
+                        }
+                    }
+                });
+            }
+            Publisher::Mqtt { host, port, topic } => {
+                std::thread::spawn(move || {
+                    let mut connection = None;
+                    for event in events.iter() {
+                        let payload = event.to_json();
+                        if let Err(why) = mqtt_send(&mut connection, &host, port, &topic, &payload)
+                        {
+                            eprintln!("MQTT {host}:{port} failed: {why}");
+                        }
+                    }
+                });
+            }
+            Publisher::Dbus => dbus(events)?,
+        }
+    }
+    Ok(())
+}
+
+fn post(host: &str, port: u16, path: &str, body: &str) -> Result<(), Report> {
+    let mut stream = TcpStream::connect((host, port))?;
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {host}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(Report::msg(format!("answered {}", status.trim()))),
+    }
+}
+
+/// Publishes `payload` to `topic`, connecting first if need be, and once
+/// more if the broker dropped the connection.
+fn mqtt_send(
+    connection: &mut Option<TcpStream>,
+    host: &str,
+    port: u16,
+    topic: &str,
+    payload: &str,
+) -> Result<(), Report> {
+    let reconnected = connection.is_none();
+    let stream = match connection {
+        Some(stream) => stream,
+        None => connection.insert(mqtt_connect(host, port)?),
+    };
+    match mqtt_publish(stream, topic, payload.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(why) if reconnected => {
+            *connection = None;
+            Err(why)
+        }
+        Err(_) => {
+            *connection = None;
+            mqtt_send(connection, host, port, topic, payload)
+        }
+    }
+}
+
+/// Appends `length` as an MQTT variable-length integer.
+fn remaining_length(packet: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn mqtt_string(packet: &mut Vec<u8>, s: &[u8]) {
+    packet.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    packet.extend_from_slice(s);
+}
+
+/// Connects with a clean session and no keep-alive, so a recording that
+/// publishes rarely isn't disconnected for going quiet.
+fn mqtt_connect(host: &str, port: u16) -> Result<TcpStream, Report> {
+    let mut stream = TcpStream::connect((host, port))?;
+    let mut body = Vec::new();
+    mqtt_string(&mut body, b"MQTT");
+    body.extend_from_slice(&[4, 0x02, 0, 0]);
+    mqtt_string(
+        &mut body,
+        format!("athletic-{}", std::process::id()).as_bytes(),
+    );
+    let mut packet = vec![0x10];
+    remaining_length(&mut packet, body.len());
+    packet.extend_from_slice(&body);
+    stream.write_all(&packet)?;
+    let mut connack = [0; 4];
+    std::io::Read::read_exact(&mut stream, &mut connack)?;
+    match connack {
+        [0x20, 2, _, 0] => Ok(stream),
+        [0x20, 2, _, code] => Err(Report::msg(format!("broker refused with code {code}"))),
+        _ => Err(Report::msg("broker didn't answer with a CONNACK")),
+    }
+}
+
+fn mqtt_publish(stream: &mut TcpStream, topic: &str, payload: &[u8]) -> Result<(), Report> {
+    let mut body = Vec::new();
+    mqtt_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    let mut packet = vec![0x30];
+    remaining_length(&mut packet, body.len());
+    packet.extend_from_slice(&body);
+    stream.write_all(&packet)?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn dbus(events: Receiver<Arc<Event>>) -> Result<(), Report> {
+    let connection = zbus::blocking::Connection::session()?;
+    std::thread::spawn(move || {
+        for event in events.iter() {
+            let result = connection.emit_signal(
+                None::<zbus::names::BusName>,
+                OBJECT_PATH,
+                INTERFACE,
+                "Event",
+                &(event.to_json(),),
+            );
+            if let Err(why) = result {
+                eprintln!("D-Bus event failed: {why}");
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn dbus(_events: Receiver<Arc<Event>>) -> Result<(), Report> {
+    Err(Report::msg(
+        "the D-Bus publisher needs D-Bus, which is only available on Linux",
+    ))
+}
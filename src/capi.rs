@@ -0,0 +1,317 @@
+//! The C API. `include/athletic.h` is generated from this file by cbindgen
+//! when the crate is built with the `capi` feature and
+//! `ATHLETIC_UPDATE_HEADER` set; other `capi` builds only write it to
+//! their `OUT_DIR`.
+//!
+//! Functions returning `int` return 0 on success and -1 on failure, with
+//! the reason available from `athletic_last_error`.
+
+use crate::capture::{self, ControlRequest, Frame, Origin};
+use crate::pixel_format::RgbAFormat;
+use flume::{Receiver, Sender};
+use nokhwa::{
+    native_api_backend, query,
+    utils::{all_known_camera_controls, CameraIndex, ControlValueSetter},
+};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(why: impl ToString) -> c_int {
+    let message = CString::new(why.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    -1
+}
+
+/// Why the last failing call on this thread failed, or NULL if none has.
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn athletic_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[repr(C)]
+pub struct AthleticDevice {
+    /// What to pass to `athletic_open`.
+    pub index: u32,
+    pub name: *mut c_char,
+    pub description: *mut c_char,
+}
+
+fn c_string(s: &str) -> *mut c_char {
+    CString::new(s.replace('\0', " "))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// Lists the cameras into a new array of `*count` devices, to be freed
+/// with `athletic_free_devices`.
+///
+/// # Safety
+///
+/// `devices` and `count` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn athletic_list_devices(
+    devices: *mut *mut AthleticDevice,
+    count: *mut usize,
+) -> c_int {
+    let Some(backend) = native_api_backend() else {
+        return fail("no camera backend on this platform");
+    };
+    let found = match query(backend) {
+        Ok(found) => found,
+        Err(why) => return fail(why),
+    };
+    let list: Box<[AthleticDevice]> = found
+        .iter()
+        .enumerate()
+        .map(|(n, info)| AthleticDevice {
+            index: info.index().as_index().unwrap_or(n as u32),
+            name: c_string(&info.human_name()),
+            description: c_string(info.description()),
+        })
+        .collect();
+    *count = list.len();
+    *devices = Box::into_raw(list) as *mut AthleticDevice;
+    0
+}
+
+/// Frees a list returned by `athletic_list_devices`.
+///
+/// # Safety
+///
+/// `devices` and `count` must come from one call to
+/// `athletic_list_devices`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn athletic_free_devices(devices: *mut AthleticDevice, count: usize) {
+    if devices.is_null() {
+        return;
+    }
+    let list = Box::from_raw(std::ptr::slice_from_raw_parts_mut(devices, count));
+    for device in list.iter() {
+        drop(CString::from_raw(device.name));
+        drop(CString::from_raw(device.description));
+    }
+}
+
+/// A decoded frame handed to a frame callback. The pixels are RGBA and
+/// only valid during the callback.
+#[repr(C)]
+pub struct AthleticFrame {
+    pub data: *const u8,
+    pub length: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Bytes per row.
+    pub stride: u32,
+    /// Counts every frame the camera delivered, so gaps are dropped frames.
+    pub sequence: u64,
+    /// Nanoseconds since the first frame.
+    pub pts_ns: u64,
+}
+
+/// Called on a thread of athletic's for every frame until the camera is
+/// closed or another callback replaces it. Frames that arrive while the
+/// callback is still running are dropped.
+pub type AthleticFrameCallback =
+    Option<unsafe extern "C" fn(frame: *const AthleticFrame, user_data: *mut c_void)>;
+
+/// `user_data` is the caller's to make safe to use from the callback thread.
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+
+struct Dispatcher {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// How long the dispatcher waits for a frame before checking whether it
+/// has been stopped, so a stalled camera can't hold up a close.
+const STOP_CHECK: Duration = Duration::from_millis(100);
+
+/// How long `athletic_set_control` waits for the camera to answer, so a
+/// stalled or unplugged one fails the call rather than hanging it.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Dispatcher {
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Called from the callback itself, the thread is left to finish
+        // once the callback returns rather than waiting on itself.
+        if self.thread.thread().id() != std::thread::current().id() {
+            let _ = self.thread.join();
+        }
+    }
+}
+
+/// An open camera.
+pub struct AthleticCamera {
+    frames: Receiver<Frame>,
+    controls: Sender<ControlRequest>,
+    dispatcher: Option<Dispatcher>,
+}
+
+/// Opens the camera at `index` and starts streaming from it. Returns NULL
+/// on failure.
+#[no_mangle]
+pub extern "C" fn athletic_open(index: u32) -> *mut AthleticCamera {
+    match capture::spawn_capture(CameraIndex::Index(index)) {
+        Ok(capture) => Box::into_raw(Box::new(AthleticCamera {
+            frames: capture.frames,
+            controls: capture.controls,
+            dispatcher: None,
+        })),
+        Err(why) => {
+            fail(why);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Stops the camera and frees it. Waits for a running frame callback to
+/// return first, unless called from that callback.
+///
+/// # Safety
+///
+/// `camera` must come from `athletic_open` and not have been closed already.
+#[no_mangle]
+pub unsafe extern "C" fn athletic_close(camera: *mut AthleticCamera) {
+    if camera.is_null() {
+        return;
+    }
+    let camera = Box::from_raw(camera);
+    if let Some(dispatcher) = camera.dispatcher {
+        dispatcher.stop();
+    }
+}
+
+/// Calls `callback` with every frame from `camera`, replacing any previous
+/// callback. A NULL callback stops the calls. This may be called from
+/// the frame callback.
+///
+/// # Safety
+///
+/// `camera` must be open. `callback` must be safe to call from another
+/// thread with `user_data`.
+#[no_mangle]
+pub unsafe extern "C" fn athletic_set_frame_callback(
+    camera: *mut AthleticCamera,
+    callback: AthleticFrameCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let Some(camera) = camera.as_mut() else {
+        return fail("camera is NULL");
+    };
+    if let Some(dispatcher) = camera.dispatcher.take() {
+        dispatcher.stop();
+    }
+    let Some(callback) = callback else {
+        return 0;
+    };
+
+    let frames = camera.frames.clone();
+    let stop = Arc::new(AtomicBool::new(false));
+    let user_data = UserData(user_data);
+    let thread = {
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            let user_data = user_data;
+            while !stop.load(Ordering::Relaxed) {
+                let frame = match frames.recv_timeout(STOP_CHECK) {
+                    Ok(frame) => frame,
+                    Err(flume::RecvTimeoutError::Timeout) => continue,
+                    Err(flume::RecvTimeoutError::Disconnected) => break,
+                };
+                let image = match frame.buffer.decode_image::<RgbAFormat>() {
+                    Ok(image) => image,
+                    Err(why) => {
+                        eprintln!("failed to decode frame: {why}");
+                        continue;
+                    }
+                };
+                let data = AthleticFrame {
+                    data: image.as_ptr(),
+                    length: image.len(),
+                    width: image.width(),
+                    height: image.height(),
+                    stride: image.width() * 4,
+                    sequence: frame.sequence,
+                    pts_ns: frame.pts.as_nanos() as u64,
+                };
+                callback(&data, user_data.0);
+            }
+        })
+    };
+    camera.dispatcher = Some(Dispatcher { stop, thread });
+    0
+}
+
+/// The controls `athletic_set_control` can change, matching what
+/// `athletic list-properties` prints.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub enum AthleticControl {
+    Brightness,
+    Contrast,
+    Hue,
+    Saturation,
+    Sharpness,
+    Gamma,
+    WhiteBalance,
+    BacklightComp,
+    Gain,
+    Pan,
+    Tilt,
+    Zoom,
+    Exposure,
+    Iris,
+    Focus,
+}
+
+/// Sets an integer control, such as brightness, to `value`. Fails if the
+/// camera hasn't answered within five seconds.
+///
+/// # Safety
+///
+/// `camera` must be open.
+#[no_mangle]
+pub unsafe extern "C" fn athletic_set_control(
+    camera: *mut AthleticCamera,
+    control: AthleticControl,
+    value: i64,
+) -> c_int {
+    let Some(camera) = camera.as_ref() else {
+        return fail("camera is NULL");
+    };
+    let id = all_known_camera_controls()[control as usize];
+    let (reply, answer) = flume::bounded(1);
+    let request = ControlRequest::Set(
+        id,
+        ControlValueSetter::Integer(value),
+        Origin::Remote,
+        reply,
+    );
+    if camera.controls.send(request).is_err() {
+        return fail("the camera has stopped");
+    }
+    match answer.recv_timeout(CONTROL_TIMEOUT) {
+        Ok(Ok(_)) => 0,
+        Ok(Err(why)) => fail(why),
+        Err(flume::RecvTimeoutError::Timeout) => fail(format!(
+            "the camera didn't answer within {}s",
+            CONTROL_TIMEOUT.as_secs()
+        )),
+        Err(flume::RecvTimeoutError::Disconnected) => fail("the camera has stopped"),
+    }
+}
@@ -0,0 +1,1030 @@
+use crate::depth;
+use crate::flicker::{self, AntiFlicker};
+use crate::memory::{self, Held};
+use crate::negotiate::{self, Negotiation};
+use crate::quirks::{Quirk, UsbId};
+use crate::stream::{Planes, Stream};
+use crate::tune::{self, Level, Tune};
+use crate::{layout, lock, pattern, trace};
+use color_eyre::Report;
+use flume::{Receiver, Sender, TrySendError};
+use nokhwa::{
+    pixel_format::RgbFormat,
+    utils::{
+        all_known_camera_controls, CameraControl, CameraFormat, CameraIndex, CameraInfo,
+        ControlValueDescription, ControlValueSetter, KnownCameraControl, RequestedFormat,
+        RequestedFormatType,
+    },
+    Buffer, Camera,
+};
+use once_cell::sync::OnceCell;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How many frames may wait for a slow consumer before new ones get dropped.
+pub const QUEUE_DEPTH: usize = 4;
+
+/// Where captures come from instead of the camera, once [`relay_through`]
+/// has said.
+static RELAY: OnceCell<Relay> = OnceCell::new();
+
+/// What cameras have their anti-flicker set to once open, once
+/// [`prevent_flicker`] has said.
+static ANTI_FLICKER: OnceCell<AntiFlicker> = OnceCell::new();
+
+/// The controls cameras are set to once open, once [`tune`] has said.
+static TUNE: OnceCell<Tune> = OnceCell::new();
+
+/// The cards whose formats are kept to what they really stream, the
+/// built-in ones unless [`allow_for`] has said otherwise.
+static QUIRKS: OnceCell<Vec<Quirk>> = OnceCell::new();
+
+/// Whether opening a camera prints how its format was settled.
+static EXPLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Opens a capture of a camera that some other process streams from.
+pub type Relay = fn(CameraIndex, RequestedFormatType, Queue) -> Result<Capture, Report>;
+
+/// How frames wait for whoever reads them from a stage of the pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Queue {
+    /// How many frames may wait.
+    pub depth: usize,
+    /// Whether a full queue holds up the stage before it until there's
+    /// room, so no frame is lost, rather than the newest frame being
+    /// dropped.
+    pub block: bool,
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Queue {
+            depth: QUEUE_DEPTH,
+            block: false,
+        }
+    }
+}
+
+/// What became of a frame handed to a [`Queue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sent {
+    Queued,
+    /// The queue was full.
+    Dropped,
+    /// No one reads the queue any more.
+    Gone,
+}
+
+impl Queue {
+    /// Puts `item` on `sender`'s queue, waiting for room or dropping it
+    /// as the queue says. A queue that may drop frames also drops it
+    /// while frames hold more than the [`memory`] cap, as long as there's
+    /// another waiting.
+    pub fn send<T>(&self, sender: &Sender<T>, item: T) -> Sent {
+        if !self.block && !sender.is_empty() && memory::over() {
+            return Sent::Dropped;
+        }
+        if self.block {
+            return match sender.send(item) {
+                Ok(()) => Sent::Queued,
+                Err(_) => Sent::Gone,
+            };
+        }
+        match sender.try_send(item) {
+            Ok(()) => Sent::Queued,
+            Err(TrySendError::Full(_)) => Sent::Dropped,
+            Err(TrySendError::Disconnected(_)) => Sent::Gone,
+        }
+    }
+}
+
+/// What captures do about a camera that stops giving frames, once
+/// [`watch_stalls`] has said.
+static STALLS: OnceCell<StallPolicy> = OnceCell::new();
+
+/// How long to leave a camera closed before opening it again, so it has
+/// time to reset.
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+/// How a capture looks after a camera that stops giving frames. Some
+/// firmware wedges that way, and only starts again once the stream, or
+/// the whole camera, is opened again.
+#[derive(Clone, Copy, Debug)]
+pub struct StallPolicy {
+    /// How long a frame may take before the camera counts as stalled.
+    pub timeout: Duration,
+    /// How many times to try getting a stalled camera going again before
+    /// giving up on it.
+    pub attempts: u32,
+    /// Told of every stall, what's being done about it, and recoveries.
+    pub report: fn(&CameraIndex, &Stall),
+}
+
+/// What's tried next to get a stalled camera going again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// Stop the stream and start it again.
+    RestartStream,
+    /// Close the camera and open it again, in the same format.
+    Reopen,
+    /// Stop trying, and end the stream as if the camera were unplugged.
+    GiveUp,
+}
+
+impl Recovery {
+    /// What to try for the `attempt`th time since the camera last gave a
+    /// frame, counting from 1, with `attempts` allowed in all.
+    pub fn after(attempt: u32, attempts: u32) -> Self {
+        match attempt {
+            _ if attempt > attempts => Recovery::GiveUp,
+            1 => Recovery::RestartStream,
+            _ => Recovery::Reopen,
+        }
+    }
+}
+
+impl Display for Recovery {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Recovery::RestartStream => "restart-stream",
+            Recovery::Reopen => "reopen",
+            Recovery::GiveUp => "give-up",
+        })
+    }
+}
+
+/// Something that happened to a capture whose camera stalled.
+#[derive(Clone, Debug)]
+pub enum Stall {
+    /// The camera didn't give a frame, because of `why`, and `recovery`
+    /// is being tried for the `attempt`th time since its last one.
+    Stalled {
+        attempt: u32,
+        recovery: Recovery,
+        why: String,
+    },
+    /// Frames come again, after `attempts` tries.
+    Recovered { attempts: u32 },
+    /// The capture thread has waited `waited` for the camera and can't be
+    /// got out of it, so there's nothing left to try.
+    Hung { waited: Duration },
+}
+
+/// A [`Buffer`] stamped at the moment it entered the pipeline.
+///
+/// `sequence` counts every frame the camera delivered, including the ones
+/// dropped because a consumer fell behind, so gaps in it are real gaps in
+/// the recording. `pts` is measured from the first frame of the session on
+/// a monotonic clock and should be used instead of assuming a constant
+/// frame interval.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub buffer: Buffer,
+    pub sequence: u64,
+    pub pts: Duration,
+    /// The camera's exposure and gain as the frame came in, once asked for
+    /// with [`ControlRequest::WatchConditions`].
+    pub conditions: Option<Conditions>,
+    /// The pictures of the source's other streams, taken with this one.
+    pub planes: Planes,
+    /// The frame's share of what [`memory`] counts.
+    pub held: Held,
+}
+
+/// Controls that change how a frame looks, read right after it was captured.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Conditions {
+    pub exposure: Option<i64>,
+    pub gain: Option<i64>,
+}
+
+impl Conditions {
+    fn read(camera: &Camera) -> Result<Self, Report> {
+        let mut conditions = Conditions::default();
+        for control in camera.camera_controls()? {
+            let value = control.value().as_integer().copied();
+            match control.control() {
+                KnownCameraControl::Exposure => conditions.exposure = value,
+                KnownCameraControl::Gain => conditions.gain = value,
+                _ => {}
+            }
+        }
+        Ok(conditions)
+    }
+
+    /// The controls that moved since `before`, and what to.
+    fn moved(&self, before: &Conditions) -> Vec<(KnownCameraControl, i64)> {
+        [
+            (KnownCameraControl::Exposure, before.exposure, self.exposure),
+            (KnownCameraControl::Gain, before.gain, self.gain),
+        ]
+        .into_iter()
+        .filter(|(_, was, is)| was != is)
+        .filter_map(|(control, _, is)| Some((control, is?)))
+        .collect()
+    }
+
+    /// Takes in a change athletic made, so it isn't taken for the camera's.
+    fn note(&mut self, control: KnownCameraControl, value: &ControlValueSetter) {
+        let ControlValueSetter::Integer(value) = *value else {
+            return;
+        };
+        match control {
+            KnownCameraControl::Exposure => self.exposure = Some(value),
+            KnownCameraControl::Gain => self.gain = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Stamps buffers with sequence numbers and PTS for one capture session.
+#[derive(Default)]
+pub struct Clock {
+    start: Option<Instant>,
+    sequence: u64,
+}
+
+impl Clock {
+    pub fn stamp(&mut self, buffer: Buffer) -> Frame {
+        let captured_at = Instant::now();
+        let start = *self.start.get_or_insert(captured_at);
+        let frame = Frame {
+            held: Held::new(buffer.buffer().len()),
+            buffer,
+            sequence: self.sequence,
+            pts: captured_at - start,
+            conditions: None,
+            planes: Planes::default(),
+        };
+        self.sequence += 1;
+        frame
+    }
+
+    /// How long since the first frame, on the clock its PTS is on; zero
+    /// before it.
+    pub fn elapsed(&self) -> Duration {
+        self.start.map_or(Duration::ZERO, |start| start.elapsed())
+    }
+}
+
+/// A change to a camera control, applied by the capture thread between frames.
+pub enum ControlRequest {
+    /// Moves an integer control by this fraction of its range.
+    Nudge(KnownCameraControl, f64),
+    /// Sets a control, for the reason given, and replies with its new
+    /// state.
+    Set(
+        KnownCameraControl,
+        ControlValueSetter,
+        Origin,
+        Sender<Result<CameraControl, Report>>,
+    ),
+    /// Replies with the current state of every control.
+    Describe(Sender<Result<Vec<CameraControl>, Report>>),
+    /// Reads the exposure and gain into every frame from now on, which
+    /// costs a round of control queries per frame.
+    WatchConditions,
+    /// Sends every control change since the camera was opened, and every
+    /// one from now on.
+    WatchChanges(Sender<ControlChange>),
+}
+
+/// What changed a camera control.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Origin {
+    /// Someone at the preview, with its keys.
+    Manual,
+    /// A profile's controls.
+    Preset,
+    Tune,
+    AntiFlicker,
+    /// One of `serve`'s scripts.
+    Script,
+    /// A program using athletic, through gRPC, the C API or Python.
+    Remote,
+    /// The camera itself, as its auto exposure or auto gain does.
+    Auto,
+    /// `controls replay`.
+    Replay,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Origin::Manual => "manual",
+            Origin::Preset => "preset",
+            Origin::Tune => "tune",
+            Origin::AntiFlicker => "anti-flicker",
+            Origin::Script => "script",
+            Origin::Remote => "remote",
+            Origin::Auto => "auto",
+            Origin::Replay => "replay",
+        })
+    }
+}
+
+impl FromStr for Origin {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Origin::Manual,
+            Origin::Preset,
+            Origin::Tune,
+            Origin::AntiFlicker,
+            Origin::Script,
+            Origin::Remote,
+            Origin::Auto,
+            Origin::Replay,
+        ]
+        .into_iter()
+        .find(|origin| origin.to_string() == s)
+        .ok_or_else(|| Report::msg(format!("no control change comes from {s}")))
+    }
+}
+
+/// A control set to a value, `pts` into the capture.
+#[derive(Clone, Debug)]
+pub struct ControlChange {
+    pub pts: Duration,
+    pub control: KnownCameraControl,
+    pub value: ControlValueSetter,
+    pub origin: Origin,
+}
+
+/// A control, what it was set to, and why.
+type Setting = (KnownCameraControl, ControlValueSetter, Origin);
+
+/// The control changes made to one camera, for whoever watches them.
+#[derive(Default)]
+struct ControlLog {
+    changes: Vec<ControlChange>,
+    watchers: Vec<Sender<ControlChange>>,
+}
+
+impl ControlLog {
+    fn watch(&mut self, watcher: Sender<ControlChange>) {
+        for change in &self.changes {
+            let _ = watcher.send(change.clone());
+        }
+        self.watchers.push(watcher);
+    }
+
+    fn record(&mut self, change: ControlChange) {
+        self.watchers
+            .retain(|watcher| watcher.send(change.clone()).is_ok());
+        self.changes.push(change);
+    }
+}
+
+/// What the capture thread has seen of the camera so far.
+#[derive(Debug, Default)]
+pub struct Counters {
+    /// Frames the camera delivered.
+    pub received: AtomicU64,
+    /// Frames dropped because the consumer fell behind.
+    pub dropped: AtomicU64,
+    /// Times the stream was restarted after the camera stalled.
+    pub restarts: AtomicU64,
+    /// Times the camera was opened again after it stalled.
+    pub reopens: AtomicU64,
+}
+
+/// A camera streaming on its own thread.
+pub struct Capture {
+    pub info: CameraInfo,
+    /// The format negotiated when the stream was opened.
+    pub format: CameraFormat,
+    /// Controls the camera reports it supports.
+    pub supported_controls: Vec<KnownCameraControl>,
+    /// The streams its frames carry, [`Stream::Main`] first.
+    pub streams: Vec<Stream>,
+    pub frames: Receiver<Frame>,
+    pub controls: Sender<ControlRequest>,
+    pub counters: Arc<Counters>,
+}
+
+impl Capture {
+    /// The same camera, with its frames read from `frames` instead, for a
+    /// stage that another hands the camera's frames to.
+    pub fn fork(&self, frames: Receiver<Frame>) -> Capture {
+        Capture {
+            info: self.info.clone(),
+            format: self.format,
+            supported_controls: self.supported_controls.clone(),
+            streams: self.streams.clone(),
+            frames,
+            controls: self.controls.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+/// Where the frames of a capture come from.
+enum Source {
+    Pattern,
+    Depth,
+    Relay(Relay),
+    Camera,
+}
+
+impl Source {
+    /// Where a capture of `index` gets its frames, saying how its format
+    /// is settled if that's being explained and it isn't the camera's to
+    /// settle.
+    fn of(index: &CameraIndex) -> Self {
+        let always = |what: &str, format: &CameraFormat| {
+            let format = negotiate::spec(format);
+            format!("{what} is always {format}, whatever is asked for.")
+        };
+        let (source, how) = if pattern::is_pattern(index) {
+            let how = always("The test pattern", &pattern::format());
+            (Source::Pattern, Some(how))
+        } else if depth::is_depth(index) {
+            (
+                Source::Depth,
+                Some(always("A depth camera", &depth::format())),
+            )
+        } else if let Some(&relay) = RELAY.get() {
+            let how = "The daemon settles the format, when it opens the camera.";
+            (Source::Relay(relay), Some(how.to_string()))
+        } else {
+            (Source::Camera, None)
+        };
+        if let Some(how) = how.filter(|_| explaining()) {
+            eprintln!("{how}");
+        }
+        source
+    }
+}
+
+/// What a capture of `index` asking for `requested` would get: the
+/// camera's info and format, settled as [`spawn_capture_queued`] settles
+/// them, quirks and relay and all, but without streaming from the camera.
+/// Through the relay the daemon is asked for the camera, as it's the
+/// daemon that settles the format, and the capture is closed again at
+/// once.
+pub fn settle(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+) -> Result<(CameraInfo, CameraFormat), Report> {
+    match Source::of(&index) {
+        Source::Pattern => Ok((pattern::info(), pattern::format())),
+        Source::Depth => Ok((depth::info(), depth::format())),
+        Source::Relay(relay) => {
+            let capture = relay(index, requested, Queue::default())?;
+            Ok((capture.info, capture.format))
+        }
+        Source::Camera => {
+            let camera = negotiated(index, requested, false)?;
+            Ok((camera.info().clone(), camera.camera_format()))
+        }
+    }
+}
+
+/// Opens the camera on a dedicated thread and streams stamped frames out of it.
+/// The device [`pattern::DEVICE`] opens the test pattern instead,
+/// [`depth::DEVICE`] a depth camera, and after
+/// [`relay_through`] every other device comes from the relay.
+///
+/// The camera is locked for this process while it streams. Returns once
+/// the stream is open.
+pub fn spawn_capture(index: CameraIndex) -> Result<Capture, Report> {
+    spawn_capture_with(index, RequestedFormatType::None)
+}
+
+/// Like [`spawn_capture`], but asks the camera for `requested` rather than
+/// leaving the format to it.
+pub fn spawn_capture_with(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+) -> Result<Capture, Report> {
+    spawn_capture_queued(index, requested, Queue::default())
+}
+
+/// Like [`spawn_capture_with`], but with frames waiting for the reader in
+/// `queue` rather than the default one.
+pub fn spawn_capture_queued(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+    queue: Queue,
+) -> Result<Capture, Report> {
+    match Source::of(&index) {
+        Source::Pattern => return Ok(pattern::spawn(queue)),
+        Source::Depth => return depth::spawn(queue),
+        Source::Relay(relay) => return relay(index, requested, queue),
+        Source::Camera => {}
+    }
+    let (sender, receiver) = flume::bounded(queue.depth);
+    let (control_sender, control_receiver) = flume::unbounded();
+    let (ready_sender, ready_receiver) = flume::bounded(1);
+    let counters = Arc::new(Counters::default());
+    let counted = counters.clone();
+    let policy = STALLS.get().copied();
+    let waiting = Arc::new(Waiting::default());
+    let waited = waiting.clone();
+    let watched = index.clone();
+
+    let thread = std::thread::spawn(move || {
+        trace::name_thread("capture");
+        let opened = lock::lock(&index)
+            .and_then(|lock| Ok((lock, open_camera_setting(index.clone(), requested)?)));
+        let (_lock, (camera, settings)) = match opened {
+            Ok(opened) => opened,
+            Err(why) => {
+                let _ = ready_sender.send(Err(why));
+                return;
+            }
+        };
+        let supported = camera.supported_camera_controls().unwrap_or_default();
+        let _ = ready_sender.send(Ok((
+            camera.info().clone(),
+            camera.camera_format(),
+            supported,
+        )));
+
+        let format = camera.camera_format();
+        // None while a stalled camera is closed to be opened again.
+        let mut camera = Some(camera);
+        let mut clock = Clock::default();
+        let mut watching = false;
+        let mut log = ControlLog::default();
+        for (control, value, origin) in settings {
+            log.record(ControlChange {
+                pts: Duration::ZERO,
+                control,
+                value,
+                origin,
+            });
+        }
+        // The exposure and gain of the last frame, to tell when the camera
+        // changes them itself.
+        let mut last_conditions: Option<Conditions> = None;
+        let mut attempts = 0;
+        loop {
+            if let Some(camera) = &mut camera {
+                for request in control_receiver.try_iter() {
+                    match request {
+                        ControlRequest::WatchConditions => watching = true,
+                        ControlRequest::WatchChanges(watcher) => log.watch(watcher),
+                        request => match apply(camera, request) {
+                            Ok(Some((control, value, origin))) => {
+                                if let Some(conditions) = &mut last_conditions {
+                                    conditions.note(control, &value);
+                                }
+                                log.record(ControlChange {
+                                    pts: clock.elapsed(),
+                                    control,
+                                    value,
+                                    origin,
+                                });
+                            }
+                            Ok(None) => {}
+                            Err(why) => eprintln!("failed to set camera control: {why}"),
+                        },
+                    }
+                }
+            }
+            let captured = {
+                let _zone = trace::zone("capture");
+                let _waiting = waited.start();
+                match &mut camera {
+                    Some(camera) => camera.frame().map_err(Report::from),
+                    None => Err(Report::msg("the camera didn't open again")),
+                }
+            };
+            let buffer = match (captured, &policy) {
+                (Ok(buffer), _) => buffer,
+                (Err(why), Some(policy)) => {
+                    attempts += 1;
+                    let recovered = recover(
+                        policy,
+                        &index,
+                        &mut camera,
+                        format,
+                        attempts,
+                        &why,
+                        &counted,
+                    );
+                    match recovered {
+                        true => continue,
+                        false => break,
+                    }
+                }
+                (Err(why), None) => {
+                    eprintln!("failed to capture frame: {why}");
+                    break;
+                }
+            };
+            if let (Some(policy), 1..) = (&policy, attempts) {
+                (policy.report)(&index, &Stall::Recovered { attempts });
+                attempts = 0;
+            }
+            let Some(camera) = &camera else {
+                unreachable!("frames only come from an open camera");
+            };
+            counted.received.fetch_add(1, Ordering::Relaxed);
+            let packed = {
+                let _zone = trace::zone("pack");
+                layout::pack(buffer)
+            };
+            let mut frame = clock.stamp(packed);
+            if watching {
+                frame.conditions = Conditions::read(camera)
+                    .map_err(|why| eprintln!("failed to read camera controls: {why}"))
+                    .ok();
+                if let (Some(before), Some(now)) = (&last_conditions, &frame.conditions) {
+                    for (control, value) in now.moved(before) {
+                        log.record(ControlChange {
+                            pts: frame.pts,
+                            control,
+                            value: ControlValueSetter::Integer(value),
+                            origin: Origin::Auto,
+                        });
+                    }
+                }
+                last_conditions = frame.conditions.or(last_conditions);
+            }
+            match queue.send(&sender, frame) {
+                Sent::Queued => {}
+                Sent::Dropped => {
+                    counted.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Sent::Gone => break,
+            }
+        }
+    });
+
+    let (info, format, supported_controls) = ready_receiver.recv()??;
+    if let Some(policy) = policy {
+        std::thread::spawn(move || watch(thread, &waiting, &watched, &policy));
+    }
+    Ok(Capture {
+        info,
+        format,
+        supported_controls,
+        streams: vec![Stream::Main],
+        frames: receiver,
+        controls: control_sender,
+        counters,
+    })
+}
+
+/// Has every capture but the test pattern's come from `relay` rather
+/// than the camera, from now on.
+pub fn relay_through(relay: Relay) -> Result<(), Report> {
+    RELAY
+        .set(relay)
+        .map_err(|_| Report::msg("captures already come from a relay"))
+}
+
+/// Has cameras opened from now on, and opened again after a stall, set
+/// their anti-flicker to `setting`.
+pub fn prevent_flicker(setting: AntiFlicker) -> Result<(), Report> {
+    ANTI_FLICKER
+        .set(setting)
+        .map_err(|_| Report::msg("anti-flicker is already set"))
+}
+
+/// Has cameras opened from now on, and opened again after a stall, set
+/// the controls `settings` gives, in order.
+pub fn tune(settings: Tune) -> Result<(), Report> {
+    TUNE.set(settings)
+        .map_err(|_| Report::msg("cameras are already tuned"))
+}
+
+/// Has cameras opened from now on allow for `quirks` rather than the
+/// built-in ones.
+pub fn allow_for(quirks: Vec<Quirk>) -> Result<(), Report> {
+    QUIRKS
+        .set(quirks)
+        .map_err(|_| Report::msg("quirks are already allowed for"))
+}
+
+/// Has cameras opened from now on print how their format was settled:
+/// what was asked for, what the camera offered, what nokhwa picked from
+/// it and what the camera was set to in the end.
+pub fn explain_formats() {
+    EXPLAIN.store(true, Ordering::Relaxed);
+}
+
+pub fn explaining() -> bool {
+    EXPLAIN.load(Ordering::Relaxed)
+}
+
+/// Has captures opened from now on watch for their camera stalling, and
+/// do what `policy` says about it.
+pub fn watch_stalls(policy: StallPolicy) -> Result<(), Report> {
+    STALLS
+        .set(policy)
+        .map_err(|_| Report::msg("captures already watch for stalls"))
+}
+
+/// When the capture thread started waiting for the frame it's waiting
+/// for, if it is.
+#[derive(Default)]
+struct Waiting {
+    /// Milliseconds from `epoch` to the start of the wait, plus one, or 0
+    /// between waits.
+    since: AtomicU64,
+    epoch: OnceCell<Instant>,
+}
+
+impl Waiting {
+    /// Marks the start of a wait, which lasts until the guard is dropped.
+    fn start(&self) -> impl Drop + '_ {
+        let epoch = *self.epoch.get_or_init(Instant::now);
+        let since = epoch.elapsed().as_millis() as u64 + 1;
+        self.since.store(since, Ordering::Relaxed);
+        Ended(self)
+    }
+
+    /// The start of the wait going on, as an id for it, and how long it's
+    /// taken so far.
+    fn current(&self) -> Option<(u64, Duration)> {
+        let since = self.since.load(Ordering::Relaxed);
+        let epoch = self.epoch.get()?;
+        let started = *epoch + Duration::from_millis(since.checked_sub(1)?);
+        Some((since, started.elapsed()))
+    }
+}
+
+struct Ended<'a>(&'a Waiting);
+
+impl Drop for Ended<'_> {
+    fn drop(&mut self) {
+        self.0.since.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Gets the capture `thread` out of a wait for a frame that's taken longer
+/// than the policy's timeout, so it can try getting the camera going
+/// again, until the thread ends. A wait it can't end is reported as
+/// [`Stall::Hung`].
+fn watch(thread: JoinHandle<()>, waiting: &Waiting, index: &CameraIndex, policy: &StallPolicy) {
+    let mut interrupted = None;
+    while !thread.is_finished() {
+        std::thread::sleep(policy.timeout / 4);
+        let Some((wait, waited)) = waiting.current() else {
+            continue;
+        };
+        if waited < policy.timeout {
+            continue;
+        }
+        if interrupted != Some(wait) {
+            interrupted = Some(wait);
+            interrupt(&thread);
+        } else if waited >= policy.timeout * 2 {
+            (policy.report)(index, &Stall::Hung { waited });
+            return;
+        }
+    }
+}
+
+/// Has the frame the capture `thread` waits for fail, with a signal that
+/// ends the camera's poll for it.
+#[cfg(target_os = "linux")]
+fn interrupt(thread: &JoinHandle<()>) {
+    use std::os::unix::thread::JoinHandleExt;
+    // Ignored unless handled, so one sent anywhere else does no harm.
+    const SIGNAL: libc::c_int = libc::SIGURG;
+    extern "C" fn woken(_signal: libc::c_int) {}
+    static HANDLED: std::sync::Once = std::sync::Once::new();
+    HANDLED.call_once(|| unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = woken as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        // No SA_RESTART, so the poll fails rather than carrying on.
+        libc::sigaction(SIGNAL, &action, std::ptr::null_mut());
+    });
+    unsafe {
+        libc::pthread_kill(thread.as_pthread_t(), SIGNAL);
+    }
+}
+
+/// Elsewhere a wait for a frame can't be ended from outside.
+#[cfg(not(target_os = "linux"))]
+fn interrupt(_thread: &JoinHandle<()>) {}
+
+/// Tries getting `camera` going again after it failed to give a frame,
+/// for the `attempt`th time since its last one. False once it's time to
+/// give up.
+fn recover(
+    policy: &StallPolicy,
+    index: &CameraIndex,
+    camera: &mut Option<Camera>,
+    format: CameraFormat,
+    attempt: u32,
+    why: &Report,
+    counters: &Counters,
+) -> bool {
+    let recovery = Recovery::after(attempt, policy.attempts);
+    let stall = Stall::Stalled {
+        attempt,
+        recovery,
+        why: why.to_string(),
+    };
+    (policy.report)(index, &stall);
+    match (recovery, camera.as_mut()) {
+        (Recovery::RestartStream, Some(camera)) => {
+            counters.restarts.fetch_add(1, Ordering::Relaxed);
+            let _ = camera.stop_stream();
+            if let Err(why) = camera.open_stream() {
+                eprintln!("failed to restart the camera's stream: {why}");
+            }
+        }
+        (Recovery::RestartStream | Recovery::Reopen, _) => {
+            // The camera has to be closed before it can be opened again.
+            *camera = None;
+            counters.reopens.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(REOPEN_DELAY);
+            match open_camera(index.clone(), RequestedFormatType::Exact(format)) {
+                Ok(reopened) => *camera = Some(reopened),
+                Err(why) => eprintln!("failed to open the camera again: {why}"),
+            }
+        }
+        (Recovery::GiveUp, _) => return false,
+    }
+    true
+}
+
+/// Looks up a control by the name `list-properties` prints for it.
+pub fn known_control(name: &str) -> Option<KnownCameraControl> {
+    all_known_camera_controls()
+        .into_iter()
+        .find(|control| control.to_string().eq_ignore_ascii_case(name))
+}
+
+/// Carries out `request`, returning the control it changed, what to and
+/// why, if it changed one.
+fn apply(camera: &mut Camera, request: ControlRequest) -> Result<Option<Setting>, Report> {
+    match request {
+        ControlRequest::Nudge(id, fraction) => {
+            let control = camera.camera_control(id)?;
+            let ControlValueDescription::IntegerRange {
+                min,
+                max,
+                value,
+                step,
+                ..
+            } = *control.description()
+            else {
+                return Err(Report::msg(format!("{id} is not an integer range")));
+            };
+            let step = step.max(1);
+            let delta = (fraction * (max - min) as f64 / step as f64).round() as i64 * step;
+            let value = ControlValueSetter::Integer((value + delta).clamp(min, max));
+            camera.set_camera_control(id, value.clone())?;
+            Ok(Some((id, value, Origin::Manual)))
+        }
+        ControlRequest::Set(id, value, origin, reply) => {
+            let result = camera
+                .set_camera_control(id, value.clone())
+                .and_then(|()| camera.camera_control(id));
+            let changed = result.is_ok().then_some((id, value, origin));
+            let _ = reply.send(result.map_err(Report::from));
+            Ok(changed)
+        }
+        ControlRequest::Describe(reply) => {
+            let _ = reply.send(camera.camera_controls().map_err(Report::from));
+            Ok(None)
+        }
+        ControlRequest::WatchConditions | ControlRequest::WatchChanges(_) => Ok(None),
+    }
+}
+
+/// Opens the camera at `index`, without starting its stream. On macOS,
+/// camera access is asked for first if it hasn't been decided, and a
+/// refusal says how to allow it rather than failing bare.
+pub fn new_camera(index: CameraIndex, requested: RequestedFormat) -> Result<Camera, Report> {
+    #[cfg(target_os = "macos")]
+    crate::permission::ensure().map_err(Report::msg)?;
+    Ok(Camera::new(index, requested)?)
+}
+
+pub(crate) fn open_camera(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+) -> Result<Camera, Report> {
+    open_camera_setting(index, requested).map(|(camera, _)| camera)
+}
+
+/// Like [`open_camera`], but also returns the controls set on it as it
+/// was opened, each with what to and why.
+fn open_camera_setting(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+) -> Result<(Camera, Vec<Setting>), Report> {
+    let mut camera = negotiated(index, requested, true)?;
+    let mut settings = Vec::new();
+    if let Some(setting) = ANTI_FLICKER.get() {
+        // A camera without the control still streams, flicker and all.
+        let value = ControlValueSetter::Integer(setting.value());
+        match camera.set_camera_control(flicker::CONTROL, value.clone()) {
+            Ok(()) => settings.push((flicker::CONTROL, value, Origin::AntiFlicker)),
+            Err(why) => eprintln!("failed to set anti-flicker to {setting}: {why}"),
+        }
+    }
+    if let Some(tune) = TUNE.get() {
+        for &(control, level) in &tune.settings {
+            match set_level(&mut camera, control, level) {
+                Ok(value) => {
+                    settings.push((control, ControlValueSetter::Integer(value), Origin::Tune))
+                }
+                Err(why) => {
+                    let name = tune::control_name(control);
+                    eprintln!("failed to set {name} for {}: {why}", tune.name);
+                }
+            }
+        }
+    }
+    Ok((camera, settings))
+}
+
+/// `requested` for the camera at `index`, kept to the formats it really
+/// streams if it's a card with quirks. An exact format is left as given.
+/// Opens the camera `index` in the format `requested` comes to once the
+/// card's quirks are allowed for, explaining how if asked to. Starts its
+/// stream if `stream`, as some backends only settle the format then.
+fn negotiated(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+    stream: bool,
+) -> Result<Camera, Report> {
+    let requested = quirked(&index, requested);
+    let mut negotiation = match explaining().then(|| Negotiation::probe(&index, requested)) {
+        Some(Ok(negotiation)) => Some(negotiation),
+        Some(Err(why)) => {
+            eprintln!("can't ask the camera what formats it offers: {why}");
+            None
+        }
+        None => None,
+    };
+    let mut opened = new_camera(index, RequestedFormat::new::<RgbFormat>(requested));
+    if stream {
+        opened = opened.and_then(|mut camera| Ok(camera.open_stream().map(|()| camera)?));
+    }
+    #[cfg(windows)]
+    {
+        opened = opened.map_err(crate::privacy::diagnose);
+    }
+    if let Some(negotiation) = &mut negotiation {
+        negotiation.settle(&mut opened);
+        eprint!("{negotiation}");
+    }
+    opened
+}
+
+fn quirked(index: &CameraIndex, requested: RequestedFormatType) -> RequestedFormatType {
+    if matches!(requested, RequestedFormatType::Exact(_)) {
+        return requested;
+    }
+    let quirks = QUIRKS.get_or_init(Quirk::builtin);
+    let Some(quirk) = UsbId::of(index).and_then(|id| quirks.iter().find(|quirk| quirk.id == id))
+    else {
+        return requested;
+    };
+    let offered = new_camera(
+        index.clone(),
+        RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+    )
+    .and_then(|mut camera| Ok(camera.compatible_camera_formats()?));
+    let picked = offered.ok().and_then(|offered| {
+        RequestedFormat::new::<RgbFormat>(requested).fulfill(&quirk.restrict(&offered))
+    });
+    match picked {
+        Some(format) => {
+            eprintln!("Allowing for the quirks of the {quirk}");
+            RequestedFormatType::Exact(format)
+        }
+        None => {
+            eprintln!("Nothing the {quirk} offers fits its quirks; opening it as it is");
+            requested
+        }
+    }
+}
+
+/// Sets `control` to `level`, returning the value it was set to.
+fn set_level(
+    camera: &mut Camera,
+    control: KnownCameraControl,
+    level: Level,
+) -> Result<i64, Report> {
+    let value = match level {
+        Level::Value(value) => value,
+        Level::Share(_) => {
+            let ControlValueDescription::IntegerRange { min, max, step, .. } =
+                *camera.camera_control(control)?.description()
+            else {
+                return Err(Report::msg("it isn't an integer range"));
+            };
+            level.within(min, max, step)
+        }
+    };
+    camera.set_camera_control(control, ControlValueSetter::Integer(value))?;
+    Ok(value)
+}
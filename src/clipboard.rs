@@ -0,0 +1,34 @@
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+use std::borrow::Cow;
+
+fn image_data(image: &RgbaImage) -> ImageData<'_> {
+    ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bytes: Cow::Borrowed(image.as_raw()),
+    }
+}
+
+/// Puts `image` on the clipboard of a long-lived `clipboard`, such as the
+/// preview window's.
+pub fn copy_image(clipboard: &mut Clipboard, image: &RgbaImage) -> Result<(), arboard::Error> {
+    clipboard.set_image(image_data(image))
+}
+
+/// Puts `image` on the clipboard from a process that is about to exit.
+///
+/// On Linux the clipboard contents are served by the owning process, so this
+/// blocks until another program (usually a clipboard manager) takes them over.
+pub fn copy_image_and_wait(image: &RgbaImage) -> Result<(), arboard::Error> {
+    let mut clipboard = Clipboard::new()?;
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().image(image_data(image))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        clipboard.set_image(image_data(image))
+    }
+}
@@ -0,0 +1,228 @@
+use crate::capture::{self, Clock, Frame};
+use crate::lock;
+use crate::pixel_format::RgbAFormat;
+use crate::window;
+use color_eyre::Report;
+use flume::{Receiver, Sender, TrySendError};
+use ggez::graphics::ImageFormat;
+use ggez::{
+    event::EventHandler,
+    graphics::{Canvas, Color, DrawParam, Image, Text},
+    Context, GameError,
+};
+use nokhwa::{
+    utils::{CameraFormat, CameraIndex, RequestedFormat, RequestedFormatType},
+    Camera,
+};
+use std::time::{Duration, Instant};
+
+/// A frame captured while the camera was switched to one side's format.
+struct Sample {
+    side: usize,
+    /// Counts format switches, so rates are only measured within one.
+    segment: u64,
+    frame: Frame,
+    /// How long it took to stop the stream, switch format and reopen it.
+    switch: Duration,
+}
+
+/// Opens the camera and keeps alternating it between `formats`, capturing
+/// `segment` worth of frames in each before switching.
+fn spawn_alternating(
+    index: CameraIndex,
+    formats: [CameraFormat; 2],
+    segment: Duration,
+) -> Result<Receiver<Sample>, Report> {
+    let (sender, receiver) = flume::bounded(4);
+    let (ready_sender, ready_receiver) = flume::bounded(1);
+
+    std::thread::spawn(move || {
+        let requested = RequestedFormat::new::<RgbAFormat>(RequestedFormatType::Exact(formats[0]));
+        let opened =
+            lock::lock(&index).and_then(|lock| Ok((lock, capture::new_camera(index, requested)?)));
+        let (_lock, mut camera) = match opened {
+            Ok(opened) => opened,
+            Err(why) => {
+                let _ = ready_sender.send(Err(why));
+                return;
+            }
+        };
+        let _ = ready_sender.send(Ok(()));
+        if let Err(why) = alternate(&mut camera, formats, segment, &sender) {
+            eprintln!("failed to capture frame: {why}");
+        }
+    });
+
+    ready_receiver.recv()??;
+    Ok(receiver)
+}
+
+fn alternate(
+    camera: &mut Camera,
+    formats: [CameraFormat; 2],
+    segment: Duration,
+    sender: &Sender<Sample>,
+) -> Result<(), Report> {
+    let mut clock = Clock::default();
+    let mut segment_id = 0;
+    loop {
+        for (side, format) in formats.into_iter().enumerate() {
+            segment_id += 1;
+            let started = Instant::now();
+            if camera.is_stream_open() {
+                camera.stop_stream()?;
+            }
+            camera.set_camera_requset(RequestedFormat::new::<RgbAFormat>(
+                RequestedFormatType::Exact(format),
+            ))?;
+            camera.open_stream()?;
+            let switch = started.elapsed();
+
+            let started = Instant::now();
+            while started.elapsed() < segment {
+                let frame = clock.stamp(camera.frame()?);
+                match sender.try_send(Sample {
+                    side,
+                    segment: segment_id,
+                    frame,
+                    switch,
+                }) {
+                    Ok(()) | Err(TrySendError::Full(_)) => {}
+                    Err(TrySendError::Disconnected(_)) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Running measurements for one side of the comparison.
+struct Side {
+    format: CameraFormat,
+    image: Option<Image>,
+    /// Segment, sequence number and PTS of the newest frame.
+    last: Option<(u64, u64, Duration)>,
+    fps: f64,
+    frame_bytes: f64,
+    decode: Duration,
+    switch: Duration,
+}
+
+impl Side {
+    fn new(format: CameraFormat) -> Self {
+        Side {
+            format,
+            image: None,
+            last: None,
+            fps: 0.0,
+            frame_bytes: 0.0,
+            decode: Duration::ZERO,
+            switch: Duration::ZERO,
+        }
+    }
+
+    fn stats(&self) -> String {
+        format!(
+            "{}x{}@{} {}\n{:.1} fps, {:.0} KiB/frame, decode {:.1?}, switch {:.0?}",
+            self.format.width(),
+            self.format.height(),
+            self.format.frame_rate(),
+            self.format.format(),
+            self.fps,
+            self.frame_bytes / 1024.0,
+            self.decode,
+            self.switch,
+        )
+    }
+}
+
+/// Exponential moving average weight given to the newest measurement.
+const SMOOTHING: f64 = 0.1;
+
+pub struct CompareState {
+    receiver: Receiver<Sample>,
+    sides: [Side; 2],
+}
+
+impl CompareState {
+    pub fn new(
+        index: CameraIndex,
+        formats: [CameraFormat; 2],
+        segment: Duration,
+    ) -> Result<Self, Report> {
+        Ok(CompareState {
+            receiver: spawn_alternating(index, formats, segment)?,
+            sides: formats.map(Side::new),
+        })
+    }
+
+    fn receive(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        let sample = self
+            .receiver
+            .recv()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let side = &mut self.sides[sample.side];
+        let frame = sample.frame;
+
+        // Consecutive sequence numbers from the same segment tell the real
+        // delivery rate, including frames too late for the queue.
+        if let Some((segment, sequence, pts)) = side.last {
+            if segment == sample.segment && frame.pts > pts {
+                let fps = (frame.sequence - sequence) as f64 / (frame.pts - pts).as_secs_f64();
+                side.fps += (fps - side.fps) * SMOOTHING;
+            }
+        }
+        side.last = Some((sample.segment, frame.sequence, frame.pts));
+        side.switch = sample.switch;
+        let bytes = frame.buffer.buffer().len() as f64;
+        side.frame_bytes += (bytes - side.frame_bytes) * SMOOTHING;
+
+        let started = Instant::now();
+        let decoded = frame
+            .buffer
+            .decode_image::<RgbAFormat>()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        side.decode = side.decode.mul_f64(1.0 - SMOOTHING) + started.elapsed().mul_f64(SMOOTHING);
+        side.image = Some(Image::from_pixels(
+            ctx,
+            decoded.as_raw(),
+            ImageFormat::Rgba8UnormSrgb,
+            decoded.width(),
+            decoded.height(),
+        ));
+        Ok(())
+    }
+}
+
+impl EventHandler<GameError> for CompareState {
+    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.receive(ctx)?;
+
+        let (width, height) = ctx.gfx.drawable_size();
+        let half = width / 2.0;
+        let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+        for (n, side) in self.sides.iter().enumerate() {
+            let left = half * n as f32;
+            if let Some(image) = &side.image {
+                let (offset, scale) = window::letterbox(
+                    (image.width() as f32, image.height() as f32),
+                    (half, height),
+                );
+                canvas.draw(
+                    image,
+                    DrawParam::new()
+                        .dest([left + offset[0], offset[1]])
+                        .scale([scale, scale]),
+                );
+            }
+            canvas.draw(
+                &Text::new(side.stats()),
+                DrawParam::new().dest([left + 8.0, 8.0]).color(Color::WHITE),
+            );
+        }
+        canvas.finish(ctx)
+    }
+}
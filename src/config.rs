@@ -0,0 +1,808 @@
+//! The config file: named profiles of the options a command would
+//! otherwise be given on the command line, picked with `--profile`.
+//!
+//! ```toml
+//! region = "GB"
+//!
+//! [devices]
+//! desk = "/dev/v4l/by-id/usb-046d_HD_Pro_Webcam_C920-video-index0"
+//!
+//! [quirks."0fd9:0066"]
+//! resolution = "3840x2160"
+//! max-frame-rate = 30
+//!
+//! [tunes.stage]
+//! ExposureAuto = 1
+//! Exposure = "40%"
+//! Gain = 10
+//!
+//! [profiles.desk]
+//! device = "desk"
+//! tune = "stage"
+//! masks = ["0,0,320,80,pixelate"]
+//! window-size = "1280x720"
+//!
+//! [profiles.desk.filters]
+//! low-light = { max-gain = 3.0 }
+//! upscale = "1.5x:lanczos"
+//! interpolate = { fps = 60, method = "flow" }
+//!
+//! [profiles.desk.controls]
+//! Brightness = 140
+//!
+//! [profiles.desk.pipeline]
+//! block-on = "pipe"
+//! queues = { capture = 2, pipe = 16 }
+//! streams = { shm = "depth" }
+//! ```
+//!
+//! `region` is where the cameras are used, as a country code, which sets
+//! their anti-flicker to the mains frequency there unless --anti-flicker
+//! says otherwise.
+//!
+//! `[quirks]` keeps capture cards, by their USB ids, to the modes they
+//! really stream, replacing what athletic knows about the card; a card
+//! given an empty table is opened the way any other camera is.
+//!
+//! `[tunes]` adds to the sets of controls --tune picks from, or replaces
+//! the built-in ones of the same name. Controls are set to a number or
+//! to a share of their range, and automatic ones before the rest.
+//!
+//! `preview` and `serve` keep watching the file while they run, and pick
+//! up changes to their profile's masks, filters and controls without
+//! restarting the stream.
+
+use crate::capture::{self, ControlRequest, Origin};
+use crate::flicker::AntiFlicker;
+use crate::interpolate::{self, InterpolateOptions};
+use crate::low_light::LowLightOptions;
+use crate::mask::{Mask, MaskOptions};
+use crate::preview::PreviewOptions;
+use crate::quirks::{Quirk, UsbId};
+use crate::serve::ServeOptions;
+use crate::sink::{self, SinkOptions, Stage};
+use crate::tune::{self, Level, Tune};
+use crate::upscale::Upscale;
+use crate::window::WindowOptions;
+use crate::{depth, device};
+use crate::{pattern, Commands, IndexKind};
+use clap::Subcommand;
+use color_eyre::Report;
+use flume::{Receiver, Sender};
+use nokhwa::utils::{ControlValueSetter, Resolution};
+use nokhwa::{native_api_backend, query};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Deserializer};
+use serde_with::{serde_as, DisplayFromStr};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+/// Longest side, in pixels, a resolution in the config may have.
+const MAX_SIDE: u32 = 16384;
+
+#[derive(Subcommand, Copy, Clone)]
+pub enum Action {
+    /// Check every profile in the config, printing each problem with its
+    /// line.
+    Check,
+}
+
+#[serde_as]
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Names for devices, taken wherever a device is: most useful for the
+    /// long but stable paths cameras have on Linux.
+    #[serde(default)]
+    pub devices: BTreeMap<String, Spanned<String>>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The country the cameras are used in, by its two letter code.
+    pub region: Option<Spanned<String>>,
+    /// Sets of controls for --tune, by name.
+    #[serde(default)]
+    pub tunes: BTreeMap<String, BTreeMap<String, Spanned<Level>>>,
+    /// What capture cards really stream, by their USB ids.
+    #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
+    #[serde(default)]
+    pub quirks: BTreeMap<UsbId, CardQuirks>,
+}
+
+/// A capture card's quirks, for `[quirks]`.
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CardQuirks {
+    /// What to call the card when saying its quirks are allowed for.
+    pub card: Option<String>,
+    #[serde(default, deserialize_with = "resolution")]
+    pub resolution: Option<Resolution>,
+    pub max_frame_rate: Option<Spanned<u32>>,
+}
+
+impl Config {
+    /// The device `device` names in `[devices]`, or `device` itself.
+    pub fn device(&self, device: IndexKind) -> IndexKind {
+        if let IndexKind::String(name) = &device {
+            if let Some(Ok(named)) = self.devices.get(name).map(|named| named.get_ref().parse()) {
+                return named;
+            }
+        }
+        device
+    }
+
+    /// The tune called `name`, from `[tunes]` or else built in.
+    pub fn tune(&self, name: &str) -> Result<Tune, Report> {
+        let Some(controls) = self.tunes.get(name) else {
+            return Tune::builtin(name).ok_or_else(|| {
+                let mut known: Vec<&str> = self.tunes.keys().map(String::as_str).collect();
+                known.extend(
+                    tune::BUILTIN
+                        .iter()
+                        .filter(|b| !self.tunes.contains_key(**b)),
+                );
+                Report::msg(format!(
+                    "no tune called {name}; there's {}",
+                    known.join(", ")
+                ))
+            });
+        };
+        // Names were checked when the config was loaded.
+        let mut settings: Vec<_> = controls
+            .iter()
+            .filter_map(|(control, level)| Some((tune::control(control)?, *level.get_ref())))
+            .collect();
+        settings.sort_by_key(|(control, _)| !tune::control_name(*control).ends_with("Auto"));
+        Ok(Tune {
+            name: name.to_string(),
+            settings,
+        })
+    }
+
+    /// The built-in quirks, with those of the cards in `[quirks]` in place
+    /// of any athletic knows about.
+    pub fn quirks(&self) -> Vec<Quirk> {
+        let mut quirks: Vec<Quirk> = Quirk::builtin()
+            .into_iter()
+            .filter(|quirk| !self.quirks.contains_key(&quirk.id))
+            .collect();
+        for (&id, card) in &self.quirks {
+            if card.resolution.is_none() && card.max_frame_rate.is_none() {
+                continue;
+            }
+            let known = Quirk::builtin().into_iter().find(|quirk| quirk.id == id);
+            quirks.push(Quirk {
+                card: card
+                    .card
+                    .clone()
+                    .or_else(|| known.map(|known| known.card))
+                    .unwrap_or_else(|| "card".to_string()),
+                id,
+                resolution: card.resolution,
+                max_frame_rate: card.max_frame_rate.as_ref().map(|fps| *fps.get_ref()),
+            });
+        }
+        quirks
+    }
+
+    /// The anti-flicker setting for the mains in the config's region.
+    #[cfg(target_os = "linux")]
+    pub fn anti_flicker(&self) -> Option<AntiFlicker> {
+        AntiFlicker::of_region(self.region.as_ref()?.get_ref())
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    /// The camera, by index, by a part of the name `list-devices` prints,
+    /// by path or by a name in `[devices]`.
+    pub device: Option<Spanned<String>>,
+    /// Regions to obscure, written as for --mask.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[serde(default)]
+    pub masks: Vec<Mask>,
+    /// Initial window size, for the commands that open one.
+    #[serde(default, deserialize_with = "resolution")]
+    pub window_size: Option<Resolution>,
+    #[serde(default)]
+    pub filters: Filters,
+    /// Integer camera controls to set, by the names `list-properties`
+    /// prints. Only `preview` and `serve` set them.
+    #[serde(default)]
+    pub controls: BTreeMap<String, Spanned<i64>>,
+    #[serde(default)]
+    pub pipeline: Pipeline,
+    /// The set of controls to set unless --tune picks another, from
+    /// `[tunes]` or built in.
+    pub tune: Option<Spanned<String>>,
+}
+
+/// How frames queue between the stages of `preview` and `serve`, written
+/// as for --block-on and --queue.
+#[serde_as]
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Pipeline {
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub block_on: Option<Stage>,
+    /// Frames that may wait for each stage.
+    #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
+    #[serde(default)]
+    pub queues: BTreeMap<Stage, Spanned<usize>>,
+    /// The stream each sink writes, as for --sink-stream.
+    #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
+    #[serde(default)]
+    pub streams: BTreeMap<Stage, Spanned<String>>,
+}
+
+/// The filters frames go through, for the commands that have them.
+#[serde_as]
+#[derive(Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Filters {
+    pub low_light: Option<LowLight>,
+    /// Written as for --upscale.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub upscale: Option<Upscale>,
+    pub interpolate: Option<Interpolate>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LowLight {
+    pub max_gain: Option<Spanned<f32>>,
+}
+
+#[serde_as]
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Interpolate {
+    pub fps: Spanned<u32>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub method: Option<interpolate::Method>,
+}
+
+fn resolution<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Resolution>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    let resolution = crate::parse_resolution(&s).map_err(serde::de::Error::custom)?;
+    let sane = |side: u32| (1..=MAX_SIDE).contains(&side);
+    if !sane(resolution.width()) || !sane(resolution.height()) {
+        return Err(serde::de::Error::custom(format!(
+            "resolution has to be between 1x1 and {MAX_SIDE}x{MAX_SIDE}, not {s}"
+        )));
+    }
+    Ok(Some(resolution))
+}
+
+/// Where the config is read from without --config: `config.toml` in
+/// [`dir`].
+pub fn default_path() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("config.toml"))
+}
+
+/// athletic's own directory in the platform's config directory.
+pub fn dir() -> Option<PathBuf> {
+    home().map(|home| home.join("athletic"))
+}
+
+/// The platform's config directory.
+pub fn home() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }
+}
+
+/// Reads the config at `path`, or at [`default_path`] if there is one.
+/// A missing default config is an empty one; a missing `path` is an error.
+pub fn load(path: Option<&Path>) -> Result<(PathBuf, Config), Report> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok((PathBuf::new(), Config::default())),
+        },
+    };
+    let source = match std::fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(why) if !required && why.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((path, Config::default()));
+        }
+        Err(why) => return Err(Report::msg(format!("can't read {}: {why}", path.display()))),
+    };
+    let config =
+        toml::from_str(&source).map_err(|why| Report::msg(format!("{}: {why}", path.display())))?;
+    Ok((path, config))
+}
+
+/// Checks the config at `path` (or the default one) and prints every
+/// problem found with its line, returning whether there were none.
+pub fn check(path: Option<&Path>) -> Result<bool, Report> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => default_path().ok_or_else(|| Report::msg("no config directory to look in"))?,
+    };
+    let source = std::fs::read_to_string(&path)
+        .map_err(|why| Report::msg(format!("can't read {}: {why}", path.display())))?;
+    // Syntax errors and fields that don't parse come out of the parse
+    // itself, already pointing at their line and column.
+    let config: Config = match toml::from_str(&source) {
+        Ok(config) => config,
+        Err(why) => {
+            println!("{}: {why}", path.display());
+            return Ok(false);
+        }
+    };
+    let problems = problems(&config);
+    for (span, problem) in &problems {
+        println!("{}:{}: {problem}", path.display(), line(&source, span));
+    }
+    if problems.is_empty() {
+        println!(
+            "{}: {} profiles, no problems found",
+            path.display(),
+            config.profiles.len()
+        );
+    }
+    Ok(problems.is_empty())
+}
+
+/// What's wrong with `config` that parsing it can't tell, each with where
+/// in the file it is.
+fn problems(config: &Config) -> Vec<(Range<usize>, String)> {
+    let mut problems = Vec::new();
+    let mut cameras = None;
+    let mut missing = |device: &Spanned<String>, whose: String| {
+        let cameras = cameras.get_or_insert_with(|| {
+            native_api_backend()
+                .and_then(|backend| query(backend).ok())
+                .unwrap_or_default()
+        });
+        let found = match device.get_ref().parse() {
+            Ok(IndexKind::Index(index)) => (index as usize) < cameras.len(),
+            Ok(IndexKind::String(device)) => {
+                device == pattern::DEVICE
+                    || device == depth::DEVICE
+                    || config.devices.contains_key(&device)
+                    || Path::new(&device).exists()
+                    || device::find(&device, cameras).is_ok()
+            }
+            Err(_) => false,
+        };
+        (!found).then(|| {
+            (
+                device.span(),
+                format!(
+                    "{whose}: no camera {:?} among the {} connected",
+                    device.get_ref(),
+                    cameras.len()
+                ),
+            )
+        })
+    };
+    for (name, device) in &config.devices {
+        problems.extend(missing(device, format!("device {name}")));
+    }
+    for (name, controls) in &config.tunes {
+        for (control, level) in controls {
+            if tune::control(control).is_none() {
+                problems.push((
+                    level.span(),
+                    format!("tune {name}: no camera control called {control}"),
+                ));
+            }
+        }
+    }
+    for (id, card) in &config.quirks {
+        if let Some(fps) = &card.max_frame_rate {
+            if !(1..=240).contains(fps.get_ref()) {
+                problems.push((
+                    fps.span(),
+                    format!(
+                        "quirks {id}: max-frame-rate has to be between 1 and 240, not {}",
+                        fps.get_ref()
+                    ),
+                ));
+            }
+        }
+    }
+    if let Some(region) = &config.region {
+        if AntiFlicker::of_region(region.get_ref()).is_none() {
+            problems.push((
+                region.span(),
+                format!(
+                    "region has to be a two letter country code, not {:?}",
+                    region.get_ref()
+                ),
+            ));
+        }
+    }
+    for (name, profile) in &config.profiles {
+        if let Some(device) = &profile.device {
+            problems.extend(missing(device, format!("profile {name}")));
+        }
+        if let Some(tune) = &profile.tune {
+            if !config.tunes.contains_key(tune.get_ref()) && Tune::builtin(tune.get_ref()).is_none()
+            {
+                problems.push((
+                    tune.span(),
+                    format!("profile {name}: no tune called {}", tune.get_ref()),
+                ));
+            }
+        }
+        for (control, value) in &profile.controls {
+            if capture::known_control(control).is_none() {
+                problems.push((
+                    value.span(),
+                    format!("profile {name}: no camera control called {control}"),
+                ));
+            }
+        }
+        if let Some(max_gain) = profile
+            .filters
+            .low_light
+            .as_ref()
+            .and_then(|low_light| low_light.max_gain.as_ref())
+        {
+            if !(1.0..=16.0).contains(max_gain.get_ref()) {
+                problems.push((
+                    max_gain.span(),
+                    format!(
+                        "profile {name}: max-gain has to be between 1 and 16, not {}",
+                        max_gain.get_ref()
+                    ),
+                ));
+            }
+        }
+        for (stage, depth) in &profile.pipeline.queues {
+            if *depth.get_ref() == 0 {
+                problems.push((
+                    depth.span(),
+                    format!("profile {name}: the {stage} queue has to hold 1 frame or more"),
+                ));
+            }
+        }
+        for (stage, stream) in &profile.pipeline.streams {
+            if let Err(why) = sink::parse_sink_stream(&format!("{stage}={}", stream.get_ref())) {
+                problems.push((stream.span(), format!("profile {name}: {why}")));
+            }
+        }
+        if let Some(interpolate) = &profile.filters.interpolate {
+            if !(2..=240).contains(interpolate.fps.get_ref()) {
+                problems.push((
+                    interpolate.fps.span(),
+                    format!(
+                        "profile {name}: interpolation fps has to be between 2 and 240, not {}",
+                        interpolate.fps.get_ref()
+                    ),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// The line, counting from 1, that `span` starts on in `source`.
+fn line(source: &str, span: &Range<usize>) -> usize {
+    source[..span.start.min(source.len())].matches('\n').count() + 1
+}
+
+impl Profile {
+    /// Fills in what `command` wasn't given on the command line from the
+    /// profile. Masks are added to those given.
+    pub fn apply(&self, command: &mut Commands) {
+        if let Commands::Preview { sinks, .. } | Commands::Serve { sinks, .. } = command {
+            self.apply_pipeline(sinks);
+        }
+        let (device, masks, window, low_light, upscale, interpolate) = match command {
+            Commands::Preview {
+                device,
+                preview,
+                window,
+                masks,
+                ..
+            } => (
+                device,
+                masks,
+                Some(window),
+                Some(&mut preview.low_light),
+                None,
+                None,
+            ),
+            Commands::Serve {
+                device,
+                serve,
+                masks,
+                ..
+            } => (
+                device,
+                masks,
+                None,
+                Some(&mut serve.low_light),
+                Some(&mut serve.upscale),
+                Some(&mut serve.interpolate),
+            ),
+            Commands::Record {
+                device,
+                record,
+                masks,
+            } => (device, masks, None, None, Some(&mut record.upscale), None),
+            Commands::Snapshot { device, masks, .. } | Commands::Booth { device, masks, .. } => {
+                (device, masks, None, None, None, None)
+            }
+            Commands::ScanDoc {
+                device,
+                window,
+                masks,
+                ..
+            }
+            | Commands::StopMotion {
+                device,
+                window,
+                masks,
+                ..
+            }
+            | Commands::Panorama {
+                device,
+                window,
+                masks,
+                ..
+            } => (device, masks, Some(window), None, None, None),
+            _ => return,
+        };
+        if device.is_none() {
+            *device = self
+                .device
+                .as_ref()
+                .and_then(|device| device.get_ref().parse().ok());
+        }
+        self.apply_masks(masks);
+        if let Some(window) = window {
+            self.apply_window(window);
+        }
+        if let Some(low_light) = low_light {
+            self.apply_low_light(low_light);
+        }
+        if let (Some(options), Some(upscale)) = (upscale, self.filters.upscale) {
+            options.upscale.get_or_insert(upscale);
+        }
+        if let (Some(options), Some(interpolate)) = (interpolate, &self.filters.interpolate) {
+            if options.interpolate.is_none() {
+                *options = InterpolateOptions {
+                    interpolate: Some(*interpolate.fps.get_ref()),
+                    interpolation: interpolate.method.unwrap_or_default(),
+                };
+            }
+        }
+    }
+
+    fn apply_masks(&self, options: &mut MaskOptions) {
+        options.masks.extend(&self.masks);
+    }
+
+    fn apply_pipeline(&self, options: &mut SinkOptions) {
+        if options.block_on.is_none() {
+            options.block_on = self.pipeline.block_on;
+        }
+        for (stage, depth) in &self.pipeline.queues {
+            if !options.queues.iter().any(|(given, _)| given == stage) {
+                // A queue can't be empty; `config check` says so.
+                options.queues.push((*stage, (*depth.get_ref()).max(1)));
+            }
+        }
+        for (stage, stream) in &self.pipeline.streams {
+            // `config check` says which don't parse.
+            let Ok(stream) = sink::parse_sink_stream(&format!("{stage}={}", stream.get_ref()))
+            else {
+                continue;
+            };
+            if !options.streams.iter().any(|(given, _)| given == stage) {
+                options.streams.push(stream);
+            }
+        }
+    }
+
+    fn apply_window(&self, options: &mut WindowOptions) {
+        if options.window_size.is_none() {
+            options.window_size = self.window_size;
+        }
+    }
+
+    fn apply_low_light(&self, options: &mut LowLightOptions) {
+        if let Some(low_light) = &self.filters.low_light {
+            if !options.low_light {
+                options.low_light = true;
+                if let Some(max_gain) = &low_light.max_gain {
+                    options.max_gain = *max_gain.get_ref();
+                }
+            }
+        }
+    }
+
+    /// Sets the profile's controls on the camera behind `controls`, waiting
+    /// for each in turn, and reports the ones the camera refuses.
+    pub fn set_controls(&self, controls: &Sender<ControlRequest>) {
+        for (name, value) in &self.controls {
+            // Names were checked when the profile was loaded.
+            let Some(control) = capture::known_control(name) else {
+                continue;
+            };
+            let (reply, replied) = flume::bounded(1);
+            let setter = ControlValueSetter::Integer(*value.get_ref());
+            if controls
+                .send(ControlRequest::Set(control, setter, Origin::Preset, reply))
+                .is_err()
+            {
+                return;
+            }
+            if let Ok(Err(why)) = replied.recv() {
+                eprintln!("failed to set {name}: {why}");
+            }
+        }
+    }
+}
+
+/// A profile in the config file, watched for changes while a command runs.
+pub struct Reload {
+    path: PathBuf,
+    name: String,
+    /// The command as given on the command line, before any profile.
+    base: Commands,
+    profile: Profile,
+    /// Kept for as long as the file is watched.
+    _watcher: Option<RecommendedWatcher>,
+    /// Signalled whenever the file may have changed.
+    changes: Receiver<()>,
+    controls: Option<Sender<ControlRequest>>,
+    /// Names of profiles to switch to.
+    switches: Option<Receiver<String>>,
+}
+
+impl Reload {
+    /// Watches the profile `name`, which `profile` was loaded as from
+    /// `path` and applied to `base` to get the command being run.
+    pub fn new(path: PathBuf, name: String, base: Commands, profile: Profile) -> Self {
+        let (changed, changes) = flume::unbounded();
+        let watcher = watch(&path, changed)
+            .map_err(|why| eprintln!("not watching {} for changes: {why}", path.display()))
+            .ok();
+        Reload {
+            path,
+            name,
+            base,
+            profile,
+            _watcher: watcher,
+            changes,
+            controls: None,
+            switches: None,
+        }
+    }
+
+    /// Switches to each profile whose name arrives through `switches`.
+    pub fn switch_on(&mut self, switches: Receiver<String>) {
+        self.switches = Some(switches);
+    }
+
+    /// Sets the profile's controls through `controls` now, and again after
+    /// every reload.
+    pub fn attach(&mut self, controls: Sender<ControlRequest>) {
+        self.profile.set_controls(&controls);
+        self.controls = Some(controls);
+    }
+
+    /// The command again, with the profile as it now is in the file, if
+    /// the file changed since it was last read or another profile was
+    /// switched to. A profile that doesn't load is reported and the old
+    /// one kept.
+    fn poll(&mut self) -> Option<Commands> {
+        let switch = self
+            .switches
+            .as_ref()
+            .and_then(|switches| switches.try_iter().last());
+        let name = match switch {
+            Some(name) => name,
+            // An editor saving the file can change it several times over.
+            None if self.changes.try_iter().count() > 0 => self.name.clone(),
+            None => return None,
+        };
+        self.profile = match profile(Some(&self.path), &name) {
+            Ok((_, profile)) => profile,
+            Err(why) => {
+                eprintln!("not loading profile {name}: {why}");
+                return None;
+            }
+        };
+        println!("Loaded profile {name} from {}", self.path.display());
+        self.name = name;
+        if let Some(controls) = &self.controls {
+            self.profile.set_controls(controls);
+        }
+        let mut command = self.base.clone();
+        self.profile.apply(&mut command);
+        Some(command)
+    }
+
+    /// `serve`'s options and masks, if the profile changed.
+    pub fn serve(&mut self) -> Option<(ServeOptions, Vec<Mask>)> {
+        match self.poll()? {
+            Commands::Serve { serve, masks, .. } => Some((serve, masks.masks)),
+            _ => None,
+        }
+    }
+
+    /// `preview`'s options and masks, if the profile changed.
+    pub fn preview(&mut self) -> Option<(PreviewOptions, Vec<Mask>)> {
+        match self.poll()? {
+            Commands::Preview { preview, masks, .. } => Some((preview, masks.masks)),
+            _ => None,
+        }
+    }
+}
+
+/// Signals `changed` whenever the file at `path` changes. Its directory is
+/// watched rather than the file, since editors often save by replacing it.
+fn watch(path: &Path, changed: Sender<()>) -> notify::Result<RecommendedWatcher> {
+    let name = path.file_name().map(ToOwned::to_owned);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        let ours = event
+            .paths
+            .iter()
+            .any(|changed| changed.file_name() == name.as_deref());
+        if ours && !event.kind.is_access() {
+            let _ = changed.send(());
+        }
+    })?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
+
+/// The profile called `name` in the config at `path`, or the default one,
+/// and where it was read from.
+pub fn profile(path: Option<&Path>, name: &str) -> Result<(PathBuf, Profile), Report> {
+    let (path, mut config) = load(path)?;
+    let profile = config
+        .profiles
+        .remove(name)
+        .ok_or_else(|| Report::msg(format!("no profile called {name} in {}", path.display())))?;
+    // Only the profile, and the device and tune it names if it names them, so a
+    // problem elsewhere in the file doesn't stop it being used.
+    let devices = profile
+        .device
+        .as_ref()
+        .and_then(|device| config.devices.remove_entry(device.get_ref()))
+        .into_iter()
+        .collect();
+    let tunes = profile
+        .tune
+        .as_ref()
+        .and_then(|tune| config.tunes.remove_entry(tune.get_ref()))
+        .into_iter()
+        .collect();
+    let config = Config {
+        devices,
+        profiles: BTreeMap::from([(name.to_string(), profile)]),
+        region: None,
+        tunes,
+        quirks: BTreeMap::new(),
+    };
+    if let Some((_, problem)) = problems(&config).into_iter().next() {
+        return Err(Report::msg(format!("{problem}; see athletic config check")));
+    }
+    Ok((path, config.profiles.into_values().next().unwrap()))
+}
@@ -0,0 +1,148 @@
+//! `controls`, for what's been done to a camera's controls.
+
+use crate::capture::{self, ControlRequest, Origin};
+use crate::sidecar;
+use crate::tune;
+use crate::IndexKind;
+use clap::Subcommand;
+use color_eyre::Report;
+use nokhwa::utils::{CameraIndex, ControlValueSetter, KnownCameraControl};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Subcommand, Clone)]
+pub enum Action {
+    /// Set some of a camera's controls. Most cameras keep them after
+    /// athletic exits, until they're unplugged.
+    Set {
+        device: Option<IndexKind>,
+        /// A control and its value, as Brightness=140, with the names
+        /// list-properties prints.
+        #[arg(short, long = "control", value_name = "NAME=VALUE", value_parser = parse_setting, required = true)]
+        controls: Vec<(KnownCameraControl, ControlValueSetter)>,
+    },
+    /// Set a camera's controls as the metadata sidecar of an earlier
+    /// session logged them being set, in order and as far apart.
+    Replay {
+        /// The sidecar, from record or the sinks' --metadata-sidecar.
+        sidecar: PathBuf,
+        device: Option<IndexKind>,
+        /// Also set the exposure and gain the camera's auto exposure
+        /// settled on, which turns it off on most cameras.
+        #[arg(long)]
+        auto: bool,
+        /// Set them one after another straight away.
+        #[arg(long)]
+        at_once: bool,
+    },
+}
+
+/// Parses a control and its value, as `Brightness=140`: a number, true or
+/// false, or else text.
+pub fn parse_setting(s: &str) -> Result<(KnownCameraControl, ControlValueSetter), Report> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| Report::msg(format!("expected NAME=VALUE, not {s}")))?;
+    let control = tune::control(name).ok_or_else(|| {
+        Report::msg(format!(
+            "no control called {name}; list-properties --controls lists a camera's"
+        ))
+    })?;
+    let value = if let Ok(n) = value.parse() {
+        ControlValueSetter::Integer(n)
+    } else if let Ok(n) = value.parse() {
+        ControlValueSetter::Float(n)
+    } else if let Ok(b) = value.parse() {
+        ControlValueSetter::Boolean(b)
+    } else {
+        ControlValueSetter::String(value.to_string())
+    };
+    Ok((control, value))
+}
+
+/// Sets `controls` on the camera at `index`, one after another, failing if
+/// the camera refuses any.
+pub fn set(
+    index: CameraIndex,
+    controls: &[(KnownCameraControl, ControlValueSetter)],
+) -> Result<(), Report> {
+    let capture = capture::spawn_capture(index)?;
+    let mut refused = 0;
+    for (control, value) in controls {
+        let shown = sidecar::to_json(value).map_or(String::new(), |value| value.to_string());
+        let name = tune::control_name(*control);
+        let (reply, replied) = flume::bounded(1);
+        let request = ControlRequest::Set(*control, value.clone(), Origin::Manual, reply);
+        if capture.controls.send(request).is_err() {
+            return Err(Report::msg("the camera stopped"));
+        }
+        match replied.recv() {
+            Ok(Ok(_)) => println!("{name} = {shown}"),
+            Ok(Err(why)) => {
+                eprintln!("failed to set {name} to {shown}: {why}");
+                refused += 1;
+            }
+            Err(_) => return Err(Report::msg("the camera stopped")),
+        }
+    }
+    match refused {
+        0 => Ok(()),
+        _ => Err(Report::msg(format!(
+            "{} refused {refused} of the {} controls",
+            capture.info.human_name(),
+            controls.len()
+        ))),
+    }
+}
+
+/// Sets the controls logged in `sidecar` on the camera at `index`, waiting
+/// between them as long as they were apart unless `at_once`, and leaving
+/// out the camera's own changes unless `auto`.
+pub fn replay(index: CameraIndex, sidecar: &Path, auto: bool, at_once: bool) -> Result<(), Report> {
+    let changes = sidecar::changes(sidecar)?;
+    let (changes, own): (Vec<_>, Vec<_>) = changes
+        .into_iter()
+        .partition(|change| auto || change.origin != Origin::Auto);
+    if changes.is_empty() {
+        return Err(Report::msg(format!(
+            "{} logs no control changes to replay",
+            sidecar.display()
+        )));
+    }
+    let capture = capture::spawn_capture(index)?;
+    println!(
+        "Replaying {} control changes on {}",
+        changes.len(),
+        capture.info.human_name()
+    );
+    let start = Instant::now();
+    for change in changes {
+        if !at_once {
+            std::thread::sleep((start + change.pts).saturating_duration_since(Instant::now()));
+        }
+        let shown =
+            sidecar::to_json(&change.value).map_or(String::new(), |value| value.to_string());
+        let (reply, replied) = flume::bounded(1);
+        let request = ControlRequest::Set(change.control, change.value, Origin::Replay, reply);
+        if capture.controls.send(request).is_err() {
+            return Err(Report::msg("the camera stopped"));
+        }
+        match replied.recv() {
+            Ok(Ok(_)) => println!(
+                "{:>8.2}s  {} = {shown} ({})",
+                change.pts.as_secs_f64(),
+                change.control,
+                change.origin
+            ),
+            Ok(Err(why)) => eprintln!("failed to set {} to {shown}: {why}", change.control),
+            Err(_) => return Err(Report::msg("the camera stopped")),
+        }
+    }
+    if !own.is_empty() {
+        println!(
+            "Left out {} changes the camera made itself; --auto sets them too",
+            own.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use crate::{
+    bayer::BayerOptions,
+    burst::BurstOptions,
+    bus::{BUS_NAME, OBJECT_PATH},
+    capture, config, device, lock, notify, parse_camera_format, relay,
+    snapshot::{self, Outputs},
+    stack::StackOptions,
+    timer::TimerOptions,
+    IndexKind,
+};
+use color_eyre::Report;
+use nokhwa::{
+    native_api_backend,
+    pixel_format::RgbFormat,
+    query,
+    utils::{frame_formats, CameraIndex, RequestedFormat, RequestedFormatType},
+    Camera,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Child, Command};
+use zbus::{blocking::connection, fdo, interface};
+
+/// The `org.athletic.Camera1` interface. Devices are named the same way as
+/// on the command line, with an empty string meaning the default camera.
+struct CameraInterface {
+    /// Preview windows started over D-Bus, by the device they show.
+    previews: HashMap<String, Child>,
+    /// Cameras shared with `--shared`, by [`lock::key`].
+    shares: HashMap<String, relay::Share>,
+}
+
+fn failed(why: impl ToString) -> fdo::Error {
+    fdo::Error::Failed(why.to_string())
+}
+
+fn index(device: &str) -> fdo::Result<CameraIndex> {
+    let device = match device {
+        "" => None,
+        device => device.parse::<IndexKind>().ok(),
+    };
+    let device = match device {
+        Some(device) => Some(config::load(None).map_err(failed)?.1.device(device)),
+        None => None,
+    };
+    device::resolve(device.as_ref()).map_err(failed)
+}
+
+fn open(device: &str) -> fdo::Result<Camera> {
+    capture::new_camera(
+        index(device)?,
+        RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+    )
+    .map_err(failed)
+}
+
+#[interface(name = "org.athletic.Camera1")]
+impl CameraInterface {
+    /// Returns `(index, name, description)` for every camera.
+    fn list_devices(&self) -> fdo::Result<Vec<(String, String, String)>> {
+        let backend = native_api_backend().ok_or_else(|| failed("no camera backend"))?;
+        let devices = query(backend).map_err(failed)?;
+        Ok(devices
+            .iter()
+            .map(|info| {
+                (
+                    info.index().to_string(),
+                    info.human_name(),
+                    info.description().to_string(),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns `(name, value)` for every control of `device`.
+    fn controls(&self, device: &str) -> fdo::Result<Vec<(String, String)>> {
+        let camera = open(device)?;
+        let controls = camera.camera_controls().map_err(failed)?;
+        Ok(controls
+            .iter()
+            .map(|control| {
+                (
+                    control.control().to_string(),
+                    control.description().to_string(),
+                )
+            })
+            .collect())
+    }
+
+    /// Returns every format `device` supports, as `WIDTHxHEIGHT@FPS:FORMAT`.
+    fn compatible_formats(&self, device: &str) -> fdo::Result<Vec<String>> {
+        let mut camera = open(device)?;
+        let mut formats = Vec::new();
+        for format in frame_formats() {
+            if let Ok(compatible) = camera.compatible_list_by_resolution(*format) {
+                let mut compatible: Vec<_> = compatible.into_iter().collect();
+                compatible.sort_by_key(|(resolution, _)| *resolution);
+                for (resolution, rates) in compatible {
+                    for fps in rates {
+                        formats.push(format!("{resolution}@{fps}:{format}"));
+                    }
+                }
+            }
+        }
+        Ok(formats)
+    }
+
+    /// Saves a single frame from `device` to `path`.
+    fn snapshot(&self, device: &str, path: &str) -> fdo::Result<()> {
+        snapshot::snapshot(
+            index(device)?,
+            &Outputs {
+                image: Some(Path::new(path)),
+                ..Outputs::default()
+            },
+            &BayerOptions::default(),
+            &[],
+            &TimerOptions::default(),
+            &StackOptions::default(),
+            &BurstOptions::default(),
+        )
+        .map_err(failed)
+    }
+
+    /// Opens `device` for the commands run with --shared, unless it's open
+    /// already, and returns the socket its frames are relayed on, its name,
+    /// and its format as `WIDTHxHEIGHT@FPS:FORMAT`. The first to ask for a
+    /// camera picks its `format`, in that form; empty leaves it to the
+    /// camera, and takes whatever another picked.
+    fn share(&mut self, device: &str, format: &str) -> fdo::Result<(String, String, String)> {
+        let index = index(device)?;
+        let requested = match format {
+            "" => RequestedFormatType::None,
+            format => RequestedFormatType::Exact(parse_camera_format(format).map_err(failed)?),
+        };
+        let key = lock::key(&index);
+        if let Some(share) = self.shares.get(&key) {
+            if let RequestedFormatType::Exact(wanted) = requested {
+                if wanted != share.format {
+                    return Err(failed(format!(
+                        "camera {index} is shared as {} already, not {format}",
+                        relay::spec(&share.format)
+                    )));
+                }
+            }
+        } else {
+            let share = relay::share(index, requested).map_err(failed)?;
+            println!("Sharing {} on {}", share.name, share.socket.display());
+            self.shares.insert(key.clone(), share);
+        }
+        let share = &self.shares[&key];
+        Ok((
+            share.socket.display().to_string(),
+            share.name.clone(),
+            relay::spec(&share.format),
+        ))
+    }
+
+    /// Opens a preview window for `device` and returns its process ID.
+    fn start_preview(&mut self, device: &str) -> fdo::Result<u32> {
+        if let Some(preview) = self.previews.get_mut(device) {
+            if preview.try_wait().map_err(failed)?.is_none() {
+                return Ok(preview.id());
+            }
+        }
+        let mut command = Command::new(std::env::current_exe().map_err(failed)?);
+        command.arg("preview");
+        if !device.is_empty() {
+            command.arg(device);
+        }
+        let preview = command.spawn().map_err(failed)?;
+        let id = preview.id();
+        self.previews.insert(device.to_string(), preview);
+        Ok(id)
+    }
+
+    /// Closes the preview window started for `device`, if there is one.
+    fn stop_preview(&mut self, device: &str) -> fdo::Result<()> {
+        if let Some(mut preview) = self.previews.remove(device) {
+            preview.kill().map_err(failed)?;
+            preview.wait().map_err(failed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Claims [`BUS_NAME`] on the session bus and answers calls until killed.
+pub fn run() -> Result<(), Report> {
+    let interface = CameraInterface {
+        previews: HashMap::new(),
+        shares: HashMap::new(),
+    };
+    let _connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, interface)?
+        .build()?;
+    println!("Serving {BUS_NAME} at {OBJECT_PATH} on the session bus");
+    notify::ready();
+    notify::keep_alive();
+    loop {
+        std::thread::park();
+    }
+}
@@ -0,0 +1,261 @@
+//! Pixels a sensor gets wrong: hot ones that glow in the dark, dead ones
+//! that stay dark in the light, and stuck ones that stay at one level
+//! whatever the light. [`find`] picks them out of frames averaged in the
+//! dark and in the light, by how far each strays from the pixels around
+//! it, and a [`PixelMap`] of them can be [corrected](correct) in every
+//! frame from then on, each filled in from its neighbours.
+
+use crate::trace;
+use color_eyre::Report;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static MAP: OnceCell<PixelMap> = OnceCell::new();
+/// Whether frames the map doesn't fit have been said to.
+static MISFIT: AtomicBool = AtomicBool::new(false);
+
+/// What's wrong with a pixel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Brighter than its neighbours in the dark.
+    Hot,
+    /// Darker than its neighbours in the light.
+    Dead,
+    /// Both: at one level, whatever the light.
+    Stuck,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Hot => "hot",
+            Kind::Dead => "dead",
+            Kind::Stuck => "stuck",
+        })
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Defect {
+    pub x: u32,
+    pub y: u32,
+    pub kind: Kind,
+}
+
+/// The defects of a sensor at one resolution, as `analyze dead-pixels`
+/// writes them.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PixelMap {
+    pub width: u32,
+    pub height: u32,
+    /// By row and then column, as [`find`] gives them.
+    pub defects: Vec<Defect>,
+}
+
+impl PixelMap {
+    pub fn load(path: &Path) -> Result<Self, Report> {
+        let read = |why: String| Report::msg(format!("can't read {}: {why}", path.display()));
+        let text = std::fs::read_to_string(path).map_err(|why| read(why.to_string()))?;
+        let mut map: PixelMap = serde_json::from_str(&text).map_err(|why| read(why.to_string()))?;
+        map.defects.sort_by_key(|defect| (defect.y, defect.x));
+        Ok(map)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Report> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .map_err(|why| Report::msg(format!("can't write {}: {why}", path.display())))
+    }
+
+    /// Fills in the pixels of `pixels`, a `width` by `height` frame with
+    /// `channels` to a pixel, that the map lists, each with the average of
+    /// its neighbours the map doesn't list.
+    pub fn correct(&self, pixels: &mut [u8], width: u32, height: u32, channels: usize) {
+        let (w, h) = (width as usize, height as usize);
+        let listed = |x: usize, y: usize| {
+            self.defects
+                .binary_search_by_key(&(y as u32, x as u32), |defect| (defect.y, defect.x))
+                .is_ok()
+        };
+        let colours = channels.min(3);
+        for defect in &self.defects {
+            let (x, y) = (defect.x as usize, defect.y as usize);
+            if x >= w || y >= h {
+                continue;
+            }
+            let mut sums = [0u32; 3];
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..(y + 2).min(h) {
+                for nx in x.saturating_sub(1)..(x + 2).min(w) {
+                    if (nx, ny) == (x, y) || listed(nx, ny) {
+                        continue;
+                    }
+                    let at = (ny * w + nx) * channels;
+                    for (sum, &level) in sums.iter_mut().zip(&pixels[at..at + colours]) {
+                        *sum += u32::from(level);
+                    }
+                    count += 1;
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+            let at = (y * w + x) * channels;
+            for (level, sum) in pixels[at..at + colours].iter_mut().zip(sums) {
+                *level = (sum / count) as u8;
+            }
+        }
+    }
+}
+
+/// Has [`correct`] fill in the pixels `map` lists from now on.
+pub fn correct_with(mut map: PixelMap) -> Result<(), Report> {
+    map.defects.sort_by_key(|defect| (defect.y, defect.x));
+    MAP.set(map)
+        .map_err(|_| Report::msg("pixels are already corrected from a map"))
+}
+
+/// The map [`correct_with`] was given, if it has been.
+pub fn map() -> Option<&'static PixelMap> {
+    MAP.get()
+}
+
+/// Fills in the defective pixels of `pixels`, a `width` by `height` frame
+/// with `channels` to a pixel, if [`correct_with`] has been given a map for
+/// frames that size. Pipelines call it on each frame as it's decoded,
+/// before anything else, so a pixelated mask doesn't take them in.
+pub fn correct(pixels: &mut [u8], width: u32, height: u32, channels: usize) {
+    let Some(map) = MAP.get() else {
+        return;
+    };
+    if (map.width, map.height) != (width, height)
+        || pixels.len() < width as usize * height as usize * channels
+    {
+        if !MISFIT.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "The pixel map is for {}x{} frames, so {width}x{height} ones go uncorrected",
+                map.width, map.height
+            );
+        }
+        return;
+    }
+    let _zone = trace::zone("pixel map");
+    map.correct(pixels, width, height, channels);
+}
+
+/// The average of frames, channel by channel, as 8-bit RGB adds up.
+pub struct Levels {
+    pub width: u32,
+    pub height: u32,
+    sums: Vec<u32>,
+    frames: u32,
+}
+
+impl Levels {
+    pub fn new(width: u32, height: u32) -> Self {
+        Levels {
+            width,
+            height,
+            sums: vec![0; width as usize * height as usize * 3],
+            frames: 0,
+        }
+    }
+
+    /// Adds a frame of 8-bit RGB the size of the others.
+    pub fn add(&mut self, rgb: &[u8]) -> Result<(), Report> {
+        if rgb.len() != self.sums.len() {
+            return Err(Report::msg("the frames changed size"));
+        }
+        for (sum, &level) in self.sums.iter_mut().zip(rgb) {
+            *sum += u32::from(level);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn at(&self, x: usize, y: usize, channel: usize) -> f32 {
+        let sum = self.sums[(y * self.width as usize + x) * 3 + channel];
+        sum as f32 / self.frames.max(1) as f32
+    }
+
+    /// The average over every pixel and channel.
+    pub fn mean(&self) -> f32 {
+        let total: u64 = self.sums.iter().map(|&sum| u64::from(sum)).sum();
+        total as f32 / (self.sums.len().max(1) as f32 * self.frames.max(1) as f32)
+    }
+}
+
+/// The pixels of `dark`, frames with the lens covered, and `bright`, frames
+/// of something evenly lit, that stray further than `threshold` levels of
+/// 255 in any channel from all but one of the pixels around them, or all
+/// three in a corner. All but one, so that pairs of them are found, but not
+/// the edges of what's in the picture, which the pixels along them match.
+pub fn find(dark: &Levels, bright: &Levels, threshold: f32) -> Result<Vec<Defect>, Report> {
+    if (dark.width, dark.height) != (bright.width, bright.height) {
+        return Err(Report::msg(
+            "the dark and bright frames are different sizes",
+        ));
+    }
+    let (w, h) = (dark.width as usize, dark.height as usize);
+    if w < 2 || h < 2 {
+        return Err(Report::msg(
+            "the frames are too small to look for pixels in",
+        ));
+    }
+    let mut defects = Vec::new();
+    let mut around = Vec::with_capacity(8);
+    for y in 0..h {
+        for x in 0..w {
+            // How many of those around it it may be like.
+            let corner = (x == 0 || x == w - 1) && (y == 0 || y == h - 1);
+            let spared = usize::from(!corner);
+            let kind = (0..3).find_map(|channel| {
+                let dark_at = |x, y| dark.at(x, y, channel);
+                let bright_at = |x, y| bright.at(x, y, channel);
+                let near = gather(&mut around, dark_at, x, y, w, h);
+                let hot = dark_at(x, y) - near[near.len() - 1 - spared] > threshold;
+                let near = gather(&mut around, bright_at, x, y, w, h);
+                let dead = near[spared] - bright_at(x, y) > threshold;
+                match (hot, dead) {
+                    (true, true) => Some(Kind::Stuck),
+                    (true, false) => Some(Kind::Hot),
+                    (false, true) => Some(Kind::Dead),
+                    (false, false) => None,
+                }
+            });
+            if let Some(kind) = kind {
+                defects.push(Defect {
+                    x: x as u32,
+                    y: y as u32,
+                    kind,
+                });
+            }
+        }
+    }
+    Ok(defects)
+}
+
+/// The levels of the pixels around `x`,`y` in a `w` by `h` frame, gathered
+/// into `around` from least to most. There are at least three.
+fn gather(
+    around: &mut Vec<f32>,
+    levels: impl Fn(usize, usize) -> f32,
+    x: usize,
+    y: usize,
+    w: usize,
+    h: usize,
+) -> &[f32] {
+    around.clear();
+    for ny in y.saturating_sub(1)..(y + 2).min(h) {
+        for nx in x.saturating_sub(1)..(x + 2).min(w) {
+            if (nx, ny) != (x, y) {
+                around.push(levels(nx, ny));
+            }
+        }
+    }
+    around.sort_by(f32::total_cmp);
+    around
+}
@@ -0,0 +1,455 @@
+//! Depth cameras, whose frames come with the distance to every pixel,
+//! through librealsense when built with the `depth` feature. Open an Intel
+//! RealSense camera as the device `realsense`.
+//!
+//! The colour and depth streams come from separate sensors; librealsense
+//! reprojects the depth onto the colour sensor's view, so each pixel of a
+//! [`DepthPlane`] lines up with the same pixel of its frame, whose
+//! [`Stream::Depth`](crate::stream::Stream::Depth) it is.
+
+use crate::capture::{Capture, Queue};
+use color_eyre::Report;
+use image::{ImageBuffer, Luma};
+use nokhwa::utils::{CameraFormat, CameraIndex, CameraInfo, FrameFormat, Resolution};
+
+/// The device name that opens a RealSense camera rather than a camera
+/// nokhwa finds.
+pub const DEVICE: &str = "realsense";
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FPS: u32 = 30;
+
+/// The distance to each pixel of the frame it came with.
+#[derive(Debug)]
+pub struct DepthPlane {
+    pub width: u32,
+    pub height: u32,
+    /// Row by row, in units of `scale` metres, 0 where the camera couldn't
+    /// tell.
+    pub samples: Vec<u16>,
+    pub scale: f32,
+}
+
+impl DepthPlane {
+    /// The distance at `(x, y)` in metres, if the camera could tell.
+    pub fn metres(&self, x: u32, y: u32) -> Option<f32> {
+        let sample = *self.samples.get((y * self.width + x) as usize)?;
+        (sample != 0).then_some(sample as f32 * self.scale)
+    }
+
+    /// Colours each distance into `rgba`, from red up close through the
+    /// spectrum to blue at `max` metres and beyond, and black where the
+    /// camera couldn't tell. `rgba` must hold four bytes for every pixel.
+    pub fn colorize(&self, max: f32, rgba: &mut [u8]) {
+        for (pixel, &sample) in rgba.chunks_exact_mut(4).zip(&self.samples) {
+            let [r, g, b] = match sample {
+                0 => [0; 3],
+                _ => hue((sample as f32 * self.scale / max).min(1.0) * 240.0),
+            };
+            pixel.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+
+    /// The samples as a 16-bit grayscale image, in the camera's units.
+    pub fn image(&self) -> Option<ImageBuffer<Luma<u16>, Vec<u16>>> {
+        ImageBuffer::from_raw(self.width, self.height, self.samples.clone())
+    }
+}
+
+/// The fully saturated colour at `degrees` round the colour wheel.
+fn hue(degrees: f32) -> [u8; 3] {
+    let h = degrees / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c: f32| (c * 255.0).round() as u8)
+}
+
+/// Whether `index` names a depth camera.
+pub fn is_depth(index: &CameraIndex) -> bool {
+    matches!(index, CameraIndex::String(name) if name == DEVICE)
+}
+
+/// A depth camera's name and index, as a camera's.
+pub fn info() -> CameraInfo {
+    CameraInfo::new(
+        "Intel RealSense",
+        "Colour with aligned depth, through librealsense",
+        "",
+        CameraIndex::String(DEVICE.to_string()),
+    )
+}
+
+/// The format of the colour frames; the depth comes at the same size.
+pub fn format() -> CameraFormat {
+    CameraFormat::new(Resolution::new(WIDTH, HEIGHT), FrameFormat::RAWRGB, FPS)
+}
+
+#[cfg(not(feature = "depth"))]
+pub fn spawn(_queue: Queue) -> Result<Capture, Report> {
+    Err(Report::msg(
+        "depth cameras need athletic built with --features depth",
+    ))
+}
+
+/// Starts the first RealSense camera connected on its own thread, as
+/// [`crate::capture::spawn_capture`] starts a camera, with each frame's
+/// depth among [`crate::capture::Frame::planes`]. It has no controls.
+#[cfg(feature = "depth")]
+pub fn spawn(queue: Queue) -> Result<Capture, Report> {
+    use crate::capture::{Clock, Counters, Sent};
+    use crate::memory::Held;
+    use crate::stream::{Plane, Stream};
+    use nokhwa::Buffer;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    let (sender, receiver) = flume::bounded(queue.depth);
+    let (control_sender, control_receiver) = flume::unbounded();
+    let (ready_sender, ready_receiver) = flume::bounded(1);
+    let counters = Arc::new(Counters::default());
+    let counted = counters.clone();
+
+    std::thread::spawn(move || {
+        let mut stream = match rs2::Stream::start(WIDTH, HEIGHT, FPS) {
+            Ok(stream) => {
+                let _ = ready_sender.send(Ok(()));
+                stream
+            }
+            Err(why) => {
+                let _ = ready_sender.send(Err(why));
+                return;
+            }
+        };
+        let mut clock = Clock::default();
+        loop {
+            crate::pattern::refuse_controls(&control_receiver, "a depth camera");
+            let (rgb, depth) = match stream.next() {
+                Ok(frames) => frames,
+                Err(why) => {
+                    eprintln!("failed to capture frame: {why}");
+                    break;
+                }
+            };
+            counted.received.fetch_add(1, Ordering::Relaxed);
+            let held = rgb.len() + depth.samples.len() * 2;
+            let mut frame = clock.stamp(Buffer::new(
+                Resolution::new(WIDTH, HEIGHT),
+                &rgb,
+                FrameFormat::RAWRGB,
+            ));
+            frame.held = Held::new(held);
+            frame.planes.insert(Stream::Depth, Plane::Depth(depth));
+            match queue.send(&sender, frame) {
+                Sent::Queued => {}
+                Sent::Dropped => {
+                    counted.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Sent::Gone => break,
+            }
+        }
+    });
+
+    ready_receiver.recv()??;
+    Ok(Capture {
+        info: info(),
+        format: format(),
+        supported_controls: Vec::new(),
+        streams: vec![Stream::Main, Stream::Depth],
+        frames: receiver,
+        controls: control_sender,
+        counters,
+    })
+}
+
+/// Just enough of librealsense's C API to stream colour and depth.
+#[cfg(feature = "depth")]
+mod rs2 {
+    use super::DepthPlane;
+    use color_eyre::Report;
+    use std::ffi::{c_char, c_float, c_int, c_uint, c_void, CStr};
+
+    /// The oldest API the calls here are in, 2.50.0, which newer libraries
+    /// stay compatible with.
+    const API_VERSION: c_int = 25000;
+    const STREAM_DEPTH: c_int = 1;
+    const STREAM_COLOR: c_int = 2;
+    const FORMAT_Z16: c_int = 1;
+    const FORMAT_RGB8: c_int = 5;
+    const TIMEOUT_MS: c_uint = 5000;
+
+    macro_rules! opaque {
+        ($($name:ident),*) => {
+            $(#[repr(C)]
+            pub struct $name {
+                _private: [u8; 0],
+            })*
+        };
+    }
+    opaque!(
+        Error,
+        Context,
+        Pipeline,
+        Config,
+        PipelineProfile,
+        ProcessingBlock,
+        FrameQueue,
+        Frame,
+        StreamProfile
+    );
+
+    #[link(name = "realsense2")]
+    extern "C" {
+        fn rs2_create_context(api_version: c_int, error: *mut *mut Error) -> *mut Context;
+        fn rs2_delete_context(context: *mut Context);
+        fn rs2_create_pipeline(context: *mut Context, error: *mut *mut Error) -> *mut Pipeline;
+        fn rs2_delete_pipeline(pipeline: *mut Pipeline);
+        fn rs2_create_config(error: *mut *mut Error) -> *mut Config;
+        fn rs2_delete_config(config: *mut Config);
+        fn rs2_config_enable_stream(
+            config: *mut Config,
+            stream: c_int,
+            index: c_int,
+            width: c_int,
+            height: c_int,
+            format: c_int,
+            framerate: c_int,
+            error: *mut *mut Error,
+        );
+        fn rs2_pipeline_start_with_config(
+            pipeline: *mut Pipeline,
+            config: *mut Config,
+            error: *mut *mut Error,
+        ) -> *mut PipelineProfile;
+        fn rs2_delete_pipeline_profile(profile: *mut PipelineProfile);
+        fn rs2_pipeline_stop(pipeline: *mut Pipeline, error: *mut *mut Error);
+        fn rs2_pipeline_wait_for_frames(
+            pipeline: *mut Pipeline,
+            timeout_ms: c_uint,
+            error: *mut *mut Error,
+        ) -> *mut Frame;
+        fn rs2_create_align(align_to: c_int, error: *mut *mut Error) -> *mut ProcessingBlock;
+        fn rs2_delete_processing_block(block: *mut ProcessingBlock);
+        fn rs2_create_frame_queue(capacity: c_int, error: *mut *mut Error) -> *mut FrameQueue;
+        fn rs2_delete_frame_queue(queue: *mut FrameQueue);
+        fn rs2_start_processing_queue(
+            block: *mut ProcessingBlock,
+            queue: *mut FrameQueue,
+            error: *mut *mut Error,
+        );
+        fn rs2_process_frame(
+            block: *mut ProcessingBlock,
+            frame: *mut Frame,
+            error: *mut *mut Error,
+        );
+        fn rs2_wait_for_frame(
+            queue: *mut FrameQueue,
+            timeout_ms: c_uint,
+            error: *mut *mut Error,
+        ) -> *mut Frame;
+        fn rs2_embedded_frames_count(composite: *mut Frame, error: *mut *mut Error) -> c_int;
+        fn rs2_extract_frame(
+            composite: *mut Frame,
+            index: c_int,
+            error: *mut *mut Error,
+        ) -> *mut Frame;
+        fn rs2_get_frame_stream_profile(
+            frame: *const Frame,
+            error: *mut *mut Error,
+        ) -> *const StreamProfile;
+        fn rs2_get_stream_profile_data(
+            profile: *const StreamProfile,
+            stream: *mut c_int,
+            format: *mut c_int,
+            index: *mut c_int,
+            unique_id: *mut c_int,
+            framerate: *mut c_int,
+            error: *mut *mut Error,
+        );
+        fn rs2_get_frame_data(frame: *const Frame, error: *mut *mut Error) -> *const c_void;
+        fn rs2_get_frame_width(frame: *const Frame, error: *mut *mut Error) -> c_int;
+        fn rs2_get_frame_height(frame: *const Frame, error: *mut *mut Error) -> c_int;
+        fn rs2_get_frame_stride_in_bytes(frame: *const Frame, error: *mut *mut Error) -> c_int;
+        fn rs2_depth_frame_get_units(frame: *const Frame, error: *mut *mut Error) -> c_float;
+        fn rs2_release_frame(frame: *mut Frame);
+        fn rs2_get_error_message(error: *const Error) -> *const c_char;
+        fn rs2_free_error(error: *mut Error);
+    }
+
+    /// Makes a call that reports failure through its last argument.
+    fn call<T>(f: impl FnOnce(*mut *mut Error) -> T) -> Result<T, Report> {
+        let mut error = std::ptr::null_mut();
+        let result = f(&mut error);
+        if error.is_null() {
+            return Ok(result);
+        }
+        // Safety: librealsense hands over an error it allocated, with a
+        // message that lives as long as it does.
+        let message = unsafe {
+            let message = CStr::from_ptr(rs2_get_error_message(error))
+                .to_string_lossy()
+                .into_owned();
+            rs2_free_error(error);
+            message
+        };
+        Err(Report::msg(format!("librealsense: {message}")))
+    }
+
+    /// A camera streaming colour and depth, the depth aligned to the colour.
+    pub struct Stream {
+        context: *mut Context,
+        pipeline: *mut Pipeline,
+        config: *mut Config,
+        profile: *mut PipelineProfile,
+        align: *mut ProcessingBlock,
+        aligned: *mut FrameQueue,
+    }
+
+    impl Stream {
+        /// Starts the first camera connected, both streams at `width` by
+        /// `height` and `fps`.
+        pub fn start(width: u32, height: u32, fps: u32) -> Result<Stream, Report> {
+            // Safety: each object is checked for failure before it's used,
+            // and belongs to the stream, which deletes it on drop.
+            unsafe {
+                let mut stream = Stream {
+                    context: call(|e| rs2_create_context(API_VERSION, e))?,
+                    pipeline: std::ptr::null_mut(),
+                    config: std::ptr::null_mut(),
+                    profile: std::ptr::null_mut(),
+                    align: std::ptr::null_mut(),
+                    aligned: std::ptr::null_mut(),
+                };
+                stream.pipeline = call(|e| rs2_create_pipeline(stream.context, e))?;
+                stream.config = call(|e| rs2_create_config(e))?;
+                for (kind, format) in [(STREAM_COLOR, FORMAT_RGB8), (STREAM_DEPTH, FORMAT_Z16)] {
+                    call(|e| {
+                        rs2_config_enable_stream(
+                            stream.config,
+                            kind,
+                            0,
+                            width as c_int,
+                            height as c_int,
+                            format,
+                            fps as c_int,
+                            e,
+                        )
+                    })?;
+                }
+                stream.profile =
+                    call(|e| rs2_pipeline_start_with_config(stream.pipeline, stream.config, e))?;
+                stream.align = call(|e| rs2_create_align(STREAM_COLOR, e))?;
+                stream.aligned = call(|e| rs2_create_frame_queue(1, e))?;
+                call(|e| rs2_start_processing_queue(stream.align, stream.aligned, e))?;
+                Ok(stream)
+            }
+        }
+
+        /// Waits for the next pair of frames: the colour as packed RGB, and
+        /// the depth lined up with it.
+        pub fn next(&mut self) -> Result<(Vec<u8>, DepthPlane), Report> {
+            // Safety: frames are released once copied out of, and the
+            // composite is handed to the align block, which releases it.
+            unsafe {
+                let composite =
+                    call(|e| rs2_pipeline_wait_for_frames(self.pipeline, TIMEOUT_MS, e))?;
+                call(|e| rs2_process_frame(self.align, composite, e))?;
+                let composite = call(|e| rs2_wait_for_frame(self.aligned, TIMEOUT_MS, e))?;
+                let copied = copy(composite);
+                rs2_release_frame(composite);
+                copied
+            }
+        }
+    }
+
+    /// Copies the colour and depth out of an aligned `composite`.
+    ///
+    /// # Safety
+    ///
+    /// `composite` has to be a frame librealsense gave and hasn't released.
+    unsafe fn copy(composite: *mut Frame) -> Result<(Vec<u8>, DepthPlane), Report> {
+        let (mut rgb, mut depth) = (None, None);
+        for n in 0..call(|e| rs2_embedded_frames_count(composite, e))? {
+            let frame = call(|e| rs2_extract_frame(composite, n, e))?;
+            let copied = (|| {
+                let profile = call(|e| rs2_get_frame_stream_profile(frame, e))?;
+                let (mut kind, mut format, mut index, mut id, mut fps) = (0, 0, 0, 0, 0);
+                call(|e| {
+                    rs2_get_stream_profile_data(
+                        profile,
+                        &mut kind,
+                        &mut format,
+                        &mut index,
+                        &mut id,
+                        &mut fps,
+                        e,
+                    )
+                })?;
+                let width = call(|e| rs2_get_frame_width(frame, e))? as usize;
+                let height = call(|e| rs2_get_frame_height(frame, e))? as usize;
+                let stride = call(|e| rs2_get_frame_stride_in_bytes(frame, e))? as usize;
+                let data = call(|e| rs2_get_frame_data(frame, e))? as *const u8;
+                let data = std::slice::from_raw_parts(data, stride * height);
+                let rows = data.chunks_exact(stride);
+                match kind {
+                    STREAM_COLOR => {
+                        rgb = Some(rows.flat_map(|row| &row[..width * 3]).copied().collect());
+                    }
+                    STREAM_DEPTH => {
+                        let samples = rows
+                            .flat_map(|row| row[..width * 2].chunks_exact(2))
+                            .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+                            .collect();
+                        depth = Some(DepthPlane {
+                            width: width as u32,
+                            height: height as u32,
+                            samples,
+                            scale: call(|e| rs2_depth_frame_get_units(frame, e))?,
+                        });
+                    }
+                    _ => {}
+                }
+                Ok::<_, Report>(())
+            })();
+            rs2_release_frame(frame);
+            copied?;
+        }
+        match (rgb, depth) {
+            (Some(rgb), Some(depth)) => Ok((rgb, depth)),
+            _ => Err(Report::msg(
+                "librealsense gave a frame without colour and depth",
+            )),
+        }
+    }
+
+    impl Drop for Stream {
+        fn drop(&mut self) {
+            // Safety: each pointer is null or an object this stream made.
+            unsafe {
+                if !self.aligned.is_null() {
+                    rs2_delete_frame_queue(self.aligned);
+                }
+                if !self.align.is_null() {
+                    rs2_delete_processing_block(self.align);
+                }
+                if !self.profile.is_null() {
+                    let _ = call(|e| rs2_pipeline_stop(self.pipeline, e));
+                    rs2_delete_pipeline_profile(self.profile);
+                }
+                if !self.config.is_null() {
+                    rs2_delete_config(self.config);
+                }
+                if !self.pipeline.is_null() {
+                    rs2_delete_pipeline(self.pipeline);
+                }
+                rs2_delete_context(self.context);
+            }
+        }
+    }
+}
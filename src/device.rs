@@ -0,0 +1,114 @@
+//! Which camera a device given on the command line means: a number is
+//! the camera at that index, and a name is the one camera whose name
+//! contains it, ignoring case, so `c920` finds "HD Pro Webcam C920". On
+//! Linux an absolute path is the video node there, so the stable links in
+//! /dev/v4l/by-id and /dev/v4l/by-path open the camera they point to.
+
+use crate::spec::IndexKind;
+use crate::{capture, depth, lock, pattern};
+use color_eyre::Report;
+use nokhwa::utils::{ApiBackend, CameraIndex, CameraInfo, RequestedFormatType};
+use nokhwa::{native_api_backend, query};
+use std::path::Path;
+
+/// The camera `device` means, the first if none is given, looking names
+/// up among the cameras connected now.
+pub fn resolve(device: Option<&IndexKind>) -> Result<CameraIndex, Report> {
+    match device {
+        None => Ok(CameraIndex::Index(0)),
+        Some(IndexKind::Index(index)) => Ok(CameraIndex::Index(*index)),
+        Some(IndexKind::String(name)) if name == pattern::DEVICE || name == depth::DEVICE => {
+            Ok(CameraIndex::String(name.clone()))
+        }
+        Some(IndexKind::String(path)) if cfg!(target_os = "linux") && path.starts_with('/') => {
+            video_node(Path::new(path)).map(CameraIndex::Index)
+        }
+        Some(IndexKind::String(name)) => {
+            let cameras = native_api_backend()
+                .and_then(|backend| query(backend).ok())
+                .unwrap_or_default();
+            find(name, &cameras)
+        }
+    }
+}
+
+/// The number of the V4L2 video node at `path`, `/dev/videoN`, following
+/// the links udev makes to it.
+pub fn video_node(path: &Path) -> Result<u32, Report> {
+    let node = std::fs::canonicalize(path)
+        .map_err(|why| Report::msg(format!("can't open {}: {why}", path.display())))?;
+    node.file_name()
+        .and_then(|name| name.to_str()?.strip_prefix("video")?.parse().ok())
+        .ok_or_else(|| Report::msg(format!("{} isn't a video device", path.display())))
+}
+
+/// The camera library's backend on this platform.
+pub fn backend() -> Result<ApiBackend, Report> {
+    native_api_backend().ok_or_else(|| Report::msg("no camera backend on this platform"))
+}
+
+/// The first connected camera that opens and streams, for when any will
+/// do but some may be busy or broken. Says on standard error which it
+/// skipped and which it took.
+pub fn first_available() -> Result<CameraIndex, Report> {
+    let cameras = query(backend()?)?;
+    for camera in &cameras {
+        let index = camera.index();
+        let opened = lock::lock(index)
+            .and_then(|_lock| capture::open_camera(index.clone(), RequestedFormatType::None));
+        match opened {
+            Ok(_) => {
+                eprintln!("Using camera {index}: {}", camera.human_name());
+                return Ok(index.clone());
+            }
+            Err(why) => eprintln!("Skipping camera {index}: {}: {why}", camera.human_name()),
+        }
+    }
+    Err(Report::msg(match cameras.len() {
+        0 => "no cameras are connected".to_string(),
+        connected => format!("none of the {connected} cameras connected would open"),
+    }))
+}
+
+/// The camera among `cameras` that `name` means: the one with that index
+/// or name exactly, or else the only one whose name contains it.
+pub fn find(name: &str, cameras: &[CameraInfo]) -> Result<CameraIndex, Report> {
+    let exact = cameras.iter().find(|camera| {
+        camera.human_name() == name
+            || matches!(camera.index(), CameraIndex::String(index) if index == name)
+    });
+    if let Some(camera) = exact {
+        return Ok(camera.index().clone());
+    }
+    let wanted = name.to_lowercase();
+    let matching: Vec<&CameraInfo> = cameras
+        .iter()
+        .filter(|camera| camera.human_name().to_lowercase().contains(&wanted))
+        .collect();
+    match matching[..] {
+        [camera] => Ok(camera.index().clone()),
+        [] if cameras.iter().any(|camera| camera.index().is_string()) => {
+            // Backends that open cameras by a string may take one that
+            // isn't listed, like a stream's address.
+            Ok(CameraIndex::String(name.to_string()))
+        }
+        [] if cameras.is_empty() => Err(Report::msg(format!(
+            "no camera's name contains {name:?}, as none are connected"
+        ))),
+        [] => Err(Report::msg(format!(
+            "no camera's name contains {name:?}; the cameras are:{}",
+            listing(cameras.iter())
+        ))),
+        _ => Err(Report::msg(format!(
+            "{name:?} could be more than one camera:{}",
+            listing(matching.into_iter())
+        ))),
+    }
+}
+
+/// A line for each of `cameras`, as `list-devices` would name them.
+fn listing<'a>(cameras: impl Iterator<Item = &'a CameraInfo>) -> String {
+    cameras
+        .map(|camera| format!("\n  {}: {}", camera.index(), camera.human_name()))
+        .collect()
+}
@@ -0,0 +1,174 @@
+use crate::bayer::{Channel, Pattern};
+use crate::mono::Depth;
+use std::io::{self, Write};
+
+// TIFF field types.
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const SRATIONAL: u16 = 10;
+
+/// Nothing is known about the sensor's colour response, so the colour matrix
+/// claims it sees linear sRGB: the XYZ to sRGB matrix under D65, in 1/10000.
+const COLOR_MATRIX: [i32; 9] = [32406, -15372, -4986, -9689, 18758, 415, 557, -2040, 10570];
+/// D65, in the EXIF LightSource numbering.
+const D65: u16 = 21;
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+impl Entry {
+    fn shorts(tag: u16, values: &[u16]) -> Entry {
+        Entry {
+            tag,
+            kind: SHORT,
+            count: values.len() as u32,
+            data: values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Entry {
+        Entry {
+            tag,
+            kind: LONG,
+            count: 1,
+            data: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn bytes(tag: u16, values: &[u8]) -> Entry {
+        Entry {
+            tag,
+            kind: BYTE,
+            count: values.len() as u32,
+            data: values.to_vec(),
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Entry {
+        let mut data = text.replace('\0', "").into_bytes();
+        data.push(0);
+        Entry {
+            tag,
+            kind: ASCII,
+            count: data.len() as u32,
+            data,
+        }
+    }
+
+    fn srationals(tag: u16, numerators: &[i32], denominator: i32) -> Entry {
+        Entry {
+            tag,
+            kind: SRATIONAL,
+            count: numerators.len() as u32,
+            data: numerators
+                .iter()
+                .flat_map(|numerator| [numerator.to_le_bytes(), denominator.to_le_bytes()])
+                .flatten()
+                .collect(),
+        }
+    }
+}
+
+/// Writes raw Bayer samples as an uncompressed DNG that raw developers can
+/// demosaic and colour-correct properly later.
+///
+/// `data` holds `width * height` samples of `depth`, 16-bit ones little-endian.
+pub fn write(
+    out: &mut impl Write,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    depth: Depth,
+    pattern: Pattern,
+    camera: &str,
+) -> io::Result<()> {
+    let (bits, white) = match depth {
+        Depth::Eight => (8, u8::MAX as u32),
+        Depth::Sixteen => (16, u16::MAX as u32),
+    };
+    let size = width as usize * height as usize * bits as usize / 8;
+    let data = data
+        .get(..size)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "raw frame is too short"))?;
+    let cfa = pattern.0.map(|channel| match channel {
+        Channel::Red => 0,
+        Channel::Green => 1,
+        Channel::Blue => 2,
+    });
+
+    // The strip offset is only known once the IFD is laid out, so it is
+    // filled in below; entries are in ascending tag order as TIFF requires.
+    let mut entries = vec![
+        Entry::long(254, 0),
+        Entry::long(256, width),
+        Entry::long(257, height),
+        Entry::shorts(258, &[bits]),
+        Entry::shorts(259, &[1]),
+        Entry::shorts(262, &[32803]),
+        Entry::ascii(272, camera),
+        Entry::long(273, 0),
+        Entry::shorts(274, &[1]),
+        Entry::shorts(277, &[1]),
+        Entry::long(278, height),
+        Entry::long(279, size as u32),
+        Entry::shorts(284, &[1]),
+        Entry::shorts(33421, &[2, 2]),
+        Entry::bytes(33422, &cfa),
+        Entry::bytes(50706, &[1, 4, 0, 0]),
+        Entry::ascii(50708, camera),
+        Entry::long(50717, white),
+        Entry::srationals(50721, &COLOR_MATRIX, 10000),
+        Entry::shorts(50778, &[D65]),
+    ];
+
+    const HEADER: usize = 8;
+    let ifd_size = 2 + entries.len() * 12 + 4;
+    let overflow: usize = entries
+        .iter()
+        .filter(|entry| entry.data.len() > 4)
+        .map(|entry| entry.data.len().next_multiple_of(2))
+        .sum();
+    let strip_offset = (HEADER + ifd_size + overflow) as u32;
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.tag == 273) {
+        entry.data = strip_offset.to_le_bytes().to_vec();
+    }
+
+    let mut head = Vec::with_capacity(strip_offset as usize);
+    head.extend_from_slice(b"II");
+    head.extend_from_slice(&42u16.to_le_bytes());
+    head.extend_from_slice(&(HEADER as u32).to_le_bytes());
+    head.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut overflow_data = Vec::with_capacity(overflow);
+    for entry in &entries {
+        head.extend_from_slice(&entry.tag.to_le_bytes());
+        head.extend_from_slice(&entry.kind.to_le_bytes());
+        head.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.data.len() <= 4 {
+            let mut value = [0; 4];
+            value[..entry.data.len()].copy_from_slice(&entry.data);
+            head.extend_from_slice(&value);
+        } else {
+            let offset = (HEADER + ifd_size + overflow_data.len()) as u32;
+            head.extend_from_slice(&offset.to_le_bytes());
+            overflow_data.extend_from_slice(&entry.data);
+            if overflow_data.len() % 2 == 1 {
+                overflow_data.push(0);
+            }
+        }
+    }
+    head.extend_from_slice(&0u32.to_le_bytes());
+    head.extend_from_slice(&overflow_data);
+
+    out.write_all(&head)?;
+    out.write_all(data)?;
+    out.flush()
+}
@@ -0,0 +1,197 @@
+//! Finding a sheet of paper in a frame and flattening it into a clean page.
+
+use crate::warp;
+use image::{imageops, imageops::FilterType, GrayImage, Luma, RgbImage};
+use std::collections::VecDeque;
+
+/// Width frames are shrunk to before looking for the page, which is plenty
+/// to find its corners and keeps detection fast enough to run every frame.
+const DETECT_WIDTH: u32 = 320;
+/// Smallest share of the frame a page may cover.
+const MIN_AREA: f32 = 0.1;
+/// How much darker than its neighbourhood a pixel has to be, as a fraction,
+/// to come out black from [`threshold`].
+const THRESHOLD_OFFSET: f32 = 0.15;
+
+/// A point in frame pixels.
+pub type Point = (f32, f32);
+
+/// Finds the largest bright quadrilateral in `image`, taken to be a sheet of
+/// paper on a darker desk. Returns its corners clockwise from the top left,
+/// in `image`'s pixels.
+///
+/// The corners are the page's extreme points along the two diagonals, which
+/// holds for pages turned by up to about 30°.
+pub fn find_page(image: &RgbImage) -> Option<[Point; 4]> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let scale = width as f32 / DETECT_WIDTH.min(width) as f32;
+    let small = imageops::resize(
+        image,
+        (width as f32 / scale) as u32,
+        ((height as f32 / scale) as u32).max(1),
+        FilterType::Triangle,
+    );
+    let gray = imageops::blur(&image::DynamicImage::ImageRgb8(small).to_luma8(), 1.5);
+    let level = otsu(&gray);
+    let (w, h) = gray.dimensions();
+    let bright = |x: u32, y: u32| gray.get_pixel(x, y)[0] > level;
+
+    // The largest connected bright region is taken to be the page.
+    let mut seen = vec![false; (w * h) as usize];
+    let mut best: Vec<(u32, u32)> = Vec::new();
+    let mut queue = VecDeque::new();
+    for start in 0..w * h {
+        if seen[start as usize] || !bright(start % w, start / w) {
+            continue;
+        }
+        let mut region = Vec::new();
+        seen[start as usize] = true;
+        queue.push_back((start % w, start / w));
+        while let Some((x, y)) = queue.pop_front() {
+            region.push((x, y));
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx < w && ny < h && !seen[(ny * w + nx) as usize] && bright(nx, ny) {
+                    seen[(ny * w + nx) as usize] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        if region.len() > best.len() {
+            best = region;
+        }
+    }
+    if (best.len() as f32) < MIN_AREA * (w * h) as f32 {
+        return None;
+    }
+
+    let extreme = |key: fn(f32, f32) -> f32| {
+        best.iter()
+            .map(|&(x, y)| (x as f32, y as f32))
+            .max_by(|a, b| key(a.0, a.1).total_cmp(&key(b.0, b.1)))
+            .unwrap_or_default()
+    };
+    let corners = [
+        extreme(|x, y| -x - y),
+        extreme(|x, y| x - y),
+        extreme(|x, y| x + y),
+        extreme(|x, y| y - x),
+    ];
+    if area(&corners) < MIN_AREA * (w * h) as f32 {
+        return None;
+    }
+    Some(corners.map(|(x, y)| ((x + 0.5) * scale, (y + 0.5) * scale)))
+}
+
+/// Area enclosed by `corners`, by the shoelace formula.
+fn area(corners: &[Point; 4]) -> f32 {
+    let twice: f32 = (0..4)
+        .map(|n| {
+            let (a, b) = (corners[n], corners[(n + 1) % 4]);
+            a.0 * b.1 - b.0 * a.1
+        })
+        .sum();
+    twice.abs() / 2.0
+}
+
+/// The level that best splits `image` into dark and bright pixels, by
+/// Otsu's method.
+fn otsu(image: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let total = image.pixels().len() as f64;
+    let sum: f64 = (0..256).map(|n| n as f64 * histogram[n] as f64).sum();
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best, mut best_variance) = (0, 0.0);
+    for (level, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        below_sum += level as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let difference = below_sum / below - (sum - below_sum) / above;
+        let variance = below * above * difference * difference;
+        if variance > best_variance {
+            (best, best_variance) = (level as u8, variance);
+        }
+    }
+    best
+}
+
+/// Maps the page with `corners` (clockwise from the top left) in `image`
+/// onto an upright rectangle as big as its longer sides.
+pub fn flatten(image: &RgbImage, corners: &[Point; 4]) -> RgbImage {
+    let distance = |a: Point, b: Point| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+    let [top_left, top_right, bottom_right, bottom_left] = *corners;
+    let width = distance(top_left, top_right).max(distance(bottom_left, bottom_right));
+    let height = distance(top_left, bottom_left).max(distance(top_right, bottom_right));
+    let (width, height) = (
+        width.round().max(1.0) as u32,
+        height.round().max(1.0) as u32,
+    );
+    let rectangle = [
+        (0.0, 0.0),
+        (width as f64, 0.0),
+        (width as f64, height as f64),
+        (0.0, height as f64),
+    ];
+    let pairs: Vec<_> = rectangle
+        .into_iter()
+        .zip(corners.map(|(x, y)| (x as f64, y as f64)))
+        .collect();
+    let Some(homography) = warp::fit(&pairs) else {
+        return image.clone();
+    };
+
+    let mut page = RgbImage::new(width, height);
+    for (x, y, pixel) in page.enumerate_pixels_mut() {
+        let (u, v) = warp::apply(&homography, (x as f64 + 0.5, y as f64 + 0.5));
+        let sampled = warp::sample(image, u as f32 - 0.5, v as f32 - 0.5);
+        pixel.0 = sampled.map(|channel| channel.round() as u8);
+    }
+    page
+}
+
+/// Turns `page` black and white, comparing each pixel with the mean of its
+/// neighbourhood so shadows and uneven light don't swallow the text.
+pub fn threshold(page: &GrayImage) -> GrayImage {
+    let (width, height) = page.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    // Sums of every rectangle from the top left corner, one larger each
+    // way so the first row and column are zero.
+    let mut integral = vec![0u64; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let mut row = 0u64;
+        for x in 0..w {
+            row += page.get_pixel(x as u32, y as u32)[0] as u64;
+            integral[(y + 1) * (w + 1) + x + 1] = integral[y * (w + 1) + x + 1] + row;
+        }
+    }
+    let radius = (w.max(h) / 32).max(7);
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let (left, right) = (x.saturating_sub(radius), (x + radius + 1).min(w));
+        let (top, bottom) = (y.saturating_sub(radius), (y + radius + 1).min(h));
+        let sum = integral[bottom * (w + 1) + right] + integral[top * (w + 1) + left]
+            - integral[top * (w + 1) + right]
+            - integral[bottom * (w + 1) + left];
+        let mean = sum as f32 / ((right - left) * (bottom - top)) as f32;
+        let value = page.get_pixel(x as u32, y as u32)[0] as f32;
+        if value < mean * (1.0 - THRESHOLD_OFFSET) {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    })
+}
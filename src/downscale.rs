@@ -0,0 +1,86 @@
+/// How many frames the preview looks back over to judge whether it keeps up.
+const WINDOW: u32 = 30;
+/// Frames in a window that may arrive with another already waiting, or
+/// after some were dropped, before the preview drops to half resolution.
+const BEHIND: u32 = WINDOW / 3;
+/// Windows in a row without any backlog before full resolution comes back,
+/// so a preview on the edge doesn't flip back and forth.
+const CAUGHT_UP: u32 = 3;
+
+/// Halves what the preview draws while it falls behind the camera, and
+/// restores it once it keeps up again. Sinks are unaffected: they still get
+/// every frame at the camera's resolution.
+#[derive(Default)]
+pub struct Downscale {
+    half: bool,
+    /// Frames seen in this window, and how many of them found a backlog.
+    seen: u32,
+    behind: u32,
+    /// Windows in a row without a backlog while at half resolution.
+    calm: u32,
+    /// The halved frame, reused between draws.
+    buffer: Vec<u8>,
+}
+
+impl Downscale {
+    /// Counts a frame taken off the queue, `waiting` being how many more were
+    /// already behind it and `dropped` how many went missing before it.
+    pub fn received(&mut self, waiting: usize, dropped: u64, width: u32, height: u32) {
+        self.seen += 1;
+        if waiting > 0 || dropped > 0 {
+            self.behind += 1;
+        }
+        if self.seen < WINDOW {
+            return;
+        }
+        if !self.half && self.behind >= BEHIND {
+            self.half = true;
+            self.calm = 0;
+            println!(
+                "Preview is falling behind the camera; drawing at {}x{} until it catches up.",
+                width / 2,
+                height / 2
+            );
+        } else if self.half && self.behind == 0 {
+            self.calm += 1;
+            if self.calm >= CAUGHT_UP {
+                self.half = false;
+                println!("Preview caught up; drawing at {width}x{height} again.");
+            }
+        } else if self.half {
+            self.calm = 0;
+        }
+        self.seen = 0;
+        self.behind = 0;
+    }
+
+    pub fn is_half(&self) -> bool {
+        self.half
+    }
+
+    /// Averages each 2x2 block of the RGBA `pixels` into one, returning the
+    /// halved frame and its size.
+    pub fn halve(&mut self, pixels: &[u8], width: u32, height: u32) -> (&mut [u8], u32, u32) {
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        self.buffer
+            .resize((half_width * half_height * 4) as usize, 0);
+        let stride = width as usize * 4;
+        for y in 0..half_height as usize {
+            let top = (y * 2).min(height as usize - 1) * stride;
+            let bottom = (y * 2 + 1).min(height as usize - 1) * stride;
+            for x in 0..half_width as usize {
+                let left = (x * 2).min(width as usize - 1) * 4;
+                let right = (x * 2 + 1).min(width as usize - 1) * 4;
+                let out = (y * half_width as usize + x) * 4;
+                for channel in 0..4 {
+                    let sum = pixels[top + left + channel] as u32
+                        + pixels[top + right + channel] as u32
+                        + pixels[bottom + left + channel] as u32
+                        + pixels[bottom + right + channel] as u32;
+                    self.buffer[out + channel] = ((sum + 2) / 4) as u8;
+                }
+            }
+        }
+        (&mut self.buffer, half_width, half_height)
+    }
+}
@@ -0,0 +1,99 @@
+//! Keeping a recording's picture in step with its sound.
+//!
+//! `record --audio` times the picture by the camera's frame timestamps and
+//! the sound by counting the microphone's samples. The two clocks are never
+//! quite the same, so over an hour the sound can end up a second ahead of
+//! the picture or behind it. [`Drift`] measures the gap as frames arrive
+//! and, once it reaches a frame, has one repeated or dropped, so the
+//! picture follows the sound.
+
+use std::time::Duration;
+
+/// How long the sound's lead is averaged over, so the blocks the
+/// microphone delivers in don't show up as drift.
+const SMOOTHING: Duration = Duration::from_millis(250);
+
+/// How far into the recording the clocks are first compared. The lead
+/// the sound has then, from the microphone's latency, is where the drift
+/// is measured from.
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// The drift between the picture and the sound of one recording, and the
+/// frames repeated and dropped to make up for it.
+pub struct Drift {
+    interval: Duration,
+    /// The sound's lead on the picture, in seconds, averaged over
+    /// [`SMOOTHING`].
+    lead: Option<f64>,
+    /// The lead once the clocks had settled.
+    baseline: Option<f64>,
+    /// How many frames the picture has been moved by: later for each one
+    /// repeated, earlier for each one dropped.
+    shift: i64,
+    pub repeated: u64,
+    pub dropped: u64,
+    /// The drift at the last frame, in seconds, positive with the sound
+    /// ahead.
+    pub last: f64,
+    /// The furthest the drift got either way.
+    pub widest: f64,
+}
+
+impl Drift {
+    /// Drift for a picture of `fps` frames a second.
+    pub fn new(fps: f64) -> Self {
+        Drift {
+            interval: Duration::from_secs_f64(1.0 / fps.max(1.0)),
+            lead: None,
+            baseline: None,
+            shift: 0,
+            repeated: 0,
+            dropped: 0,
+            last: 0.0,
+            widest: 0.0,
+        }
+    }
+
+    /// Compares a frame captured at `pts` with the sound, which has got to
+    /// `sound`, and returns the times to write the frame at: its own,
+    /// moved by the frames repeated and dropped so far; none, to drop it;
+    /// or two, to repeat it.
+    pub fn correct(&mut self, pts: Duration, sound: Duration) -> Vec<Duration> {
+        let lead = sound.as_secs_f64() - pts.as_secs_f64();
+        let weight = (self.interval.as_secs_f64() / SMOOTHING.as_secs_f64()).min(1.0);
+        let lead = match self.lead {
+            Some(smoothed) => smoothed + (lead - smoothed) * weight,
+            None => lead,
+        };
+        self.lead = Some(lead);
+        if pts < SETTLE {
+            return vec![pts];
+        }
+        let drift = lead - *self.baseline.get_or_insert(lead);
+        self.last = drift;
+        if drift.abs() > self.widest.abs() {
+            self.widest = drift;
+        }
+
+        let moved = |shift: i64| match shift >= 0 {
+            true => Some(pts + self.interval * shift as u32),
+            false => pts.checked_sub(self.interval * shift.unsigned_abs() as u32),
+        };
+        let interval = self.interval.as_secs_f64();
+        let behind = drift - self.shift as f64 * interval;
+        if behind >= interval {
+            self.shift += 1;
+            self.repeated += 1;
+            [moved(self.shift - 1), moved(self.shift)]
+                .into_iter()
+                .flatten()
+                .collect()
+        } else if behind <= -interval {
+            self.shift -= 1;
+            self.dropped += 1;
+            Vec::new()
+        } else {
+            moved(self.shift).into_iter().collect()
+        }
+    }
+}
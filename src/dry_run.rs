@@ -0,0 +1,542 @@
+//! `--dry-run`: works out what a command would do, down to the camera
+//! format it would be given and the files it would write, and prints it
+//! without streaming from the camera or writing anything.
+
+use crate::audio::{self, AudioOptions};
+use crate::bus::PublishOptions;
+use crate::interpolate::{self, InterpolateOptions};
+use crate::low_light::LowLightOptions;
+use crate::mask::Mask;
+use crate::rate::{RateControl, RateOptions};
+use crate::sink::{SinkFormat, SinkOptions, Stage};
+use crate::stream::Stream;
+use crate::upscale::UpscaleOptions;
+use crate::{
+    analyze, benchmark, capture, config, controls, defects, device, manifest, pipe, record, script,
+    serve, service, shm, sidecar, tune, CommandsProper, IndexKind,
+};
+use color_eyre::Report;
+use nokhwa::utils::{CameraFormat, FrameFormat, RequestedFormatType};
+use std::fmt::Display;
+use std::path::Path;
+
+/// Prints what `cmd` would do. Fails where the command itself would fail
+/// before streaming: a camera, microphone or format that can't be had.
+pub fn plan(cmd: &CommandsProper) -> Result<(), Report> {
+    match cmd {
+        CommandsProper::ListDevices | CommandsProper::ListProperties { .. } => {
+            println!("Would only ask the cameras about themselves");
+        }
+        CommandsProper::Preview {
+            device,
+            preview,
+            window,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => {
+            let format = camera(device, RequestedFormatType::None)?;
+            microphone(&preview.audio)?;
+            let mut steps = mask_steps(&masks.masks);
+            steps.extend(low_light_step(&preview.low_light));
+            // Demosaicing is left out of `steps` as the sinks get the
+            // camera's own format with it on.
+            let mut shown = steps.clone();
+            if let Some(pattern) = preview.bayer.bayer {
+                shown.insert(0, format!("{pattern:?} demosaicing").to_lowercase());
+            }
+            filters(&shown);
+            let size = window.window_size.unwrap_or(format.resolution());
+            line("Window", format!("{size}, titled {:?}", window.title));
+            sink_lines(sinks, &steps);
+            if let Some(path) = &preview.snapshot_rendered {
+                writes(path, "on S");
+            }
+            if let Some(path) = &dump_bad_frames {
+                writes(path, "with every frame that fails to decode");
+            }
+            if let Some(path) = &away.away_card {
+                line("Away card", path.display());
+            }
+        }
+        CommandsProper::Snapshot {
+            device,
+            output,
+            clipboard,
+            save_raw,
+            ocr,
+            bayer,
+            masks,
+            timer,
+            stack,
+            burst,
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            let mut steps = mask_steps(&masks.masks);
+            if let Some(pattern) = bayer.bayer {
+                steps.insert(0, format!("{pattern:?} demosaicing").to_lowercase());
+            }
+            filters(&steps);
+            if let Some(delay) = timer.timer {
+                line("Timer", format!("{}s", delay.as_secs_f64()));
+            }
+            if let Some(frames) = stack.stack {
+                line(
+                    "Stacks",
+                    format!("{frames} frames by {:?}", stack.mode).to_lowercase(),
+                );
+            }
+            if let Some(frames) = burst.burst {
+                line("Burst", format!("{frames} frames"));
+            }
+            if let Some(path) = output {
+                writes(path, "");
+            }
+            if let Some(path) = save_raw {
+                writes(path, "as DNG");
+            }
+            if *clipboard {
+                line("Copies", "the frame to the clipboard");
+            }
+            if *ocr {
+                line("Prints", "the text in the frame");
+            }
+        }
+        CommandsProper::Booth {
+            device,
+            booth,
+            masks,
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            filters(&mask_steps(&masks.masks));
+            line("Shots", booth.shots);
+            writes(&booth.output, "");
+        }
+        CommandsProper::ScanDoc {
+            device,
+            scan,
+            masks,
+            ..
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            filters(&mask_steps(&masks.masks));
+            line("Writes", &scan.output);
+        }
+        CommandsProper::StopMotion {
+            device,
+            stop_motion,
+            masks,
+            ..
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            filters(&mask_steps(&masks.masks));
+            writes(&stop_motion.directory, "with a frame per take");
+        }
+        CommandsProper::Panorama {
+            device,
+            panorama,
+            masks,
+            ..
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            filters(&mask_steps(&masks.masks));
+            writes(&panorama.output, "");
+        }
+        CommandsProper::CompareFormats {
+            device, formats, ..
+        } => {
+            for format in formats {
+                camera(device, RequestedFormatType::Exact(*format))?;
+            }
+        }
+        CommandsProper::Selftest {
+            device,
+            formats,
+            timeout,
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            line(
+                "Checks",
+                format!(
+                    "the picture, that it follows Brightness and Contrast, and streaming in \
+                     {formats} formats, giving each {timeout:?} for a frame"
+                ),
+            );
+        }
+        CommandsProper::Serve {
+            device,
+            serve,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            let mut steps = mask_steps(&masks.masks);
+            steps.extend(low_light_step(&serve.low_light));
+            steps.extend(upscale_step(&serve.upscale));
+            steps.extend(interpolate_step(&serve.interpolate));
+            filters(&steps);
+            line("Encoder", format!("JPEG at quality {}", serve.quality));
+            let protocol = match serve.protocol() {
+                serve::Protocol::Mjpeg => "MJPEG over HTTP",
+                serve::Protocol::Ws => "WebSocket",
+                serve::Protocol::Grpc => "gRPC",
+            };
+            line("Serves", format!("{protocol} on {}", serve.bind));
+            if let Some(dir) = &serve.scripts {
+                let dir = match dir {
+                    Some(dir) => dir.clone(),
+                    None => config::dir()
+                        .ok_or_else(|| Report::msg("no config directory to find scripts in"))?
+                        .join("scripts"),
+                };
+                for script in script::scripts(&dir)? {
+                    line("Runs", script.display());
+                }
+            }
+            sink_lines(sinks, &steps);
+            publishers(&serve.publish);
+            if let Some(path) = &dump_bad_frames {
+                writes(path, "with every frame that fails to decode");
+            }
+            if let Some(path) = &away.away_card {
+                line("Away card", path.display());
+            }
+        }
+        CommandsProper::Record {
+            device,
+            record,
+            masks,
+        } => plan_record(device, record, &masks.masks)?,
+        CommandsProper::Decrypt { input, output, .. } => {
+            line("Reads", input.display());
+            writes(output, "decrypted");
+        }
+        CommandsProper::Verify {
+            recordings,
+            finalize,
+        } => {
+            for recording in recordings {
+                if *finalize && !manifest::path(recording).exists() {
+                    line("Finishes", recording.display());
+                }
+                line("Checks", recording.display());
+            }
+        }
+        CommandsProper::Benchmark { suite } => match suite {
+            benchmark::Suite::Decoders(options) => {
+                line(
+                    "Decodes",
+                    format!(
+                        "test frames at {} for {}s with each decoder",
+                        options
+                            .resolutions
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        options.time.as_secs_f64()
+                    ),
+                );
+                if let Some(path) = &options.recording {
+                    line("Reads", path.display());
+                }
+            }
+        },
+        CommandsProper::Daemon => {
+            line("Exposes", "the cameras over D-Bus");
+        }
+        CommandsProper::InstallService {
+            service,
+            config,
+            profile,
+        } => {
+            let (path, _) = service::unit(service, config.as_deref(), profile.as_deref())?;
+            writes(&path, "systemd unit");
+        }
+        CommandsProper::Controls {
+            action: controls::Action::Set { device, controls },
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            let list: Vec<_> = controls
+                .iter()
+                .map(|(control, value)| {
+                    let shown =
+                        sidecar::to_json(value).map_or(String::new(), |value| value.to_string());
+                    format!("{} = {shown}", tune::control_name(*control))
+                })
+                .collect();
+            line("Sets", list.join(", "));
+        }
+        CommandsProper::Controls {
+            action:
+                controls::Action::Replay {
+                    sidecar,
+                    device,
+                    auto,
+                    at_once,
+                },
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            line("Reads", sidecar.display());
+            let changes = if *auto {
+                "every control change it logs"
+            } else {
+                "the control changes it logs, but the camera's own"
+            };
+            let when = if *at_once {
+                "one after another"
+            } else {
+                "as far apart as they were"
+            };
+            line("Sets", format!("{changes}, {when}"));
+        }
+        CommandsProper::Analyze {
+            action:
+                analyze::Action::DeadPixels {
+                    device,
+                    frames,
+                    output,
+                    ..
+                },
+        } => {
+            camera(device, RequestedFormatType::None)?;
+            line(
+                "Averages",
+                format!("{frames} frames with the lens covered, then {frames} lit"),
+            );
+            if let Some(output) = output {
+                writes(output, "as a pixel map");
+            }
+        }
+        CommandsProper::Config { path, .. } => match path.clone().or_else(config::default_path) {
+            Some(path) => line("Checks", path.display()),
+            None => line("Checks", "no config, there's no config directory"),
+        },
+    }
+    Ok(())
+}
+
+fn plan_record(
+    device: &Option<IndexKind>,
+    options: &record::RecordOptions,
+    masks: &[Mask],
+) -> Result<(), Report> {
+    let recording_audio = options.audio.audio.is_some() && !options.no_audio;
+    microphone(&options.audio)?;
+    let mut tracks = Vec::new();
+    if options.no_video {
+        line("Camera", "none, --no-video");
+    } else {
+        let requested = match options.slowmo {
+            Some(_) => RequestedFormatType::Exact(record::fastest_format(&device::resolve(
+                device.as_ref(),
+            )?)?),
+            None => RequestedFormatType::None,
+        };
+        let format = camera(device, requested)?;
+        let mut steps = mask_steps(masks);
+        if options.event_stdin {
+            steps.push("events from standard input burnt in".to_string());
+        }
+        steps.extend(upscale_step(&options.upscale));
+        filters(&steps);
+        line("Encoder", encoder(&format, options, &steps));
+        let slowmo = options.slowmo.unwrap_or(1.0);
+        let timing = match options.cfr {
+            Some(fps) => format!(
+                "a constant {:.2} fps",
+                fps.unwrap_or(format.frame_rate() as f64 / slowmo)
+            ),
+            None => "each frame as it arrived".to_string(),
+        };
+        match options.slowmo {
+            Some(slowmo) => line("Timing", format!("{timing}, {slowmo}x slower")),
+            None => line("Timing", timing),
+        }
+        tracks.push("MJPEG video");
+    }
+    if recording_audio {
+        tracks.push("16-bit PCM sound");
+    }
+    if let Some(duration) = options.duration {
+        line("Stops", format!("after {}s", duration.as_secs_f64()));
+    }
+    let encrypted = match &options.encrypt {
+        Some(crate::encrypt::Encryption::Age(recipients)) => {
+            format!(", encrypted to the recipients in {}", recipients.display())
+        }
+        None => String::new(),
+    };
+    writes(
+        &options.output,
+        &format!("as Matroska with {}{encrypted}", tracks.join(" and ")),
+    );
+    if options.rate.two_pass {
+        let mut name = options.output.as_os_str().to_owned();
+        name.push(".pass1.mkv");
+        writes(Path::new(&name), "for the first pass, removed after");
+    }
+    writes(&manifest::path(&options.output), "");
+    if let Some(path) = &options.metadata_sidecar {
+        writes(path, "with a JSON line per frame");
+    }
+    publishers(&options.publish);
+    Ok(())
+}
+
+fn publishers(options: &PublishOptions) {
+    for publisher in &options.publishers {
+        line("Publishes", format!("events to {publisher}"));
+    }
+}
+
+/// How `record` would encode frames from a camera in `format`, given the
+/// filters in `steps`.
+fn encoder(format: &CameraFormat, options: &record::RecordOptions, steps: &[String]) -> String {
+    let rate = RateControl::new(&options.rate, options.quality);
+    if format.format() == FrameFormat::MJPEG && steps.is_empty() && !rate.is_active() {
+        return "none, the camera's MJPEG is passed through".to_string();
+    }
+    let RateOptions {
+        bitrate,
+        crf,
+        maxrate,
+        two_pass,
+        ..
+    } = options.rate;
+    let mut encoder = match (bitrate, crf) {
+        (Some(bitrate), _) => format!("MJPEG at {bitrate} bits/s"),
+        (None, Some(crf)) => format!("MJPEG at CRF {crf}"),
+        (None, None) => format!("MJPEG at quality {}", options.quality),
+    };
+    if let Some(maxrate) = maxrate {
+        encoder += &format!(", at most {maxrate} bits/s");
+    }
+    if two_pass {
+        encoder += ", in two passes";
+    }
+    encoder
+}
+
+/// Settles the format of the camera `device` asking for `requested`, as
+/// the command would, and says what it got, without starting the stream.
+fn camera(
+    device: &Option<IndexKind>,
+    requested: RequestedFormatType,
+) -> Result<CameraFormat, Report> {
+    let index = device::resolve(device.as_ref())?;
+    let (info, format) = capture::settle(index, requested)?;
+    line(
+        "Camera",
+        format!("{} ({})", info.human_name(), info.index()),
+    );
+    line("Format", format);
+    Ok(format)
+}
+
+fn microphone(options: &AudioOptions) -> Result<(), Report> {
+    if let Some(microphone) = audio::describe(options)? {
+        line("Microphone", microphone);
+    }
+    Ok(())
+}
+
+/// The sinks, which get the camera's own format, if asked for, only while
+/// none of the filters in `steps` change the frames.
+fn sink_lines(options: &SinkOptions, steps: &[String]) {
+    let format = match options.pixel_format {
+        SinkFormat::Native if steps.is_empty() => "the camera's own format".to_string(),
+        SinkFormat::Native => "decoded frames, as filters are on".to_string(),
+        SinkFormat::Convert(format) => format!("{format:?}").to_uppercase(),
+    };
+    // Streams other than the main one go out as the camera sends them.
+    let format = |stage| match options.stream(stage) {
+        Stream::Main => format.clone(),
+        stream => format!("the {stream} stream"),
+    };
+    if let Some(name) = &options.shm_sink {
+        writes(
+            &shm::path(name),
+            &format!("as a ring buffer of {}", format(Stage::Shm)),
+        );
+    }
+    if let Some(name) = &options.pipe_sink {
+        writes(
+            &pipe::path(name),
+            &format!("as a pipe of {}", format(Stage::Pipe)),
+        );
+    }
+    if let Some(path) = &options.metadata_sidecar {
+        writes(path, "with a JSON line per frame");
+    }
+    if let Some(stage) = options.block_on {
+        line(
+            "Blocks",
+            format!("on {stage}, which holds up the stages before it rather than drop frames"),
+        );
+    }
+}
+
+fn mask_steps(masks: &[Mask]) -> Vec<String> {
+    // The pixel map goes with the masks.
+    let map = defects::map().map(|map| {
+        format!(
+            "pixel map filling in {} pixels at {}x{}",
+            map.defects.len(),
+            map.width,
+            map.height
+        )
+    });
+    map.into_iter()
+        .chain(masks.iter().map(|mask| {
+            format!(
+                "{:?} mask at {},{} {}x{}",
+                mask.style, mask.x, mask.y, mask.width, mask.height
+            )
+            .to_lowercase()
+        }))
+        .collect()
+}
+
+fn low_light_step(options: &LowLightOptions) -> Option<String> {
+    options
+        .low_light
+        .then(|| format!("low-light gain up to {}x", options.max_gain))
+}
+
+fn upscale_step(options: &UpscaleOptions) -> Option<String> {
+    options
+        .upscale
+        .map(|upscale| format!("{}x {:?} upscale", upscale.factor, upscale.method).to_lowercase())
+}
+
+fn interpolate_step(options: &InterpolateOptions) -> Option<String> {
+    options.interpolate.map(|fps| {
+        let method = match options.interpolation {
+            interpolate::Method::Flow => "motion-following",
+            interpolate::Method::Blend => "blended",
+        };
+        format!("{method} interpolation to {fps} fps")
+    })
+}
+
+fn filters(steps: &[String]) {
+    match steps {
+        [] => line("Filters", "none"),
+        steps => line("Filters", steps.join(", ")),
+    }
+}
+
+fn writes(path: &Path, how: &str) {
+    match how {
+        "" => line("Writes", path.display()),
+        how => line("Writes", format!("{} {how}", path.display())),
+    }
+}
+
+fn line(label: &str, value: impl Display) {
+    println!("{label:>10}: {value}");
+}
@@ -0,0 +1,136 @@
+use color_eyre::Report;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How a recording is encrypted as it's written.
+#[derive(Clone)]
+pub enum Encryption {
+    /// To every age recipient (`age1…`) listed in this file, one per line.
+    Age(PathBuf),
+}
+
+impl FromStr for Encryption {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("age", path)) if !path.is_empty() => Ok(Encryption::Age(PathBuf::from(path))),
+            _ => Err(Report::msg(format!(
+                "expected age:RECIPIENTS_FILE, got: {s}"
+            ))),
+        }
+    }
+}
+
+impl Encryption {
+    /// Starts an encrypted stream into `out`.
+    pub fn wrap(&self, out: BufWriter<File>) -> Result<Output, Report> {
+        let Encryption::Age(path) = self;
+        let recipients = std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse::<age::x25519::Recipient>()
+                    .map_err(|why| Report::msg(format!("invalid recipient {line}: {why}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if recipients.is_empty() {
+            return Err(Report::msg(format!("no recipients in {}", path.display())));
+        }
+        let encryptor = age::Encryptor::with_recipients(
+            recipients
+                .iter()
+                .map(|recipient| recipient as &dyn age::Recipient),
+        )?;
+        Ok(Output::Age(encryptor.wrap_output(Synced(out))?))
+    }
+}
+
+/// How every age file begins.
+const MAGIC: &[u8] = b"age-encryption.org/";
+
+/// Whether `path` holds an age-encrypted recording.
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(MAGIC.len());
+    File::open(path)?
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut start)?;
+    Ok(start == MAGIC)
+}
+
+/// The file under an encrypted stream, synced to disk when flushed. The
+/// stream only passes on whole 64KiB chunks, so a recording that a crash
+/// or power cut ends keeps every one finished before its last flush.
+pub struct Synced(BufWriter<File>);
+
+impl Write for Synced {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.0.get_ref().sync_data()
+    }
+}
+
+/// Where a recording is written: straight to the file or through a cipher.
+pub enum Output {
+    Plain(BufWriter<File>),
+    Age(age::stream::StreamWriter<Synced>),
+}
+
+impl Output {
+    /// Flushes everything out, including the end of the encrypted stream,
+    /// without which it can't be decrypted.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut file) => file.flush(),
+            Output::Age(stream) => stream.finish()?.flush(),
+        }
+    }
+}
+
+/// Flushing a recording also syncs it to disk, so one that a crash or
+/// power cut ends keeps everything flushed before it: all of it when it's
+/// plain, up to the last whole chunk when it's encrypted.
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(file) => file.write(buf),
+            Output::Age(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(file) => {
+                file.flush()?;
+                file.get_ref().sync_data()
+            }
+            Output::Age(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Decrypts an age-encrypted recording with the identities in `identity`.
+/// One that never finished decrypts up to its last whole chunk, and then
+/// fails as truncated.
+pub fn decrypt(input: &Path, identity: &Path, output: &Path) -> Result<(), Report> {
+    let identities = age::IdentityFile::from_file(identity.display().to_string())?
+        .into_identities()
+        .map_err(|why| Report::msg(why.to_string()))?;
+    let decryptor = age::Decryptor::new_buffered(BufReader::new(File::open(input)?))
+        .map_err(|why| Report::msg(why.to_string()))?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|identity| identity.as_ref()))
+        .map_err(|why| Report::msg(why.to_string()))?;
+    let mut out = BufWriter::new(File::create(output)?);
+    io::copy(&mut reader, &mut out)?;
+    out.flush()?;
+    println!("Decrypted {} to {}", input.display(), output.display());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Lines piped into athletic, timestamped and burned into the frames so
+//! instruments and test scripts can annotate footage as it's recorded.
+
+use crate::font;
+use flume::Receiver;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::time::Duration;
+
+/// How long an event stays on the frame.
+const SHOWN_FOR: Duration = Duration::from_secs(5);
+/// How many of the latest events are shown at once.
+const SHOWN: usize = 4;
+
+/// Events read from standard input.
+pub struct Events {
+    lines: Receiver<String>,
+    /// Recent events and the PTS they arrived at, oldest first.
+    recent: VecDeque<(Duration, String)>,
+}
+
+impl Events {
+    /// Starts reading standard input, one event per line.
+    pub fn stdin() -> Self {
+        let (sender, receiver) = flume::unbounded();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                let line = line.trim_end().to_string();
+                if !line.is_empty() && sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Events {
+            lines: receiver,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Takes the events that arrived since the last call, stamping them
+    /// with `pts`, the frame they arrived during.
+    pub fn poll(&mut self, pts: Duration) -> Vec<String> {
+        let new: Vec<String> = self.lines.try_iter().collect();
+        self.recent
+            .extend(new.iter().map(|line| (pts, line.clone())));
+        while self.recent.len() > SHOWN
+            || self
+                .recent
+                .front()
+                .is_some_and(|(at, _)| pts.saturating_sub(*at) > SHOWN_FOR)
+        {
+            self.recent.pop_front();
+        }
+        new
+    }
+
+    /// Whether there's anything to burn into the frame.
+    pub fn showing(&self) -> bool {
+        !self.recent.is_empty()
+    }
+
+    /// Burns the recent events into the bottom of a frame of `width` by
+    /// `height` pixels of `channels` bytes, newest last, each on a dark
+    /// band so it reads over any picture.
+    pub fn burn(&self, pixels: &mut [u8], width: u32, height: u32, channels: usize) {
+        if pixels.len() < width as usize * height as usize * channels {
+            return;
+        }
+        let scale = (height / 240).max(1);
+        let line_height = (font::GLYPH_HEIGHT + 3) * scale;
+        let count = self.recent.len() as u32;
+        for (n, (pts, line)) in self.recent.iter().enumerate() {
+            let text = format!("{:>9.3}s {line}", pts.as_secs_f64());
+            let top = height.saturating_sub((count - n as u32) * line_height + scale);
+            let right = (font::width(&text, scale) + 3 * scale).min(width);
+            for y in top..(top + line_height).min(height) {
+                let row = y as usize * width as usize * channels;
+                for pixel in pixels[row..row + right as usize * channels].chunks_exact_mut(channels)
+                {
+                    for value in &mut pixel[..channels.min(3)] {
+                        *value /= 4;
+                    }
+                }
+            }
+            font::draw(
+                pixels,
+                (width, height),
+                channels,
+                (2 * scale, top + 2 * scale),
+                scale,
+                &text,
+                [255, 255, 0],
+            );
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! The camera's anti-flicker control, which matches the exposure to the
+//! mains frequency so lights running off it don't show as bands rolling
+//! through the frame.
+//!
+//! V4L2 has a standard control for it; nokhwa doesn't know it by name, so
+//! it's set by its id, and only on Linux.
+
+use color_eyre::Report;
+use nokhwa::utils::KnownCameraControl;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// V4L2_CID_POWER_LINE_FREQUENCY.
+pub const CONTROL: KnownCameraControl = KnownCameraControl::Other(0x0098_0918);
+
+/// Countries whose mains run at 60 Hz, by ISO 3166 code. Japan is split
+/// between the two and left to the camera.
+const SIXTY_HZ: &[&str] = &[
+    "AG", "AI", "AS", "AW", "BM", "BR", "BS", "BZ", "CA", "CO", "CR", "CU", "DO", "EC", "FM", "GT",
+    "GU", "HN", "HT", "KN", "KR", "KY", "LR", "MH", "MP", "MS", "MX", "NI", "PA", "PE", "PH", "PR",
+    "PW", "SA", "SR", "SV", "TC", "TT", "TW", "US", "VE", "VG", "VI",
+];
+const SPLIT: &[&str] = &["JP"];
+
+/// What the camera's anti-flicker is set to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AntiFlicker {
+    Off,
+    Hz50,
+    Hz60,
+    /// Left for the camera to work out from the picture.
+    Auto,
+}
+
+impl AntiFlicker {
+    /// The mains frequency of the country `region`, given as its two letter
+    /// code, or `None` for a code that isn't one.
+    pub fn of_region(region: &str) -> Option<AntiFlicker> {
+        let region = region.to_ascii_uppercase();
+        if region.len() != 2 || !region.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return None;
+        }
+        Some(if SPLIT.contains(&region.as_str()) {
+            AntiFlicker::Auto
+        } else if SIXTY_HZ.contains(&region.as_str()) {
+            AntiFlicker::Hz60
+        } else {
+            AntiFlicker::Hz50
+        })
+    }
+
+    /// The control's value for it, as V4L2 numbers the menu.
+    pub fn value(self) -> i64 {
+        match self {
+            AntiFlicker::Off => 0,
+            AntiFlicker::Hz50 => 1,
+            AntiFlicker::Hz60 => 2,
+            AntiFlicker::Auto => 3,
+        }
+    }
+}
+
+impl FromStr for AntiFlicker {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(AntiFlicker::Off),
+            "50hz" | "50" => Ok(AntiFlicker::Hz50),
+            "60hz" | "60" => Ok(AntiFlicker::Hz60),
+            "auto" => Ok(AntiFlicker::Auto),
+            _ => Err(Report::msg(format!(
+                "no anti-flicker setting {s}; there's 50hz, 60hz, auto and off"
+            ))),
+        }
+    }
+}
+
+impl Display for AntiFlicker {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AntiFlicker::Off => "off",
+            AntiFlicker::Hz50 => "50hz",
+            AntiFlicker::Hz60 => "60hz",
+            AntiFlicker::Auto => "auto",
+        })
+    }
+}
@@ -0,0 +1,227 @@
+use crate::capture::{self, Capture, ControlRequest, Origin};
+use crate::record::Recordings;
+use crate::serve::{Clients, Connected, Outgoing, CLIENT_QUEUE_DEPTH};
+use color_eyre::Report;
+use flume::Sender;
+use nokhwa::{
+    native_api_backend, query,
+    utils::{CameraControl, CameraFormat, CameraInfo, ControlValueSetter},
+};
+use proto::control_value::Value;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{transport::Server, Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("athletic.v1");
+}
+
+/// Answers gRPC calls about the camera being served.
+pub struct CameraService {
+    info: CameraInfo,
+    format: CameraFormat,
+    controls: Sender<ControlRequest>,
+    clients: Clients,
+    away: Arc<AtomicBool>,
+    recordings: Recordings,
+}
+
+impl CameraService {
+    pub fn new(
+        capture: &Capture,
+        clients: Clients,
+        away: Arc<AtomicBool>,
+        recordings: Recordings,
+    ) -> Self {
+        CameraService {
+            info: capture.info.clone(),
+            format: capture.format,
+            controls: capture.controls.clone(),
+            clients,
+            away,
+            recordings,
+        }
+    }
+
+    /// Sends a request to the capture thread and waits for its reply.
+    async fn ask<T>(
+        &self,
+        request: impl FnOnce(Sender<Result<T, Report>>) -> ControlRequest,
+    ) -> Result<T, Status> {
+        let (reply, answer) = flume::bounded(1);
+        self.controls
+            .send(request(reply))
+            .map_err(|_| Status::unavailable("the camera has stopped"))?;
+        answer
+            .recv_async()
+            .await
+            .map_err(|_| Status::unavailable("the camera has stopped"))?
+            .map_err(|why| Status::internal(why.to_string()))
+    }
+}
+
+/// Serves `service` on `bind` until the server fails.
+pub fn run(bind: SocketAddr, service: CameraService) -> Result<(), Report> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(
+        Server::builder()
+            .add_service(proto::camera_server::CameraServer::new(service))
+            .serve(bind),
+    )?;
+    Ok(())
+}
+
+fn device(info: &CameraInfo) -> proto::Device {
+    proto::Device {
+        index: info.index().to_string(),
+        name: info.human_name(),
+        description: info.description().to_string(),
+        misc: info.misc(),
+    }
+}
+
+fn control(control: &CameraControl) -> proto::Control {
+    let value = match control.value() {
+        ControlValueSetter::Integer(n) | ControlValueSetter::EnumValue(n) => {
+            Some(Value::Integer(n))
+        }
+        ControlValueSetter::Float(n) => Some(Value::Float(n)),
+        ControlValueSetter::Boolean(b) => Some(Value::Boolean(b)),
+        ControlValueSetter::String(s) => Some(Value::Text(s)),
+        _ => None,
+    };
+    proto::Control {
+        name: control.control().to_string(),
+        value: value.map(|value| proto::ControlValue { value: Some(value) }),
+        description: control.description().to_string(),
+        flags: control.flag().iter().map(ToString::to_string).collect(),
+    }
+}
+
+#[tonic::async_trait]
+impl proto::camera_server::Camera for CameraService {
+    async fn list_devices(
+        &self,
+        _request: Request<proto::ListDevicesRequest>,
+    ) -> Result<Response<proto::ListDevicesResponse>, Status> {
+        let backend = native_api_backend()
+            .ok_or_else(|| Status::unavailable("no camera backend on this platform"))?;
+        let devices = query(backend).map_err(|why| Status::internal(why.to_string()))?;
+        Ok(Response::new(proto::ListDevicesResponse {
+            devices: devices.iter().map(device).collect(),
+        }))
+    }
+
+    async fn get_properties(
+        &self,
+        _request: Request<proto::GetPropertiesRequest>,
+    ) -> Result<Response<proto::Properties>, Status> {
+        let controls = self.ask(ControlRequest::Describe).await?;
+        Ok(Response::new(proto::Properties {
+            device: Some(device(&self.info)),
+            format: Some(proto::Format {
+                width: self.format.width(),
+                height: self.format.height(),
+                frame_rate: self.format.frame_rate(),
+                frame_format: self.format.format().to_string(),
+            }),
+            controls: controls.iter().map(control).collect(),
+        }))
+    }
+
+    async fn set_control(
+        &self,
+        request: Request<proto::SetControlRequest>,
+    ) -> Result<Response<proto::Control>, Status> {
+        let request = request.into_inner();
+        let id = capture::known_control(&request.name).ok_or_else(|| {
+            Status::invalid_argument(format!("unknown control: {}", request.name))
+        })?;
+        let value = match request.value.and_then(|value| value.value) {
+            Some(Value::Integer(n)) => ControlValueSetter::Integer(n),
+            Some(Value::Float(n)) => ControlValueSetter::Float(n),
+            Some(Value::Boolean(b)) => ControlValueSetter::Boolean(b),
+            Some(Value::Text(s)) => ControlValueSetter::String(s),
+            None => return Err(Status::invalid_argument("missing control value")),
+        };
+        let updated = self
+            .ask(|reply| ControlRequest::Set(id, value, Origin::Remote, reply))
+            .await?;
+        Ok(Response::new(control(&updated)))
+    }
+
+    type StreamFramesStream = Pin<Box<dyn Stream<Item = Result<proto::Frame, Status>> + Send>>;
+
+    async fn stream_frames(
+        &self,
+        _request: Request<proto::StreamFramesRequest>,
+    ) -> Result<Response<Self::StreamFramesStream>, Status> {
+        let (sender, receiver) = flume::bounded(CLIENT_QUEUE_DEPTH);
+        self.clients.lock().unwrap().push(sender);
+        let connected = Connected::new();
+        let frames = receiver.into_stream().filter_map(move |outgoing| {
+            // The client counts as connected for as long as its stream lasts.
+            let _connected = &connected;
+            match outgoing {
+                Outgoing::Frame(frame) => Some(Ok(proto::Frame {
+                    jpeg: frame.jpeg.clone(),
+                    sequence: frame.sequence,
+                    pts_us: frame.pts.as_micros() as u64,
+                })),
+                Outgoing::Event(_) => None,
+            }
+        });
+        Ok(Response::new(Box::pin(frames)))
+    }
+
+    async fn start_recording(
+        &self,
+        request: Request<proto::StartRecordingRequest>,
+    ) -> Result<Response<proto::StartRecordingResponse>, Status> {
+        let path = request.into_inner().path;
+        if path.is_empty() {
+            return Err(Status::invalid_argument("missing path to record to"));
+        }
+        if let Some(recording) = self.recordings.active() {
+            return Err(Status::failed_precondition(format!(
+                "already recording to {}",
+                recording.display()
+            )));
+        }
+        self.recordings
+            .start(Path::new(&path))
+            .map_err(|why| Status::internal(why.to_string()))?;
+        Ok(Response::new(proto::StartRecordingResponse {}))
+    }
+
+    async fn stop_recording(
+        &self,
+        _request: Request<proto::StopRecordingRequest>,
+    ) -> Result<Response<proto::StopRecordingResponse>, Status> {
+        if self.recordings.active().is_none() {
+            return Err(Status::failed_precondition("not recording"));
+        }
+        // Finishing the file takes as long as writing its last second.
+        let recordings = self.recordings.clone();
+        let path = tokio::task::spawn_blocking(move || recordings.stop())
+            .await
+            .map_err(|why| Status::internal(why.to_string()))?
+            .map_err(|why| Status::internal(why.to_string()))?;
+        Ok(Response::new(proto::StopRecordingResponse {
+            path: path.display().to_string(),
+        }))
+    }
+
+    async fn set_away(
+        &self,
+        request: Request<proto::SetAwayRequest>,
+    ) -> Result<Response<proto::SetAwayResponse>, Status> {
+        let away = request.into_inner().away;
+        self.away.store(away, Ordering::Relaxed);
+        Ok(Response::new(proto::SetAwayResponse { away }))
+    }
+}
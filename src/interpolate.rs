@@ -0,0 +1,287 @@
+use crate::trace;
+use crate::warp::sample;
+use clap::Args;
+use color_eyre::Report;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Motion is estimated on frames shrunk by this much each way.
+const SCALE: u32 = 2;
+/// Side of the blocks motion is estimated for, in shrunk pixels.
+const BLOCK: u32 = 8;
+/// Furthest, in shrunk pixels either way, a block is searched for in the
+/// next frame.
+const SEARCH: i32 = 8;
+/// Search step; the match is refined around the best one afterwards.
+const COARSE: i32 = 2;
+/// Gaps longer than this, left by a stall or by frames nobody wanted,
+/// aren't filled in.
+const MAX_GAP: Duration = Duration::from_millis(250);
+
+/// Making up frames between the camera's.
+#[derive(Args, Clone, Default)]
+pub struct InterpolateOptions {
+    /// Make up frames between the camera's to reach this many a second.
+    /// Made-up frames are counted as synthetic in the stats, and each
+    /// camera frame goes out about a frame late.
+    #[arg(long, value_name = "FPS", value_parser = clap::value_parser!(u32).range(2..=240))]
+    pub interpolate: Option<u32>,
+    /// How frames are made up: `flow` follows the motion between frames,
+    /// `blend` crossfades them, which is cheaper but ghosts.
+    #[arg(long, default_value = "flow", requires = "interpolate")]
+    pub interpolation: Method,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Method {
+    #[default]
+    Flow,
+    Blend,
+}
+
+impl FromStr for Method {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flow" | "FLOW" | "motion" => Ok(Method::Flow),
+            "blend" | "BLEND" | "crossfade" => Ok(Method::Blend),
+            _ => Err(Report::msg(format!("unknown interpolation method: {s}"))),
+        }
+    }
+}
+
+/// The last camera frame, kept to make up the frames after it once the
+/// next one arrives.
+pub struct Interpolator {
+    interval: Duration,
+    method: Method,
+    last: Option<(Duration, RgbImage, GrayImage)>,
+}
+
+impl Interpolator {
+    pub fn new(options: &InterpolateOptions) -> Option<Self> {
+        options.interpolate.map(|fps| Interpolator {
+            interval: Duration::from_secs(1) / fps,
+            method: options.interpolation,
+            last: None,
+        })
+    }
+
+    /// Takes the camera frame `image` shown at `pts` and returns the frames
+    /// to make up between the last one and it, with when each falls. The
+    /// frame itself isn't among them.
+    pub fn push(&mut self, pts: Duration, image: &RgbImage) -> Vec<(Duration, RgbImage)> {
+        let _zone = trace::zone("interpolate");
+        let gray = shrink(image);
+        let last = self.last.replace((pts, image.clone(), gray));
+        let (Some((last_pts, last, last_gray)), Some((_, _, gray))) = (last, &self.last) else {
+            return Vec::new();
+        };
+        if pts <= last_pts || pts - last_pts > MAX_GAP || last.dimensions() != image.dimensions() {
+            return Vec::new();
+        }
+        let gap = pts - last_pts;
+        // Frames less than half an interval before the camera's own are
+        // left out, so a source close to the rate asked for gets none.
+        let times: Vec<_> = (1..)
+            .map(|n| self.interval * n)
+            .take_while(|&at| at + self.interval / 2 <= gap)
+            .collect();
+        if times.is_empty() {
+            return Vec::new();
+        }
+        let flow = match self.method {
+            Method::Flow => Some(Flow::estimate(&last_gray, gray)),
+            Method::Blend => None,
+        };
+        times
+            .into_iter()
+            .map(|at| {
+                let alpha = (at.as_secs_f64() / gap.as_secs_f64()) as f32;
+                let frame = match &flow {
+                    Some(flow) => flow.warp(&last, image, alpha),
+                    None => blend(&last, image, alpha),
+                };
+                (last_pts + at, frame)
+            })
+            .collect()
+    }
+}
+
+/// Motion from one frame to the next, one vector per block.
+struct Flow {
+    columns: u32,
+    rows: u32,
+    vectors: Vec<(f32, f32)>,
+}
+
+impl Flow {
+    /// Finds where each block of `from` moved to in `to`, by the smallest
+    /// sum of absolute differences, then takes the median of each vector's
+    /// neighbourhood so stray matches in flat areas don't tear the frame.
+    fn estimate(from: &GrayImage, to: &GrayImage) -> Self {
+        let (width, height) = from.dimensions();
+        let (columns, rows) = (width.div_ceil(BLOCK), height.div_ceil(BLOCK));
+        let vectors: Vec<(f32, f32)> = (0..columns * rows)
+            .into_par_iter()
+            .map(|block| {
+                let (column, row) = (block % columns, block / columns);
+                let (x, y) = ((column * BLOCK) as i32, (row * BLOCK) as i32);
+                let cost = |dx: i32, dy: i32| sad(from, to, x, y, dx, dy);
+                let mut best = ((0, 0), cost(0, 0));
+                for dy in (-SEARCH..=SEARCH).step_by(COARSE as usize) {
+                    for dx in (-SEARCH..=SEARCH).step_by(COARSE as usize) {
+                        let cost = cost(dx, dy);
+                        if cost < best.1 {
+                            best = ((dx, dy), cost);
+                        }
+                    }
+                }
+                let (cx, cy) = best.0;
+                for dy in cy - COARSE + 1..cy + COARSE {
+                    for dx in cx - COARSE + 1..cx + COARSE {
+                        let cost = cost(dx, dy);
+                        if cost < best.1 {
+                            best = ((dx, dy), cost);
+                        }
+                    }
+                }
+                let (dx, dy) = best.0;
+                ((dx * SCALE as i32) as f32, (dy * SCALE as i32) as f32)
+            })
+            .collect();
+
+        let at = |column: i64, row: i64| {
+            let column = column.clamp(0, columns as i64 - 1) as u32;
+            let row = row.clamp(0, rows as i64 - 1) as u32;
+            vectors[(row * columns + column) as usize]
+        };
+        let mut smoothed = Vec::with_capacity(vectors.len());
+        for row in 0..rows as i64 {
+            for column in 0..columns as i64 {
+                let mut xs = [0.0; 9];
+                let mut ys = [0.0; 9];
+                for (n, (dx, dy)) in (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .enumerate()
+                {
+                    (xs[n], ys[n]) = at(column + dx, row + dy);
+                }
+                xs.sort_by(f32::total_cmp);
+                ys.sort_by(f32::total_cmp);
+                smoothed.push((xs[4], ys[4]));
+            }
+        }
+        Flow {
+            columns,
+            rows,
+            vectors: smoothed,
+        }
+    }
+
+    /// The frame `alpha` of the way from `from` to `to`, each pulled along
+    /// the motion to meet there and then blended. The motion at each pixel
+    /// is interpolated between those of the blocks around it.
+    fn warp(&self, from: &RgbImage, to: &RgbImage, alpha: f32) -> RgbImage {
+        let (width, height) = from.dimensions();
+        let block = (BLOCK * SCALE) as f32;
+        // Where a pixel falls between block centres, as the block before
+        // it, the one after it, and how far along it is.
+        let between = |at: usize, blocks: u32| {
+            let at = ((at as f32 - block / 2.0) / block).clamp(0.0, (blocks - 1) as f32);
+            let before = at as usize;
+            (before, (before + 1).min(blocks as usize - 1), at.fract())
+        };
+        let columns = self.columns as usize;
+        let mut out = RgbImage::new(width, height);
+        out.par_chunks_mut(width as usize * 3)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let (top, bottom, fy) = between(y, self.rows);
+                let (top, bottom) = (
+                    &self.vectors[top * columns..][..columns],
+                    &self.vectors[bottom * columns..][..columns],
+                );
+                let vectors: Vec<_> = top
+                    .iter()
+                    .zip(bottom)
+                    .map(|(top, bottom)| mix(*top, *bottom, fy))
+                    .collect();
+                let y = y as f32;
+                for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                    let (left, right, fx) = between(x, self.columns);
+                    let (dx, dy) = mix(vectors[left], vectors[right], fx);
+                    let x = x as f32;
+                    let before = sample(from, x - dx * alpha, y - dy * alpha);
+                    let after = sample(to, x + dx * (1.0 - alpha), y + dy * (1.0 - alpha));
+                    for ((out, before), after) in pixel.iter_mut().zip(before).zip(after) {
+                        *out = (before + (after - before) * alpha + 0.5) as u8;
+                    }
+                }
+            });
+        out
+    }
+}
+
+fn mix(a: (f32, f32), b: (f32, f32), t: f32) -> (f32, f32) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+fn blend(from: &RgbImage, to: &RgbImage, alpha: f32) -> RgbImage {
+    let mut out = from.clone();
+    for (out, &to) in out.iter_mut().zip(to.iter()) {
+        *out = (*out as f32 + (to as f32 - *out as f32) * alpha).round() as u8;
+    }
+    out
+}
+
+/// `image`'s luma, shrunk by `SCALE` each way.
+fn shrink(image: &RgbImage) -> GrayImage {
+    let (width, height) = (
+        (image.width() / SCALE).max(1),
+        (image.height() / SCALE).max(1),
+    );
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut sum = 0;
+        for (px, py) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let px = (x * SCALE + px).min(image.width() - 1);
+            let py = (y * SCALE + py).min(image.height() - 1);
+            let [r, g, b] = image.get_pixel(px, py).0;
+            sum += r as u32 * 77 + g as u32 * 150 + b as u32 * 29;
+        }
+        image::Luma([(sum >> 10) as u8])
+    })
+}
+
+/// The sum of absolute differences between the block at `(x, y)` in `from`
+/// and the one `(dx, dy)` away in `to`, counting only pixels inside both
+/// and scaled up for blocks cut short by the edge.
+fn sad(from: &GrayImage, to: &GrayImage, x: i32, y: i32, dx: i32, dy: i32) -> u32 {
+    let (width, height) = (from.width() as i32, from.height() as i32);
+    // Only the part of the block that lands inside `to` is compared.
+    let (left, right) = (x.max(-dx), (x + BLOCK as i32).min(width).min(width - dx));
+    let (top, bottom) = (y.max(-dy), (y + BLOCK as i32).min(height).min(height - dy));
+    if left >= right || top >= bottom {
+        return u32::MAX;
+    }
+    let (from, to) = (from.as_raw(), to.as_raw());
+    let mut sum = 0u32;
+    for py in top..bottom {
+        let a = (py * width) as usize;
+        let b = ((py + dy) * width + dx) as usize;
+        let (a, b) = (
+            &from[a + left as usize..a + right as usize],
+            &to[b + left as usize..b + right as usize],
+        );
+        sum += a
+            .iter()
+            .zip(b)
+            .map(|(&a, &b)| a.abs_diff(b) as u32)
+            .sum::<u32>();
+    }
+    let count = ((right - left) * (bottom - top)) as u32;
+    sum * (BLOCK * BLOCK) / count
+}
@@ -0,0 +1,114 @@
+use image::Pixel;
+use nokhwa::{
+    pixel_format::FormatDecoder,
+    utils::{CameraFormat, FrameFormat, Resolution},
+    Buffer,
+};
+
+/// The most padding a row is assumed to carry. Backends pad rows to an
+/// alignment boundary, which is far less than this; anything longer is
+/// more likely a different format than padding.
+const MAX_ROW_PADDING: usize = 256;
+
+/// How the rows of a frame are laid out in its buffer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Layout {
+    /// Bytes of pixel data in each row.
+    pub row: usize,
+    /// Bytes from the start of one row to the start of the next.
+    pub stride: usize,
+    /// Rows in the buffer, counting every plane.
+    pub rows: usize,
+}
+
+impl Layout {
+    /// Works out the layout of `length` bytes of `format` at `resolution`,
+    /// or `None` for compressed formats and buffers that don't divide into
+    /// whole rows.
+    ///
+    /// Grayscale rows may hold 8- or 16-bit samples; the wider reading is
+    /// taken when the stride fits it.
+    pub fn of(format: FrameFormat, resolution: Resolution, length: usize) -> Option<Layout> {
+        let (width, height) = (resolution.width() as usize, resolution.height() as usize);
+        let (rows, candidates): (usize, &[usize]) = match format {
+            FrameFormat::MJPEG => return None,
+            FrameFormat::YUYV => (height, &[width.div_ceil(2) * 4]),
+            FrameFormat::NV12 => (height + height.div_ceil(2), &[width.div_ceil(2) * 2]),
+            FrameFormat::GRAY => (height, &[width * 2, width]),
+            FrameFormat::RAWRGB => (height, &[width * 3]),
+        };
+        if rows == 0 || !length.is_multiple_of(rows) {
+            return None;
+        }
+        let stride = length / rows;
+        let row = candidates
+            .iter()
+            .copied()
+            .find(|&row| row <= stride && stride - row < MAX_ROW_PADDING)?;
+        Some(Layout { row, stride, rows })
+    }
+
+    pub fn is_packed(&self) -> bool {
+        self.row == self.stride
+    }
+}
+
+/// How a frame is laid out once decoded, which unlike the layout it arrives
+/// in doesn't depend on the source format: every format decodes to one
+/// pixel for each of the frame's, packed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FrameLayout {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes each decoded pixel takes, 3 for RGB and 4 for RGBA.
+    pub channels: usize,
+}
+
+impl FrameLayout {
+    /// The layout of a frame of `resolution` decoded with `F`.
+    pub fn decoded<F: FormatDecoder>(resolution: Resolution) -> FrameLayout {
+        FrameLayout {
+            width: resolution.width(),
+            height: resolution.height(),
+            channels: F::Output::CHANNEL_COUNT as usize,
+        }
+    }
+
+    /// The layout of frames the camera sends in `format`, decoded with `F`.
+    pub fn of<F: FormatDecoder>(format: &CameraFormat) -> FrameLayout {
+        FrameLayout::decoded::<F>(format.resolution())
+    }
+
+    /// Bytes in each row.
+    pub fn row(&self) -> usize {
+        self.width as usize * self.channels
+    }
+
+    /// Bytes in the whole frame, which is what a buffer to decode into needs.
+    pub fn bytes(&self) -> usize {
+        self.row() * self.height as usize
+    }
+}
+
+/// Drops the padding at the end of each row, so the rest of the pipeline
+/// can take `width * bytes per pixel` as the row length.
+///
+/// Buffers that are already packed, compressed or of a layout that can't
+/// be worked out are returned as they are.
+pub fn pack(buffer: Buffer) -> Buffer {
+    let format = buffer.source_frame_format();
+    let resolution = buffer.resolution();
+    let data = buffer.buffer();
+    let Some(layout) = Layout::of(format, resolution, data.len()) else {
+        return buffer;
+    };
+    if layout.is_packed() {
+        return buffer;
+    }
+    let packed: Vec<u8> = data
+        .chunks_exact(layout.stride)
+        .flat_map(|row| &row[..layout.row])
+        .copied()
+        .collect();
+    Buffer::new(resolution, &packed, format)
+}
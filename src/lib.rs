@@ -0,0 +1,46 @@
+//! Camera capture and the frame filters for the athletic CLI, usable on
+//! their own from Rust and, with the `capi` feature, from C.
+
+pub mod bad_frames;
+pub mod bayer;
+pub mod bus;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capture;
+pub mod defects;
+pub mod depth;
+pub mod device;
+pub mod dng;
+pub mod document;
+pub mod drift;
+pub mod encrypt;
+pub mod flicker;
+pub mod layout;
+pub mod lock;
+pub mod low_light;
+pub mod mask;
+pub mod memory;
+pub mod mkv;
+pub mod mono;
+pub mod negotiate;
+pub mod pattern;
+#[cfg(target_os = "macos")]
+pub mod permission;
+pub mod pipe;
+pub mod pixel_format;
+pub mod privacy;
+pub mod ptz;
+pub mod quirks;
+pub mod rate;
+pub mod scopes;
+pub mod shm;
+pub mod sink_frame;
+pub mod spec;
+pub mod stitch;
+pub mod stream;
+pub mod thermal;
+pub mod trace;
+pub mod tune;
+pub mod upscale;
+pub mod warp;
+pub mod window;
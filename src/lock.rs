@@ -0,0 +1,77 @@
+//! Advisory locks on cameras, so a second athletic asking for a camera
+//! another is streaming from is told who has it, rather than getting
+//! whatever error the driver gives. Where both want the camera, `--shared`
+//! has the daemon open it once and relay its frames to each.
+//!
+//! The lock is a file per camera in athletic's runtime directory, held
+//! locked for as long as the camera streams; the operating system lets go
+//! of it when the process ends, however it ends.
+
+use color_eyre::Report;
+use nokhwa::utils::CameraIndex;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+
+/// A camera locked for this process until dropped.
+#[derive(Debug)]
+pub struct DeviceLock {
+    _file: File,
+}
+
+/// Where athletic keeps the files that live as long as a process does:
+/// under `$XDG_RUNTIME_DIR` where there is one, else the temporary
+/// directory.
+pub fn runtime_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("athletic")
+}
+
+/// A name for `index` that is safe in a file name.
+pub fn key(index: &CameraIndex) -> String {
+    match index {
+        CameraIndex::Index(index) => format!("camera-{index}"),
+        CameraIndex::String(name) => name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect(),
+    }
+}
+
+/// Locks `index` for this process, failing if another holds it.
+pub fn lock(index: &CameraIndex) -> Result<DeviceLock, Report> {
+    let dir = runtime_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.lock", key(index)));
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            // Windows won't let the file be read while it's locked.
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            let holder = match holder.trim() {
+                "" => "another athletic".to_string(),
+                pid => format!("athletic process {pid}"),
+            };
+            return Err(Report::msg(format!(
+                "camera {index} is in use by {holder}; to watch it from both, \
+                 run them with --shared"
+            )));
+        }
+        Err(TryLockError::Error(why)) => {
+            return Err(Report::msg(format!("can't lock {}: {why}", path.display())))
+        }
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(DeviceLock { _file: file })
+}
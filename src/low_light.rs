@@ -0,0 +1,128 @@
+use crate::trace;
+use clap::Args;
+
+/// Mean brightness, from 0 to 1, that the gain aims a frame at.
+const TARGET: f32 = 0.4;
+/// How far the gain moves towards its target each frame, so it settles
+/// over a second or so rather than pumping with every movement.
+const SMOOTHING: f32 = 0.05;
+/// Only every this many pixels along each axis are looked at to measure a
+/// frame's brightness.
+const METER_STEP: usize = 8;
+
+/// Brightening dark frames.
+#[derive(Args, Clone, Default)]
+pub struct LowLightOptions {
+    /// Brighten dark frames with digital gain, smoothing the shadows where
+    /// it would mostly amplify noise and rolling highlights off rather than
+    /// clipping them.
+    #[arg(long)]
+    pub low_light: bool,
+    /// The most --low-light may multiply brightness by.
+    #[arg(long, default_value_t = 4.0, requires = "low_light")]
+    pub max_gain: f32,
+}
+
+/// The gain applied to the last frame, and the tone curve it makes.
+pub struct LowLight {
+    max_gain: f32,
+    gain: f32,
+    curve: [u8; 256],
+    /// A 3×3 box blur of the frame being enhanced, reused between frames.
+    smooth: Vec<u8>,
+}
+
+impl LowLight {
+    pub fn new(options: &LowLightOptions) -> Option<Self> {
+        options.low_light.then(|| LowLight {
+            max_gain: options.max_gain.max(1.0),
+            gain: 1.0,
+            curve: curve(1.0),
+            smooth: Vec::new(),
+        })
+    }
+
+    /// Brightens `pixels`, RGB or RGBA as `channels` says,
+    /// by a gain that follows how dark the frames have been.
+    pub fn apply(&mut self, pixels: &mut [u8], width: u32, height: u32, channels: usize) {
+        let _zone = trace::zone("low light");
+        let (width, height) = (width as usize, height as usize);
+        if width < 3 || height < 3 || pixels.len() < width * height * channels {
+            return;
+        }
+        let mean = mean_luma(pixels, width, height, channels).max(1.0 / 255.0);
+        let wanted = (TARGET / mean).clamp(1.0, self.max_gain);
+        let gain = self.gain + (wanted - self.gain) * SMOOTHING;
+        if (gain - self.gain).abs() > 0.005 {
+            self.gain = gain;
+            self.curve = curve(gain);
+        }
+        if self.gain <= 1.01 {
+            return;
+        }
+
+        // Amplified noise shows most in the shadows, so those are blended
+        // towards their neighbourhood the more the frame is being pushed.
+        let colours = channels.min(3);
+        box_blur(pixels, width, height, channels, &mut self.smooth);
+        let strength = ((self.gain - 1.0) / 3.0).min(1.0);
+        for (pixel, smooth) in pixels
+            .chunks_exact_mut(channels)
+            .zip(self.smooth.chunks_exact(channels))
+        {
+            let darkness = 1.0 - luma(pixel) / 255.0;
+            let weight = strength * darkness * darkness;
+            for (value, &smooth) in pixel[..colours].iter_mut().zip(smooth) {
+                let blended = *value as f32 + (smooth as f32 - *value as f32) * weight;
+                *value = self.curve[blended.round() as usize];
+            }
+        }
+    }
+}
+
+/// `gain` applied with an extended Reinhard roll-off, so full white stays
+/// full white instead of everything above 1/gain clipping to it.
+fn curve(gain: f32) -> [u8; 256] {
+    let mut curve = [0; 256];
+    for (value, out) in curve.iter_mut().enumerate() {
+        let x = value as f32 / 255.0 * gain;
+        let y = x * (1.0 + x / (gain * gain)) / (1.0 + x);
+        *out = (y * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+    curve
+}
+
+fn luma(pixel: &[u8]) -> f32 {
+    0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+}
+
+fn mean_luma(pixels: &[u8], width: usize, height: usize, channels: usize) -> f32 {
+    let (mut sum, mut count) = (0.0, 0);
+    for y in (0..height).step_by(METER_STEP) {
+        for x in (0..width).step_by(METER_STEP) {
+            sum += luma(&pixels[(y * width + x) * channels..]);
+            count += 1;
+        }
+    }
+    sum / count as f32 / 255.0
+}
+
+/// Writes a 3×3 box blur of `pixels` into `out`, leaving the edges as they
+/// are.
+fn box_blur(pixels: &[u8], width: usize, height: usize, channels: usize, out: &mut Vec<u8>) {
+    out.clear();
+    out.extend_from_slice(&pixels[..width * height * channels]);
+    let stride = width * channels;
+    for y in 1..height - 1 {
+        for x in channels..stride - channels {
+            let at = y * stride + x;
+            let mut sum = 0u32;
+            for row in [at - stride, at, at + stride] {
+                sum += pixels[row - channels] as u32
+                    + pixels[row] as u32
+                    + pixels[row + channels] as u32;
+            }
+            out[at] = ((sum + 4) / 9) as u8;
+        }
+    }
+}
@@ -1,201 +1,1185 @@
+mod analyze;
+mod annotate;
+mod audio;
+mod away;
+mod batch;
+mod benchmark;
+mod booth;
+mod burst;
+mod clipboard;
+mod compare;
+mod config;
+mod controls;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod downscale;
+mod dry_run;
+mod events;
+mod font;
+mod grpc;
+mod interpolate;
+mod manifest;
+mod motion;
+mod mute;
+mod notify;
+mod ocr;
+mod panorama;
+mod pdf;
+mod preview;
+mod progress;
+mod record;
+#[cfg(target_os = "linux")]
+mod relay;
+mod repl;
+mod scan_doc;
+mod script;
+mod selftest;
+mod serve;
+mod service;
+mod shutdown;
+mod sidecar;
+mod sink;
+mod snapshot;
+mod stack;
+mod stall;
+mod stop_motion;
+mod summary;
+mod timer;
+
+use athletic::flicker::AntiFlicker;
+use athletic::pixel_format::ColorSpace;
+use athletic::spec::{
+    parse_camera_format, parse_duration, parse_resolution, parse_size, IndexKind, PropertyKind,
+};
+use athletic::{
+    bad_frames, bayer, bus, capture, defects, depth, device, dng, document, drift, encrypt,
+    flicker, layout, lock, low_light, mask, memory, mkv, mono, negotiate, pattern, pipe,
+    pixel_format, ptz, quirks, rate, scopes, shm, sink_frame, stitch, stream, thermal, trace, tune,
+    upscale, warp, window,
+};
 use clap::{Parser, Subcommand};
 use color_eyre::Report;
-use flume::Receiver;
-use ggez::graphics::ImageFormat;
-use ggez::{
-    event::{EventHandler},
-    graphics::{Canvas, Image},
-    Context, GameError,
-};
 use nokhwa::pixel_format::RgbFormat;
 use nokhwa::{
-    native_api_backend,
-    pixel_format::RgbAFormat,
     query,
     utils::{
-        frame_formats, yuyv422_predicted_size, CameraFormat, CameraIndex,
-        RequestedFormat, RequestedFormatType,
+        frame_formats, CameraFormat, CameraIndex, RequestedFormat, RequestedFormatType, Resolution,
     },
-    Buffer, Camera,
+    Camera,
 };
-use std::str::FromStr;
-use std::sync::Arc;
+use std::path::PathBuf;
 use std::time::Duration;
 
-struct CaptureState {
-    receiver: Arc<Receiver<Buffer>>,
-    buffer: Vec<u8>,
-    format: CameraFormat,
-}
-
-impl EventHandler<GameError> for CaptureState {
-    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
-        Ok(())
-    }
-
-    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        let buffer = self
-            .receiver
-            .recv()
-            .map_err(|why| GameError::RenderError(why.to_string()))?;
-        self.buffer
-            .resize(yuyv422_predicted_size(buffer.buffer().len(), true), 0);
-        buffer
-            .decode_image_to_buffer::<RgbAFormat>(&mut self.buffer)
-            .map_err(|why| GameError::RenderError(why.to_string()))?;
-        let image = Image::from_pixels(
-            ctx,
-            &self.buffer,
-            ImageFormat::Rgba8Uint,
-            self.format.width(),
-            self.format.height(),
-        );
-        let canvas = Canvas::from_image(ctx, image, None);
-        canvas.finish(ctx)
-    }
-}
-
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// Print what the command would do, from the camera format it gets to
+    /// the files it writes, without streaming or writing anything.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Read profiles from this file rather than athletic/config.toml in
+    /// the user's config directory.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Take the options not given on the command line from this profile
+    /// in the config.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Also write the summary printed when a command that captures ends
+    /// to this file, as JSON.
+    #[arg(long, global = true, value_name = "FILE")]
+    summary: Option<PathBuf>,
+    /// Write profiler zones around the pipeline's stages to this file, as
+    /// a Chrome trace, as well as to Tracy. Needs athletic built with the
+    /// profiling feature.
+    #[arg(long, global = true, value_name = "FILE")]
+    trace: Option<PathBuf>,
+    /// Use the first camera that opens, skipping any that are busy or
+    /// broken, rather than one given by index or name.
+    #[arg(long, global = true)]
+    first_available: bool,
+    /// Get the camera's frames from the daemon, which opens it once for
+    /// every command run with --shared, rather than opening it here. Needs
+    /// athletic daemon running, so Linux.
+    #[arg(long, global = true)]
+    shared: bool,
+    /// Try getting the camera going again when it sends no frame for this
+    /// long, as some wedge without saying; 0 for never.
+    #[arg(long, global = true, value_parser = parse_duration, default_value = "10s", value_name = "DURATION")]
+    stall_timeout: Duration,
+    /// Give up on a stalled camera after this many tries at getting it
+    /// going again, first restarting its stream and then opening it
+    /// again, and exit with code 75.
+    #[arg(long, global = true, default_value_t = 3, value_name = "TRIES")]
+    stall_attempts: u32,
+    /// Keep what frames waiting in queues and the replay buffer hold under
+    /// this, as 256M or 1G: queues that may drop frames drop new ones, and
+    /// the replay buffer gets shorter, rather than go over.
+    #[arg(long, global = true, value_parser = parse_size, value_name = "SIZE")]
+    max_memory: Option<u64>,
+    /// The matrix YUYV and NV12 frames were encoded with, for cameras that
+    /// don't follow the usual BT.709 for HD and BT.601 below.
+    #[arg(long, global = true, value_enum, default_value_t = ColorSpace::Auto, value_name = "MATRIX")]
+    color_space: ColorSpace,
+    /// Say how the camera's format was settled: what was asked for, what
+    /// the camera offered, which of it was picked and what the camera was
+    /// set to in the end.
+    #[arg(long, global = true)]
+    explain_format: bool,
+    /// Set the camera's anti-flicker to the mains frequency of the lights
+    /// around it, 50hz or 60hz, to stop bands rolling through the picture;
+    /// or to auto or off. Without it, the config's region decides. Needs
+    /// V4L2, so Linux.
+    #[arg(long, global = true, value_name = "SETTING")]
+    anti_flicker: Option<AntiFlicker>,
+    /// Set the camera's controls for a kind of shot: low-light, document,
+    /// greenscreen, or a tune from the config.
+    #[arg(long, global = true, value_name = "TUNE")]
+    tune: Option<String>,
+    /// Fill in the hot, dead and stuck pixels this map lists, as analyze
+    /// dead-pixels writes it, from those around them, in every frame.
+    #[arg(long, global = true, value_name = "FILE")]
+    pixel_map: Option<PathBuf>,
 }
 
-#[derive(Clone)]
-enum IndexKind {
-    String(String),
-    Index(u32),
-}
-
-impl FromStr for IndexKind {
-    type Err = Report;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.parse::<u32>() {
-            Ok(p) => Ok(IndexKind::Index(p)),
-            Err(_) => Ok(IndexKind::String(s.to_string())),
-        }
-    }
-}
-
-#[derive(Subcommand)]
+#[derive(Subcommand, Clone)]
 enum Commands {
     ListDevices,
+    /// List a camera's controls and the formats it can stream in.
     ListProperties {
+        #[arg(conflicts_with = "all_devices")]
         device: Option<IndexKind>,
+        /// What to list; both unless this, --controls or --formats says.
+        #[arg(ignore_case = true, conflicts_with_all = ["controls", "formats"])]
         kind: Option<PropertyKind>,
+        /// List the controls.
+        #[arg(long)]
+        controls: bool,
+        /// List the formats.
+        #[arg(long)]
+        formats: bool,
+        /// List the properties of every camera rather than one.
+        #[arg(long)]
+        all_devices: bool,
+    },
+    /// Show the camera feed in a window.
+    Preview {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        preview: preview::PreviewOptions,
+        #[command(flatten)]
+        window: window::WindowOptions,
+        #[command(flatten)]
+        sinks: sink::SinkOptions,
+        /// Save the payload of every frame that fails to decode into this directory.
+        #[arg(long, value_name = "DIR")]
+        dump_bad_frames: Option<PathBuf>,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+        #[command(flatten)]
+        away: away::AwayOptions,
+    },
+    /// Save a single frame from the camera.
+    Snapshot {
+        device: Option<IndexKind>,
+        /// Where to save the frame; defaults to snapshot.png unless --clipboard,
+        /// --save-raw or --ocr is given.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Copy the frame to the clipboard.
+        #[arg(long)]
+        clipboard: bool,
+        /// Also save the undemosaiced frame to this DNG file; needs --bayer.
+        /// Masks can't be applied to raw data, so this can't be combined with --mask.
+        #[arg(long, requires = "bayer", conflicts_with = "masks")]
+        save_raw: Option<PathBuf>,
+        /// Print the text in the frame, read with tesseract. Needs athletic
+        /// built with the ocr feature.
+        #[arg(long)]
+        ocr: bool,
+        #[command(flatten)]
+        bayer: bayer::BayerOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+        #[command(flatten)]
+        timer: timer::TimerOptions,
+        #[command(flatten)]
+        stack: stack::StackOptions,
+        #[command(flatten)]
+        burst: burst::BurstOptions,
+    },
+    /// Take a few shots after a countdown each and lay them out on a strip.
+    Booth {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        booth: booth::BoothOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+    },
+    /// Find a sheet of paper in view and save it flattened and cleaned up.
+    ScanDoc {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        scan: scan_doc::ScanOptions,
+        #[command(flatten)]
+        window: window::WindowOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+    },
+    /// Take frames for stop motion, with the last one shown over the camera.
+    StopMotion {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        stop_motion: stop_motion::StopMotionOptions,
+        #[command(flatten)]
+        window: window::WindowOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+    },
+    /// Sweep the camera across a view and stitch the frames into a panorama.
+    Panorama {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        panorama: panorama::PanoramaOptions,
+        #[command(flatten)]
+        window: window::WindowOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+    },
+    /// Alternate the camera between two formats and show them side by side.
+    CompareFormats {
+        device: Option<IndexKind>,
+        /// Format shown on the left, as WIDTHxHEIGHT@FPS:FORMAT.
+        #[arg(long, value_parser = parse_camera_format)]
+        left: CameraFormat,
+        /// Format shown on the right, as WIDTHxHEIGHT@FPS:FORMAT.
+        #[arg(long, value_parser = parse_camera_format)]
+        right: CameraFormat,
+        /// How long to capture in one format before switching to the other.
+        #[arg(long, value_parser = parse_duration, default_value = "2s")]
+        segment: Duration,
+        #[command(flatten)]
+        window: window::WindowOptions,
+    },
+    /// Check that a camera works, and say which checks passed.
+    ///
+    /// It has to open and stream in a few of its formats, its picture
+    /// mustn't be black or frozen, and the picture has to follow its
+    /// brightness and contrast. Exits with 1 if any check fails.
+    Selftest {
+        device: Option<IndexKind>,
+        /// How many of the camera's formats to stream in.
+        #[arg(long, default_value_t = 3, value_name = "COUNT")]
+        formats: usize,
+        /// How long to wait for a frame before failing the check.
+        #[arg(long, value_parser = parse_duration, default_value = "5s")]
+        timeout: Duration,
     },
+    /// Stream the camera to browsers or other programs over the network.
+    Serve {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        serve: serve::ServeOptions,
+        #[command(flatten)]
+        sinks: sink::SinkOptions,
+        /// Save the payload of every frame that fails to decode into this directory.
+        #[arg(long, value_name = "DIR")]
+        dump_bad_frames: Option<PathBuf>,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+        #[command(flatten)]
+        away: away::AwayOptions,
+    },
+    /// Record the camera to a file.
+    Record {
+        device: Option<IndexKind>,
+        #[command(flatten)]
+        record: record::RecordOptions,
+        #[command(flatten)]
+        masks: mask::MaskOptions,
+    },
+    /// Decrypt a recording made with record --encrypt.
+    Decrypt {
+        input: PathBuf,
+        /// File holding the age identity (AGE-SECRET-KEY-…) to decrypt with.
+        #[arg(short, long)]
+        identity: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check recordings against their manifests for truncation or corruption.
+    Verify {
+        /// Recordings, or their .manifest.json files.
+        #[arg(required = true)]
+        recordings: Vec<PathBuf>,
+        /// First finish the recordings that a crash or power cut ended,
+        /// which have no manifest: cut off the cluster that was being
+        /// written, and write the manifest. Encrypted recordings have to
+        /// be decrypted first.
+        #[arg(long)]
+        finalize: bool,
+    },
+    /// Run in the background, exposing the cameras over D-Bus.
+    Daemon,
+    /// Write a systemd unit that runs daemon, record or serve as a
+    /// service, restarted if it fails or its frames stop.
+    InstallService {
+        #[command(flatten)]
+        service: service::ServiceOptions,
+    },
+    /// Work with the config file.
+    Config {
+        #[command(subcommand)]
+        action: config::Action,
+    },
+    /// Work with a camera's controls.
+    Controls {
+        #[command(subcommand)]
+        action: controls::Action,
+    },
+    /// Measure what's wrong with a camera's picture.
+    Analyze {
+        #[command(subcommand)]
+        action: analyze::Action,
+    },
+    /// Measure how fast parts of the pipeline run on this machine.
+    Benchmark {
+        #[command(subcommand)]
+        suite: benchmark::Suite,
+    },
+    /// Run the commands in a script one after another, in one process.
+    ///
+    /// Between commands, a script can let variables, wait and echo. The
+    /// options given to run hold for every command. See docs/scripts.md.
+    Run {
+        script: PathBuf,
+        /// Variables for the script, overriding its own.
+        #[arg(value_name = "NAME=VALUE", value_parser = batch::parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Run commands one after another at a prompt, in one process.
+    ///
+    /// The options given to repl hold for every command. Commands with a
+    /// window run in another athletic, and stop closes the window.
+    Repl,
+}
+
+impl Commands {
+    /// The camera the command uses, if it uses one.
+    fn device_mut(&mut self) -> Option<&mut Option<IndexKind>> {
+        match self {
+            Commands::ListProperties {
+                all_devices: false,
+                device,
+                ..
+            }
+            | Commands::Preview { device, .. }
+            | Commands::Snapshot { device, .. }
+            | Commands::Booth { device, .. }
+            | Commands::ScanDoc { device, .. }
+            | Commands::StopMotion { device, .. }
+            | Commands::Panorama { device, .. }
+            | Commands::CompareFormats { device, .. }
+            | Commands::Selftest { device, .. }
+            | Commands::Serve { device, .. }
+            | Commands::Record { device, .. }
+            | Commands::Controls {
+                action:
+                    controls::Action::Set { device, .. } | controls::Action::Replay { device, .. },
+            }
+            | Commands::Analyze {
+                action: analyze::Action::DeadPixels { device, .. },
+            } => Some(device),
+            _ => None,
+        }
+    }
+
+    /// Whether the command shows a window, which keeps the main thread
+    /// until it closes.
+    fn opens_window(&self) -> bool {
+        matches!(
+            self,
+            Commands::Preview { .. }
+                | Commands::ScanDoc { .. }
+                | Commands::StopMotion { .. }
+                | Commands::Panorama { .. }
+                | Commands::CompareFormats { .. }
+        )
+    }
 }
 
 enum CommandsProper {
     ListDevices,
     ListProperties {
-        device: Option<IndexKind>,
+        /// None for every camera.
+        device: Option<Option<IndexKind>>,
         kind: PropertyKind,
     },
+    Preview {
+        device: Option<IndexKind>,
+        preview: preview::PreviewOptions,
+        window: window::WindowOptions,
+        sinks: sink::SinkOptions,
+        dump_bad_frames: Option<PathBuf>,
+        masks: mask::MaskOptions,
+        away: away::AwayOptions,
+    },
+    Snapshot {
+        device: Option<IndexKind>,
+        output: Option<PathBuf>,
+        clipboard: bool,
+        save_raw: Option<PathBuf>,
+        ocr: bool,
+        bayer: bayer::BayerOptions,
+        masks: mask::MaskOptions,
+        timer: timer::TimerOptions,
+        stack: stack::StackOptions,
+        burst: burst::BurstOptions,
+    },
+    Booth {
+        device: Option<IndexKind>,
+        booth: booth::BoothOptions,
+        masks: mask::MaskOptions,
+    },
+    ScanDoc {
+        device: Option<IndexKind>,
+        scan: scan_doc::ScanOptions,
+        window: window::WindowOptions,
+        masks: mask::MaskOptions,
+    },
+    StopMotion {
+        device: Option<IndexKind>,
+        stop_motion: stop_motion::StopMotionOptions,
+        window: window::WindowOptions,
+        masks: mask::MaskOptions,
+    },
+    Panorama {
+        device: Option<IndexKind>,
+        panorama: panorama::PanoramaOptions,
+        window: window::WindowOptions,
+        masks: mask::MaskOptions,
+    },
+    CompareFormats {
+        device: Option<IndexKind>,
+        formats: [CameraFormat; 2],
+        segment: Duration,
+        window: window::WindowOptions,
+    },
+    Selftest {
+        device: Option<IndexKind>,
+        formats: usize,
+        timeout: Duration,
+    },
+    Serve {
+        device: Option<IndexKind>,
+        serve: serve::ServeOptions,
+        sinks: sink::SinkOptions,
+        dump_bad_frames: Option<PathBuf>,
+        masks: mask::MaskOptions,
+        away: away::AwayOptions,
+    },
+    Record {
+        device: Option<IndexKind>,
+        record: record::RecordOptions,
+        masks: mask::MaskOptions,
+    },
+    Decrypt {
+        input: PathBuf,
+        identity: PathBuf,
+        output: PathBuf,
+    },
+    Verify {
+        recordings: Vec<PathBuf>,
+        finalize: bool,
+    },
+    Daemon,
+    InstallService {
+        service: service::ServiceOptions,
+        config: Option<PathBuf>,
+        profile: Option<String>,
+    },
+    Config {
+        action: config::Action,
+        path: Option<PathBuf>,
+    },
+    Controls {
+        action: controls::Action,
+    },
+    Analyze {
+        action: analyze::Action,
+    },
+    Benchmark {
+        suite: benchmark::Suite,
+    },
 }
 
-#[derive(Copy, Clone)]
-enum PropertyKind {
-    All,
-    Controls,
-    CompatibleFormats,
+fn main() {
+    // The command runs on the main thread, which windows need. Camera
+    // access is asked for as the first camera is opened, in
+    // capture::new_camera, keeping nokhwa_initialize's ask out of the
+    // commands that don't use one.
+    let code = nokhwa_main();
+    trace::finish();
+    if stall::gave_up() {
+        std::process::exit(stall::EXIT);
+    }
+    std::process::exit(code);
 }
 
-impl FromStr for PropertyKind {
-    type Err = Report;
+/// Runs the command athletic was given, returning the code to exit with.
+fn nokhwa_main() -> i32 {
+    let cli = Cli::parse();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "All" | "ALL" | "all" => Ok(PropertyKind::All),
-            "Controls" | "controls" | "CONTROLS" | "ctrls" => Ok(PropertyKind::Controls),
-            "CompatibleFormats" | "compatibleformats" | "COMPATIBLEFORMATS" | "cf"
-            | "compatfmts" => Ok(PropertyKind::CompatibleFormats),
-            _ => Err(Report::msg(format!("unknown PropertyKind: {s}"))),
+    let Some(mut cmd) = cli.command.clone() else {
+        println!("Unknown command \"\". Do --help for info.");
+        return 0;
+    };
+    if let Err(why) = configure(&cli, &mut cmd) {
+        eprintln!("{why}");
+        return 1;
+    }
+    match cmd {
+        Commands::Repl => {
+            repl::run();
+            0
         }
+        Commands::Run { script, vars } => batch::run(&cli, &script, &vars),
+        cmd => repl::attempt(&cli, cmd),
     }
 }
 
-fn main() {
-    nokhwa::nokhwa_initialize(|x| {
-        if x {
-            nokhwa_main()
-        } else {
-            eprintln!("failed to initialize camera library");
-            std::process::exit(84);
-        }
-    });
-    std::thread::sleep(Duration::from_millis(2000));
-}
+/// Sets up what holds for every command the process runs, from the
+/// options `cli` gives for `cmd`.
+fn configure(cli: &Cli, cmd: &mut Commands) -> Result<(), Report> {
+    // The REPL's commands are what take the camera; they're given it as
+    // they come.
+    let uses_camera =
+        cmd.device_mut().is_some() || matches!(cmd, Commands::Repl | Commands::Run { .. });
+    if cli.shared {
+        match uses_camera {
+            #[cfg(target_os = "linux")]
+            true => capture::relay_through(relay::open)?,
+            #[cfg(not(target_os = "linux"))]
+            true => {
+                return Err(Report::msg(
+                    "--shared needs the daemon, which is only available on Linux",
+                ))
+            }
+            false => {
+                return Err(Report::msg(
+                    "--shared is for the commands that use a camera",
+                ))
+            }
+        }
+    }
+    if let Some(bytes) = cli.max_memory {
+        memory::limit(bytes);
+    }
+    if cli.explain_format {
+        capture::explain_formats();
+    }
+    if let Some(path) = &cli.pixel_map {
+        defects::PixelMap::load(path).and_then(defects::correct_with)?;
+    }
+    if cli.color_space != ColorSpace::Auto {
+        pixel_format::assume_color_space(cli.color_space)?;
+    }
+    if !cli.stall_timeout.is_zero() {
+        capture::watch_stalls(capture::StallPolicy {
+            timeout: cli.stall_timeout,
+            attempts: cli.stall_attempts,
+            report: stall::report,
+        })?;
+    }
+    let mut tune = cli.tune.clone();
+    if let Some(name) = &cli.profile {
+        let (_, profile) = config::profile(cli.config.as_deref(), name)?;
+        tune = tune.or_else(|| profile.tune.as_ref().map(|tune| tune.get_ref().clone()));
+    }
 
-fn nokhwa_main() {
-    let cli = Cli::parse();
+    if uses_camera || matches!(cmd, Commands::Daemon) {
+        let (_, config) = config::load(cli.config.as_deref())?;
+        capture::allow_for(config.quirks())?;
+        #[cfg(target_os = "linux")]
+        if let Some(setting) = cli.anti_flicker.or_else(|| config.anti_flicker()) {
+            capture::prevent_flicker(setting)?;
+        }
+        if let Some(name) = &tune {
+            capture::tune(config.tune(name)?)?;
+        }
+        // Only V4L2 has the control; elsewhere a region just goes unused.
+        #[cfg(not(target_os = "linux"))]
+        if cli.anti_flicker.is_some() {
+            return Err(Report::msg(
+                "--anti-flicker needs V4L2, which is only available on Linux",
+            ));
+        }
+    } else if cli.anti_flicker.is_some() || cli.tune.is_some() {
+        return Err(Report::msg(
+            "--anti-flicker and --tune are for the commands that use a camera",
+        ));
+    }
+    if !cli.dry_run {
+        if let Some(path) = &cli.trace {
+            trace::start(path)?;
+        }
+        trace::listen();
+        trace::name_thread("main");
+    }
+    Ok(())
+}
 
-    let cmd = match &cli.command {
-        Some(cmd) => cmd,
-        None => {
-            println!("Unknown command \"\". Do --help for info.");
-            return;
+/// Runs `cmd`, with the options `cli` gives for it, returning the code
+/// to exit with: 0, or 1 when what it checked turned out wrong, as when
+/// verify finds a recording that doesn't match its manifest. A command
+/// that fails to run returns why.
+fn execute(cli: &Cli, mut cmd: Commands) -> Result<i32, Report> {
+    let cmd = &mut cmd;
+    if cli.first_available {
+        match cmd.device_mut() {
+            Some(device @ None) => {
+                *device = Some(match device::first_available()? {
+                    CameraIndex::Index(index) => IndexKind::Index(index),
+                    CameraIndex::String(name) => IndexKind::String(name),
+                });
+            }
+            Some(Some(_)) => {
+                return Err(Report::msg(
+                    "--first-available picks the camera, so don't give one",
+                ))
+            }
+            None => {
+                return Err(Report::msg(
+                    "--first-available is for the commands that use a camera",
+                ))
+            }
         }
-    };
+    }
+    let mut reload = None;
+    if let Some(name) = &cli.profile {
+        let (path, profile) = config::profile(cli.config.as_deref(), name)?;
+        let base = cmd.clone();
+        profile.apply(cmd);
+        reload = Some(config::Reload::new(path, name.clone(), base, profile));
+    }
+    if let Some(Some(device @ IndexKind::String(_))) = cmd.device_mut() {
+        let (_, config) = config::load(cli.config.as_deref())?;
+        *device = config.device(device.clone());
+    }
 
     let cmd = match cmd {
         Commands::ListDevices => CommandsProper::ListDevices,
-        Commands::ListProperties { device, kind } => CommandsProper::ListProperties {
+        Commands::ListProperties {
+            device,
+            kind,
+            controls,
+            formats,
+            all_devices,
+        } => CommandsProper::ListProperties {
+            device: (!*all_devices).then(|| device.clone()),
+            kind: match (kind, controls, formats) {
+                (Some(kind), _, _) => *kind,
+                (None, true, false) => PropertyKind::Controls,
+                (None, false, true) => PropertyKind::CompatibleFormats,
+                (None, _, _) => PropertyKind::All,
+            },
+        },
+        Commands::Preview {
+            device,
+            preview,
+            window,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => CommandsProper::Preview {
             device: device.clone(),
-            kind: match kind {
-                Some(k) => *k,
-                None => {
-                    println!("Expected Positional Argument \"All\", \"Controls\", or \"CompatibleFormats\"");
-                    return;
-                }
+            preview: preview.clone(),
+            window: window.clone(),
+            sinks: sinks.clone(),
+            dump_bad_frames: dump_bad_frames.clone(),
+            masks: masks.clone(),
+            away: away.clone(),
+        },
+        Commands::Snapshot {
+            device,
+            output,
+            clipboard,
+            save_raw,
+            ocr,
+            bayer,
+            masks,
+            timer,
+            stack,
+            burst,
+        } => CommandsProper::Snapshot {
+            device: device.clone(),
+            output: match (output, *clipboard || save_raw.is_some() || *ocr) {
+                (Some(path), _) => Some(path.clone()),
+                (None, true) => None,
+                (None, false) => Some(PathBuf::from("snapshot.png")),
             },
+            clipboard: *clipboard,
+            save_raw: save_raw.clone(),
+            ocr: *ocr,
+            bayer: bayer.clone(),
+            masks: masks.clone(),
+            timer: timer.clone(),
+            stack: stack.clone(),
+            burst: burst.clone(),
+        },
+        Commands::Booth {
+            device,
+            booth,
+            masks,
+        } => CommandsProper::Booth {
+            device: device.clone(),
+            booth: booth.clone(),
+            masks: masks.clone(),
+        },
+        Commands::ScanDoc {
+            device,
+            scan,
+            window,
+            masks,
+        } => CommandsProper::ScanDoc {
+            device: device.clone(),
+            scan: scan.clone(),
+            window: window.clone(),
+            masks: masks.clone(),
+        },
+        Commands::StopMotion {
+            device,
+            stop_motion,
+            window,
+            masks,
+        } => CommandsProper::StopMotion {
+            device: device.clone(),
+            stop_motion: stop_motion.clone(),
+            window: window.clone(),
+            masks: masks.clone(),
+        },
+        Commands::Panorama {
+            device,
+            panorama,
+            window,
+            masks,
+        } => CommandsProper::Panorama {
+            device: device.clone(),
+            panorama: panorama.clone(),
+            window: window.clone(),
+            masks: masks.clone(),
+        },
+        Commands::CompareFormats {
+            device,
+            left,
+            right,
+            segment,
+            window,
+        } => CommandsProper::CompareFormats {
+            device: device.clone(),
+            formats: [*left, *right],
+            segment: *segment,
+            window: window.clone(),
+        },
+        Commands::Selftest {
+            device,
+            formats,
+            timeout,
+        } => CommandsProper::Selftest {
+            device: device.clone(),
+            formats: *formats,
+            timeout: *timeout,
+        },
+        Commands::Serve {
+            device,
+            serve,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => CommandsProper::Serve {
+            device: device.clone(),
+            serve: serve.clone(),
+            sinks: sinks.clone(),
+            dump_bad_frames: dump_bad_frames.clone(),
+            masks: masks.clone(),
+            away: away.clone(),
+        },
+        Commands::Record {
+            device,
+            record,
+            masks,
+        } => CommandsProper::Record {
+            device: device.clone(),
+            record: record.clone(),
+            masks: masks.clone(),
+        },
+        Commands::Decrypt {
+            input,
+            identity,
+            output,
+        } => CommandsProper::Decrypt {
+            input: input.clone(),
+            identity: identity.clone(),
+            output: output.clone(),
+        },
+        Commands::Verify {
+            recordings,
+            finalize,
+        } => CommandsProper::Verify {
+            recordings: recordings.clone(),
+            finalize: *finalize,
+        },
+        Commands::Daemon => CommandsProper::Daemon,
+        Commands::InstallService { service } => CommandsProper::InstallService {
+            service: service.clone(),
+            config: cli.config.clone(),
+            profile: cli.profile.clone(),
+        },
+        Commands::Config { action } => CommandsProper::Config {
+            action: *action,
+            path: cli.config.clone(),
         },
+        Commands::Controls { action } => CommandsProper::Controls {
+            action: action.clone(),
+        },
+        Commands::Analyze { action } => CommandsProper::Analyze {
+            action: action.clone(),
+        },
+        Commands::Benchmark { suite } => CommandsProper::Benchmark {
+            suite: suite.clone(),
+        },
+        Commands::Repl | Commands::Run { .. } => {
+            return Err(Report::msg(
+                "repl and run can't run in turn with other commands",
+            ))
+        }
     };
 
+    if cli.dry_run {
+        dry_run::plan(&cmd)?;
+        return Ok(0);
+    }
+
+    let captures = matches!(
+        cmd,
+        CommandsProper::Preview { .. }
+            | CommandsProper::Snapshot { .. }
+            | CommandsProper::Booth { .. }
+            | CommandsProper::ScanDoc { .. }
+            | CommandsProper::StopMotion { .. }
+            | CommandsProper::Panorama { .. }
+            | CommandsProper::CompareFormats { .. }
+            | CommandsProper::Serve { .. }
+            | CommandsProper::Record { .. }
+    );
+    let summary = captures.then(|| summary::begin(cli.summary.clone()));
     match cmd {
         CommandsProper::ListDevices => {
-            let backend = native_api_backend().unwrap();
-            let devices = query(backend).unwrap();
+            let devices = query(device::backend()?)?;
             println!("There are {} available cameras.", devices.len());
             for device in devices {
                 println!("{device}");
             }
         }
-        CommandsProper::ListProperties { device, kind } => {
-            let index = match device.as_ref().unwrap_or(&IndexKind::Index(0)) {
-                IndexKind::String(s) => CameraIndex::String(s.clone()),
-                IndexKind::Index(i) => CameraIndex::Index(*i),
-            };
-            let mut camera = Camera::new(
-                index,
+        CommandsProper::ListProperties {
+            device: Some(device),
+            kind,
+        } => {
+            let mut camera = capture::new_camera(
+                camera_index(&device)?,
                 RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
-            )
-            .unwrap();
-            match kind {
-                PropertyKind::All => {
-                    camera_print_controls(&camera);
-                    camera_compatible_formats(&mut camera);
-                }
-                PropertyKind::Controls => {
-                    camera_print_controls(&camera);
+            )?;
+            camera_print_properties(&mut camera, kind)?;
+        }
+        CommandsProper::ListProperties { device: None, kind } => {
+            let mut failed = false;
+            for device in query(device::backend()?)? {
+                println!("{device}");
+                let printed = capture::new_camera(
+                    device.index().clone(),
+                    RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+                )
+                .and_then(|mut camera| camera_print_properties(&mut camera, kind));
+                match printed {
+                    Ok(()) => {}
+                    Err(why) => {
+                        eprintln!("can't open camera {}: {why}", device.index());
+                        failed = true;
+                    }
                 }
-                PropertyKind::CompatibleFormats => {
-                    camera_compatible_formats(&mut camera);
+                println!();
+            }
+            if failed {
+                return Ok(1);
+            }
+        }
+        CommandsProper::Preview {
+            device,
+            preview,
+            window,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => {
+            // Only the newest frame is worth drawing, so the preview is
+            // the first to drop frames.
+            let capture = capture::spawn_capture_queued(
+                camera_index(&device)?,
+                RequestedFormatType::None,
+                sinks.queue(sink::Stage::Capture, 1),
+            )?;
+            summary::watch(&capture);
+            let sinks = sink::Sinks::open(&sinks, &capture)?;
+            let title = window::title(&window.title, &capture.info, &capture.format);
+            let (ctx, event_loop) = window::build(&title, capture.format.resolution(), &window)?;
+            let state = preview::CaptureState::new(
+                capture,
+                preview,
+                window.title,
+                sinks,
+                bad_frames::BadFrames::new(dump_bad_frames),
+                masks.masks,
+                away::Away::open(&away)?,
+            )
+            .with_reload(reload)
+            .with_present(window.present);
+            ggez::event::run(ctx, event_loop, summary::Summarized::new(state, summary))
+        }
+        CommandsProper::Snapshot {
+            device,
+            output,
+            clipboard,
+            save_raw,
+            ocr,
+            bayer,
+            masks,
+            timer,
+            stack,
+            burst,
+        } => {
+            snapshot::snapshot(
+                camera_index(&device)?,
+                &snapshot::Outputs {
+                    image: output.as_deref(),
+                    clipboard,
+                    raw: save_raw.as_deref(),
+                    text: ocr,
+                },
+                &bayer,
+                &masks.masks,
+                &timer,
+                &stack,
+                &burst,
+            )?;
+        }
+        CommandsProper::Booth {
+            device,
+            booth,
+            masks,
+        } => {
+            booth::booth(camera_index(&device)?, &booth, &masks.masks)?;
+        }
+        CommandsProper::ScanDoc {
+            device,
+            scan,
+            window,
+            masks,
+        } => {
+            let capture = capture::spawn_capture(camera_index(&device)?)?;
+            summary::watch(&capture);
+            let (ctx, event_loop) = window::build(
+                "athletic — scan document",
+                capture.format.resolution(),
+                &window,
+            )?;
+            if scan.ocr {
+                ocr::available()?;
+            }
+            let state = scan_doc::ScanState::new(capture, scan, masks.masks);
+            ggez::event::run(ctx, event_loop, summary::Summarized::new(state, summary))
+        }
+        CommandsProper::StopMotion {
+            device,
+            stop_motion,
+            window,
+            masks,
+        } => {
+            let capture = capture::spawn_capture(camera_index(&device)?)?;
+            summary::watch(&capture);
+            let (mut ctx, event_loop) = window::build(
+                "athletic — stop motion",
+                capture.format.resolution(),
+                &window,
+            )?;
+            let state =
+                stop_motion::StopMotionState::new(&mut ctx, capture, stop_motion, masks.masks)?;
+            ggez::event::run(ctx, event_loop, summary::Summarized::new(state, summary))
+        }
+        CommandsProper::Panorama {
+            device,
+            panorama,
+            window,
+            masks,
+        } => {
+            let capture = capture::spawn_capture(camera_index(&device)?)?;
+            summary::watch(&capture);
+            let (ctx, event_loop) =
+                window::build("athletic — panorama", capture.format.resolution(), &window)?;
+            let state = panorama::PanoramaState::new(capture, panorama, masks.masks);
+            ggez::event::run(ctx, event_loop, summary::Summarized::new(state, summary))
+        }
+        CommandsProper::CompareFormats {
+            device,
+            formats,
+            segment,
+            window,
+        } => {
+            let [left, right] = formats.map(|format| format.resolution());
+            let size = Resolution::new(
+                left.width() + right.width(),
+                left.height().max(right.height()),
+            );
+            let (ctx, event_loop) = window::build("athletic — compare formats", size, &window)?;
+            let state = compare::CompareState::new(camera_index(&device)?, formats, segment)?;
+            ggez::event::run(ctx, event_loop, summary::Summarized::new(state, summary))
+        }
+        CommandsProper::Serve {
+            device,
+            serve,
+            sinks,
+            dump_bad_frames,
+            masks,
+            away,
+        } => {
+            shutdown::catch();
+            serve::serve(
+                camera_index(&device)?,
+                &serve,
+                &sinks,
+                bad_frames::BadFrames::new(dump_bad_frames),
+                &masks.masks,
+                away::Away::open(&away)?,
+                reload,
+            )?;
+        }
+        CommandsProper::Record {
+            device,
+            record,
+            masks,
+        } => {
+            shutdown::catch();
+            record::record(camera_index(&device)?, &record, &masks.masks)?;
+        }
+        CommandsProper::Decrypt {
+            input,
+            identity,
+            output,
+        } => {
+            encrypt::decrypt(&input, &identity, &output)?;
+        }
+        CommandsProper::Verify {
+            recordings,
+            finalize,
+        } => {
+            if finalize {
+                manifest::finalize(&recordings)?;
+            }
+            if !manifest::verify(&recordings)? {
+                return Ok(1);
+            }
+        }
+        CommandsProper::Daemon => {
+            #[cfg(target_os = "linux")]
+            dbus::run()?;
+            #[cfg(not(target_os = "linux"))]
+            return Err(Report::msg(
+                "daemon mode needs D-Bus, which is only available on Linux",
+            ));
+        }
+        CommandsProper::InstallService {
+            service,
+            config,
+            profile,
+        } => {
+            service::install(&service, config.as_deref(), profile.as_deref())?;
+        }
+        CommandsProper::Config { action, path } => match action {
+            config::Action::Check => {
+                if !config::check(path.as_deref())? {
+                    return Ok(1);
                 }
             }
+        },
+        CommandsProper::Selftest {
+            device,
+            formats,
+            timeout,
+        } => {
+            selftest::run(camera_index(&device)?, formats, timeout)?;
+        }
+        CommandsProper::Controls { action } => match action {
+            controls::Action::Set { device, controls } => {
+                controls::set(camera_index(&device)?, &controls)?;
+            }
+            controls::Action::Replay {
+                sidecar,
+                device,
+                auto,
+                at_once,
+            } => {
+                controls::replay(camera_index(&device)?, &sidecar, auto, at_once)?;
+            }
+        },
+        CommandsProper::Analyze { action } => match action {
+            analyze::Action::DeadPixels {
+                device,
+                frames,
+                threshold,
+                output,
+            } => {
+                analyze::dead_pixels(camera_index(&device)?, frames, threshold, output.as_deref())?;
+            }
+        },
+        CommandsProper::Benchmark { suite } => {
+            benchmark::run(&suite)?;
+        }
+    }
+    Ok(0)
+}
+
+fn camera_index(device: &Option<IndexKind>) -> Result<CameraIndex, Report> {
+    device::resolve(device.as_ref())
+}
+
+fn camera_print_properties(cam: &mut Camera, kind: PropertyKind) -> Result<(), Report> {
+    match kind {
+        PropertyKind::All => {
+            camera_print_controls(cam)?;
+            camera_compatible_formats(cam);
+        }
+        PropertyKind::Controls => {
+            camera_print_controls(cam)?;
+        }
+        PropertyKind::CompatibleFormats => {
+            camera_compatible_formats(cam);
         }
     }
+    Ok(())
 }
 
-fn camera_print_controls(cam: &Camera) {
-    let ctrls = cam.camera_controls().unwrap();
+fn camera_print_controls(cam: &Camera) -> Result<(), Report> {
+    let ctrls = cam.camera_controls()?;
     let index = cam.index();
     println!("Controls for camera {index}");
     for ctrl in ctrls {
         println!("{ctrl}")
     }
+    Ok(())
 }
 
 fn camera_compatible_formats(cam: &mut Camera) {
@@ -206,7 +1190,7 @@ fn camera_compatible_formats(cam: &mut Camera) {
             for (resolution, fps) in compatible {
                 formats.push((resolution, fps));
             }
-            formats.sort_by(|a, b| a.0.cmp(&b.0));
+            formats.sort_by_key(|a| a.0);
             for fmt in formats {
                 let (resolution, res) = fmt;
                 println!(" - {resolution}: {res:?}")
@@ -0,0 +1,212 @@
+use crate::{encrypt, mkv};
+use color_eyre::Report;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// What was written to one recording file.
+pub struct Segment {
+    pub frames: u64,
+    /// PTS of the first and last frames.
+    pub start: Duration,
+    pub end: Duration,
+    /// Wall-clock times the first and last frames were written.
+    pub started: SystemTime,
+    pub ended: SystemTime,
+    pub encrypted: bool,
+}
+
+/// Where the manifest of `recording` is kept: beside it, with
+/// `.manifest.json` added to its name.
+pub fn path(recording: &Path) -> PathBuf {
+    let mut name = recording.as_os_str().to_owned();
+    name.push(".manifest.json");
+    PathBuf::from(name)
+}
+
+fn sha256(path: &Path) -> io::Result<(u64, String)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut bytes = 0;
+    loop {
+        let read = input.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        bytes += read as u64;
+    }
+    let digest = hasher.finalize();
+    Ok((
+        bytes,
+        digest.iter().map(|byte| format!("{byte:02x}")).collect(),
+    ))
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Checksums the finished `recording` and writes its manifest.
+pub fn write(recording: &Path, segments: &[(PathBuf, Segment)]) -> Result<PathBuf, Report> {
+    let directory = recording.parent().unwrap_or(Path::new(""));
+    let segments = segments
+        .iter()
+        .map(|(file, segment)| {
+            let (bytes, sha256) = sha256(file)?;
+            let name = file.strip_prefix(directory).unwrap_or(file);
+            Ok(json!({
+                "file": name,
+                "bytes": bytes,
+                "sha256": sha256,
+                "frames": segment.frames,
+                "start": segment.start.as_secs_f64(),
+                "end": segment.end.as_secs_f64(),
+                "started": unix_seconds(segment.started),
+                "ended": unix_seconds(segment.ended),
+                "encrypted": segment.encrypted,
+            }))
+        })
+        .collect::<Result<Vec<_>, Report>>()?;
+    let manifest = json!({ "version": 1, "segments": segments });
+    let path = path(recording);
+    std::fs::write(&path, serde_json::to_string_pretty(&manifest)? + "\n")?;
+    Ok(path)
+}
+
+/// Turns recordings that a crash or power cut ended, and so have no
+/// manifest, into finished ones: cuts off the cluster that was being
+/// written when they ended and writes the manifest `record` would have.
+/// Encrypted ones are refused, as their clusters can't be told apart
+/// until they're decrypted.
+pub fn finalize(paths: &[PathBuf]) -> Result<(), Report> {
+    for recording in paths {
+        let name = recording.display();
+        if path(recording).exists() {
+            println!("{name}: finished already");
+            continue;
+        }
+        if encrypt::is_encrypted(recording)? {
+            return Err(Report::msg(format!(
+                "can't finalize {name}: it's encrypted. Decrypt it, which keeps it \
+                 up to its last whole chunk, and finalize the decrypted copy"
+            )));
+        }
+        let (mut start, mut end) = (None, Duration::ZERO);
+        let scan = mkv::read_frames(BufReader::new(File::open(recording)?), |pts, _| {
+            start.get_or_insert(pts);
+            end = pts;
+            Ok(())
+        })
+        .map_err(|why| Report::msg(format!("can't finalize {name}: {why}")))?;
+        if scan.trailing > 0 {
+            let file = OpenOptions::new().write(true).open(recording)?;
+            file.set_len(scan.complete)?;
+            file.sync_all()?;
+            println!(
+                "{name}: cut off {} bytes of an unfinished cluster",
+                scan.trailing
+            );
+        }
+        // When the frames were written is lost with the manifest; the file
+        // was last written to with the last of them.
+        let start = start.unwrap_or_default();
+        let ended = std::fs::metadata(recording)?.modified()?;
+        let segment = Segment {
+            frames: scan.frames,
+            start,
+            end,
+            started: ended
+                .checked_sub(end.saturating_sub(start))
+                .unwrap_or(ended),
+            ended,
+            encrypted: false,
+        };
+        let manifest = write(recording, &[(recording.clone(), segment)])?;
+        println!(
+            "{name}: {} frames, wrote {}",
+            scan.frames,
+            manifest.display()
+        );
+    }
+    Ok(())
+}
+
+/// Checks one segment listed in a manifest against the file on disk,
+/// returning what's wrong with it.
+fn check(directory: &Path, segment: &Value) -> Result<Vec<String>, Report> {
+    let invalid = |field: &str| Report::msg(format!("manifest segment without {field}"));
+    let file = directory.join(segment["file"].as_str().ok_or_else(|| invalid("file"))?);
+    let bytes = segment["bytes"].as_u64().ok_or_else(|| invalid("bytes"))?;
+    let sha256 = segment["sha256"]
+        .as_str()
+        .ok_or_else(|| invalid("sha256"))?;
+    let frames = segment["frames"]
+        .as_u64()
+        .ok_or_else(|| invalid("frames"))?;
+    let encrypted = segment["encrypted"].as_bool().unwrap_or(false);
+
+    let mut problems = Vec::new();
+    let (actual_bytes, actual_sha256) = match self::sha256(&file) {
+        Ok(found) => found,
+        Err(why) => return Ok(vec![format!("can't read {}: {why}", file.display())]),
+    };
+    if actual_bytes != bytes {
+        problems.push(format!("{actual_bytes} bytes, expected {bytes}"));
+    }
+    if actual_sha256 != sha256 {
+        problems.push("checksum doesn't match".to_string());
+    }
+    // Encrypted recordings can only be looked into once decrypted.
+    if !encrypted {
+        match mkv::scan(BufReader::new(File::open(&file)?)) {
+            Ok(scan) => {
+                if scan.trailing > 0 {
+                    problems.push(format!("truncated, {} bytes left over", scan.trailing));
+                }
+                if scan.frames != frames {
+                    problems.push(format!("{} frames, expected {frames}", scan.frames));
+                }
+            }
+            Err(why) => problems.push(format!("unreadable: {why}")),
+        }
+    }
+    Ok(problems)
+}
+
+/// Checks recordings against their manifests, given either the manifests
+/// or the recordings themselves. Returns whether all of them passed.
+pub fn verify(paths: &[PathBuf]) -> Result<bool, Report> {
+    let mut passed = true;
+    for given in paths {
+        let manifest = if given.to_string_lossy().ends_with(".manifest.json") {
+            given.clone()
+        } else {
+            path(given)
+        };
+        let text = std::fs::read_to_string(&manifest)
+            .map_err(|why| Report::msg(format!("can't read {}: {why}", manifest.display())))?;
+        let manifest_json: Value = serde_json::from_str(&text)?;
+        let segments = manifest_json["segments"]
+            .as_array()
+            .ok_or_else(|| Report::msg(format!("no segments in {}", manifest.display())))?;
+        let directory = manifest.parent().unwrap_or(Path::new(""));
+        for segment in segments {
+            let name = segment["file"].as_str().unwrap_or("?");
+            let problems = check(directory, segment)?;
+            if problems.is_empty() {
+                println!("{name}: OK");
+            } else {
+                println!("{name}: FAILED ({})", problems.join("; "));
+                passed = false;
+            }
+        }
+    }
+    Ok(passed)
+}
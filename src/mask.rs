@@ -0,0 +1,112 @@
+use crate::trace;
+use clap::Args;
+use color_eyre::Report;
+use std::str::FromStr;
+
+/// Side of the blocks a pixelated region is averaged over.
+const PIXELATE_BLOCK: u32 = 16;
+
+/// Regions to obscure before a frame is shown, served or saved.
+#[derive(Args, Clone, Default)]
+pub struct MaskOptions {
+    /// Obscure a region of the frame, as X,Y,WIDTH,HEIGHT in pixels,
+    /// optionally followed by ,black (the default) or ,pixelate. Repeatable.
+    #[arg(long = "mask", value_name = "REGION")]
+    pub masks: Vec<Mask>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Style {
+    Black,
+    Pixelate,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Mask {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub style: Style,
+}
+
+impl FromStr for Mask {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            Report::msg(format!(
+                "expected X,Y,WIDTH,HEIGHT[,pixelate|black], got: {s}"
+            ))
+        };
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let (numbers, style) = match parts[..] {
+            [x, y, width, height] => ([x, y, width, height], Style::Black),
+            [x, y, width, height, style] => (
+                [x, y, width, height],
+                match style {
+                    "black" | "Black" | "BLACK" => Style::Black,
+                    "pixelate" | "Pixelate" | "PIXELATE" => Style::Pixelate,
+                    _ => return Err(Report::msg(format!("unknown mask style: {style}"))),
+                },
+            ),
+            _ => return Err(invalid()),
+        };
+        let [x, y, width, height] = numbers
+            .map(|n| n.parse::<u32>())
+            .map(|n| n.map_err(|_| invalid()));
+        Ok(Mask {
+            x: x?,
+            y: y?,
+            width: width?,
+            height: height?,
+            style,
+        })
+    }
+}
+
+/// Obscures `masks` in a frame of `width` by `height` pixels, each of
+/// `channels` bytes. A fourth channel is taken to be alpha and left alone.
+pub fn apply(masks: &[Mask], pixels: &mut [u8], width: u32, height: u32, channels: usize) {
+    let _zone = trace::zone("masks");
+    if pixels.len() < width as usize * height as usize * channels {
+        // Rather nothing than a frame with the masked parts showing.
+        pixels.fill(0);
+        return;
+    }
+    let colours = channels.min(3);
+    for mask in masks {
+        let right = mask.x.saturating_add(mask.width).min(width);
+        let bottom = mask.y.saturating_add(mask.height).min(height);
+        let block = match mask.style {
+            Style::Black => 1,
+            Style::Pixelate => PIXELATE_BLOCK,
+        };
+        for top in (mask.y..bottom).step_by(block as usize) {
+            for left in (mask.x..right).step_by(block as usize) {
+                let (block_right, block_bottom) =
+                    ((left + block).min(right), (top + block).min(bottom));
+                let at = |x: u32, y: u32| (y as usize * width as usize + x as usize) * channels;
+                let mut colour = [0u8; 3];
+                if mask.style == Style::Pixelate {
+                    let mut sums = [0u64; 3];
+                    for y in top..block_bottom {
+                        for x in left..block_right {
+                            let pixel = &pixels[at(x, y)..][..colours];
+                            for (sum, &value) in sums.iter_mut().zip(pixel) {
+                                *sum += value as u64;
+                            }
+                        }
+                    }
+                    let count = ((block_right - left) * (block_bottom - top)) as u64;
+                    colour = sums.map(|sum| (sum / count) as u8);
+                }
+                for y in top..block_bottom {
+                    for x in left..block_right {
+                        pixels[at(x, y)..][..colours].copy_from_slice(&colour[..colours]);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! What frames held in the pipeline's queues and buffers cost, and, with
+//! `--max-memory`, a cap on it. Each frame carries a [`Held`] for its
+//! bytes until the last copy of it is dropped. Close to the cap, the
+//! stages that can give way do: queues that may drop frames drop new
+//! ones, and the preview's replay buffer gets shorter. Stages that never
+//! drop, like `record`'s, wait instead.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+static LIMIT: AtomicU64 = AtomicU64::new(u64::MAX);
+static HELD: AtomicU64 = AtomicU64::new(0);
+static PEAK: AtomicU64 = AtomicU64::new(0);
+
+/// Caps what frames may hold at `bytes`, from now on.
+pub fn limit(bytes: u64) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+/// The bytes frames hold now.
+pub fn held() -> u64 {
+    HELD.load(Ordering::Relaxed)
+}
+
+/// The most frames have held at once.
+pub fn peak() -> u64 {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Whether frames hold more than the cap.
+pub fn over() -> bool {
+    held() > LIMIT.load(Ordering::Relaxed)
+}
+
+/// Bytes counted as held until every clone has been dropped, as the data
+/// of a frame's clones is shared.
+#[derive(Clone, Debug)]
+pub struct Held {
+    _count: Arc<Count>,
+}
+
+#[derive(Debug)]
+struct Count(u64);
+
+impl Held {
+    pub fn new(bytes: usize) -> Self {
+        let bytes = bytes as u64;
+        let held = HELD.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        PEAK.fetch_max(held, Ordering::Relaxed);
+        Held {
+            _count: Arc::new(Count(bytes)),
+        }
+    }
+}
+
+impl Drop for Count {
+    fn drop(&mut self) {
+        HELD.fetch_sub(self.0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,385 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+// Element IDs, from the Matroska specification.
+const EBML: u32 = 0x1A45DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const BIT_DEPTH: u32 = 0x6264;
+const CLUSTER: u32 = 0x1F43B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+/// The size written for the segment, whose length isn't known until the
+/// recording ends: all ones, which Matroska reads as "unknown".
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+/// Timestamps are counted in milliseconds.
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// A new cluster is started at least this often, which is also how much a
+/// crash can lose.
+const CLUSTER_LENGTH: Duration = Duration::from_secs(1);
+
+fn id(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Encodes `size` as an EBML variable-length integer.
+fn size(size: u64) -> Vec<u8> {
+    let length = (1..=8)
+        .find(|&length| size < (1 << (7 * length)) - 1)
+        .unwrap_or(8);
+    let marked = size | 1 << (7 * length);
+    marked.to_be_bytes()[8 - length..].to_vec()
+}
+
+fn element(element: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = id(element);
+    out.extend(size(data.len() as u64));
+    out.extend_from_slice(data);
+    out
+}
+
+fn uint(element_id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&byte| byte == 0).count().min(7);
+    element(element_id, &bytes[skip..])
+}
+
+fn string(element_id: u32, value: &str) -> Vec<u8> {
+    element(element_id, value.as_bytes())
+}
+
+fn float(element_id: u32, value: f64) -> Vec<u8> {
+    element(element_id, &value.to_be_bytes())
+}
+
+/// The video track is always track 1 and the audio track, if any, track 2.
+const VIDEO_TRACK: u8 = 1;
+const AUDIO_TRACK: u8 = 2;
+
+/// A video track of `codec` (a Matroska codec ID such as `V_MJPEG`) at
+/// `width` by `height`.
+#[derive(Copy, Clone)]
+pub struct VideoTrack<'a> {
+    pub codec: &'a str,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A track of interleaved 16-bit little-endian PCM.
+#[derive(Copy, Clone)]
+pub struct AudioTrack {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Writes a video track, an audio track or both to a Matroska file as
+/// frames and samples arrive.
+///
+/// Nothing is ever rewritten, so the output can go to a pipe or through
+/// a stream cipher, and a recording cut short is still playable up to its
+/// last complete cluster.
+pub struct MatroskaWriter<W: Write> {
+    out: W,
+    /// Start of the cluster being filled, and its blocks so far.
+    cluster: Option<(Duration, Vec<u8>)>,
+}
+
+impl<W: Write> MatroskaWriter<W> {
+    /// Writes the file header for the tracks given: video as track 1 and
+    /// sound as track 2.
+    pub fn new(
+        mut out: W,
+        video: Option<VideoTrack>,
+        audio: Option<AudioTrack>,
+    ) -> io::Result<Self> {
+        let header = [
+            uint(EBML_VERSION, 1),
+            uint(EBML_READ_VERSION, 1),
+            uint(EBML_MAX_ID_LENGTH, 4),
+            uint(EBML_MAX_SIZE_LENGTH, 8),
+            string(DOC_TYPE, "matroska"),
+            uint(DOC_TYPE_VERSION, 4),
+            uint(DOC_TYPE_READ_VERSION, 2),
+        ]
+        .concat();
+        out.write_all(&element(EBML, &header))?;
+
+        out.write_all(&id(SEGMENT))?;
+        out.write_all(&UNKNOWN_SIZE)?;
+        let app = concat!("athletic ", env!("CARGO_PKG_VERSION"));
+        let info = [
+            uint(TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS),
+            string(MUXING_APP, app),
+            string(WRITING_APP, app),
+        ]
+        .concat();
+        out.write_all(&element(INFO, &info))?;
+        let mut tracks = Vec::new();
+        if let Some(video) = video {
+            let settings = [
+                uint(PIXEL_WIDTH, video.width as u64),
+                uint(PIXEL_HEIGHT, video.height as u64),
+            ]
+            .concat();
+            let track = [
+                uint(TRACK_NUMBER, VIDEO_TRACK as u64),
+                uint(TRACK_UID, VIDEO_TRACK as u64),
+                uint(TRACK_TYPE, 1),
+                uint(FLAG_LACING, 0),
+                string(CODEC_ID, video.codec),
+                element(VIDEO, &settings),
+            ]
+            .concat();
+            tracks.extend(element(TRACK_ENTRY, &track));
+        }
+        if let Some(audio) = audio {
+            let settings = [
+                float(SAMPLING_FREQUENCY, audio.sample_rate as f64),
+                uint(CHANNELS, audio.channels as u64),
+                uint(BIT_DEPTH, 16),
+            ]
+            .concat();
+            let track = [
+                uint(TRACK_NUMBER, AUDIO_TRACK as u64),
+                uint(TRACK_UID, AUDIO_TRACK as u64),
+                uint(TRACK_TYPE, 2),
+                uint(FLAG_LACING, 0),
+                string(CODEC_ID, "A_PCM/INT/LIT"),
+                element(AUDIO, &settings),
+            ]
+            .concat();
+            tracks.extend(element(TRACK_ENTRY, &track));
+        }
+        out.write_all(&element(TRACKS, &tracks))?;
+        Ok(MatroskaWriter { out, cluster: None })
+    }
+
+    /// Adds a frame shown at `pts`, which must not be earlier than the
+    /// previous frame's.
+    pub fn write_frame(&mut self, pts: Duration, frame: &[u8]) -> io::Result<()> {
+        self.write_block(VIDEO_TRACK, pts, frame)
+    }
+
+    /// Adds a block of samples starting at `pts`.
+    pub fn write_audio(&mut self, pts: Duration, samples: &[u8]) -> io::Result<()> {
+        self.write_block(AUDIO_TRACK, pts, samples)
+    }
+
+    fn write_block(&mut self, track: u8, pts: Duration, data: &[u8]) -> io::Result<()> {
+        if let Some((start, _)) = &self.cluster {
+            if pts.saturating_sub(*start) >= CLUSTER_LENGTH {
+                self.flush_cluster()?;
+            }
+        }
+        let (start, blocks) = self.cluster.get_or_insert_with(|| (pts, Vec::new()));
+        // Audio can start a little before the cluster its block lands in.
+        let offset = (pts.as_millis() as i64 - start.as_millis() as i64) as i16;
+        // The track, the offset from the cluster's timestamp, and the
+        // keyframe flag, since every frame stands on its own.
+        let mut block = vec![0x80 | track];
+        block.extend(offset.to_be_bytes());
+        block.push(0x80);
+        block.extend_from_slice(data);
+        blocks.extend(element(SIMPLE_BLOCK, &block));
+        Ok(())
+    }
+
+    fn flush_cluster(&mut self) -> io::Result<()> {
+        if let Some((start, blocks)) = self.cluster.take() {
+            let timestamp = uint(TIMESTAMP, start.as_millis() as u64);
+            self.out
+                .write_all(&element(CLUSTER, &[timestamp, blocks].concat()))?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last cluster and hands back the output.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_cluster()?;
+        Ok(self.out)
+    }
+}
+
+/// What a pass over a Matroska file found.
+pub struct Scan {
+    pub frames: u64,
+    /// Bytes up to the end of the last complete element.
+    pub complete: u64,
+    /// Bytes after the last complete element, left by a recording that was
+    /// cut short.
+    pub trailing: u64,
+}
+
+/// Counts the bytes read through it.
+struct Counted<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// Reads an EBML variable-length integer, keeping its length marker for
+/// element IDs. Returns `None` at the end of the input.
+fn read_vint(input: &mut impl Read, marker: bool) -> io::Result<Option<u64>> {
+    let mut first = [0];
+    if input.read(&mut first)? == 0 {
+        return Ok(None);
+    }
+    let length = first[0].leading_zeros() as usize + 1;
+    if length > 8 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid EBML length",
+        ));
+    }
+    let mut rest = [0; 7];
+    input.read_exact(&mut rest[..length - 1])?;
+    let mut value = if marker {
+        first[0] as u64
+    } else {
+        first[0] as u64 & (0xFF >> length)
+    };
+    for byte in &rest[..length - 1] {
+        value = value << 8 | *byte as u64;
+    }
+    Ok(Some(value))
+}
+
+/// Walks a file written by [`MatroskaWriter`], counting its frames and
+/// noticing where it ends early.
+pub fn scan(input: impl Read) -> io::Result<Scan> {
+    read_frames(input, |_, _| Ok(()))
+}
+
+/// Walks a file written by [`MatroskaWriter`] like [`scan`], handing each
+/// video frame and its PTS to `each` on the way.
+pub fn read_frames(
+    input: impl Read,
+    mut each: impl FnMut(Duration, &[u8]) -> io::Result<()>,
+) -> io::Result<Scan> {
+    read_blocks(input, |block| match block {
+        Block::Video(pts, frame) => each(pts, frame),
+        Block::Audio(..) => Ok(()),
+    })
+}
+
+/// A block read back by [`read_blocks`].
+pub enum Block<'a> {
+    Video(Duration, &'a [u8]),
+    Audio(Duration, &'a [u8]),
+}
+
+/// Walks a file written by [`MatroskaWriter`] like [`scan`], handing every
+/// block of either track to `each`. Only video frames are counted.
+///
+/// Whatever follows the last complete element, whether the start of one
+/// that was cut short or the junk a power cut can leave, is counted as
+/// trailing rather than failing the read.
+pub fn read_blocks(
+    input: impl Read,
+    mut each: impl FnMut(Block) -> io::Result<()>,
+) -> io::Result<Scan> {
+    let invalid = |why| io::Error::new(io::ErrorKind::InvalidData, why);
+    let unknown = u64::from_be_bytes(UNKNOWN_SIZE) & !(1 << 56);
+    let mut input = Counted {
+        inner: input,
+        read: 0,
+    };
+    let mut scan = Scan {
+        frames: 0,
+        complete: 0,
+        trailing: 0,
+    };
+    let leftover = |mut scan: Scan, input: &mut Counted<_>| {
+        io::copy(input, &mut io::sink())?;
+        scan.trailing = input.read - scan.complete;
+        Ok(scan)
+    };
+    loop {
+        scan.complete = input.read;
+        let element = match read_vint(&mut input, true) {
+            Ok(Some(element)) => element,
+            Ok(None) => return Ok(scan),
+            Err(_) if scan.complete > 0 => return leftover(scan, &mut input),
+            Err(why) => return Err(why),
+        };
+        if scan.complete == 0 && element != EBML as u64 {
+            return Err(invalid("not a Matroska file"));
+        }
+        let Ok(Some(size)) = read_vint(&mut input, false) else {
+            return leftover(scan, &mut input);
+        };
+        // The segment's children follow straight on; everything else is
+        // read whole.
+        if element == SEGMENT as u64 && size == unknown {
+            continue;
+        }
+        let mut data = Vec::new();
+        (&mut input).take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return leftover(scan, &mut input);
+        }
+        if element == CLUSTER as u64 {
+            let mut cluster = data.as_slice();
+            let mut start = 0;
+            while let Some(child) = read_vint(&mut cluster, true)? {
+                let size = read_vint(&mut cluster, false)?.unwrap_or(0) as usize;
+                let body = cluster
+                    .get(..size)
+                    .ok_or_else(|| invalid("block overruns its cluster"))?;
+                if child == TIMESTAMP as u64 {
+                    start = body.iter().fold(0, |value, &byte| value << 8 | byte as u64);
+                } else if child == SIMPLE_BLOCK as u64 {
+                    // Track number, offset from the cluster's timestamp and
+                    // flags, as written by `write_block`.
+                    let (header, frame) = body
+                        .split_at_checked(4)
+                        .ok_or_else(|| invalid("block too short"))?;
+                    let offset = i16::from_be_bytes([header[1], header[2]]);
+                    let pts = start.saturating_add_signed(offset as i64);
+                    let pts = Duration::from_millis(pts);
+                    if header[0] & 0x7F == AUDIO_TRACK {
+                        each(Block::Audio(pts, frame))?;
+                    } else {
+                        each(Block::Video(pts, frame))?;
+                        scan.frames += 1;
+                    }
+                }
+                cluster = &cluster[size..];
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::capture::Frame;
+use crate::thermal::Palette;
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma};
+use nokhwa::utils::FrameFormat;
+
+/// How far one key press moves the window, as a fraction of the full range.
+const WINDOW_STEP: f32 = 1.0 / 32.0;
+/// Narrowest window allowed, as a fraction of the full range.
+const MIN_WIDTH: f32 = 1.0 / 256.0;
+
+/// Sample depth of a grayscale frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Depth {
+    Eight,
+    /// Little-endian 16-bit samples, of which the sensor may fill only the
+    /// low 10 or 12 bits.
+    Sixteen,
+}
+
+impl Depth {
+    /// The depth of `frame` if it is grayscale.
+    ///
+    /// nokhwa reports every grayscale format as `GRAY`, so 16-bit frames are
+    /// told apart by carrying two bytes per pixel.
+    pub fn of(frame: &Frame) -> Option<Depth> {
+        let buffer = &frame.buffer;
+        if buffer.source_frame_format() != FrameFormat::GRAY {
+            return None;
+        }
+        let resolution = buffer.resolution();
+        let pixels = resolution.width() as usize * resolution.height() as usize;
+        if buffer.buffer().len() >= pixels * 2 {
+            Some(Depth::Sixteen)
+        } else {
+            Some(Depth::Eight)
+        }
+    }
+
+    fn max(self) -> f32 {
+        match self {
+            Depth::Eight => u8::MAX as f32,
+            Depth::Sixteen => u16::MAX as f32,
+        }
+    }
+}
+
+/// The part of a grayscale frame's range stretched over the 8 bits shown on
+/// screen, as a level (the centre) and a width, both fractions of the full
+/// range.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Windowing {
+    pub level: f32,
+    pub width: f32,
+}
+
+impl Default for Windowing {
+    fn default() -> Self {
+        Windowing {
+            level: 0.5,
+            width: 1.0,
+        }
+    }
+}
+
+impl Windowing {
+    /// Moves the level up (or down) by one step.
+    pub fn shift(&mut self, up: bool) {
+        let step = if up { WINDOW_STEP } else { -WINDOW_STEP };
+        self.level = (self.level + step).clamp(0.0, 1.0);
+    }
+
+    /// Widens (or narrows) the window by one step.
+    pub fn widen(&mut self, wider: bool) {
+        let step = if wider { WINDOW_STEP } else { -WINDOW_STEP };
+        self.width = (self.width + step).clamp(MIN_WIDTH, 1.0);
+    }
+
+    /// The window from the lowest sample of `frame` to its highest, so a
+    /// frame using a sliver of the range still shows its detail.
+    pub fn spanning(frame: &Frame, depth: Depth) -> Windowing {
+        let data = frame.buffer.buffer();
+        let (low, high) = match depth {
+            Depth::Eight => data
+                .iter()
+                .fold((u8::MAX as u32, 0), |(low, high), &sample| {
+                    (low.min(sample as u32), high.max(sample as u32))
+                }),
+            Depth::Sixteen => {
+                data.chunks_exact(2)
+                    .fold((u16::MAX as u32, 0), |(low, high), sample| {
+                        let sample = u16::from_le_bytes([sample[0], sample[1]]) as u32;
+                        (low.min(sample), high.max(sample))
+                    })
+            }
+        };
+        if low > high {
+            return Windowing::default();
+        }
+        let (low, high) = (low as f32 / depth.max(), high as f32 / depth.max());
+        Windowing {
+            level: (low + high) / 2.0,
+            width: (high - low).max(MIN_WIDTH),
+        }
+    }
+
+    /// Maps every possible sample of `depth` to the 8 bits it is shown at.
+    fn table(&self, depth: Depth) -> Vec<u8> {
+        let max = depth.max();
+        let low = self.level - self.width / 2.0;
+        (0..=max as u32)
+            .map(|sample| {
+                let value = (sample as f32 / max - low) / self.width;
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8
+            })
+            .collect()
+    }
+
+    /// Renders a grayscale frame of `depth` into `rgba` in the colours of
+    /// `palette`. `rgba` must hold four bytes for every pixel.
+    pub fn render(&self, frame: &Frame, depth: Depth, palette: Palette, rgba: &mut [u8]) {
+        let table = self.table(depth);
+        let colours: Vec<[u8; 4]> = (0..=u8::MAX)
+            .map(|level| {
+                let [r, g, b] = palette.colour(level);
+                [r, g, b, 255]
+            })
+            .collect();
+        let data = frame.buffer.buffer();
+        let pixels = rgba.chunks_exact_mut(4);
+        match depth {
+            Depth::Eight => {
+                for (pixel, &sample) in pixels.zip(data) {
+                    pixel.copy_from_slice(&colours[table[sample as usize] as usize]);
+                }
+            }
+            Depth::Sixteen => {
+                for (pixel, sample) in pixels.zip(data.chunks_exact(2)) {
+                    let value = table[u16::from_le_bytes([sample[0], sample[1]]) as usize];
+                    pixel.copy_from_slice(&colours[value as usize]);
+                }
+            }
+        }
+    }
+}
+
+/// A grayscale frame as an image at its own depth, so saving it keeps every
+/// bit the camera delivered.
+pub fn image(frame: &Frame, depth: Depth) -> Option<DynamicImage> {
+    let resolution = frame.buffer.resolution();
+    let (width, height) = (resolution.width(), resolution.height());
+    let pixels = width as usize * height as usize;
+    let data = frame.buffer.buffer();
+    match depth {
+        Depth::Eight => {
+            let image = GrayImage::from_raw(width, height, data.get(..pixels)?.to_vec())?;
+            Some(DynamicImage::ImageLuma8(image))
+        }
+        Depth::Sixteen => {
+            let samples = data
+                .get(..pixels * 2)?
+                .chunks_exact(2)
+                .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+                .collect();
+            let image = ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(width, height, samples)?;
+            Some(DynamicImage::ImageLuma16(image))
+        }
+    }
+}
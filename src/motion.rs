@@ -0,0 +1,51 @@
+/// Motion is measured on a grid of at most this many samples per axis.
+const SAMPLES: u32 = 64;
+
+/// Scores how much each frame differs from the one before it.
+#[derive(Default)]
+pub struct Motion {
+    previous: Vec<u8>,
+}
+
+impl Motion {
+    /// Mean change in brightness since the previous frame, from 0 to 1, for
+    /// packed RGB, RGBA or BGRA pixels. `None` for the first frame or one
+    /// of a different size.
+    pub fn score(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        channels: usize,
+    ) -> Option<f64> {
+        let luma = sample_luma(pixels, width, height, channels);
+        let score = (luma.len() == self.previous.len()).then(|| difference(&luma, &self.previous));
+        self.previous = luma;
+        score
+    }
+}
+
+/// Luma on a coarse grid, enough to notice something moving.
+fn sample_luma(pixels: &[u8], width: u32, height: u32, channels: usize) -> Vec<u8> {
+    if pixels.len() < width as usize * height as usize * channels {
+        return Vec::new();
+    }
+    let step_x = (width / SAMPLES).max(1);
+    let step_y = (height / SAMPLES).max(1);
+    (0..height)
+        .step_by(step_y as usize)
+        .flat_map(|y| {
+            (0..width).step_by(step_x as usize).map(move |x| {
+                let at = (y as usize * width as usize + x as usize) * channels;
+                let [r, g, b] = [0, 1, 2].map(|c| pixels[at + c] as u32);
+                ((r * 54 + g * 183 + b * 19) >> 8) as u8
+            })
+        })
+        .collect()
+}
+
+/// Mean absolute difference between two luma grids, from 0 to 1.
+fn difference(a: &[u8], b: &[u8]) -> f64 {
+    let total: u64 = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+    total as f64 / (a.len().max(1) as f64 * 255.0)
+}
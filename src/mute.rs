@@ -0,0 +1,39 @@
+//! Blanking a recording's video or silencing its audio while it runs,
+//! without stopping it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static VIDEO: AtomicBool = AtomicBool::new(false);
+static AUDIO: AtomicBool = AtomicBool::new(false);
+
+/// Starts toggling the video mute on SIGUSR1 and the audio mute on SIGUSR2,
+/// so `kill -USR1` blanks the picture and sending it again brings it back.
+#[cfg(unix)]
+pub fn listen() {
+    extern "C" fn toggle(signal: libc::c_int) {
+        let muted = if signal == libc::SIGUSR1 {
+            &VIDEO
+        } else {
+            &AUDIO
+        };
+        // Atomics are among the few things safe to touch in a handler.
+        muted.fetch_xor(true, Ordering::Relaxed);
+    }
+    let handler = toggle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+    }
+}
+
+/// Windows has no user signals, so there's nothing to listen for.
+#[cfg(not(unix))]
+pub fn listen() {}
+
+pub fn video() -> bool {
+    VIDEO.load(Ordering::Relaxed)
+}
+
+pub fn audio() -> bool {
+    AUDIO.load(Ordering::Relaxed)
+}
@@ -0,0 +1,169 @@
+//! How a camera's format gets settled, for `--explain-format`.
+//!
+//! nokhwa picks the format from what the camera offers by a rule that
+//! depends on what was asked for, and the driver may then set something
+//! else. None of that is visible from the format a capture ends up with,
+//! so [`Negotiation`] goes through it step by step.
+
+use crate::capture;
+use color_eyre::Report;
+use nokhwa::{
+    pixel_format::{FormatDecoder, RgbFormat},
+    utils::{CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType},
+    Camera,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// What a camera was asked for, what it offered and what it ended up in.
+pub struct Negotiation {
+    pub camera: String,
+    pub requested: RequestedFormatType,
+    /// Formats the frames can be decoded from; nokhwa passes over the rest.
+    pub decodable: &'static [FrameFormat],
+    pub offered: Vec<CameraFormat>,
+    /// What nokhwa's rule picks from `offered`.
+    pub picked: Option<CameraFormat>,
+    /// What the camera was set to once opened, or why it couldn't be.
+    pub configured: Option<Result<CameraFormat, String>>,
+}
+
+impl Negotiation {
+    /// Asks the camera `index` what it offers and works out what
+    /// `requested` picks from it. The camera is closed again afterwards.
+    pub fn probe(index: &CameraIndex, requested: RequestedFormatType) -> Result<Self, Report> {
+        let mut camera = capture::new_camera(
+            index.clone(),
+            RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+        )?;
+        let offered = camera.compatible_camera_formats()?;
+        let picked = RequestedFormat::new::<RgbFormat>(requested).fulfill(&offered);
+        Ok(Negotiation {
+            camera: format!("{} ({})", camera.info().human_name(), camera.index()),
+            requested,
+            decodable: RgbFormat::FORMATS,
+            offered,
+            picked,
+            configured: None,
+        })
+    }
+
+    /// Notes what the camera was set to, rereading it from the driver in
+    /// case it quietly changed what it was given.
+    pub fn settle(&mut self, opened: &mut Result<Camera, Report>) {
+        self.configured = Some(match opened {
+            Ok(camera) => camera
+                .refresh_camera_format()
+                .map_err(|why| why.to_string()),
+            Err(why) => Err(why.to_string()),
+        });
+    }
+
+    /// Why `candidate` loses out, if it says anything more than not being
+    /// what the rule picked.
+    fn note(&self, candidate: &CameraFormat) -> Option<String> {
+        if !self.decodable.contains(&candidate.format()) {
+            return Some("can't be decoded".to_string());
+        }
+        match self.requested {
+            RequestedFormatType::HighestResolution(resolution)
+                if candidate.resolution() != resolution =>
+            {
+                Some("another resolution".to_string())
+            }
+            RequestedFormatType::HighestFrameRate(fps) if candidate.frame_rate() != fps => {
+                Some("another frame rate".to_string())
+            }
+            RequestedFormatType::Closest(wanted) if candidate.format() != wanted.format() => {
+                Some("another format".to_string())
+            }
+            RequestedFormatType::Closest(wanted) => {
+                let dx = candidate.width() as f64 - wanted.width() as f64;
+                let dy = candidate.height() as f64 - wanted.height() as f64;
+                Some(format!(
+                    "{:.0} pixels and {} fps off",
+                    dx.hypot(dy),
+                    candidate.frame_rate().abs_diff(wanted.frame_rate())
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `format` as `WIDTHxHEIGHT@FPS:FORMAT`, the way formats are given.
+pub fn spec(format: &CameraFormat) -> String {
+    format!(
+        "{}@{}:{}",
+        format.resolution(),
+        format.frame_rate(),
+        format.format()
+    )
+}
+
+/// The rule nokhwa picks by for `requested`.
+fn rule(requested: &RequestedFormatType) -> String {
+    match requested {
+        RequestedFormatType::None => "the first format offered that can be decoded".to_string(),
+        RequestedFormatType::Exact(format) => {
+            format!("exactly {}, whether or not it's offered", spec(format))
+        }
+        RequestedFormatType::Closest(format) => format!(
+            "the resolution closest to {} in {}, then the closest frame rate",
+            format.resolution(),
+            format.format()
+        ),
+        RequestedFormatType::HighestResolution(resolution) => {
+            format!("the highest frame rate at {resolution}")
+        }
+        RequestedFormatType::HighestFrameRate(fps) => {
+            format!("the highest resolution at {fps} fps")
+        }
+        RequestedFormatType::AbsoluteHighestResolution => {
+            "the highest resolution, then the highest frame rate at it".to_string()
+        }
+        RequestedFormatType::AbsoluteHighestFrameRate => {
+            "the highest frame rate, then the highest resolution at it".to_string()
+        }
+    }
+}
+
+impl Display for Negotiation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Format negotiation for {}:", self.camera)?;
+        writeln!(f, "  Requested:  {}", rule(&self.requested))?;
+        let decodable: Vec<String> = self.decodable.iter().map(ToString::to_string).collect();
+        writeln!(f, "  Decodes:    {}", decodable.join(", "))?;
+        writeln!(f, "  Offered:")?;
+        let width = self
+            .offered
+            .iter()
+            .map(|c| spec(c).len())
+            .max()
+            .unwrap_or(0);
+        for candidate in &self.offered {
+            let mark = if self.picked.as_ref() == Some(candidate) {
+                Some("picked".to_string())
+            } else {
+                self.note(candidate)
+            };
+            match mark {
+                Some(mark) => writeln!(f, "    {:width$}  {mark}", spec(candidate))?,
+                None => writeln!(f, "    {}", spec(candidate))?,
+            }
+        }
+        match &self.picked {
+            Some(picked) => writeln!(f, "  Picked:     {}", spec(picked))?,
+            None => writeln!(f, "  Picked:     nothing, as nothing offered fits")?,
+        }
+        match &self.configured {
+            Some(Ok(configured)) if Some(configured) != self.picked.as_ref() => writeln!(
+                f,
+                "  Configured: {}, as the driver set that instead",
+                spec(configured)
+            ),
+            Some(Ok(configured)) => writeln!(f, "  Configured: {}", spec(configured)),
+            Some(Err(why)) => writeln!(f, "  Configured: nothing, as the camera refused: {why}"),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Telling systemd how a service is doing, for the units `install-service`
+//! writes: that it's ready once the camera streams, and, while frames keep
+//! getting through, that it's alive, so a capture loop that hangs misses
+//! the watchdog and the service is restarted.
+//!
+//! Outside a unit that asks for it, there's no `NOTIFY_SOCKET` and all
+//! of this does nothing.
+
+use crate::capture::{Capture, Counters};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// Says the service has started.
+pub fn ready() {
+    send("READY=1");
+}
+
+/// Says the service is shutting down.
+pub fn stopping() {
+    send("STOPPING=1");
+}
+
+/// Pets the watchdog for as long as frames from `capture` get to whoever
+/// reads them: a camera that stops delivering and a reader that stops
+/// reading, so that every frame is dropped, both stop it.
+pub fn watch(capture: &Capture) {
+    let counters = capture.counters.clone();
+    let mut last = None;
+    pet_while(move || {
+        let now = through(&counters);
+        let moved = last != Some(now);
+        last = Some(now);
+        moved
+    });
+}
+
+/// Frames that got to their reader, rather than being dropped.
+fn through(counters: &Counters) -> u64 {
+    // The two are read apart, with nothing to say the loads see them in
+    // the order they were counted.
+    let dropped = counters.dropped.load(Ordering::Relaxed);
+    counters
+        .received
+        .load(Ordering::Relaxed)
+        .saturating_sub(dropped)
+}
+
+/// Pets the watchdog for as long as the process runs, for services with
+/// no frames to go by.
+#[cfg(target_os = "linux")]
+pub fn keep_alive() {
+    pet_while(|| true);
+}
+
+/// Pets the watchdog, at half the interval systemd gives, every time
+/// `healthy` says to, from a thread of its own.
+fn pet_while(mut healthy: impl FnMut() -> bool + Send + 'static) {
+    let Some(interval) = watchdog() else {
+        return;
+    };
+    // The first look is straight away, before any frames could come.
+    healthy();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval / 2);
+        if healthy() {
+            send("WATCHDOG=1");
+        }
+    });
+}
+
+/// The watchdog interval systemd gave this process, if it gave one.
+fn watchdog() -> Option<Duration> {
+    if let Some(pid) = std::env::var_os("WATCHDOG_PID") {
+        if pid.to_str()?.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    Some(Duration::from_micros(usec)).filter(|interval| !interval.is_zero())
+}
+
+#[cfg(unix)]
+fn send(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = UnixDatagram::unbound().and_then(|socket| {
+        match path.to_str().and_then(|path| path.strip_prefix('@')) {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &address)
+            }
+            _ => socket.send_to(state.as_bytes(), &path),
+        }
+    });
+    if let Err(why) = sent {
+        eprintln!("failed to tell systemd {state}: {why}");
+    }
+}
+
+#[cfg(not(unix))]
+fn send(_state: &str) {}
@@ -0,0 +1,110 @@
+//! Reading the text in a frame, through the tesseract command when built
+//! with the `ocr` feature.
+
+use crate::pdf::Word;
+use color_eyre::Report;
+use image::DynamicImage;
+
+/// Text read from an image, as lines and as words with their boxes.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+pub struct Text {
+    pub text: String,
+    pub words: Vec<Word>,
+}
+
+/// Checks that text can be read at all, so a missing tesseract is found
+/// before anything is captured.
+#[cfg(feature = "ocr")]
+pub fn available() -> Result<(), Report> {
+    use std::process::{Command, Stdio};
+
+    Command::new("tesseract")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|_| ())
+        .map_err(|why| Report::msg(format!("can't run tesseract: {why}")))
+}
+
+#[cfg(not(feature = "ocr"))]
+const UNAVAILABLE: &str = "reading text needs athletic built with --features ocr";
+
+#[cfg(not(feature = "ocr"))]
+pub fn available() -> Result<(), Report> {
+    Err(Report::msg(UNAVAILABLE))
+}
+
+/// Reads the text in `image`.
+#[cfg(feature = "ocr")]
+pub fn recognize(image: &DynamicImage) -> Result<Text, Report> {
+    use image::ImageOutputFormat;
+    use std::io::{Cursor, Write};
+    use std::process::{Command, Stdio};
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    let mut child = Command::new("tesseract")
+        .args(["stdin", "stdout", "tsv"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // tesseract reads the whole image before writing anything, so this
+    // can't fill its output pipe first.
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| Report::msg("tesseract has no input"))?
+        .write_all(&png)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Report::msg(format!("tesseract failed: {}", output.status)));
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[cfg(not(feature = "ocr"))]
+pub fn recognize(_image: &DynamicImage) -> Result<Text, Report> {
+    Err(Report::msg(UNAVAILABLE))
+}
+
+/// Reads tesseract's TSV output, a row for every block, paragraph, line
+/// and word, keeping the words and breaking lines where it did.
+#[cfg(feature = "ocr")]
+fn parse(tsv: &str) -> Text {
+    let mut text = String::new();
+    let mut words = Vec::new();
+    let mut last_line: Option<(&str, &str, &str)> = None;
+    // The first row names the columns.
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.splitn(12, '\t').collect();
+        let [level, _page, block, paragraph, line, _word, left, top, width, height, _confidence, word] =
+            columns[..]
+        else {
+            continue;
+        };
+        let word = word.trim();
+        if level != "5" || word.is_empty() {
+            continue;
+        }
+        let this_line = (block, paragraph, line);
+        match last_line {
+            Some((last_block, ..)) if last_block != block => text.push_str("\n\n"),
+            Some(last_line) if last_line != this_line => text.push('\n'),
+            Some(_) => text.push(' '),
+            None => {}
+        }
+        last_line = Some(this_line);
+        text.push_str(word);
+        let number = |column: &str| column.parse().unwrap_or(0);
+        words.push(Word {
+            text: word.to_string(),
+            left: number(left),
+            top: number(top),
+            width: number(width),
+            height: number(height),
+        });
+    }
+    Text { text, words }
+}
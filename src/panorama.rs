@@ -0,0 +1,174 @@
+use crate::capture::Capture;
+use crate::defects;
+use crate::mask::{self, Mask};
+use crate::pixel_format::RgbAFormat;
+use crate::{stitch, window};
+use clap::Args;
+use color_eyre::Report;
+use ggez::{
+    event::EventHandler,
+    graphics::{Canvas, Color, DrawParam, Image, ImageFormat, Rect, Text},
+    input::keyboard::{KeyCode, KeyInput},
+    Context, GameError,
+};
+use image::{DynamicImage, RgbaImage};
+use std::path::PathBuf;
+
+/// Share of each frame the next one should overlap, which the guide shows
+/// by drawing that much of the last frame at the left of the view.
+const OVERLAP: f32 = 0.4;
+const GUIDE_OPACITY: f32 = 0.5;
+
+/// Where `panorama` saves the stitched image.
+#[derive(Args, Clone)]
+pub struct PanoramaOptions {
+    /// Where the stitched panorama is saved.
+    #[arg(short, long, default_value = "panorama.png")]
+    pub output: PathBuf,
+}
+
+/// Guides a sweep from left to right: the right edge of the last frame
+/// taken is drawn faintly at the left of the view, and once the camera has
+/// turned far enough for it to line up, Space takes the next frame. Return
+/// stitches them and quits.
+pub struct PanoramaState {
+    capture: Capture,
+    options: PanoramaOptions,
+    masks: Vec<Mask>,
+    /// The newest camera frame, as taken and as shown.
+    live: Option<(RgbaImage, Image)>,
+    frames: Vec<RgbaImage>,
+    /// The last frame taken, shown as the guide.
+    guide: Option<Image>,
+}
+
+impl PanoramaState {
+    pub fn new(capture: Capture, options: PanoramaOptions, masks: Vec<Mask>) -> Self {
+        PanoramaState {
+            capture,
+            options,
+            masks,
+            live: None,
+            frames: Vec::new(),
+            guide: None,
+        }
+    }
+
+    fn receive(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        let frame = self
+            .capture
+            .frames
+            .recv()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let mut decoded = frame
+            .buffer
+            .decode_image::<RgbAFormat>()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let (width, height) = decoded.dimensions();
+        defects::correct(&mut decoded, width, height, 4);
+        mask::apply(&self.masks, &mut decoded, width, height, 4);
+        let image = to_image(ctx, &decoded);
+        self.live = Some((decoded, image));
+        Ok(())
+    }
+
+    fn take(&mut self) {
+        if let Some((frame, image)) = &self.live {
+            self.frames.push(frame.clone());
+            self.guide = Some(image.clone());
+            println!("Took frame {}", self.frames.len());
+        }
+    }
+
+    fn save(&self) -> Result<(), Report> {
+        if self.frames.len() < 2 {
+            return Err(Report::msg("a panorama needs at least two frames"));
+        }
+        println!("Stitching {} frames", self.frames.len());
+        let frames: Vec<_> = self
+            .frames
+            .iter()
+            .map(|frame| DynamicImage::ImageRgba8(frame.clone()).to_rgb8())
+            .collect();
+        let panorama = stitch::stitch(&frames)?;
+        panorama.save(&self.options.output)?;
+        println!(
+            "Saved {}x{} panorama to {}",
+            panorama.width(),
+            panorama.height(),
+            self.options.output.display()
+        );
+        Ok(())
+    }
+}
+
+fn to_image(ctx: &mut Context, image: &RgbaImage) -> Image {
+    Image::from_pixels(
+        ctx,
+        image.as_raw(),
+        ImageFormat::Rgba8UnormSrgb,
+        image.width(),
+        image.height(),
+    )
+}
+
+impl EventHandler<GameError> for PanoramaState {
+    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.receive(ctx)?;
+
+        let size = ctx.gfx.drawable_size();
+        let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+        if let Some((_, image)) = &self.live {
+            let (offset, scale) =
+                window::letterbox((image.width() as f32, image.height() as f32), size);
+            canvas.draw(image, DrawParam::new().dest(offset).scale([scale, scale]));
+            if let Some(guide) = &self.guide {
+                canvas.draw(
+                    guide,
+                    DrawParam::new()
+                        .src(Rect::new(1.0 - OVERLAP, 0.0, OVERLAP, 1.0))
+                        .dest(offset)
+                        .scale([scale, scale])
+                        .color(Color::new(1.0, 1.0, 1.0, GUIDE_OPACITY)),
+                );
+            }
+        }
+        let hint = match self.frames.len() {
+            0 => "Space to take the leftmost frame".to_string(),
+            taken => format!(
+                "{taken} taken. Turn right until the left edge lines up, then Space; Return to stitch"
+            ),
+        };
+        canvas.draw(
+            &Text::new(hint),
+            DrawParam::new().dest([8.0, 8.0]).color(Color::WHITE),
+        );
+        canvas.finish(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        repeated: bool,
+    ) -> Result<(), GameError> {
+        match input.keycode {
+            Some(KeyCode::Space) if !repeated => self.take(),
+            Some(KeyCode::Back) if !repeated => {
+                self.frames.pop();
+                self.guide = self.frames.last().map(|frame| to_image(ctx, frame));
+            }
+            Some(KeyCode::Return) => match self.save() {
+                Ok(()) => ctx.request_quit(),
+                Err(why) => eprintln!("failed to stitch: {why}"),
+            },
+            Some(KeyCode::Escape) => ctx.request_quit(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! A camera that isn't there: colour bars that move a little every frame,
+//! for trying athletic and testing it without hardware. Open it as the
+//! device `test-pattern`.
+
+use crate::capture::{Capture, Clock, ControlRequest, Counters, Queue, Sent};
+use crate::stream::Stream;
+use color_eyre::Report;
+use flume::Receiver;
+use nokhwa::utils::{CameraFormat, CameraIndex, CameraInfo, FrameFormat, Resolution};
+use nokhwa::Buffer;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The device name that opens the test pattern rather than a camera.
+pub const DEVICE: &str = "test-pattern";
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+const FPS: u32 = 30;
+
+/// The bars, as YUV: white, yellow, cyan, green, magenta, red, blue and
+/// black, in BT.601 video range.
+const BARS: [[u8; 3]; 8] = [
+    [235, 128, 128],
+    [210, 16, 146],
+    [170, 166, 16],
+    [145, 54, 34],
+    [106, 202, 222],
+    [81, 90, 240],
+    [41, 240, 110],
+    [16, 128, 128],
+];
+
+/// Whether `index` names the test pattern.
+pub fn is_pattern(index: &CameraIndex) -> bool {
+    matches!(index, CameraIndex::String(name) if name == DEVICE)
+}
+
+/// The test pattern's name and index, as a camera's.
+pub fn info() -> CameraInfo {
+    CameraInfo::new(
+        "athletic test pattern",
+        "Moving colour bars",
+        "",
+        CameraIndex::String(DEVICE.to_string()),
+    )
+}
+
+/// The only format the test pattern comes in.
+pub fn format() -> CameraFormat {
+    CameraFormat::new(Resolution::new(WIDTH, HEIGHT), FrameFormat::YUYV, FPS)
+}
+
+/// Starts the test pattern on its own thread, as [`crate::capture::spawn_capture`]
+/// starts a camera. It has no controls.
+pub fn spawn(queue: Queue) -> Capture {
+    let (sender, receiver) = flume::bounded(queue.depth);
+    let (control_sender, control_receiver) = flume::unbounded();
+    let counters = Arc::new(Counters::default());
+    let counted = counters.clone();
+    let resolution = format().resolution();
+
+    std::thread::spawn(move || {
+        let mut clock = Clock::default();
+        let started = Instant::now();
+        let interval = Duration::from_secs(1) / FPS;
+        for n in 0u32.. {
+            refuse_controls(&control_receiver, "the test pattern");
+            std::thread::sleep((started + interval * n).saturating_duration_since(Instant::now()));
+            counted.received.fetch_add(1, Ordering::Relaxed);
+            let buffer = Buffer::new(resolution, &frame(n), FrameFormat::YUYV);
+            match queue.send(&sender, clock.stamp(buffer)) {
+                Sent::Queued => {}
+                Sent::Dropped => {
+                    counted.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Sent::Gone => break,
+            }
+        }
+    });
+
+    Capture {
+        info: info(),
+        format: format(),
+        supported_controls: Vec::new(),
+        streams: vec![Stream::Main],
+        frames: receiver,
+        controls: control_sender,
+        counters,
+    }
+}
+
+/// Answers the control requests waiting in `requests` for a source
+/// without controls, `source`.
+pub(crate) fn refuse_controls(requests: &Receiver<ControlRequest>, source: &str) {
+    for request in requests.try_iter() {
+        match request {
+            ControlRequest::Set(id, _, _, reply) => {
+                let _ = reply.send(Err(Report::msg(format!("{source} has no {id} control"))));
+            }
+            ControlRequest::Describe(reply) => {
+                let _ = reply.send(Ok(Vec::new()));
+            }
+            ControlRequest::Nudge(..)
+            | ControlRequest::WatchConditions
+            | ControlRequest::WatchChanges(_) => {}
+        }
+    }
+}
+
+/// Frame `n`: the bars, moved `n` pixels to the left.
+fn frame(n: u32) -> Vec<u8> {
+    let bar = |x: u32| BARS[((x + n) % WIDTH * BARS.len() as u32 / WIDTH) as usize];
+    let mut row = Vec::with_capacity(WIDTH as usize * 2);
+    for x in (0..WIDTH).step_by(2) {
+        let ([y0, u, v], [y1, _, _]) = (bar(x), bar(x + 1));
+        row.extend_from_slice(&[y0, u, y1, v]);
+    }
+    row.repeat(HEIGHT as usize)
+}
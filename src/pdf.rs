@@ -0,0 +1,229 @@
+//! Just enough PDF to put scanned pages in one, an image to a page.
+
+use color_eyre::Report;
+use flate2::{write::ZlibEncoder, Compression};
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, GrayImage};
+use std::io::{self, Write};
+
+/// JPEG quality pages are stored at.
+const QUALITY: u8 = 85;
+
+/// Widths of Helvetica's characters average about half its size, which is
+/// what invisible words are stretched from to cover the word in the image.
+const CHARACTER_WIDTH: f32 = 0.5;
+
+/// A page's image, already compressed the way the PDF will hold it.
+struct Page {
+    width: u32,
+    height: u32,
+    colour_space: &'static str,
+    bits: u8,
+    filter: &'static str,
+    data: Vec<u8>,
+    words: Vec<Word>,
+}
+
+/// A word found in a page's image, with its box in the image's pixels.
+#[cfg_attr(not(feature = "ocr"), allow(dead_code))]
+pub struct Word {
+    pub text: String,
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A document built up page by page.
+pub struct Pdf {
+    /// Resolution the pages were scanned at, which sets their printed size.
+    dpi: f32,
+    pages: Vec<Page>,
+}
+
+impl Pdf {
+    pub fn new(dpi: f32) -> Self {
+        Pdf {
+            dpi,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Adds `image` as a page of its own, compressed as a JPEG and kept in
+    /// grey if it's grayscale.
+    pub fn add_page(&mut self, image: &DynamicImage) -> Result<(), Report> {
+        let mut data = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut data, QUALITY);
+        let colour_space = match image {
+            DynamicImage::ImageLuma8(gray) => {
+                encoder.encode_image(gray)?;
+                "/DeviceGray"
+            }
+            _ => {
+                encoder.encode_image(&image.to_rgb8())?;
+                "/DeviceRGB"
+            }
+        };
+        self.pages.push(Page {
+            width: image.width(),
+            height: image.height(),
+            colour_space,
+            bits: 8,
+            filter: "/DCTDecode",
+            data,
+            words: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Adds a black and white `page`, at one bit per pixel and compressed
+    /// losslessly, which keeps text sharp and pages a few tens of kilobytes.
+    pub fn add_bilevel_page(&mut self, page: &GrayImage) -> Result<(), Report> {
+        let (width, height) = page.dimensions();
+        // Rows are padded to whole bytes, with 1 for white.
+        let row_bytes = (width as usize).div_ceil(8);
+        let mut bits = vec![0u8; row_bytes * height as usize];
+        for (x, y, pixel) in page.enumerate_pixels() {
+            if pixel[0] >= 128 {
+                bits[y as usize * row_bytes + x as usize / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&bits)?;
+        self.pages.push(Page {
+            width,
+            height,
+            colour_space: "/DeviceGray",
+            bits: 1,
+            filter: "/FlateDecode",
+            data: encoder.finish()?,
+            words: Vec::new(),
+        });
+        Ok(())
+    }
+
+    /// Lays `words` invisibly over the last page added, so its text can be
+    /// searched and copied.
+    #[cfg(feature = "ocr")]
+    pub fn add_text(&mut self, words: Vec<Word>) {
+        if let Some(page) = self.pages.last_mut() {
+            page.words = words;
+        }
+    }
+
+    /// Writes the whole document to `out`.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        let mut out = Counting { out, written: 0 };
+        // Byte offsets of each object, which the cross-reference table lists.
+        let mut offsets = Vec::new();
+        out.write_all(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n")?;
+
+        // Objects are the catalog, the page tree, the font for text, then a
+        // page, its contents and its image for each page.
+        let page_ids: Vec<String> = (0..self.pages.len())
+            .map(|n| format!("{} 0 R", 4 + n * 3))
+            .collect();
+        offsets.push(out.written);
+        out.write_all(b"1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n")?;
+        offsets.push(out.written);
+        write!(
+            out,
+            "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
+            page_ids.join(" "),
+            self.pages.len()
+        )?;
+        offsets.push(out.written);
+        out.write_all(
+            b"3 0 obj\n<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica \
+              /Encoding /WinAnsiEncoding >>\nendobj\n",
+        )?;
+        for (n, page) in self.pages.iter().enumerate() {
+            let id = 4 + n * 3;
+            let points = |pixels: u32| pixels as f32 * 72.0 / self.dpi;
+            let (width, height) = (points(page.width), points(page.height));
+            offsets.push(out.written);
+            write!(
+                out,
+                "{id} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width:.2} {height:.2}] \
+                 /Resources << /XObject << /Im0 {} 0 R >> /Font << /F0 3 0 R >> >> \
+                 /Contents {} 0 R >>\nendobj\n",
+                id + 2,
+                id + 1
+            )?;
+            let mut contents = format!("q {width:.2} 0 0 {height:.2} 0 0 cm /Im0 Do Q");
+            for word in &page.words {
+                contents += &text(word, points, height);
+            }
+            offsets.push(out.written);
+            write!(
+                out,
+                "{} 0 obj\n<< /Length {} >>\nstream\n{contents}\nendstream\nendobj\n",
+                id + 1,
+                contents.len()
+            )?;
+            offsets.push(out.written);
+            write!(
+                out,
+                "{} 0 obj\n<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+                 /ColorSpace {} /BitsPerComponent {} /Filter {} /Length {} >>\nstream\n",
+                id + 2,
+                page.width,
+                page.height,
+                page.colour_space,
+                page.bits,
+                page.filter,
+                page.data.len()
+            )?;
+            out.write_all(&page.data)?;
+            out.write_all(b"\nendstream\nendobj\n")?;
+        }
+
+        let xref = out.written;
+        write!(out, "xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1)?;
+        for offset in &offsets {
+            writeln!(out, "{offset:010} 00000 n ")?;
+        }
+        write!(
+            out,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            offsets.len() + 1
+        )?;
+        out.flush()
+    }
+}
+
+/// Draws `word` invisibly (render mode 3) over where it is in the image,
+/// with `points` converting pixels to points on a page `height` points tall.
+fn text(word: &Word, points: impl Fn(u32) -> f32, height: f32) -> String {
+    // Only ASCII is kept as is, and \, ( and ) need escaping.
+    let mut escaped = String::new();
+    for character in word.text.chars() {
+        match character {
+            '\\' | '(' | ')' => escaped.extend(['\\', character]),
+            ' '..='~' => escaped.push(character),
+            _ => escaped.push('?'),
+        }
+    }
+    let size = points(word.height).max(1.0);
+    let natural = size * CHARACTER_WIDTH * word.text.chars().count().max(1) as f32;
+    let stretch = 100.0 * points(word.width) / natural;
+    let (x, y) = (points(word.left), height - points(word.top + word.height));
+    format!("\nBT 3 Tr /F0 {size:.2} Tf {stretch:.1} Tz {x:.2} {y:.2} Td ({escaped}) Tj ET")
+}
+
+/// Keeps count of the bytes written through it.
+struct Counting<W> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> Write for Counting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
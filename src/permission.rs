@@ -0,0 +1,70 @@
+//! Camera access on macOS, which the system grants per app the first time
+//! one asks. From a terminal it's the terminal app that's asked about and
+//! that the answer sticks to, so once it's been refused nokhwa can only
+//! fail to open anything; this says where to turn it back on instead.
+
+use nokhwa_bindings_macos::{
+    current_authorization_status, request_permission_with_callback, AVAuthorizationStatus,
+};
+use std::sync::mpsc;
+
+/// Makes sure athletic may use the camera, asking if it hasn't been
+/// decided yet, or says why not and how to allow it.
+pub fn ensure() -> Result<(), String> {
+    match current_authorization_status() {
+        AVAuthorizationStatus::Authorized => Ok(()),
+        AVAuthorizationStatus::NotDetermined => {
+            eprintln!(
+                "athletic needs the camera; macOS will ask whether {} may use it.",
+                terminal()
+            );
+            let (answered, answer) = mpsc::channel();
+            request_permission_with_callback(move |granted| {
+                let _ = answered.send(granted);
+            });
+            if answer.recv().unwrap_or(false) {
+                Ok(())
+            } else {
+                Err(denied())
+            }
+        }
+        AVAuthorizationStatus::Denied => Err(denied()),
+        AVAuthorizationStatus::Restricted => Err(format!(
+            "{} isn't allowed to use the camera, and it's not up to you: a configuration \
+             profile or Screen Time restricts it. Ask whoever manages this Mac.",
+            terminal()
+        )),
+    }
+}
+
+/// The app the permission belongs to, going by what terminals set
+/// `TERM_PROGRAM` to.
+fn terminal() -> String {
+    match std::env::var("TERM_PROGRAM").as_deref() {
+        Ok("Apple_Terminal") => "Terminal".to_string(),
+        Ok("iTerm.app") => "iTerm".to_string(),
+        Ok("vscode") => "Visual Studio Code".to_string(),
+        Ok("WezTerm") => "WezTerm".to_string(),
+        Ok(other) if !other.is_empty() => other.to_string(),
+        _ => "the app athletic runs in".to_string(),
+    }
+}
+
+fn denied() -> String {
+    let terminal = terminal();
+    let mut steps = format!(
+        "{terminal} isn't allowed to use the camera. To allow it:\n\
+         \x20 1. Open System Settings, then Privacy & Security, then Camera.\n\
+         \x20 2. Turn on {terminal}. If it isn't listed, run\n\
+         \x20    `tccutil reset Camera` and then athletic again to be asked anew.\n\
+         \x20 3. Quit {terminal} and open it again, as macOS only applies the\n\
+         \x20    change to apps started after it."
+    );
+    if std::env::var_os("SSH_CONNECTION").is_some() {
+        steps.push_str(
+            "\nOver SSH there's nobody for macOS to ask, so it refuses; run athletic \
+             once from a terminal on the Mac itself.",
+        );
+    }
+    steps
+}
@@ -0,0 +1,189 @@
+//! Frames written to a named pipe on Windows, or a FIFO elsewhere, for
+//! consumers such as OBS scripts that can read a stream but not map
+//! shared memory. The protocol is described in docs/pipe-sink.md.
+
+use crate::capture::{Queue, Sent};
+use crate::sink_frame::{SinkFrame, FRAME_HEADER_SIZE};
+use color_eyre::Report;
+use flume::{Receiver, Sender};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+const MAGIC: &[u8; 8] = b"ATHLPIPE";
+const VERSION: u32 = 1;
+
+pub struct PipeSink {
+    #[cfg(unix)]
+    path: PathBuf,
+    frames: Sender<Vec<u8>>,
+    queue: Queue,
+}
+
+/// Where a pipe called `name` lives: `name` itself if it is a path,
+/// otherwise a named pipe in `\\.\pipe\` on Windows or a FIFO in the
+/// temporary directory elsewhere.
+pub fn path(name: &str) -> PathBuf {
+    if name.contains(['/', '\\']) {
+        PathBuf::from(name)
+    } else if cfg!(windows) {
+        PathBuf::from(format!(r"\\.\pipe\{name}"))
+    } else {
+        std::env::temp_dir().join(name)
+    }
+}
+
+impl PipeSink {
+    /// Makes the pipe, with frames waiting for the reader in `queue`.
+    pub fn create(name: &str, queue: Queue) -> Result<Self, Report> {
+        let path = path(name);
+        #[cfg(unix)]
+        make_fifo(&path)?;
+
+        let (sender, receiver) = flume::bounded(queue.depth);
+        {
+            let path = path.clone();
+            std::thread::spawn(move || feed(&path, &receiver));
+        }
+        println!("Writing frames to {}", path.display());
+        Ok(PipeSink {
+            #[cfg(unix)]
+            path,
+            frames: sender,
+            queue,
+        })
+    }
+
+    pub fn write(&mut self, frame: &SinkFrame) -> Result<(), Report> {
+        let mut message = frame.header().to_vec();
+        message.extend_from_slice(frame.data);
+        match self.queue.send(&self.frames, message) {
+            Sent::Queued | Sent::Dropped => Ok(()),
+            Sent::Gone => Err(Report::msg("pipe writer stopped")),
+        }
+    }
+}
+
+impl Drop for PipeSink {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// What a reader is sent before the first frame.
+pub fn stream_header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(FRAME_HEADER_SIZE as u32).to_le_bytes());
+    header
+}
+
+/// Checks a stream header read from a pipe, returning the size of each
+/// frame header after it.
+#[cfg(target_os = "linux")]
+pub fn read_stream_header(header: &[u8; 16]) -> Result<usize, Report> {
+    if &header[..8] != MAGIC {
+        return Err(Report::msg("not an athletic frame stream"));
+    }
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let size = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+    if version != VERSION || size < FRAME_HEADER_SIZE {
+        return Err(Report::msg(format!(
+            "frame stream version {version} isn't one this athletic reads"
+        )));
+    }
+    Ok(size)
+}
+
+/// Waits for a reader, sends it frames until it goes away, and repeats.
+fn feed(path: &std::path::Path, frames: &Receiver<Vec<u8>>) {
+    loop {
+        let mut pipe = match connect(path) {
+            Ok(pipe) => pipe,
+            Err(why) => {
+                eprintln!("failed to open {}: {why}", path.display());
+                return;
+            }
+        };
+        // Frames queued while nobody was reading are stale by now.
+        frames.drain();
+        if pipe.write_all(&stream_header()).is_err() {
+            continue;
+        }
+        for frame in frames.iter() {
+            if pipe.write_all(&frame).is_err() {
+                break;
+            }
+        }
+        if frames.is_disconnected() {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn make_fifo(path: &std::path::Path) -> Result<(), Report> {
+    use std::os::unix::{ffi::OsStrExt, fs::FileTypeExt};
+
+    if let Ok(metadata) = std::fs::metadata(path) {
+        if metadata.file_type().is_fifo() {
+            return Ok(());
+        }
+        return Err(Report::msg(format!(
+            "{} exists and is not a FIFO",
+            path.display()
+        )));
+    }
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // Safety: `path` is a valid NUL-terminated string.
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Blocks until a reader opens the FIFO.
+#[cfg(unix)]
+fn connect(path: &std::path::Path) -> std::io::Result<File> {
+    std::fs::OpenOptions::new().write(true).open(path)
+}
+
+/// Creates a pipe instance and blocks until a reader connects to it.
+#[cfg(windows)]
+fn connect(path: &std::path::Path) -> std::io::Result<File> {
+    use std::os::windows::{ffi::OsStrExt, io::FromRawHandle};
+    use windows_sys::Win32::{
+        Foundation::{GetLastError, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_OUTBOUND,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_WAIT,
+        },
+    };
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // Safety: `name` is NUL-terminated and the handle is checked before use.
+    unsafe {
+        let handle = CreateNamedPipeW(
+            name.as_ptr(),
+            PIPE_ACCESS_OUTBOUND,
+            PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            1 << 20,
+            0,
+            0,
+            std::ptr::null(),
+        );
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error());
+        }
+        let pipe = File::from_raw_handle(handle);
+        if ConnectNamedPipe(handle, std::ptr::null_mut()) == 0
+            && GetLastError() != ERROR_PIPE_CONNECTED
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(pipe)
+    }
+}
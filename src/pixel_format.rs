@@ -0,0 +1,273 @@
+//! Stand-ins for nokhwa's `RgbFormat` and `RgbAFormat` that survive bad
+//! frames.
+//!
+//! nokhwa decodes MJPEG with mozjpeg, which reports a truncated or corrupt
+//! frame by unwinding through C, and that aborts the whole process rather
+//! than returning an error. Here MJPEG goes through jpeg-decoder instead,
+//! and raw frames that are empty or don't fit are refused.
+//!
+//! YUYV and NV12 are converted here too, as nokhwa's conversion always uses
+//! the BT.601 matrix and wraps colours past the edge of the gamut round to
+//! the other end rather than clamping them. Everything else is nokhwa's own
+//! conversion.
+
+use crate::layout::FrameLayout;
+use clap::ValueEnum;
+use color_eyre::Report;
+use jpeg_decoder::PixelFormat;
+use nokhwa::pixel_format::FormatDecoder;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::NokhwaError;
+use once_cell::sync::OnceCell;
+
+static COLOR_SPACE: OnceCell<ColorSpace> = OnceCell::new();
+
+/// Rows from which a frame is taken to be high definition, and so
+/// encoded with BT.709.
+const HD_ROWS: u32 = 720;
+
+/// The matrix YUV frames were encoded with, which cameras seldom say.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, ValueEnum)]
+pub enum ColorSpace {
+    /// BT.709 for frames 720 rows or taller and BT.601 below, as most
+    /// cameras encode.
+    #[default]
+    Auto,
+    /// BT.601, the standard definition matrix.
+    Bt601,
+    /// BT.709, the high definition matrix.
+    Bt709,
+}
+
+impl ColorSpace {
+    /// The matrix a frame of `resolution` is read with.
+    pub fn of(self, resolution: Resolution) -> ColorSpace {
+        match self {
+            ColorSpace::Auto if resolution.height() >= HD_ROWS => ColorSpace::Bt709,
+            ColorSpace::Auto => ColorSpace::Bt601,
+            space => space,
+        }
+    }
+
+    /// Converts a limited range YCbCr sample to RGB, clamping colours the
+    /// gamut can't hold. `Auto` is read as BT.601.
+    pub fn rgb(self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        // The matrices scaled by 256, from Y', Cb and Cr to R, G and B.
+        let (rv, gu, gv, bu) = match self {
+            ColorSpace::Auto | ColorSpace::Bt601 => (409, 100, 208, 516),
+            ColorSpace::Bt709 => (459, 55, 136, 541),
+        };
+        let c = (y as i32 - 16) * 298 + 128;
+        let (d, e) = (u as i32 - 128, v as i32 - 128);
+        let clamp = |x: i32| (x >> 8).clamp(0, 255) as u8;
+        [
+            clamp(c + rv * e),
+            clamp(c - gu * d - gv * e),
+            clamp(c + bu * d),
+        ]
+    }
+}
+
+/// Has YUV frames read with `space` from now on, rather than the matrix
+/// their size suggests.
+pub fn assume_color_space(space: ColorSpace) -> Result<(), Report> {
+    COLOR_SPACE
+        .set(space)
+        .map_err(|_| Report::msg("a colour space is already assumed"))
+}
+
+/// Decodes frames to 8-bit RGB.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RgbFormat;
+
+/// Decodes frames to 8-bit RGBA, opaque.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RgbAFormat;
+
+impl FormatDecoder for RgbFormat {
+    type Output = image::Rgb<u8>;
+    const FORMATS: &'static [FrameFormat] = nokhwa::pixel_format::RgbFormat::FORMATS;
+
+    fn write_output(
+        fcc: FrameFormat,
+        resolution: Resolution,
+        data: &[u8],
+    ) -> Result<Vec<u8>, NokhwaError> {
+        nonempty(fcc, resolution)?;
+        match fcc {
+            FrameFormat::MJPEG => jpeg(data, resolution, 3),
+            FrameFormat::YUYV | FrameFormat::NV12 => {
+                let mut dest = vec![0; FrameLayout::decoded::<RgbFormat>(resolution).bytes()];
+                yuv(fcc, resolution, data, &mut dest, 3)?;
+                Ok(dest)
+            }
+            _ => nokhwa::pixel_format::RgbFormat::write_output(fcc, resolution, data),
+        }
+    }
+
+    fn write_output_buffer(
+        fcc: FrameFormat,
+        resolution: Resolution,
+        data: &[u8],
+        dest: &mut [u8],
+    ) -> Result<(), NokhwaError> {
+        nonempty(fcc, resolution)?;
+        match fcc {
+            FrameFormat::MJPEG => copy(fcc, &jpeg(data, resolution, 3)?, dest),
+            FrameFormat::RAWRGB => copy(fcc, data, dest),
+            FrameFormat::YUYV | FrameFormat::NV12 => yuv(fcc, resolution, data, dest, 3),
+            _ => nokhwa::pixel_format::RgbFormat::write_output_buffer(fcc, resolution, data, dest),
+        }
+    }
+}
+
+impl FormatDecoder for RgbAFormat {
+    type Output = image::Rgba<u8>;
+    const FORMATS: &'static [FrameFormat] = nokhwa::pixel_format::RgbAFormat::FORMATS;
+
+    fn write_output(
+        fcc: FrameFormat,
+        resolution: Resolution,
+        data: &[u8],
+    ) -> Result<Vec<u8>, NokhwaError> {
+        nonempty(fcc, resolution)?;
+        match fcc {
+            FrameFormat::MJPEG => jpeg(data, resolution, 4),
+            FrameFormat::YUYV | FrameFormat::NV12 => {
+                let mut dest = vec![0; FrameLayout::decoded::<RgbAFormat>(resolution).bytes()];
+                yuv(fcc, resolution, data, &mut dest, 4)?;
+                Ok(dest)
+            }
+            _ => nokhwa::pixel_format::RgbAFormat::write_output(fcc, resolution, data),
+        }
+    }
+
+    fn write_output_buffer(
+        fcc: FrameFormat,
+        resolution: Resolution,
+        data: &[u8],
+        dest: &mut [u8],
+    ) -> Result<(), NokhwaError> {
+        nonempty(fcc, resolution)?;
+        match fcc {
+            FrameFormat::MJPEG => copy(fcc, &jpeg(data, resolution, 4)?, dest),
+            FrameFormat::YUYV | FrameFormat::NV12 => yuv(fcc, resolution, data, dest, 4),
+            FrameFormat::RAWRGB if dest.len() != data.len() / 3 * 4 => {
+                Err(error(fcc, "the frame doesn't fit the buffer".to_string()))
+            }
+            _ => nokhwa::pixel_format::RgbAFormat::write_output_buffer(fcc, resolution, data, dest),
+        }
+    }
+}
+
+fn error(fcc: FrameFormat, why: String) -> NokhwaError {
+    NokhwaError::ProcessFrameError {
+        src: fcc,
+        destination: "RGB".to_string(),
+        error: why,
+    }
+}
+
+/// nokhwa's NV12 conversion panics on frames with no width.
+fn nonempty(fcc: FrameFormat, resolution: Resolution) -> Result<(), NokhwaError> {
+    if resolution.width() == 0 || resolution.height() == 0 {
+        return Err(error(fcc, format!("can't decode a {resolution} frame")));
+    }
+    Ok(())
+}
+
+fn copy(fcc: FrameFormat, decoded: &[u8], dest: &mut [u8]) -> Result<(), NokhwaError> {
+    if decoded.len() != dest.len() {
+        return Err(error(fcc, "the frame doesn't fit the buffer".to_string()));
+    }
+    dest.copy_from_slice(decoded);
+    Ok(())
+}
+
+/// Converts a YUYV or NV12 frame of `resolution` into `dest`, `channels`
+/// bytes a pixel, the fourth being opaque alpha.
+fn yuv(
+    fcc: FrameFormat,
+    resolution: Resolution,
+    data: &[u8],
+    dest: &mut [u8],
+    channels: usize,
+) -> Result<(), NokhwaError> {
+    let (width, height) = (resolution.width() as usize, resolution.height() as usize);
+    let (length, even) = match fcc {
+        FrameFormat::NV12 => (width * height * 3 / 2, width % 2 == 0 && height % 2 == 0),
+        _ => (width * height * 2, width * height % 2 == 0),
+    };
+    if data.len() != length || !even {
+        return Err(error(fcc, format!("the frame isn't {resolution}")));
+    }
+    if dest.len() != width * height * channels {
+        return Err(error(fcc, "the frame doesn't fit the buffer".to_string()));
+    }
+    let space = COLOR_SPACE
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .of(resolution);
+    let mut pixels = dest.chunks_exact_mut(channels);
+    let mut put = |[r, g, b]: [u8; 3]| {
+        if let Some(pixel) = pixels.next() {
+            pixel[..3].copy_from_slice(&[r, g, b]);
+            if channels == 4 {
+                pixel[3] = 255;
+            }
+        }
+    };
+    match fcc {
+        FrameFormat::NV12 => {
+            let (luma, chroma) = data.split_at(width * height);
+            for (i, &y) in luma.iter().enumerate() {
+                let at = i / width / 2 * width + i % width / 2 * 2;
+                put(space.rgb(y, chroma[at], chroma[at + 1]));
+            }
+        }
+        _ => {
+            for yuyv in data.chunks_exact(4) {
+                put(space.rgb(yuyv[0], yuyv[1], yuyv[3]));
+                put(space.rgb(yuyv[2], yuyv[1], yuyv[3]));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a JPEG of `resolution` to `channels` bytes a pixel, the fourth
+/// being opaque alpha.
+fn jpeg(data: &[u8], resolution: Resolution, channels: usize) -> Result<Vec<u8>, NokhwaError> {
+    let fail = |why: String| error(FrameFormat::MJPEG, why);
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().map_err(|why| fail(why.to_string()))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| fail("no JPEG header".to_string()))?;
+    if (info.width as u32, info.height as u32) != (resolution.width(), resolution.height()) {
+        return Err(fail(format!(
+            "the frame is {}x{}, not {resolution}",
+            info.width, info.height
+        )));
+    }
+    let opaque = |[r, g, b]: [u8; 3]| [r, g, b, 255].into_iter().take(channels);
+    let decoded: Vec<u8> = match (info.pixel_format, channels) {
+        (PixelFormat::RGB24, 3) => pixels,
+        (PixelFormat::RGB24, _) => pixels
+            .chunks_exact(3)
+            .flat_map(|pixel| opaque([pixel[0], pixel[1], pixel[2]]))
+            .collect(),
+        (PixelFormat::L8, _) => pixels.iter().flat_map(|&l| opaque([l, l, l])).collect(),
+        // Big-endian, so the first byte of each is the one that shows.
+        (PixelFormat::L16, _) => pixels
+            .chunks_exact(2)
+            .flat_map(|l| opaque([l[0], l[0], l[0]]))
+            .collect(),
+        (PixelFormat::CMYK32, _) => return Err(fail("CMYK JPEGs aren't supported".to_string())),
+    };
+    if decoded.len() != info.width as usize * info.height as usize * channels {
+        return Err(fail("the JPEG is short of pixels".to_string()));
+    }
+    Ok(decoded)
+}
@@ -0,0 +1,995 @@
+use crate::layout::FrameLayout;
+use crate::pixel_format::RgbAFormat;
+use crate::{
+    annotate::{Annotations, Tool},
+    audio::{self, AudioOptions, Meter},
+    away::Away,
+    bad_frames::BadFrames,
+    bayer::{self, BayerOptions},
+    capture::{Capture, Frame},
+    clipboard,
+    config::Reload,
+    defects,
+    downscale::Downscale,
+    low_light::{LowLight, LowLightOptions},
+    mask::{self, Mask},
+    memory,
+    mono::{self, Depth, Windowing},
+    ptz::Ptz,
+    record::Recordings,
+    scopes::{self, Dock, Scope},
+    sink::Sinks,
+    sink_frame::{PixelFormat, SinkFrame},
+    summary,
+    thermal::{self, ThermalOptions},
+    timer::{Countdown, TimerOptions},
+    window::{self, Present},
+};
+use arboard::Clipboard;
+use clap::Args;
+use flume::{Receiver, TryRecvError};
+use ggez::graphics::ImageFormat;
+use ggez::{
+    event::{EventHandler, MouseButton},
+    glam::Vec2,
+    graphics::{Canvas, Color, DrawMode, DrawParam, Image, Mesh, Rect, Text, TextLayout},
+    input::gamepad::{
+        gilrs::{Axis, Button},
+        GamepadId,
+    },
+    input::keyboard::{KeyCode, KeyInput, KeyMods},
+    Context, GameError,
+};
+use nokhwa::utils::{CameraFormat, CameraInfo};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How the preview keeps and saves what it shows.
+#[derive(Args, Clone)]
+pub struct PreviewOptions {
+    /// How much recent footage to keep for stepping through while paused.
+    #[arg(long, value_parser = crate::parse_duration, default_value = "5s")]
+    pub replay_buffer: Duration,
+    /// Save the first frame as rendered in the window to this file, then quit.
+    #[arg(long)]
+    pub snapshot_rendered: Option<PathBuf>,
+    #[command(flatten)]
+    pub bayer: BayerOptions,
+    #[command(flatten)]
+    pub audio: AudioOptions,
+    /// How long T counts down before saving the rendered frame, 3s unless
+    /// given.
+    #[command(flatten)]
+    pub timer: TimerOptions,
+    #[command(flatten)]
+    pub low_light: LowLightOptions,
+    #[command(flatten)]
+    pub thermal: ThermalOptions,
+    /// Show a depth camera's depth, coloured from red up close to blue far
+    /// away, rather than its colour. V switches between them.
+    #[arg(long)]
+    pub depth_view: bool,
+    /// The distance shown blue in the depth view, and anything further.
+    #[arg(long, value_name = "METRES", default_value_t = 4.0)]
+    pub max_depth: f32,
+    /// Always draw at the camera's resolution, rather than at half of it
+    /// while the preview falls behind.
+    #[arg(long)]
+    pub full_resolution: bool,
+}
+
+pub struct CaptureState {
+    receiver: Arc<Receiver<Frame>>,
+    buffer: Vec<u8>,
+    info: CameraInfo,
+    format: CameraFormat,
+    title: String,
+    last_sequence: Option<u64>,
+    last_pts: Duration,
+    drawn: u64,
+    dropped: u64,
+    bad_frames: BadFrames,
+    /// Regions obscured before the frame is shown or written to a sink.
+    masks: Vec<Mask>,
+    away: Away,
+    replay: VecDeque<Frame>,
+    replay_length: Duration,
+    /// Whether the replay buffer has had to keep less than `replay_length`
+    /// to stay under the memory cap.
+    replay_shrunk: bool,
+    /// Index into `replay` of the frame on screen while paused.
+    paused_at: Option<usize>,
+    /// The decoded image on screen, and the sequence number it came from.
+    image: Option<(u64, Image)>,
+    /// Where the split between the frame before its filters, on the left,
+    /// and after them is, as a fraction of the width shown, while S shows
+    /// it.
+    split: Option<f32>,
+    /// The frame on screen as it was decoded, before the filters ran.
+    unfiltered: Vec<u8>,
+    before: Option<Image>,
+    /// Where to write the next rendered snapshot, and whether to quit afterwards.
+    snapshot: Option<(PathBuf, bool)>,
+    clipboard: Option<Clipboard>,
+    annotations: Annotations,
+    scope: Option<Scope>,
+    scope_image: Option<Image>,
+    dock: Dock,
+    zebra: bool,
+    /// How grayscale frames are stretched for display.
+    windowing: Windowing,
+    thermal: ThermalOptions,
+    /// The temperature at the centre of the frame on screen, in °C, from
+    /// a radiometric camera.
+    spot: Option<f32>,
+    /// Whether a depth camera's depth is shown rather than its colour, and
+    /// the distance coloured blue.
+    depth_view: bool,
+    max_depth: f32,
+    bayer: BayerOptions,
+    ptz: Ptz,
+    sinks: Sinks,
+    /// What North on a gamepad records, of the frames drawn while the
+    /// preview isn't paused.
+    recordings: Recordings,
+    meter: Option<Meter>,
+    timer: TimerOptions,
+    countdown: Option<Countdown>,
+    low_light: Option<LowLight>,
+    /// Halves what is drawn while the preview can't keep up, unless
+    /// --full-resolution is given.
+    downscale: Option<Downscale>,
+    present: Present,
+    /// When the next draw is due, at a fixed rate.
+    next_draw: Option<Instant>,
+    /// The profile followed for masks, filters and controls.
+    reload: Option<Reload>,
+}
+
+/// Luma from which zebra stripes are drawn, about 95%.
+const ZEBRA_THRESHOLD: u8 = 242;
+/// Width of the border and size of the text marking the camera as away.
+const AWAY_BORDER: f32 = 6.0;
+const AWAY_TEXT_SIZE: f32 = 24.0;
+/// Size of the countdown's digits, and the countdown T starts without --timer.
+const COUNTDOWN_SIZE: f32 = 200.0;
+const DEFAULT_TIMER: Duration = Duration::from_secs(3);
+/// Size of the crosshair marking the spot a temperature is read at, and of
+/// the reading.
+const SPOT_SIZE: f32 = 12.0;
+const SPOT_TEXT_SIZE: f32 = 20.0;
+/// Size of the audio meter in the bottom left corner.
+const METER_WIDTH: f32 = 160.0;
+const METER_HEIGHT: f32 = 10.0;
+/// Size of the labels on either side of the split view.
+const SPLIT_TEXT_SIZE: f32 = 20.0;
+
+impl CaptureState {
+    pub fn new(
+        capture: Capture,
+        options: PreviewOptions,
+        title: String,
+        sinks: Sinks,
+        bad_frames: BadFrames,
+        masks: Vec<Mask>,
+        away: Away,
+    ) -> Self {
+        let recordings = Recordings::new(&capture, &masks);
+        let ptz = Ptz::new(capture.controls, &capture.supported_controls);
+        // The preview is still useful without the meter.
+        let meter = Meter::open(&options.audio).unwrap_or_else(|why| {
+            eprintln!("failed to open the microphone: {why}");
+            None
+        });
+        CaptureState {
+            receiver: Arc::new(capture.frames),
+            buffer: Vec::new(),
+            info: capture.info,
+            format: capture.format,
+            title,
+            last_sequence: None,
+            last_pts: Duration::ZERO,
+            drawn: 0,
+            dropped: 0,
+            bad_frames,
+            masks,
+            away,
+            replay: VecDeque::new(),
+            replay_length: options.replay_buffer,
+            replay_shrunk: false,
+            paused_at: None,
+            image: None,
+            split: None,
+            unfiltered: Vec::new(),
+            before: None,
+            snapshot: options.snapshot_rendered.map(|path| (path, true)),
+            clipboard: None,
+            annotations: Annotations::new(),
+            scope: None,
+            scope_image: None,
+            dock: Dock::Right,
+            zebra: false,
+            windowing: Windowing::default(),
+            thermal: options.thermal,
+            spot: None,
+            depth_view: options.depth_view,
+            max_depth: options.max_depth,
+            bayer: options.bayer,
+            ptz,
+            sinks,
+            recordings,
+            meter,
+            timer: options.timer,
+            countdown: None,
+            low_light: LowLight::new(&options.low_light),
+            downscale: (!options.full_resolution).then(Downscale::default),
+            present: Present::Vsync,
+            next_draw: None,
+            reload: None,
+        }
+    }
+
+    /// Follows changes to the profile `reload` watches, setting its
+    /// controls now.
+    pub fn with_reload(mut self, mut reload: Option<Reload>) -> Self {
+        if let Some(reload) = &mut reload {
+            reload.attach(self.ptz.controls().clone());
+        }
+        self.reload = reload;
+        self
+    }
+
+    /// Draws as `present` says, rather than each time the camera sends a frame.
+    pub fn with_present(mut self, present: Present) -> Self {
+        self.present = present;
+        self
+    }
+
+    /// Takes the next frame if the camera has sent one, leaving the last
+    /// on screen otherwise.
+    fn receive(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        let frame = match self.receiver.try_recv() {
+            Ok(frame) => frame,
+            Err(TryRecvError::Empty) => return Ok(()),
+            Err(why) => return Err(GameError::RenderError(why.to_string())),
+        };
+        let (resolution, source) = (
+            frame.buffer.resolution(),
+            frame.buffer.source_frame_format(),
+        );
+        if resolution != self.format.resolution() || source != self.format.format() {
+            self.format.set_resolution(resolution);
+            self.format.set_format(source);
+            ctx.gfx
+                .set_window_title(&window::title(&self.title, &self.info, &self.format));
+        }
+        let dropped = match self.last_sequence {
+            Some(last) => frame.sequence.saturating_sub(last + 1),
+            None => 0,
+        };
+        self.dropped += dropped;
+        if let Some(downscale) = &mut self.downscale {
+            downscale.received(
+                self.receiver.len(),
+                dropped,
+                self.format.width(),
+                self.format.height(),
+            );
+        }
+        self.last_sequence = Some(frame.sequence);
+        self.last_pts = frame.pts;
+        self.drawn += 1;
+        self.recordings.send(&frame);
+
+        while let Some(oldest) = self.replay.front() {
+            if frame.pts.saturating_sub(oldest.pts) <= self.replay_length {
+                break;
+            }
+            self.replay.pop_front();
+        }
+        self.replay.push_back(frame);
+        // Over the memory cap, the replay buffer is the first to give way.
+        while memory::over() && self.replay.len() > 1 {
+            self.replay.pop_front();
+            if let Some(at) = self.paused_at.as_mut() {
+                *at = at.saturating_sub(1);
+            }
+            if !self.replay_shrunk {
+                self.replay_shrunk = true;
+                eprintln!(
+                    "keeping less than the replay buffer asks for, to stay under --max-memory"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused_at = match self.paused_at {
+            Some(_) => None,
+            None => self.replay.len().checked_sub(1),
+        };
+    }
+
+    /// Starts recording to a file named after the newest frame, or stops
+    /// the recording under way.
+    fn toggle_recording(&mut self) {
+        let result = match self.recordings.active() {
+            Some(_) => self.recordings.stop().map(drop),
+            None => {
+                let sequence = self.last_sequence.unwrap_or(0);
+                let path = PathBuf::from(format!("recording-{sequence}.mkv"));
+                self.recordings.start(&path)
+            }
+        };
+        if let Err(why) = result {
+            eprintln!("failed to record: {why}");
+        }
+    }
+
+    fn step(&mut self, forward: bool) {
+        if let Some(at) = self.paused_at.as_mut() {
+            *at = if forward {
+                (*at + 1).min(self.replay.len() - 1)
+            } else {
+                at.saturating_sub(1)
+            };
+        }
+    }
+
+    /// The frame on screen: the paused one, or else the newest.
+    fn current_frame(&self) -> Option<&Frame> {
+        match self.paused_at {
+            Some(at) => self.replay.get(at),
+            None => self.replay.back(),
+        }
+    }
+
+    fn copy_to_clipboard(&mut self) -> Result<(), color_eyre::Report> {
+        let Some(frame) = self.current_frame() else {
+            return Ok(());
+        };
+        let mut image = match (Depth::of(frame), self.bayer.bayer) {
+            (Some(depth), Some(pattern)) => {
+                let resolution = frame.buffer.resolution();
+                let mut image = image::RgbaImage::new(resolution.width(), resolution.height());
+                bayer::demosaic(frame, depth, pattern, self.bayer.demosaic, &mut image);
+                image
+            }
+            (Some(depth), None) => mono::image(frame, depth)
+                .ok_or_else(|| color_eyre::Report::msg("grayscale frame is too short"))?
+                .to_rgba8(),
+            (None, _) => frame.buffer.decode_image::<RgbAFormat>()?,
+        };
+        let (width, height) = image.dimensions();
+        defects::correct(&mut image, width, height, 4);
+        mask::apply(&self.masks, &mut image, width, height, 4);
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => clipboard,
+            None => self.clipboard.insert(Clipboard::new()?),
+        };
+        clipboard::copy_image(clipboard, &image)?;
+        Ok(())
+    }
+
+    /// Draws everything that ends up on screen for the current frame, on a
+    /// target of `size` pixels.
+    fn render(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        image: &Image,
+        size: (f32, f32),
+    ) -> Result<(), GameError> {
+        let (offset, scale) =
+            window::letterbox((image.width() as f32, image.height() as f32), size);
+        let view = self.ptz.view();
+        canvas.draw(
+            image,
+            DrawParam::new()
+                .src(view)
+                .dest(offset)
+                .scale([scale / view.w, scale / view.h]),
+        );
+        if let (Some(split), Some(before)) = (self.split, &self.before) {
+            self.draw_split(ctx, canvas, before, split, size)?;
+        }
+        let (offset, scale) = self.zoomed(size);
+        self.annotations.draw(ctx, canvas, offset, scale)?;
+
+        if self.away.is_active() {
+            let (width, height) = size;
+            let border = Mesh::new_rectangle(
+                ctx,
+                DrawMode::stroke(AWAY_BORDER),
+                Rect::new(0.0, 0.0, width, height),
+                Color::RED,
+            )?;
+            canvas.draw(&border, DrawParam::new());
+            let mut text = Text::new("AWAY: outgoing frames are blanked, B to resume");
+            text.set_scale(AWAY_TEXT_SIZE);
+            canvas.draw(
+                &text,
+                DrawParam::new()
+                    .dest([AWAY_BORDER * 2.0, AWAY_BORDER * 2.0])
+                    .color(Color::RED),
+            );
+        }
+
+        if let Some(celsius) = self.spot {
+            let centre = Vec2::from(offset)
+                + Vec2::new(self.format.width() as f32, self.format.height() as f32) / 2.0 * scale;
+            let mut cross = ggez::graphics::MeshBuilder::new();
+            cross
+                .line(
+                    &[centre - Vec2::X * SPOT_SIZE, centre + Vec2::X * SPOT_SIZE],
+                    2.0,
+                    Color::WHITE,
+                )?
+                .line(
+                    &[centre - Vec2::Y * SPOT_SIZE, centre + Vec2::Y * SPOT_SIZE],
+                    2.0,
+                    Color::WHITE,
+                )?;
+            canvas.draw(&Mesh::from_data(ctx, cross.build()), DrawParam::new());
+            let mut reading = Text::new(format!("{celsius:.1} °C"));
+            reading.set_scale(SPOT_TEXT_SIZE);
+            canvas.draw(
+                &reading,
+                DrawParam::new()
+                    .dest(centre + Vec2::splat(SPOT_SIZE))
+                    .color(Color::WHITE),
+            );
+        }
+
+        if let Some(meter) = &self.meter {
+            self.draw_meter(ctx, canvas, meter, size)?;
+        }
+
+        if let Some(countdown) = &self.countdown {
+            let mut text = Text::new(countdown.remaining().to_string());
+            text.set_scale(COUNTDOWN_SIZE)
+                .set_layout(TextLayout::center());
+            canvas.draw(
+                &text,
+                DrawParam::new()
+                    .dest([size.0 / 2.0, size.1 / 2.0])
+                    .color(Color::WHITE),
+            );
+        }
+
+        if let Some(scope) = &self.scope_image {
+            let (width, height) = size;
+            let panel = match self.dock {
+                Dock::Right => Rect::new(width * 2.0 / 3.0, 0.0, width / 3.0, height),
+                Dock::Bottom => Rect::new(0.0, height * 2.0 / 3.0, width, height / 3.0),
+            };
+            canvas.draw(
+                scope,
+                DrawParam::new().dest(panel.point()).scale([
+                    panel.w / scope.width() as f32,
+                    panel.h / scope.height() as f32,
+                ]),
+            );
+        }
+        Ok(())
+    }
+
+    /// Draws the part of the frame before its filters left of `split` over
+    /// the filtered one, with a line between them.
+    fn draw_split(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        before: &Image,
+        split: f32,
+        size: (f32, f32),
+    ) -> Result<(), GameError> {
+        let (offset, scale) =
+            window::letterbox((before.width() as f32, before.height() as f32), size);
+        let view = self.ptz.view();
+        canvas.draw(
+            before,
+            DrawParam::new()
+                .src(Rect::new(view.x, view.y, view.w * split, view.h))
+                .dest(offset)
+                .scale([scale / view.w, scale / view.h]),
+        );
+        let x = offset[0] + before.width() as f32 * scale * split;
+        let bottom = offset[1] + before.height() as f32 * scale;
+        let line = Mesh::new_line(ctx, &[[x, offset[1]], [x, bottom]], 2.0, Color::WHITE)?;
+        canvas.draw(&line, DrawParam::new());
+        for (label, at) in [("Before", offset[0]), ("After", x)] {
+            let mut text = Text::new(label);
+            text.set_scale(SPLIT_TEXT_SIZE);
+            canvas.draw(
+                &text,
+                DrawParam::new()
+                    .dest([
+                        at + SPLIT_TEXT_SIZE / 2.0,
+                        offset[1] + SPLIT_TEXT_SIZE / 2.0,
+                    ])
+                    .color(Color::WHITE),
+            );
+        }
+        Ok(())
+    }
+
+    /// Where the split goes for the mouse at `x` in the window.
+    fn split_at(&self, ctx: &Context, x: f32) -> f32 {
+        let image = (self.format.width() as f32, self.format.height() as f32);
+        let (offset, scale) = window::letterbox(image, ctx.gfx.drawable_size());
+        ((x - offset[0]) / (image.0 * scale)).clamp(0.0, 1.0)
+    }
+
+    /// Draws the audio level as a bar with its reading beside it, in red
+    /// while the microphone is silent.
+    fn draw_meter(
+        &self,
+        ctx: &Context,
+        canvas: &mut Canvas,
+        meter: &Meter,
+        size: (f32, f32),
+    ) -> Result<(), GameError> {
+        let db = meter.db();
+        let (x, y) = (METER_HEIGHT, size.1 - METER_HEIGHT * 3.0);
+        let fill = METER_WIDTH * (1.0 - db / audio::FLOOR_DB);
+        let colour = if meter.is_silent() {
+            Color::RED
+        } else if db > -6.0 {
+            Color::YELLOW
+        } else {
+            Color::GREEN
+        };
+        let outline = Mesh::new_rectangle(
+            ctx,
+            DrawMode::stroke(1.0),
+            Rect::new(x, y, METER_WIDTH, METER_HEIGHT),
+            Color::WHITE,
+        )?;
+        canvas.draw(&outline, DrawParam::new());
+        if fill >= 1.0 {
+            let bar = Mesh::new_rectangle(
+                ctx,
+                DrawMode::fill(),
+                Rect::new(x, y, fill, METER_HEIGHT),
+                colour,
+            )?;
+            canvas.draw(&bar, DrawParam::new());
+        }
+        let mut label = Text::new(format!("{db:.0} dB"));
+        label.set_scale(METER_HEIGHT * 1.6);
+        canvas.draw(
+            &label,
+            DrawParam::new()
+                .dest([x + METER_WIDTH + METER_HEIGHT, y - METER_HEIGHT * 0.3])
+                .color(colour),
+        );
+        Ok(())
+    }
+
+    /// Where frame pixel (0, 0) lands on a target of `size` and how much
+    /// frame pixels are scaled, taking the digital zoom into account.
+    fn zoomed(&self, size: (f32, f32)) -> ([f32; 2], f32) {
+        let image = Vec2::new(self.format.width() as f32, self.format.height() as f32);
+        let (offset, scale) = window::letterbox(image.into(), size);
+        let view = self.ptz.view();
+        let scale = scale / view.w;
+        let offset = Vec2::from(offset) - Vec2::from(view.point()) * image * scale;
+        (offset.into(), scale)
+    }
+
+    /// Maps a point in the window to the pixel of the frame under it.
+    fn to_image(&self, ctx: &Context, x: f32, y: f32) -> Vec2 {
+        let (offset, scale) = self.zoomed(ctx.gfx.drawable_size());
+        (Vec2::new(x, y) - Vec2::from(offset)) / scale
+    }
+
+    fn request_snapshot(&mut self) {
+        if let Some((sequence, _)) = &self.image {
+            let path = PathBuf::from(format!("rendered-{sequence}.png"));
+            self.snapshot = Some((path, false));
+        }
+    }
+
+    /// Renders the scene again into an offscreen image and saves it, so the
+    /// file shows exactly what the window does rather than the raw frame.
+    fn save_rendered(
+        &self,
+        ctx: &mut Context,
+        image: &Image,
+        path: &Path,
+    ) -> Result<(), GameError> {
+        let size = ctx.gfx.drawable_size();
+        let (width, height) = (size.0 as u32, size.1 as u32);
+        let target = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, width, height, 1);
+        let mut canvas = Canvas::from_image(ctx, target.clone(), Color::BLACK);
+        self.render(ctx, &mut canvas, image, size)?;
+        canvas.finish(ctx)?;
+
+        let pixels = target.to_pixels(ctx)?;
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| GameError::RenderError("rendered frame has the wrong size".into()))?
+            .save(path)
+            .map_err(|why| GameError::RenderError(why.to_string()))
+    }
+
+    fn decode(&mut self, ctx: &mut Context, frame: &Frame) -> Result<Image, GameError> {
+        let (width, height) = (self.format.width(), self.format.height());
+        let layout = FrameLayout::of::<RgbAFormat>(&self.format);
+        self.buffer.resize(layout.bytes(), 0);
+        match (Depth::of(frame), frame.planes.depth()) {
+            (_, Some(depth)) if self.depth_view => depth.colorize(self.max_depth, &mut self.buffer),
+            (Some(depth), _) => match self.bayer.bayer {
+                Some(pattern) => {
+                    bayer::demosaic(frame, depth, pattern, self.bayer.demosaic, &mut self.buffer)
+                }
+                None => {
+                    let windowing = if self.thermal.auto_range {
+                        Windowing::spanning(frame, depth)
+                    } else {
+                        self.windowing
+                    };
+                    let palette = self.thermal.palette.unwrap_or_default();
+                    windowing.render(frame, depth, palette, &mut self.buffer);
+                    self.spot = self
+                        .thermal
+                        .radiometric
+                        .and_then(|kelvin| thermal::spot(frame, depth, kelvin));
+                }
+            },
+            (None, _) => frame
+                .buffer
+                .decode_image_to_buffer::<RgbAFormat>(&mut self.buffer)
+                .map_err(|why| GameError::RenderError(why.to_string()))?,
+        }
+
+        self.before = match self.split {
+            Some(_) => {
+                self.unfiltered.clone_from(&self.buffer);
+                Some(Image::from_pixels(
+                    ctx,
+                    &self.unfiltered,
+                    ImageFormat::Rgba8UnormSrgb,
+                    width,
+                    height,
+                ))
+            }
+            None => None,
+        };
+        let pixels = &mut self.buffer[..];
+        defects::correct(pixels, width, height, 4);
+        if let Some(low_light) = &mut self.low_light {
+            low_light.apply(pixels, width, height, 4);
+        }
+        mask::apply(&self.masks, pixels, width, height, 4);
+        if self.paused_at.is_none() {
+            // The camera's own format can't be corrected, masked, blanked
+            // or brightened, so any of them turns passthrough off.
+            let away = self.away.is_active();
+            let passthrough = self.sinks.native()
+                && defects::map().is_none()
+                && self.masks.is_empty()
+                && !away
+                && self.low_light.is_none();
+            let data: &[u8] = if away {
+                self.away.card(width, height)
+            } else {
+                pixels
+            };
+            self.sinks.write(&if passthrough {
+                SinkFrame::native(frame)
+            } else {
+                SinkFrame {
+                    sequence: frame.sequence,
+                    pts: frame.pts,
+                    width,
+                    height,
+                    format: PixelFormat::Rgba,
+                    data,
+                    conditions: frame.conditions,
+                    planes: &frame.planes,
+                }
+            });
+        }
+        // Sinks have had the full frame; only what is drawn is halved.
+        let (pixels, width, height) = match &mut self.downscale {
+            Some(downscale) if downscale.is_half() => downscale.halve(pixels, width, height),
+            _ => (pixels, width, height),
+        };
+        self.scope_image = self.scope.map(|scope| {
+            let (scope, scope_width, scope_height) = scopes::compute(scope, pixels, width, height);
+            Image::from_pixels(
+                ctx,
+                &scope,
+                ImageFormat::Rgba8UnormSrgb,
+                scope_width,
+                scope_height,
+            )
+        });
+        if self.zebra {
+            scopes::zebra(pixels, width, ZEBRA_THRESHOLD);
+        }
+        // Frames are sRGB encoded. Saying so lets the GPU scale them in
+        // linear light and the window encode them back, rather than
+        // showing them darker or paler than they are.
+        Ok(Image::from_pixels(
+            ctx,
+            pixels,
+            ImageFormat::Rgba8UnormSrgb,
+            width,
+            height,
+        ))
+    }
+}
+
+impl EventHandler<GameError> for CaptureState {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.ptz.update(ctx.time.delta());
+        if let Some((options, masks)) = self.reload.as_mut().and_then(Reload::preview) {
+            self.masks = masks;
+            self.low_light = LowLight::new(&options.low_light);
+        }
+        if self.countdown.as_mut().is_some_and(Countdown::tick) {
+            self.countdown = None;
+            self.request_snapshot();
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        if let Some(interval) = self.present.interval() {
+            let now = Instant::now();
+            let due = self.next_draw.unwrap_or(now);
+            // Until it's due the window keeps what it showed last, and the
+            // event loop goes on handling input rather than waiting here.
+            if due > now {
+                return Ok(());
+            }
+            // A draw that ran a whole interval late starts the next one
+            // rather than being caught up on with several in a row.
+            let next = due + interval;
+            self.next_draw = Some(if next > now { next } else { now + interval });
+        }
+        if self.paused_at.is_none() {
+            self.receive(ctx)?;
+        }
+        // Nothing to draw until the first frame comes in.
+        let Some(newest) = self.replay.len().checked_sub(1) else {
+            return Ok(());
+        };
+        let index = self.paused_at.unwrap_or(newest);
+        let frame = self.replay[index].clone();
+        let fresh = !matches!(&self.image, Some((sequence, _)) if *sequence == frame.sequence);
+        let image = match self.image.take() {
+            Some((sequence, image)) if sequence == frame.sequence => image,
+            // A frame that doesn't decode, usually a JPEG truncated by a USB
+            // glitch, leaves the last good one on screen.
+            previous => match summary::decode(|| self.decode(ctx, &frame)) {
+                Ok(image) => image,
+                Err(why) => {
+                    self.bad_frames.record(&frame, why);
+                    match previous {
+                        Some((_, image)) => image,
+                        None => return Ok(()),
+                    }
+                }
+            },
+        };
+
+        summary::render(|| {
+            let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+            self.render(ctx, &mut canvas, &image, ctx.gfx.drawable_size())?;
+            canvas.finish(ctx)
+        })?;
+        if fresh {
+            summary::sent("window", 0);
+        }
+
+        if let Some((path, quit)) = self.snapshot.take() {
+            match self.save_rendered(ctx, &image, &path) {
+                Ok(()) => println!("Saved rendered frame to {}", path.display()),
+                Err(why) => eprintln!("failed to save rendered frame: {why}"),
+            }
+            if quit {
+                ctx.request_quit();
+            }
+        }
+        self.image = Some((frame.sequence, image));
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> Result<(), GameError> {
+        if self.annotations.typing() {
+            match input.keycode {
+                Some(KeyCode::Return | KeyCode::Escape) => self.annotations.finish(),
+                Some(KeyCode::Back) => self.annotations.undo(),
+                _ => {}
+            }
+            return Ok(());
+        }
+        if self.annotations.enabled {
+            let tool = match input.keycode {
+                Some(KeyCode::Key1) => Some(Tool::Pen),
+                Some(KeyCode::Key2) => Some(Tool::Rectangle),
+                Some(KeyCode::Key3) => Some(Tool::Arrow),
+                Some(KeyCode::Key4) => Some(Tool::Text),
+                Some(KeyCode::Back) => {
+                    self.annotations.undo();
+                    None
+                }
+                Some(KeyCode::Delete) => {
+                    self.annotations.clear();
+                    None
+                }
+                _ => None,
+            };
+            if let Some(tool) = tool {
+                self.annotations.tool = tool;
+            }
+        }
+        match input.keycode {
+            Some(KeyCode::W) => {
+                self.scope = Scope::next(self.scope);
+                self.image = None;
+            }
+            Some(KeyCode::Z) => {
+                self.zebra = !self.zebra;
+                self.image = None;
+            }
+            Some(KeyCode::LBracket | KeyCode::RBracket) => {
+                self.windowing
+                    .shift(input.keycode == Some(KeyCode::RBracket));
+                self.image = None;
+            }
+            Some(KeyCode::Minus | KeyCode::Equals) => {
+                self.windowing.widen(input.keycode == Some(KeyCode::Equals));
+                self.image = None;
+            }
+            Some(KeyCode::Key0) => {
+                self.windowing = Windowing::default();
+                self.image = None;
+            }
+            Some(KeyCode::R) => {
+                self.thermal.auto_range = !self.thermal.auto_range;
+                self.image = None;
+            }
+            Some(KeyCode::V) => {
+                self.depth_view = !self.depth_view;
+                self.image = None;
+            }
+            Some(KeyCode::S) => {
+                self.split = match self.split {
+                    Some(_) => None,
+                    None => Some(0.5),
+                };
+                self.image = None;
+            }
+            Some(KeyCode::B) => self.away.toggle(),
+            Some(KeyCode::D) => {
+                self.dock = match self.dock {
+                    Dock::Right => Dock::Bottom,
+                    Dock::Bottom => Dock::Right,
+                };
+            }
+            Some(KeyCode::A) => {
+                self.annotations.finish();
+                self.annotations.enabled = !self.annotations.enabled;
+            }
+            Some(KeyCode::C) if input.mods.contains(KeyMods::CTRL) => {
+                if let Err(why) = self.copy_to_clipboard() {
+                    eprintln!("failed to copy frame to the clipboard: {why}");
+                }
+            }
+            Some(KeyCode::Escape) => ctx.request_quit(),
+            Some(KeyCode::Space) => self.toggle_pause(),
+            Some(KeyCode::Left) => self.step(false),
+            Some(KeyCode::Right) => self.step(true),
+            Some(KeyCode::F12) => self.request_snapshot(),
+            Some(KeyCode::T) => {
+                let length = self.timer.timer.unwrap_or(DEFAULT_TIMER);
+                self.countdown = Some(Countdown::start(length, self.timer.beep));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        if button == MouseButton::Left {
+            // Drawing takes the mouse over dragging the split.
+            if self.split.is_some() && !self.annotations.enabled {
+                self.split = Some(self.split_at(ctx, x));
+            } else {
+                self.annotations.press(self.to_image(ctx, x, y));
+            }
+        }
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        _dx: f32,
+        _dy: f32,
+    ) -> Result<(), GameError> {
+        if ctx.mouse.button_pressed(MouseButton::Left) {
+            if self.split.is_some() && !self.annotations.enabled {
+                self.split = Some(self.split_at(ctx, x));
+            } else {
+                self.annotations.drag(self.to_image(ctx, x, y));
+            }
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> Result<(), GameError> {
+        if button == MouseButton::Left {
+            self.annotations.release();
+        }
+        Ok(())
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> Result<(), GameError> {
+        self.annotations.type_char(character);
+        Ok(())
+    }
+
+    fn gamepad_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: Button,
+        _id: GamepadId,
+    ) -> Result<(), GameError> {
+        match button {
+            Button::South => self.request_snapshot(),
+            Button::East => self.toggle_pause(),
+            Button::North => self.toggle_recording(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn gamepad_axis_event(
+        &mut self,
+        _ctx: &mut Context,
+        axis: Axis,
+        value: f32,
+        _id: GamepadId,
+    ) -> Result<(), GameError> {
+        self.ptz.axis(axis, value);
+        Ok(())
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> Result<bool, GameError> {
+        if self.recordings.active().is_some() {
+            self.toggle_recording();
+        }
+        println!(
+            "Drew {} frames over {:.2?}, {} dropped, {} corrupt.",
+            self.drawn, self.last_pts, self.dropped, self.bad_frames.count
+        );
+        Ok(false)
+    }
+}
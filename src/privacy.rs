@@ -0,0 +1,216 @@
+//! Why Windows won't let athletic have a camera. Media Foundation fails
+//! the same way whether the camera was turned off in the privacy settings
+//! or another app has it, as Windows gives a camera to one app at a time;
+//! the settings, and which apps are using the camera, are in the registry,
+//! so a failed open can say which it is.
+
+/// A privacy setting that keeps the camera from athletic, each one a
+/// switch under Settings > Privacy & security > Camera.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Blocked {
+    /// Camera access, which is off for every app and every user.
+    Device,
+    /// Let apps access your camera.
+    Apps,
+    /// Let desktop apps access your camera, which athletic is one of.
+    DesktopApps,
+}
+
+impl Blocked {
+    /// How to turn it back on.
+    pub fn guidance(self) -> &'static str {
+        match self {
+            Blocked::Device => {
+                "the camera is turned off for this device. Turn on Camera access in \
+                 Settings > Privacy & security > Camera; it takes an administrator."
+            }
+            Blocked::Apps => {
+                "apps aren't allowed to use the camera. Turn on Let apps access your \
+                 camera in Settings > Privacy & security > Camera."
+            }
+            Blocked::DesktopApps => {
+                "desktop apps aren't allowed to use the camera. Turn on Let desktop apps \
+                 access your camera in Settings > Privacy & security > Camera."
+            }
+        }
+    }
+}
+
+/// The name of the app the consent store keeps under `key`: a desktop app
+/// by its path with `#` for each `\`, or a Store app by its package family
+/// name, whose publisher hash says nothing to anyone.
+pub fn app_name(key: &str) -> String {
+    if let Some((_, file)) = key.rsplit_once('#') {
+        return file.to_string();
+    }
+    match key.rsplit_once('_') {
+        Some((name, hash))
+            if hash.len() == 13 && hash.bytes().all(|b| b.is_ascii_alphanumeric()) =>
+        {
+            name.to_string()
+        }
+        _ => key.to_string(),
+    }
+}
+
+#[cfg(windows)]
+const WEBCAM: &str =
+    r"Software\Microsoft\Windows\CurrentVersion\CapabilityAccessManager\ConsentStore\webcam";
+
+/// The privacy setting keeping the camera from athletic, if one does.
+#[cfg(windows)]
+pub fn blocked() -> Option<Blocked> {
+    use registry::{string, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+
+    let denied = |root, key: &str| string(root, key, "Value").as_deref() == Some("Deny");
+    let non_packaged = format!(r"{WEBCAM}\NonPackaged");
+    if denied(HKEY_LOCAL_MACHINE, WEBCAM) {
+        Some(Blocked::Device)
+    } else if denied(HKEY_CURRENT_USER, WEBCAM) {
+        Some(Blocked::Apps)
+    } else if denied(HKEY_CURRENT_USER, &non_packaged) {
+        Some(Blocked::DesktopApps)
+    } else {
+        None
+    }
+}
+
+/// The apps using a camera right now, other than athletic: the ones that
+/// started using one and haven't stopped.
+#[cfg(windows)]
+pub fn in_use() -> Vec<String> {
+    use registry::{qword, subkeys, HKEY_CURRENT_USER};
+
+    let own = std::env::current_exe()
+        .ok()
+        .map(|path| path.to_string_lossy().replace('\\', "#"));
+    let non_packaged = format!(r"{WEBCAM}\NonPackaged");
+    let mut apps = Vec::new();
+    for parent in [WEBCAM, non_packaged.as_str()] {
+        for key in subkeys(HKEY_CURRENT_USER, parent) {
+            let path = format!(r"{parent}\{key}");
+            let started = qword(HKEY_CURRENT_USER, &path, "LastUsedTimeStart").unwrap_or(0);
+            let stopped = qword(HKEY_CURRENT_USER, &path, "LastUsedTimeStop").unwrap_or(0);
+            if started != 0 && stopped == 0 && own.as_deref() != Some(key.as_str()) {
+                apps.push(app_name(&key));
+            }
+        }
+    }
+    apps
+}
+
+/// Adds why the camera couldn't be opened, where Windows can tell, to
+/// Media Foundation's failure `why`.
+#[cfg(windows)]
+pub fn diagnose(why: color_eyre::Report) -> color_eyre::Report {
+    let hint = if let Some(blocked) = blocked() {
+        blocked.guidance().to_string()
+    } else {
+        match in_use().as_slice() {
+            [] => return why,
+            [app] => format!(
+                "{app} is using a camera, and Windows lets only one app have it; \
+                 close it and try again."
+            ),
+            apps => format!(
+                "{} are using cameras, and Windows lets only one app have each; \
+                 close the one using this camera and try again.",
+                apps.join(", ")
+            ),
+        }
+    };
+    color_eyre::Report::msg(format!("{why}\nThat's likely because {hint}"))
+}
+
+#[cfg(windows)]
+mod registry {
+    pub use windows_sys::Win32::System::Registry::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    use windows_sys::Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{
+            RegCloseKey, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY, KEY_READ,
+            RRF_RT_REG_QWORD, RRF_RT_REG_SZ,
+        },
+    };
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain([0]).collect()
+    }
+
+    /// The string `value` of `key` under `root`.
+    pub fn string(root: HKEY, key: &str, value: &str) -> Option<String> {
+        let (key, value) = (wide(key), wide(value));
+        let mut buffer = [0u16; 256];
+        let mut bytes = std::mem::size_of_val(&buffer) as u32;
+        // Safety: the names are NUL-terminated and `bytes` is the buffer's size.
+        let status = unsafe {
+            RegGetValueW(
+                root,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_SZ,
+                std::ptr::null_mut(),
+                buffer.as_mut_ptr().cast(),
+                &mut bytes,
+            )
+        };
+        (status == ERROR_SUCCESS).then(|| {
+            let chars = &buffer[..bytes as usize / 2];
+            String::from_utf16_lossy(chars.strip_suffix(&[0]).unwrap_or(chars))
+        })
+    }
+
+    /// The 64-bit `value` of `key` under `root`.
+    pub fn qword(root: HKEY, key: &str, value: &str) -> Option<u64> {
+        let (key, value) = (wide(key), wide(value));
+        let mut data = 0u64;
+        let mut bytes = std::mem::size_of::<u64>() as u32;
+        // Safety: the names are NUL-terminated and `bytes` is `data`'s size.
+        let status = unsafe {
+            RegGetValueW(
+                root,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_QWORD,
+                std::ptr::null_mut(),
+                (&mut data as *mut u64).cast(),
+                &mut bytes,
+            )
+        };
+        (status == ERROR_SUCCESS).then_some(data)
+    }
+
+    /// The names of the keys right under `key`, or none if it can't be read.
+    pub fn subkeys(root: HKEY, key: &str) -> Vec<String> {
+        let key = wide(key);
+        let mut handle: HKEY = std::ptr::null_mut();
+        let mut names = Vec::new();
+        // Safety: `key` is NUL-terminated, `handle` is only used once opened
+        // and closed after, and each name's length is the buffer's size.
+        unsafe {
+            if RegOpenKeyExW(root, key.as_ptr(), 0, KEY_READ, &mut handle) != ERROR_SUCCESS {
+                return names;
+            }
+            let mut buffer = [0u16; 256];
+            for index in 0.. {
+                let mut chars = buffer.len() as u32;
+                let status = RegEnumKeyExW(
+                    handle,
+                    index,
+                    buffer.as_mut_ptr(),
+                    &mut chars,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                );
+                if status != ERROR_SUCCESS {
+                    break;
+                }
+                names.push(String::from_utf16_lossy(&buffer[..chars as usize]));
+            }
+            RegCloseKey(handle);
+        }
+        names
+    }
+}
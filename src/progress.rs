@@ -0,0 +1,152 @@
+use crate::bus::{self, Event};
+use color_eyre::Report;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a recording reports its progress.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Style {
+    /// A status line on standard error, rewritten in place.
+    Line,
+    /// Every event as a JSON object per line on standard output, as
+    /// `--publish stdout` prints them.
+    Json,
+    Off,
+}
+
+impl FromStr for Style {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" | "LINE" => Ok(Style::Line),
+            "json" | "JSON" | "json-progress" => Ok(Style::Json),
+            "off" | "OFF" | "none" => Ok(Style::Off),
+            _ => Err(Report::msg(format!("unknown progress style: {s}"))),
+        }
+    }
+}
+
+/// Reports how a recording is going about once a second.
+pub struct Progress {
+    style: Style,
+    /// The recording, whose disk is watched for space.
+    path: PathBuf,
+    start: Instant,
+    /// When the last report went out, and the bytes written by then.
+    last: (Instant, u64),
+}
+
+impl Progress {
+    pub fn new(style: Style, path: &Path) -> Self {
+        let now = Instant::now();
+        Progress {
+            style,
+            path: path.to_path_buf(),
+            start: now,
+            last: (now, 0),
+        }
+    }
+
+    /// Notes that `frames` frames and `bytes` bytes have been written so far,
+    /// reporting them if it's time. Progress is published whatever the
+    /// style.
+    pub fn update(&mut self, frames: u64, bytes: u64) {
+        let now = Instant::now();
+        let since = now - self.last.0;
+        if since < INTERVAL {
+            return;
+        }
+        let elapsed = now - self.start;
+        let bitrate = (bytes - self.last.1) as f64 * 8.0 / since.as_secs_f64();
+        let per_hour = bytes as f64 / elapsed.as_secs_f64() * 3600.0;
+        let free = free_space(&self.path);
+        self.last = (now, bytes);
+
+        bus::publish(Event::Progress {
+            elapsed: elapsed.as_secs_f64(),
+            frames,
+            bytes,
+            bitrate,
+            bytes_per_hour: per_hour,
+            free,
+        });
+        match self.style {
+            Style::Line => {
+                let seconds = elapsed.as_secs();
+                let free = free.map_or("?".into(), |free| size(free as f64));
+                eprint!(
+                    "\r{:02}:{:02}:{:02}  {frames} frames  {:.1} Mbit/s  {}/h  {free} free ",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60,
+                    bitrate / 1e6,
+                    size(per_hour),
+                );
+                let _ = std::io::stderr().flush();
+            }
+            Style::Json | Style::Off => {}
+        }
+    }
+
+    /// Ends the status line, so whatever's printed next starts on its own.
+    pub fn finish(&self) {
+        if self.style == Style::Line && self.last.0 != self.start {
+            eprintln!();
+        }
+    }
+}
+
+fn size(bytes: f64) -> String {
+    match bytes {
+        bytes if bytes >= 1e9 => format!("{:.1} GB", bytes / 1e9),
+        bytes if bytes >= 1e6 => format!("{:.1} MB", bytes / 1e6),
+        bytes => format!("{:.0} kB", bytes / 1e3),
+    }
+}
+
+/// Bytes free for unprivileged users on the disk holding `path`.
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    let directory = directory.unwrap_or(Path::new("."));
+    let directory = std::ffi::CString::new(directory.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // Safety: `directory` is NUL-terminated and `stats` is a valid statvfs.
+    if unsafe { libc::statvfs(directory.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+/// Bytes free for the current user on the disk holding `path`.
+#[cfg(windows)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    let directory = directory.unwrap_or(Path::new("."));
+    let wide: Vec<u16> = directory.as_os_str().encode_wide().chain([0]).collect();
+    let mut free = 0u64;
+    // Safety: `wide` is NUL-terminated and the out-pointers are valid or null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(free)
+}
@@ -0,0 +1,105 @@
+use crate::capture::ControlRequest;
+use flume::Sender;
+use ggez::{glam::Vec2, graphics::Rect, input::gamepad::gilrs::Axis};
+use nokhwa::utils::KnownCameraControl;
+use std::time::Duration;
+
+/// Stick deflection below which the stick counts as centred.
+const DEAD_ZONE: f32 = 0.15;
+/// How often held sticks are turned into control changes for the camera.
+const NUDGE_INTERVAL: Duration = Duration::from_millis(100);
+/// Fraction of a control's range covered per second at full deflection.
+const HARDWARE_SPEED: f64 = 0.25;
+/// Digital zoom factor gained per second at full deflection.
+const ZOOM_SPEED: f32 = 2.0;
+const MAX_ZOOM: f32 = 8.0;
+
+const CONTROLS: [KnownCameraControl; 3] = [
+    KnownCameraControl::Pan,
+    KnownCameraControl::Tilt,
+    KnownCameraControl::Zoom,
+];
+
+/// Drives pan, tilt and zoom from analog sticks: through the camera's own
+/// controls where it has them, otherwise by cropping the frame.
+pub struct Ptz {
+    controls: Sender<ControlRequest>,
+    /// Which of `CONTROLS` the camera supports.
+    hardware: [bool; 3],
+    /// Current deflection of the stick driving each of `CONTROLS`.
+    sticks: [f32; 3],
+    since_nudge: Duration,
+    zoom: f32,
+    /// Centre of the cropped view, in UV coordinates.
+    center: Vec2,
+}
+
+impl Ptz {
+    pub fn new(controls: Sender<ControlRequest>, supported: &[KnownCameraControl]) -> Self {
+        Ptz {
+            controls,
+            hardware: CONTROLS.map(|control| supported.contains(&control)),
+            sticks: [0.0; 3],
+            since_nudge: Duration::ZERO,
+            zoom: 1.0,
+            center: Vec2::splat(0.5),
+        }
+    }
+
+    /// Where requests for the camera's controls go.
+    pub fn controls(&self) -> &Sender<ControlRequest> {
+        &self.controls
+    }
+
+    pub fn axis(&mut self, axis: Axis, value: f32) {
+        let value = if value.abs() < DEAD_ZONE { 0.0 } else { value };
+        match axis {
+            Axis::LeftStickX => self.sticks[0] = value,
+            Axis::LeftStickY => self.sticks[1] = value,
+            Axis::RightStickY => self.sticks[2] = value,
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f32();
+        for (n, &stick) in self.sticks.iter().enumerate() {
+            if stick == 0.0 || self.hardware[n] {
+                continue;
+            }
+            match n {
+                0 => self.center.x += stick * seconds / self.zoom,
+                1 => self.center.y -= stick * seconds / self.zoom,
+                _ => {
+                    self.zoom = (self.zoom * ZOOM_SPEED.powf(stick * seconds)).clamp(1.0, MAX_ZOOM)
+                }
+            }
+        }
+        let half = 0.5 / self.zoom;
+        self.center = self
+            .center
+            .clamp(Vec2::splat(half), Vec2::splat(1.0 - half));
+
+        self.since_nudge += elapsed;
+        if self.since_nudge < NUDGE_INTERVAL {
+            return;
+        }
+        let interval = self.since_nudge.as_secs_f64();
+        self.since_nudge = Duration::ZERO;
+        for (n, &stick) in self.sticks.iter().enumerate() {
+            if stick != 0.0 && self.hardware[n] {
+                let fraction = stick as f64 * HARDWARE_SPEED * interval;
+                let _ = self
+                    .controls
+                    .send(ControlRequest::Nudge(CONTROLS[n], fraction));
+            }
+        }
+    }
+
+    /// The part of the frame to show, in UV coordinates.
+    pub fn view(&self) -> Rect {
+        let size = 1.0 / self.zoom;
+        let corner = self.center - Vec2::splat(size / 2.0);
+        Rect::new(corner.x, corner.y, size, size)
+    }
+}
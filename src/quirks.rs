@@ -0,0 +1,168 @@
+//! Capture cards that generic UVC handling gets wrong. HDMI capture cards
+//! list the modes their EDID offers the source rather than what they can
+//! stream, and some list frame rates they never deliver, so nokhwa picks a
+//! mode that opens and then gives nothing, or half the frames it should.
+//!
+//! A [`Quirk`] keeps such a card to the modes that do work, by its USB
+//! vendor and product ids. The built-in ones are applied to every camera
+//! opened; the config may replace them or add its own.
+
+use color_eyre::Report;
+use nokhwa::utils::{CameraFormat, CameraIndex, Resolution};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A USB device's vendor and product ids, written `0fd9:0066` as `lsusb`
+/// prints them.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct UsbId {
+    pub vendor: u16,
+    pub product: u16,
+}
+
+impl UsbId {
+    /// The ids in a platform's name for a device: a Windows device path,
+    /// as `\\?\usb#vid_0fd9&pid_0066&mi_00#...`, or an AVFoundation
+    /// unique id, the location with the ids in its low bits in hex.
+    pub fn in_name(name: &str) -> Option<UsbId> {
+        let lower = name.to_ascii_lowercase();
+        if let Some(hex) = lower.strip_prefix("0x") {
+            let id = u64::from_str_radix(hex, 16).ok()?;
+            return Some(UsbId {
+                vendor: (id >> 16) as u16,
+                product: id as u16,
+            });
+        }
+        let after = |key: &str| {
+            let start = lower.find(key)? + key.len();
+            u16::from_str_radix(lower.get(start..start + 4)?, 16).ok()
+        };
+        Some(UsbId {
+            vendor: after("vid_")?,
+            product: after("pid_")?,
+        })
+    }
+
+    /// The ids of the camera at `index`, if it's a USB one.
+    pub fn of(index: &CameraIndex) -> Option<UsbId> {
+        #[cfg(target_os = "linux")]
+        {
+            // The node's device is the USB interface; the ids are on the
+            // USB device it belongs to.
+            let CameraIndex::Index(node) = index else {
+                return None;
+            };
+            let device = format!("/sys/class/video4linux/video{node}/device/..");
+            let id = |file: &str| {
+                let hex = std::fs::read_to_string(format!("{device}/{file}")).ok()?;
+                u16::from_str_radix(hex.trim(), 16).ok()
+            };
+            Some(UsbId {
+                vendor: id("idVendor")?,
+                product: id("idProduct")?,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let cameras = nokhwa::query(nokhwa::native_api_backend()?).ok()?;
+            let camera = cameras.iter().find(|camera| camera.index() == index)?;
+            UsbId::in_name(&camera.misc())
+        }
+    }
+}
+
+impl FromStr for UsbId {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let id = |hex: &str| u16::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 4);
+        match s.split_once(':') {
+            Some((vendor, product)) => match (id(vendor), id(product)) {
+                (Some(vendor), Some(product)) => Ok(UsbId { vendor, product }),
+                _ => Err(Report::msg(format!(
+                    "USB ids are two four digit hex numbers, as 0fd9:0066, not {s}"
+                ))),
+            },
+            None => Err(Report::msg(format!(
+                "expected VENDOR:PRODUCT, as 0fd9:0066, got: {s}"
+            ))),
+        }
+    }
+}
+
+impl Display for UsbId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vendor, self.product)
+    }
+}
+
+/// What a card really streams, where it says otherwise.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Quirk {
+    pub card: String,
+    pub id: UsbId,
+    /// The only resolution that streams, where the others it lists depend
+    /// on what the source is sending.
+    pub resolution: Option<Resolution>,
+    /// The highest frame rate it delivers, where it lists higher ones.
+    pub max_frame_rate: Option<u32>,
+}
+
+impl Quirk {
+    /// The cards athletic knows about.
+    pub fn builtin() -> Vec<Quirk> {
+        let quirk = |card: &str, vendor, product, resolution, max_frame_rate| Quirk {
+            card: card.to_string(),
+            id: UsbId { vendor, product },
+            resolution,
+            max_frame_rate,
+        };
+        vec![
+            // Both ask sources for 1080p60 unless Elgato's software gave
+            // them another EDID, yet list 4K modes that only stream while
+            // a 4K signal comes in.
+            quirk(
+                "Elgato Cam Link 4K",
+                0x0fd9,
+                0x0066,
+                Some(Resolution::new(1920, 1080)),
+                None,
+            ),
+            quirk(
+                "Elgato HD60 S+",
+                0x0fd9,
+                0x006a,
+                Some(Resolution::new(1920, 1080)),
+                None,
+            ),
+            // The MS2109 in most unbranded HDMI dongles lists 60 fps
+            // modes but, on USB 2, delivers 30.
+            quirk("MacroSilicon MS2109", 0x534d, 0x2109, None, Some(30)),
+        ]
+    }
+
+    /// The formats among `offered` the card really streams.
+    pub fn restrict(&self, offered: &[CameraFormat]) -> Vec<CameraFormat> {
+        offered
+            .iter()
+            .filter(|format| self.resolution.is_none_or(|r| format.resolution() == r))
+            .filter(|format| {
+                self.max_frame_rate
+                    .is_none_or(|fps| format.frame_rate() <= fps)
+            })
+            .copied()
+            .collect()
+    }
+}
+
+impl Display for Quirk {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.card, self.id)?;
+        match (self.resolution, self.max_frame_rate) {
+            (Some(resolution), Some(fps)) => write!(f, ": only {resolution}, up to {fps} fps"),
+            (Some(resolution), None) => write!(f, ": only {resolution}"),
+            (None, Some(fps)) => write!(f, ": up to {fps} fps"),
+            (None, None) => write!(f, ": nothing"),
+        }
+    }
+}
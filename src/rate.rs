@@ -0,0 +1,181 @@
+use clap::Args;
+use color_eyre::Report;
+use image::{codecs::jpeg::JpegEncoder, RgbImage};
+use std::time::Duration;
+
+/// How quickly a bitrate target makes up for being over or under, as the
+/// time over which the difference is spread.
+const CATCH_UP: Duration = Duration::from_secs(2);
+/// Lowest and highest JPEG quality rate control picks from.
+const MIN_QUALITY: f64 = 5.0;
+const MAX_QUALITY: f64 = 98.0;
+/// How far the quality may move for one frame.
+const MAX_STEP: f64 = 4.0;
+
+/// What the encoder aims for, beyond a fixed --quality.
+#[derive(Args, Clone, Default)]
+pub struct RateOptions {
+    /// Aim for this average bitrate, in bits per second with an optional
+    /// k, M or G suffix, by adjusting the JPEG quality as frames come in.
+    #[arg(long, value_parser = parse_bitrate, conflicts_with = "crf")]
+    pub bitrate: Option<u64>,
+    /// Constant quality on x264's scale, from 0 (best) to 51 (worst),
+    /// instead of --quality.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=51), conflicts_with = "quality")]
+    pub crf: Option<u8>,
+    /// Never go over this bitrate for longer than --bufsize allows, lowering
+    /// the quality or encoding frames again as far as JPEG can go.
+    #[arg(long, value_parser = parse_bitrate, requires = "bufsize")]
+    pub maxrate: Option<u64>,
+    /// Size, in bits, of the buffer --maxrate is measured over, usually one
+    /// or two seconds' worth.
+    #[arg(long, value_parser = parse_bitrate, requires = "maxrate")]
+    pub bufsize: Option<u64>,
+    /// Record first, then encode the recording again to land on --bitrate,
+    /// spending more on the frames that need it. The first pass is kept
+    /// beside the output as OUTPUT.pass1.mkv until the second pass is done,
+    /// so this can't be combined with --encrypt.
+    #[arg(long, requires = "bitrate", conflicts_with = "encrypt")]
+    pub two_pass: bool,
+}
+
+/// Parses bitrates such as `4M`, `800k` or `250000`, in bits per second.
+pub fn parse_bitrate(s: &str) -> Result<u64, Report> {
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&s[..at], 1e3),
+        Some((at, 'm' | 'M')) => (&s[..at], 1e6),
+        Some((at, 'g' | 'G')) => (&s[..at], 1e9),
+        _ => (s, 1.0),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| Report::msg(format!("invalid bitrate: {s}")))?;
+    Ok((number * multiplier) as u64)
+}
+
+/// Picks the JPEG quality for each frame to meet [`RateOptions`].
+pub struct RateControl {
+    quality: f64,
+    bitrate: Option<f64>,
+    /// --maxrate and --bufsize, and how full the buffer is, all in bits.
+    buffer: Option<(f64, f64, f64)>,
+    spent: f64,
+    last_pts: Option<Duration>,
+}
+
+impl RateControl {
+    /// Starts from `quality`, or the quality --crf stands for.
+    pub fn new(options: &RateOptions, quality: u8) -> Self {
+        let quality = match options.crf {
+            Some(crf) => 100.0 - crf as f64 * 99.0 / 51.0,
+            None => quality as f64,
+        };
+        RateControl {
+            quality,
+            bitrate: options.bitrate.map(|bitrate| bitrate as f64),
+            buffer: options
+                .maxrate
+                .zip(options.bufsize)
+                .map(|(maxrate, bufsize)| (maxrate as f64, bufsize as f64, 0.0)),
+            spent: 0.0,
+            last_pts: None,
+        }
+    }
+
+    /// Whether frame sizes are being controlled, so frames the camera
+    /// already compressed have to be encoded again.
+    pub fn is_active(&self) -> bool {
+        self.bitrate.is_some() || self.buffer.is_some()
+    }
+
+    /// Encodes a frame shown at `pts`.
+    pub fn encode(&mut self, image: &RgbImage, pts: Duration) -> Result<Vec<u8>, Report> {
+        let elapsed = self
+            .last_pts
+            .map_or(Duration::ZERO, |last| pts.saturating_sub(last));
+        self.last_pts = Some(pts);
+        let mut buffer_room = f64::INFINITY;
+        if let Some((maxrate, bufsize, fullness)) = &mut self.buffer {
+            *fullness = (*fullness - *maxrate * elapsed.as_secs_f64()).max(0.0);
+            buffer_room = *bufsize - *fullness;
+        }
+
+        let mut quality = self.quality.round() as u8;
+        let mut jpeg = encode(image, quality)?;
+        // A frame that would overflow the buffer is encoded again, worse,
+        // until it fits or can't get any smaller.
+        while (jpeg.len() * 8) as f64 > buffer_room && quality as f64 > MIN_QUALITY {
+            quality = (quality as f64 * 0.75).max(MIN_QUALITY) as u8;
+            jpeg = encode(image, quality)?;
+        }
+        let bits = (jpeg.len() * 8) as f64;
+        self.spent += bits;
+        if let Some((_, _, fullness)) = &mut self.buffer {
+            *fullness += bits;
+        }
+        self.adjust(pts, elapsed, bits, quality as f64);
+        Ok(jpeg)
+    }
+
+    /// Nudges the quality for the next frame towards the bitrate target,
+    /// and away from a buffer that's filling up.
+    fn adjust(&mut self, pts: Duration, elapsed: Duration, bits: f64, used: f64) {
+        let mut ratio = 1.0f64;
+        if let Some(bitrate) = self.bitrate {
+            if !elapsed.is_zero() {
+                // The rate this frame went at, against the rate that would
+                // bring the total back on target over the next few seconds.
+                let rate = bits / elapsed.as_secs_f64();
+                let behind = bitrate * pts.as_secs_f64() - self.spent;
+                let wanted = (bitrate + behind / CATCH_UP.as_secs_f64()).max(bitrate * 0.1);
+                ratio = wanted / rate;
+            }
+        }
+        if let Some((_, bufsize, fullness)) = self.buffer {
+            // Start backing off once the buffer is half full.
+            let full = fullness / bufsize;
+            if full > 0.5 {
+                ratio = ratio.min(1.0 - (full - 0.5));
+            }
+        }
+        let step = (ratio.ln() * 10.0).clamp(-MAX_STEP, MAX_STEP);
+        self.quality = (used.min(self.quality) + step).clamp(MIN_QUALITY, MAX_QUALITY);
+        if self.bitrate.is_none() && self.buffer.is_some() {
+            // Without a bitrate target there's no reason to sink below what
+            // was asked for once the buffer drains.
+            self.quality = self.quality.max(used);
+        }
+    }
+}
+
+/// Encodes `image` as a JPEG at `quality`.
+pub fn encode(image: &RgbImage, quality: u8) -> Result<Vec<u8>, Report> {
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, quality.clamp(1, 100)).encode_image(image)?;
+    Ok(jpeg)
+}
+
+/// Encodes `image` as close to `bits` as the JPEG qualities allow without
+/// going over, searching from `hint`.
+pub fn encode_to_size(image: &RgbImage, bits: f64, hint: u8) -> Result<(Vec<u8>, u8), Report> {
+    let (mut low, mut high) = (MIN_QUALITY as u8, MAX_QUALITY as u8);
+    let mut quality = hint.clamp(low, high);
+    let mut best = None;
+    while low <= high {
+        let jpeg = encode(image, quality)?;
+        if (jpeg.len() * 8) as f64 <= bits {
+            low = quality + 1;
+            best = Some((jpeg, quality));
+        } else {
+            high = quality - 1;
+        }
+        quality = low + (high.saturating_sub(low)) / 2;
+    }
+    match best {
+        Some(best) => Ok(best),
+        None => {
+            let quality = MIN_QUALITY as u8;
+            Ok((encode(image, quality)?, quality))
+        }
+    }
+}
@@ -0,0 +1,886 @@
+use crate::audio::{AudioOptions, Meter, Tap};
+use crate::bad_frames::BadFrames;
+use crate::bus::{self, Event, PublishOptions, Publisher};
+use crate::capture::{self, Capture, Frame, Queue};
+use crate::defects;
+use crate::drift::Drift;
+use crate::encrypt::{Encryption, Output};
+use crate::events::Events;
+use crate::manifest::{self, Segment};
+use crate::mask::{self, Mask};
+use crate::mkv::{self, AudioTrack, Block, MatroskaWriter, VideoTrack};
+use crate::motion::Motion;
+use crate::mute;
+use crate::notify;
+use crate::pixel_format::RgbFormat;
+use crate::progress::{self, Progress};
+use crate::rate::{self, RateControl, RateOptions};
+use crate::shutdown;
+use crate::sidecar::Sidecar;
+use crate::summary;
+use crate::upscale::UpscaleOptions;
+use clap::{Args, Parser};
+use color_eyre::Report;
+use flume::{Sender, TrySendError};
+use image::{codecs::jpeg::JpegEncoder, ImageFormat, RgbImage};
+use nokhwa::utils::{
+    frame_formats, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType,
+};
+use std::cmp::Reverse;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// What `record` writes and for how long.
+#[derive(Args, Clone)]
+pub struct RecordOptions {
+    /// Where to write the recording, a Matroska file of Motion JPEG frames.
+    /// It's written and synced a second at a time, so a recording a crash
+    /// or power cut ends plays up to its last second; verify --finalize
+    /// finishes it.
+    #[arg(short, long)]
+    pub output: PathBuf,
+    /// Stop after this long; otherwise record until the camera stops.
+    #[arg(long, value_parser = crate::parse_duration)]
+    pub duration: Option<Duration>,
+    /// Record slow motion: capture in the camera's fastest format, at the
+    /// smallest resolution that reaches it, and write the frames spread
+    /// out this many times as long, e.g. 4x. --duration still counts real
+    /// time.
+    #[arg(long, value_name = "FACTORx", value_parser = parse_slowmo)]
+    pub slowmo: Option<f64>,
+    /// Write every frame at the time it was captured, so the frame rate
+    /// follows the camera's. This is the default.
+    #[arg(long, conflicts_with = "cfr")]
+    pub vfr: bool,
+    /// Write frames at a constant rate, by default the camera's, repeating
+    /// frames over gaps and dropping ones that come in early, for editors
+    /// that can't cope with a varying rate.
+    #[arg(long, value_name = "FPS", value_parser = parse_fps)]
+    pub cfr: Option<Option<f64>>,
+    /// JPEG quality, from 1 to 100, for frames that have to be encoded.
+    /// MJPEG cameras' frames are kept as they are unless masks or a
+    /// bitrate apply.
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: u8,
+    #[command(flatten)]
+    pub rate: RateOptions,
+    #[command(flatten)]
+    pub upscale: UpscaleOptions,
+    /// Encrypt the recording as it's written, as age:FILE with FILE listing
+    /// one age recipient per line. An encrypted recording that doesn't
+    /// finish only decrypts up to its last whole 64KiB chunk, so bound it
+    /// with --duration.
+    #[arg(long, value_name = "age:FILE")]
+    pub encrypt: Option<Encryption>,
+    /// Also log every recorded frame to this file, one JSON object per
+    /// line. See docs/metadata-sidecar.md.
+    #[arg(long, value_name = "FILE")]
+    pub metadata_sidecar: Option<PathBuf>,
+    /// Also write a depth camera's depth to this directory, each frame's as
+    /// a 16-bit grayscale PNG named by its sequence number, in the
+    /// camera's units.
+    #[arg(long, value_name = "DIR")]
+    pub depth_output: Option<PathBuf>,
+    #[command(flatten)]
+    pub audio: AudioOptions,
+    /// Stop once the microphone has been silent this long.
+    #[arg(long, requires = "audio", value_parser = crate::parse_duration)]
+    pub stop_on_silence: Option<Duration>,
+    /// Only record while the microphone hears something, and for a moment
+    /// after.
+    #[arg(long, requires = "audio")]
+    pub audio_gate: bool,
+    /// Only meter the microphone given with --audio, leaving the sound out
+    /// of the recording.
+    #[arg(long, requires = "audio")]
+    pub no_audio: bool,
+    /// Record only the microphone given with --audio, without opening the
+    /// camera.
+    #[arg(
+        long,
+        requires = "audio",
+        conflicts_with_all = ["no_audio", "slowmo", "cfr", "two_pass", "metadata_sidecar", "depth_output"]
+    )]
+    pub no_video: bool,
+    /// Burn each line read from standard input into the frames for a few
+    /// seconds, stamped with the time it arrived, and log it in the
+    /// metadata sidecar.
+    #[arg(long)]
+    pub event_stdin: bool,
+    /// How to show progress: a status line (`line`), every event as a
+    /// JSON object per line on standard output (`json`), or `off`.
+    #[arg(long, default_value = "line")]
+    pub progress: progress::Style,
+    #[command(flatten)]
+    pub publish: PublishOptions,
+}
+
+impl RecordOptions {
+    /// What `record --output path` records with, but without progress.
+    fn to(path: &Path) -> Self {
+        #[derive(Parser)]
+        struct Record {
+            #[command(flatten)]
+            options: RecordOptions,
+        }
+        let mut output = OsString::from("--output=");
+        output.push(path);
+        let args = [OsStr::new("record"), OsStr::new("--progress=off"), &output];
+        Record::parse_from(args).options
+    }
+}
+
+/// How long the --audio-gate stays open after the last sound.
+const GATE_HOLD: Duration = Duration::from_secs(1);
+
+/// Where a recording's frames come from.
+enum Source {
+    /// The camera, opened for the recording.
+    Camera(CameraIndex),
+    /// A camera another command has open, which hands its frames on until
+    /// the recording is to end.
+    Shared(Capture),
+}
+
+/// Records the camera, and the microphone if asked, to `options.output`
+/// until `options.duration` has passed or the camera stops, then writes
+/// its manifest beside it.
+///
+/// On Unix, SIGUSR1 and SIGUSR2 blank the video and silence the audio
+/// until they're sent again; see [`mute`].
+pub fn record(index: CameraIndex, options: &RecordOptions, masks: &[Mask]) -> Result<(), Report> {
+    record_from(Source::Camera(index), options, masks)
+}
+
+fn record_from(source: Source, options: &RecordOptions, masks: &[Mask]) -> Result<(), Report> {
+    // A two-pass recording is captured at --quality first, and only gets
+    // its bitrate in the second pass.
+    let first_pass = options.rate.two_pass.then(|| {
+        let mut name = options.output.as_os_str().to_owned();
+        name.push(".pass1.mkv");
+        PathBuf::from(name)
+    });
+    let path = first_pass.as_deref().unwrap_or(&options.output);
+    let mut rate = match first_pass {
+        Some(_) => RateControl::new(
+            &RateOptions {
+                crf: options.rate.crf,
+                ..RateOptions::default()
+            },
+            options.quality,
+        ),
+        None => RateControl::new(&options.rate, options.quality),
+    };
+    let file = BufWriter::new(File::create(path)?);
+    let out = match &options.encrypt {
+        Some(encryption) => encryption.wrap(file)?,
+        None => Output::Plain(file),
+    };
+    let (meter, tap) = match options.no_audio {
+        true => (Meter::open(&options.audio)?, None),
+        false => match Meter::open_recording(&options.audio)? {
+            Some((meter, tap)) => (Some(meter), Some(tap)),
+            None => (None, None),
+        },
+    };
+    let mut publish = options.publish.clone();
+    if options.progress == progress::Style::Json
+        && !publish
+            .publishers
+            .iter()
+            .any(|publisher| matches!(publisher, Publisher::Stdout))
+    {
+        publish.publishers.push(Publisher::Stdout);
+    }
+    bus::start(&publish)?;
+    mute::listen();
+    if options.no_video {
+        let (Some(meter), Some(tap)) = (meter, tap) else {
+            return Err(Report::msg("--no-video needs a microphone to record"));
+        };
+        return record_audio(options, out, &meter, tap);
+    }
+    if options.slowmo.is_some() && tap.is_some() {
+        return Err(Report::msg(
+            "sound can't be slowed down with the picture; add --no-audio to --slowmo",
+        ));
+    }
+    let audio = tap.as_ref().map(|tap| AudioTrack {
+        sample_rate: tap.sample_rate,
+        channels: tap.channels,
+    });
+    let mut sound = tap.map(Sound::new);
+    let slowmo = options.slowmo.unwrap_or(1.0);
+    let (capture, own) = match source {
+        Source::Camera(index) => {
+            let requested = match options.slowmo {
+                Some(_) => {
+                    let format = fastest_format(&index)?;
+                    println!("Capturing slow motion at {format}");
+                    RequestedFormatType::Exact(format)
+                }
+                None => RequestedFormatType::None,
+            };
+            // A recording never drops frames: the camera waits for the writer.
+            let queue = Queue {
+                block: true,
+                ..Queue::default()
+            };
+            let capture = capture::spawn_capture_queued(index, requested, queue)?;
+            summary::watch(&capture);
+            (capture, true)
+        }
+        Source::Shared(capture) => (capture, false),
+    };
+    let upscale = options.upscale.upscale;
+    let (width, height) = (capture.format.width(), capture.format.height());
+    let (width, height) = match upscale {
+        Some(upscale) => upscale.size(width, height),
+        None => (width, height),
+    };
+    let video = VideoTrack {
+        codec: "V_MJPEG",
+        width,
+        height,
+    };
+    let mut writer = MatroskaWriter::new(out, Some(video), audio)?;
+    // What goes in instead of the camera's frames while the video is muted.
+    let mut black = None;
+    let mut muted = (false, false);
+    let mut bad_frames = BadFrames::new(None);
+    let mut sidecar = options
+        .metadata_sidecar
+        .as_deref()
+        .map(|path| Sidecar::create(path, &capture))
+        .transpose()?;
+    if let Some(dir) = &options.depth_output {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut motion = Motion::default();
+    let mut events = options.event_stdin.then(Events::stdin);
+    // Events that arrived since the last frame that made it into the sidecar.
+    let mut new_events = Vec::new();
+    println!(
+        "Recording {} to {}",
+        capture.info.human_name(),
+        path.display()
+    );
+    if own {
+        notify::ready();
+        notify::watch(&capture);
+    }
+    bus::publish(Event::Recording {
+        active: true,
+        path: options.output.clone(),
+        frames: 0,
+    });
+
+    let mut constant_rate = options.cfr.map(|fps| {
+        let fps = fps.unwrap_or(capture.format.frame_rate() as f64 / slowmo);
+        println!("Writing a constant {fps:.2} fps");
+        ConstantRate::new(fps)
+    });
+    // The picture is kept with the sound by repeating and dropping frames.
+    let mut drift = sound
+        .as_ref()
+        .map(|_| Drift::new(capture.format.frame_rate() as f64));
+    let mut progress = Progress::new(options.progress, path);
+    let mut written = 0u64;
+    let mut segment = Segment {
+        frames: 0,
+        start: Duration::ZERO,
+        end: Duration::ZERO,
+        started: SystemTime::now(),
+        ended: SystemTime::now(),
+        encrypted: options.encrypt.is_some(),
+    };
+    // Frames from a camera another command opened may be well into its
+    // session, so the recording is timed from its own first frame.
+    let mut first_pts = None;
+    for frame in capture.frames.iter() {
+        let since = frame.pts - *first_pts.get_or_insert(frame.pts);
+        if options.duration.is_some_and(|duration| since >= duration) || shutdown::requested() {
+            break;
+        }
+        if let Some(meter) = &meter {
+            if options
+                .stop_on_silence
+                .is_some_and(|limit| meter.silent_for() >= limit)
+            {
+                println!(
+                    "Stopping, the microphone has been silent for {}s",
+                    meter.silent_for().as_secs()
+                );
+                break;
+            }
+        }
+        let gate_closed = meter
+            .as_ref()
+            .is_some_and(|meter| options.audio_gate && meter.silent_for() >= GATE_HOLD);
+        if mute::video() != muted.0 || mute::audio() != muted.1 {
+            muted = (mute::video(), mute::audio());
+            println!(
+                "Video {}, audio {}",
+                if muted.0 { "muted" } else { "on" },
+                if muted.1 { "muted" } else { "on" }
+            );
+        }
+        if let Some(sound) = &mut sound {
+            // The sound is timed from the first frame, as the picture is.
+            if segment.frames == 0 {
+                sound.restart(since);
+            }
+            written += sound.write(&mut writer, !gate_closed, muted.1)?;
+        }
+        if gate_closed {
+            continue;
+        }
+        // A slow-motion recording is written as if it had taken longer.
+        let pts = since.mul_f64(slowmo);
+        let times = match (&mut drift, &sound) {
+            (Some(drift), Some(sound)) => drift.correct(pts, sound.pts()),
+            _ => vec![pts],
+        };
+        let Some(&pts) = times.last() else {
+            continue;
+        };
+        // The times the frame goes into the file at: its own, or at a
+        // constant rate as many slots as fall to it, which may be none.
+        let slots = match &constant_rate {
+            Some(constant_rate) => constant_rate.slots(pts),
+            None => times,
+        };
+        let Some(&first_slot) = slots.first() else {
+            if let Some(constant_rate) = &mut constant_rate {
+                constant_rate.commit(&slots);
+            }
+            continue;
+        };
+        let mut write = |data: &[u8]| -> Result<(), Report> {
+            for &slot in &slots {
+                writer.write_frame(slot, data)?;
+                summary::sent("recording", data.len());
+            }
+            written += (data.len() * slots.len()) as u64;
+            Ok(())
+        };
+        let mut burning = false;
+        if let Some(events) = &mut events {
+            new_events.extend(events.poll(since));
+            burning = events.showing();
+        }
+        // Motion is scored on decoded frames, so the sidecar costs MJPEG
+        // recordings a decode per frame.
+        let passthrough = frame.buffer.source_frame_format() == FrameFormat::MJPEG
+            && defects::map().is_none()
+            && masks.is_empty()
+            && !burning
+            && !rate.is_active()
+            && upscale.is_none();
+        let mut score = None;
+        if muted.0 {
+            if black.is_none() {
+                let mut jpeg = Vec::new();
+                JpegEncoder::new(&mut jpeg).encode_image(&RgbImage::new(width, height))?;
+                black = Some(jpeg);
+            }
+            write(black.as_deref().unwrap())?;
+        } else if passthrough && sidecar.is_none() {
+            write(frame.buffer.buffer())?;
+        } else {
+            let mut image = match summary::decode(|| frame.buffer.decode_image::<RgbFormat>()) {
+                Ok(image) => image,
+                Err(why) => {
+                    bad_frames.record(&frame, why);
+                    continue;
+                }
+            };
+            let (width, height) = image.dimensions();
+            defects::correct(&mut image, width, height, 3);
+            score = motion.score(&image, width, height, 3);
+            if passthrough {
+                write(frame.buffer.buffer())?;
+            } else {
+                mask::apply(masks, &mut image, width, height, 3);
+                if let Some(events) = &events {
+                    events.burn(&mut image, width, height, 3);
+                }
+                if let Some(upscale) = upscale {
+                    image = upscale.apply(&image);
+                }
+                let jpeg = summary::render(|| rate.encode(&image, first_slot))?;
+                write(&jpeg)?;
+            }
+        }
+        if let Some(constant_rate) = &mut constant_rate {
+            constant_rate.commit(&slots);
+        }
+        if let (Some(dir), Some(depth)) = (&options.depth_output, frame.planes.depth()) {
+            if let Some(image) = depth.image() {
+                image.save(dir.join(format!("{:08}.png", frame.sequence)))?;
+            }
+        }
+        if let Some(sidecar) = &mut sidecar {
+            sidecar.write(
+                frame.sequence,
+                first_slot,
+                frame.conditions,
+                score,
+                &new_events,
+            )?;
+        }
+        new_events.clear();
+        if segment.frames == 0 {
+            segment.start = first_slot;
+            segment.started = SystemTime::now();
+        }
+        segment.frames += slots.len() as u64;
+        segment.end = *slots.last().unwrap();
+        segment.ended = SystemTime::now();
+        progress.update(segment.frames, written);
+    }
+    progress.finish();
+    // The frames only run out before the end if the camera stopped, or,
+    // for a camera that isn't ours, if the recording was stopped.
+    if own && capture.frames.is_disconnected() {
+        bus::publish(Event::CameraLost {
+            camera: capture.info.human_name(),
+        });
+    }
+    writer.finish()?.finish()?;
+    println!(
+        "Recorded {} frames to {}, {} corrupt ones skipped.",
+        segment.frames,
+        path.display(),
+        bad_frames.count
+    );
+    if let Some(constant_rate) = &constant_rate {
+        println!(
+            "Repeated {} frames and dropped {} to keep the rate constant.",
+            constant_rate.repeated, constant_rate.dropped
+        );
+    }
+    if let Some(drift) = &drift {
+        summary::drift(drift);
+    }
+    if options.slowmo.is_some() && segment.frames > 1 {
+        let real = (segment.end - segment.start).div_f64(slowmo);
+        let fps = (segment.frames - 1) as f64 / real.as_secs_f64().max(f64::EPSILON);
+        println!(
+            "Captured at {fps:.1} fps, playing back {slowmo}x slower at {:.1} fps",
+            fps / slowmo
+        );
+    }
+    if let (Some(first_pass), Some(bitrate)) = (&first_pass, options.rate.bitrate) {
+        second_pass(first_pass, &options.output, bitrate, video, audio)?;
+        std::fs::remove_file(first_pass)?;
+    }
+    bus::publish(Event::Recording {
+        active: false,
+        path: options.output.clone(),
+        frames: segment.frames,
+    });
+    let manifest = manifest::write(&options.output, &[(options.output.clone(), segment)])?;
+    println!("Wrote manifest to {}", manifest.display());
+    Ok(())
+}
+
+/// A recording made while a command that has the camera open carries on,
+/// as `serve` makes when asked to over gRPC or by a script: one at a time,
+/// of the frames the command hands it.
+#[derive(Clone)]
+pub struct Recordings {
+    /// The camera, without frames: each recording gets its own.
+    camera: Arc<Capture>,
+    masks: Arc<[Mask]>,
+    current: Arc<Mutex<Option<Recorder>>>,
+}
+
+struct Recorder {
+    path: PathBuf,
+    frames: Sender<Frame>,
+    thread: JoinHandle<Result<(), Report>>,
+}
+
+impl Recorder {
+    /// Lets the recording finish, returning how it went.
+    fn finish(self) -> Result<PathBuf, Report> {
+        drop(self.frames);
+        match self.thread.join() {
+            Ok(result) => result.map(|()| self.path),
+            Err(_) => Err(Report::msg("the recording crashed")),
+        }
+    }
+}
+
+impl Recordings {
+    /// Recordings of the frames of `capture`, masked with `masks`.
+    pub fn new(capture: &Capture, masks: &[Mask]) -> Self {
+        let (_, none) = flume::bounded(0);
+        Recordings {
+            camera: Arc::new(capture.fork(none)),
+            masks: masks.into(),
+            current: Arc::default(),
+        }
+    }
+
+    /// Starts recording to `path`, as `record --output path` would, unless
+    /// a recording is under way.
+    pub fn start(&self, path: &Path) -> Result<(), Report> {
+        let mut current = self.current.lock().unwrap();
+        if let Some(recorder) = &*current {
+            return Err(Report::msg(format!(
+                "already recording to {}",
+                recorder.path.display()
+            )));
+        }
+        // Fails here, for whoever asked, rather than on the recording's
+        // thread.
+        File::create(path)?;
+        let (frames, queued) = flume::bounded(capture::QUEUE_DEPTH);
+        let source = Source::Shared(self.camera.fork(queued));
+        let options = RecordOptions::to(path);
+        let masks = self.masks.clone();
+        *current = Some(Recorder {
+            path: path.to_path_buf(),
+            frames,
+            thread: std::thread::spawn(move || record_from(source, &options, &masks)),
+        });
+        Ok(())
+    }
+
+    /// Ends the recording under way and waits for it to be written,
+    /// returning where it went.
+    pub fn stop(&self) -> Result<PathBuf, Report> {
+        let recorder = self.current.lock().unwrap().take();
+        recorder
+            .ok_or_else(|| Report::msg("not recording"))?
+            .finish()
+    }
+
+    /// Where the recording under way goes, if there is one.
+    pub fn active(&self) -> Option<PathBuf> {
+        let current = self.current.lock().unwrap();
+        current.as_ref().map(|recorder| recorder.path.clone())
+    }
+
+    /// Hands `frame` to the recording under way, if there is one. The
+    /// command capturing doesn't wait for it, so the recording loses
+    /// frames it can't keep up with.
+    pub fn send(&self, frame: &Frame) {
+        let mut current = self.current.lock().unwrap();
+        let Some(recorder) = &*current else {
+            return;
+        };
+        if let Err(TrySendError::Disconnected(_)) = recorder.frames.try_send(frame.clone()) {
+            if let Err(why) = current.take().unwrap().finish() {
+                eprintln!("recording failed: {why}");
+            }
+        }
+    }
+}
+
+/// Records the microphone alone until `options.duration` has passed, then
+/// writes the manifest, which counts no frames.
+fn record_audio(
+    options: &RecordOptions,
+    out: Output,
+    meter: &Meter,
+    tap: Tap,
+) -> Result<(), Report> {
+    let audio = AudioTrack {
+        sample_rate: tap.sample_rate,
+        channels: tap.channels,
+    };
+    let mut writer = MatroskaWriter::new(out, None, Some(audio))?;
+    let mut sound = Sound::new(tap);
+    println!("Recording sound only to {}", options.output.display());
+    bus::publish(Event::Recording {
+        active: true,
+        path: options.output.clone(),
+        frames: 0,
+    });
+    let mut progress = Progress::new(options.progress, &options.output);
+    let mut written = 0;
+    let started = SystemTime::now();
+    let mut muted = false;
+    while options
+        .duration
+        .is_none_or(|duration| sound.pts() < duration)
+        && !shutdown::requested()
+    {
+        if options
+            .stop_on_silence
+            .is_some_and(|limit| meter.silent_for() >= limit)
+        {
+            println!(
+                "Stopping, the microphone has been silent for {}s",
+                meter.silent_for().as_secs()
+            );
+            break;
+        }
+        if mute::audio() != muted {
+            muted = mute::audio();
+            println!("Audio {}", if muted { "muted" } else { "on" });
+        }
+        if !sound.wait() {
+            break;
+        }
+        let gate_open = !options.audio_gate || meter.silent_for() < GATE_HOLD;
+        written += sound.write(&mut writer, gate_open, muted)?;
+        progress.update(0, written);
+    }
+    progress.finish();
+    writer.finish()?.finish()?;
+    println!(
+        "Recorded {:.1}s of sound to {}",
+        sound.pts().as_secs_f64(),
+        options.output.display()
+    );
+    let segment = Segment {
+        frames: 0,
+        start: Duration::ZERO,
+        end: sound.pts(),
+        started,
+        ended: SystemTime::now(),
+        encrypted: options.encrypt.is_some(),
+    };
+    bus::publish(Event::Recording {
+        active: false,
+        path: options.output.clone(),
+        frames: 0,
+    });
+    let manifest = manifest::write(&options.output, &[(options.output.clone(), segment)])?;
+    println!("Wrote manifest to {}", manifest.display());
+    Ok(())
+}
+
+/// The microphone's side of a recording, timed by counting its samples so
+/// it keeps pace with the microphone's own clock.
+struct Sound {
+    tap: Tap,
+    /// When the first sample taken was heard, on the picture's clock.
+    start: Duration,
+    /// Samples per channel taken so far, written or not.
+    taken: u64,
+    /// What [`Sound::wait`] received, still to be written.
+    waited: Option<Vec<i16>>,
+}
+
+impl Sound {
+    fn new(tap: Tap) -> Self {
+        Sound {
+            tap,
+            start: Duration::ZERO,
+            taken: 0,
+            waited: None,
+        }
+    }
+
+    /// Where the next block starts.
+    fn pts(&self) -> Duration {
+        self.start + Duration::from_secs_f64(self.taken as f64 / self.tap.sample_rate as f64)
+    }
+
+    /// Throws away what the microphone heard so far, if nothing has been
+    /// taken yet, so the sound starts with the picture's frame at `pts`.
+    fn restart(&mut self, pts: Duration) {
+        if self.taken == 0 {
+            self.start = pts;
+            self.waited = None;
+            self.tap.samples.drain();
+        }
+    }
+
+    /// Blocks until the microphone has something, returning false once it
+    /// has stopped.
+    fn wait(&mut self) -> bool {
+        if self.waited.is_none() {
+            self.waited = self.tap.samples.recv().ok();
+        }
+        self.waited.is_some()
+    }
+
+    /// Writes what the microphone heard since the last call, as silence if
+    /// `muted`, or leaves it out unless `keep`. Returns the bytes written.
+    fn write<W: Write>(
+        &mut self,
+        writer: &mut MatroskaWriter<W>,
+        keep: bool,
+        muted: bool,
+    ) -> Result<u64, Report> {
+        let mut written = 0;
+        let waited = self.waited.take();
+        for samples in waited.into_iter().chain(self.tap.samples.try_iter()) {
+            let pts = self.pts();
+            self.taken += (samples.len() / self.tap.channels.max(1) as usize) as u64;
+            if !keep {
+                continue;
+            }
+            let bytes: Vec<u8> = match muted {
+                true => vec![0; samples.len() * 2],
+                false => samples
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes())
+                    .collect(),
+            };
+            writer.write_audio(pts, &bytes)?;
+            written += bytes.len() as u64;
+        }
+        Ok(written)
+    }
+}
+
+/// Fits frames to a constant rate, each taking the slots nearest its
+/// capture time that haven't been taken yet.
+struct ConstantRate {
+    interval: Duration,
+    /// The time of the next slot, once the first frame has set where they
+    /// start.
+    next: Option<Duration>,
+    repeated: u64,
+    dropped: u64,
+}
+
+impl ConstantRate {
+    fn new(fps: f64) -> Self {
+        ConstantRate {
+            interval: Duration::from_secs_f64(1.0 / fps),
+            next: None,
+            repeated: 0,
+            dropped: 0,
+        }
+    }
+
+    /// The slots a frame at `pts` would fill: none if it came too soon
+    /// after the last one, several if frames before it went missing.
+    fn slots(&self, pts: Duration) -> Vec<Duration> {
+        let Some(mut next) = self.next else {
+            return vec![pts];
+        };
+        let mut slots = Vec::new();
+        while next <= pts + self.interval / 2 {
+            slots.push(next);
+            next += self.interval;
+        }
+        slots
+    }
+
+    /// Marks `slots` as filled.
+    fn commit(&mut self, slots: &[Duration]) {
+        match slots.last() {
+            Some(&last) => {
+                self.next = Some(last + self.interval);
+                self.repeated += slots.len() as u64 - 1;
+            }
+            None => self.dropped += 1,
+        }
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f64, Report> {
+    match s.parse() {
+        Ok(fps) if fps > 0.0 && fps <= 1000.0 => Ok(fps),
+        _ => Err(Report::msg(format!("invalid frame rate: {s}"))),
+    }
+}
+
+fn parse_slowmo(s: &str) -> Result<f64, Report> {
+    let factor: f64 = s
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| Report::msg(format!("invalid slow-motion factor: {s}")))?;
+    if !(factor > 1.0 && factor <= 64.0) {
+        return Err(Report::msg(format!(
+            "slow-motion factor has to be above 1 and at most 64, not {factor}"
+        )));
+    }
+    Ok(factor)
+}
+
+/// The camera's highest frame rate, at the smallest resolution it comes in.
+pub fn fastest_format(index: &CameraIndex) -> Result<CameraFormat, Report> {
+    let mut camera = capture::new_camera(
+        index.clone(),
+        RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+    )?;
+    let key = |format: &CameraFormat| (format.frame_rate(), Reverse(format.resolution()));
+    let mut fastest: Option<CameraFormat> = None;
+    for &format in frame_formats() {
+        let Ok(compatible) = camera.compatible_list_by_resolution(format) else {
+            continue;
+        };
+        for (resolution, rates) in compatible {
+            for fps in rates {
+                let candidate = CameraFormat::new(resolution, format, fps);
+                if fastest.is_none_or(|fastest| key(&candidate) > key(&fastest)) {
+                    fastest = Some(candidate);
+                }
+            }
+        }
+    }
+    fastest.ok_or_else(|| Report::msg("the camera doesn't list any formats"))
+}
+
+/// Encodes the first pass of a two-pass recording again into `output`,
+/// sharing `bitrate` out between frames in proportion to how big they came
+/// out the first time.
+fn second_pass(
+    first_pass: &Path,
+    output: &Path,
+    bitrate: u64,
+    video: VideoTrack,
+    audio: Option<AudioTrack>,
+) -> Result<(), Report> {
+    let open = || -> Result<_, Report> { Ok(BufReader::new(File::open(first_pass)?)) };
+    let (mut first_bits, mut last_pts) = (0.0, Duration::ZERO);
+    let scan = mkv::read_frames(open()?, |pts, frame| {
+        first_bits += (frame.len() * 8) as f64;
+        last_pts = pts;
+        Ok(())
+    })?;
+    if scan.frames == 0 {
+        std::fs::copy(first_pass, output)?;
+        return Ok(());
+    }
+    // The last frame is shown for about as long as the others.
+    let length = last_pts.as_secs_f64() * scan.frames as f64 / (scan.frames - 1).max(1) as f64;
+    let share = bitrate as f64 * length.max(f64::EPSILON) / first_bits;
+    println!("Encoding {} at {bitrate} bit/s", output.display());
+
+    let file = BufWriter::new(File::create(output)?);
+    let mut writer = MatroskaWriter::new(file, Some(video), audio)?;
+    // Bits given to the frames so far and bits they used; frames that come
+    // in under their share leave the rest to the ones after them.
+    let (mut given, mut used) = (0.0, 0.0);
+    let mut quality = 75;
+    // The sound is copied over as it is.
+    mkv::read_blocks(open()?, |block| {
+        let (pts, frame) = match block {
+            Block::Video(pts, frame) => (pts, frame),
+            Block::Audio(pts, samples) => return writer.write_audio(pts, samples),
+        };
+        let failed = |why: Report| std::io::Error::other(why.to_string());
+        let image = image::load_from_memory_with_format(frame, ImageFormat::Jpeg)
+            .map_err(|why| failed(why.into()))?
+            .to_rgb8();
+        given += (frame.len() * 8) as f64 * share;
+        let jpeg;
+        (jpeg, quality) = rate::encode_to_size(&image, given - used, quality).map_err(failed)?;
+        used += (jpeg.len() * 8) as f64;
+        writer.write_frame(pts, &jpeg)
+    })?;
+    writer.finish()?.flush()?;
+    println!(
+        "Encoded {} frames at {:.0} bit/s on average",
+        scan.frames,
+        used / length.max(f64::EPSILON)
+    );
+    Ok(())
+}
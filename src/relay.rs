@@ -0,0 +1,276 @@
+//! `--shared`: a camera opened once, by the daemon, with its frames relayed
+//! to every athletic run with `--shared`, so several can watch it at once.
+//!
+//! A command asks the daemon over D-Bus to share its device. The daemon
+//! opens the camera if it hasn't yet, and sends each process connected to
+//! the device's Unix socket the frames as the camera sent them, in the
+//! pipe sink's stream format. Each process decodes them itself. The
+//! camera's controls stay the daemon's. docs/relay.md has the details.
+
+use crate::bus::{BUS_NAME, INTERFACE, OBJECT_PATH};
+use crate::capture::{self, Capture, ControlRequest, Counters, Frame, Queue, Sent};
+use crate::memory::Held;
+use crate::sink_frame::SinkFrame;
+use crate::stream::{Planes, Stream};
+use crate::{lock, parse_camera_format, pipe};
+use color_eyre::Report;
+use flume::{Receiver, Sender, TrySendError};
+use nokhwa::utils::{
+    CameraFormat, CameraIndex, CameraInfo, FrameFormat, RequestedFormatType, Resolution,
+};
+use nokhwa::Buffer;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A camera the daemon is sharing.
+pub struct Share {
+    pub socket: PathBuf,
+    pub name: String,
+    pub format: CameraFormat,
+}
+
+/// One frame, header and all, as it's sent to every client.
+type Message = Arc<Vec<u8>>;
+
+/// How long the daemon keeps a camera open once no one is watching, so a
+/// command run again straight after doesn't wait for it to start up, and
+/// one that asked for it has time to connect.
+const LINGER: Duration = Duration::from_secs(5);
+
+/// `format` as [`parse_camera_format`] reads it.
+pub fn spec(format: &CameraFormat) -> String {
+    format!(
+        "{}@{}:{}",
+        format.resolution(),
+        format.frame_rate(),
+        format.format()
+    )
+}
+
+/// Opens the camera `index` in the `requested` format and relays its
+/// frames to whoever connects to the socket of the returned share. The
+/// camera is closed while no one is connected, and opened again in the
+/// same format for the next to connect.
+pub fn share(index: CameraIndex, requested: RequestedFormatType) -> Result<Share, Report> {
+    let capture = capture::spawn_capture_with(index.clone(), requested)?;
+    let dir = lock::runtime_dir();
+    std::fs::create_dir_all(&dir)?;
+    let socket = dir.join(format!("{}.sock", lock::key(&index)));
+    // The lock on the camera says no one else is serving from here.
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)?;
+    let (joined, joining) = flume::unbounded();
+    std::thread::spawn(move || accept(listener, &joined));
+    let share = Share {
+        socket,
+        name: capture.info.human_name(),
+        format: capture.format,
+    };
+    std::thread::spawn(move || serve(index, capture, &joining));
+    Ok(share)
+}
+
+/// Gives each client that connects a queue of frames, and a thread that
+/// sends it them.
+fn accept(listener: UnixListener, joined: &Sender<Sender<Message>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let (sender, frames) = flume::bounded::<Message>(capture::QUEUE_DEPTH);
+        if joined.send(sender).is_err() {
+            return;
+        }
+        std::thread::spawn(move || {
+            if stream.write_all(&pipe::stream_header()).is_err() {
+                return;
+            }
+            for frame in frames.iter() {
+                if stream.write_all(&frame).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Relays `capture` until no one has watched it for [`LINGER`], then
+/// waits for someone to and opens the camera again.
+fn serve(index: CameraIndex, capture: Capture, joining: &Receiver<Sender<Message>>) {
+    let format = capture.format;
+    let mut capture = Some(capture);
+    let mut clients = Vec::new();
+    loop {
+        if let Some(capture) = capture.take() {
+            fan_out(&capture, joining, &mut clients);
+            // Dropping the senders ends each client's stream.
+            clients.clear();
+            println!("Closed camera {index}, as no one is watching it");
+        }
+        let Ok(client) = joining.recv() else {
+            return;
+        };
+        clients.push(client);
+        match capture::spawn_capture_with(index.clone(), RequestedFormatType::Exact(format)) {
+            Ok(reopened) => {
+                println!("Opened camera {index} again for a client");
+                capture = Some(reopened);
+            }
+            Err(why) => {
+                eprintln!("can't open camera {index} again: {why}");
+                clients.clear();
+            }
+        }
+    }
+}
+
+/// Hands every frame to every client, dropping it for the ones that fall
+/// behind and forgetting the ones that have gone, until no one has been
+/// connected for [`LINGER`] or the camera stops.
+fn fan_out(
+    capture: &Capture,
+    joining: &Receiver<Sender<Message>>,
+    clients: &mut Vec<Sender<Message>>,
+) {
+    let mut watched = Instant::now();
+    for frame in capture.frames.iter() {
+        clients.extend(joining.try_iter());
+        let frame = SinkFrame::native(&frame);
+        let mut message = frame.header().to_vec();
+        message.extend_from_slice(frame.data);
+        let message = Arc::new(message);
+        clients.retain(|client| {
+            !matches!(
+                client.try_send(message.clone()),
+                Err(TrySendError::Disconnected(_))
+            )
+        });
+        if !clients.is_empty() {
+            watched = Instant::now();
+        } else if watched.elapsed() > LINGER {
+            return;
+        }
+    }
+}
+
+/// For [`capture::relay_through`]: asks the daemon to share `index` and
+/// streams its frames from there, as if from the camera.
+pub fn open(
+    index: CameraIndex,
+    requested: RequestedFormatType,
+    queue: Queue,
+) -> Result<Capture, Report> {
+    let format = match requested {
+        RequestedFormatType::Exact(format) => spec(&format),
+        // Whatever the camera is shared as will do.
+        _ => String::new(),
+    };
+    let connection = zbus::blocking::Connection::session()?;
+    let reply = connection
+        .call_method(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            Some(INTERFACE),
+            "Share",
+            &(index.to_string(), format),
+        )
+        .map_err(|why| {
+            Report::msg(format!(
+                "can't have the daemon share camera {index}; is athletic daemon running? {why}"
+            ))
+        })?;
+    let (socket, name, format): (String, String, String) = reply.body().deserialize()?;
+    let format = parse_camera_format(&format)?;
+
+    let mut stream = UnixStream::connect(&socket)?;
+    let mut header = [0; 16];
+    stream.read_exact(&mut header)?;
+    let header_size = pipe::read_stream_header(&header)?;
+    let (sender, receiver) = flume::bounded(queue.depth);
+    let (control_sender, control_receiver) = flume::unbounded();
+    let counters = Arc::new(Counters::default());
+    let counted = counters.clone();
+    std::thread::spawn(move || loop {
+        refuse(&control_receiver);
+        let frame = match read_frame(&mut stream, header_size) {
+            Ok(frame) => frame,
+            Err(why) => {
+                eprintln!("the daemon stopped sharing the camera: {why}");
+                return;
+            }
+        };
+        counted.received.fetch_add(1, Ordering::Relaxed);
+        match queue.send(&sender, frame) {
+            Sent::Queued => {}
+            Sent::Dropped => {
+                counted.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Sent::Gone => return,
+        }
+    });
+
+    Ok(Capture {
+        info: CameraInfo::new(&name, "shared by the athletic daemon", "", index),
+        format,
+        supported_controls: Vec::new(),
+        streams: vec![Stream::Main],
+        frames: receiver,
+        controls: control_sender,
+        counters,
+    })
+}
+
+/// Answers control requests, which a shared camera doesn't take.
+fn refuse(requests: &Receiver<ControlRequest>) {
+    for request in requests.try_iter() {
+        match request {
+            ControlRequest::Set(id, _, _, reply) => {
+                let _ = reply.send(Err(Report::msg(format!(
+                    "can't set {id} on a camera the daemon shares"
+                ))));
+            }
+            ControlRequest::Describe(reply) => {
+                let _ = reply.send(Ok(Vec::new()));
+            }
+            ControlRequest::Nudge(..)
+            | ControlRequest::WatchConditions
+            | ControlRequest::WatchChanges(_) => {}
+        }
+    }
+}
+
+/// Reads one frame of a stream whose frame headers are `header_size`
+/// bytes.
+fn read_frame(stream: &mut UnixStream, header_size: usize) -> Result<Frame, Report> {
+    let mut header = vec![0; header_size];
+    stream.read_exact(&mut header)?;
+    let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+    let format = match &header[28..32] {
+        b"MJPG" => FrameFormat::MJPEG,
+        b"YUYV" => FrameFormat::YUYV,
+        b"NV12" => FrameFormat::NV12,
+        b"GREY" => FrameFormat::GRAY,
+        b"RGB3" => FrameFormat::RAWRGB,
+        other => {
+            return Err(Report::msg(format!(
+                "frames in {} aren't ones a camera sends",
+                String::from_utf8_lossy(other)
+            )))
+        }
+    };
+    let mut data = vec![0; u64_at(32) as usize];
+    stream.read_exact(&mut data)?;
+    Ok(Frame {
+        buffer: Buffer::new(Resolution::new(u32_at(16), u32_at(20)), &data, format),
+        sequence: u64_at(0),
+        pts: Duration::from_nanos(u64_at(8)),
+        conditions: None,
+        planes: Planes::default(),
+        held: Held::new(data.len()),
+    })
+}
@@ -0,0 +1,258 @@
+//! `athletic repl`: a prompt running athletic's commands one after another
+//! in one process, so the camera library is set up once rather than for
+//! every command, which on macOS takes a while.
+//!
+//! A line is a command as it would follow `athletic` on the command line.
+//! The options that hold for the whole process, as --tune and --shared
+//! do, are given to `athletic repl` and hold for every command. The
+//! commands with a window run as another athletic in the background, as
+//! a window keeps the main thread until it closes and ends the process
+//! then; `stop` closes it.
+
+use crate::{execute, Cli, Commands};
+use athletic::pixel_format::ColorSpace;
+use clap::{CommandFactory, Parser};
+use std::ffi::OsString;
+use std::io::Write;
+use std::process::{Child, Command};
+
+/// Runs `cmd` with `cli`'s options, returning the code it exited with: 0
+/// if it ran to the end, and 1 if it failed, once it has said why.
+pub fn attempt(cli: &Cli, cmd: Commands) -> i32 {
+    match execute(cli, cmd) {
+        Ok(code) => code,
+        Err(why) => {
+            eprintln!("{why}");
+            1
+        }
+    }
+}
+
+/// The options athletic was given, without those of `own`, the command's
+/// name and its own arguments, for the commands it runs.
+pub fn options(own: &[OsString]) -> Vec<OsString> {
+    let mut own = own.iter().peekable();
+    std::env::args_os()
+        .skip(1)
+        .filter(|arg| {
+            let theirs = own.peek() == Some(&arg);
+            if theirs {
+                own.next();
+            }
+            !theirs
+        })
+        .collect()
+}
+
+/// Reads commands from standard input and runs them until it ends or
+/// `quit`, with the options given to `athletic repl`.
+pub fn run() {
+    let mut repl = Repl {
+        options: options(&["repl".into()]),
+        device: None,
+        window: None,
+    };
+    println!("Type help for the commands, quit to leave.");
+    let mut line = String::new();
+    loop {
+        print!("athletic> ");
+        let _ = std::io::stdout().flush();
+        line.clear();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if !repl.line(&line) => break,
+            Ok(_) => {}
+        }
+    }
+    repl.stop();
+}
+
+struct Repl {
+    /// The options `athletic repl` was given, which come before each
+    /// command's own.
+    options: Vec<OsString>,
+    /// The camera `open` picked.
+    device: Option<String>,
+    /// The athletic showing a window, and its command.
+    window: Option<(Child, String)>,
+}
+
+impl Repl {
+    /// Carries out `line`. False once it's time to leave.
+    fn line(&mut self, line: &str) -> bool {
+        let Some(words) = shlex::split(line) else {
+            eprintln!("a quote isn't closed");
+            return true;
+        };
+        if let Some((window, name)) = &mut self.window {
+            if !matches!(window.try_wait(), Ok(None)) {
+                println!("The {name} window closed");
+                self.window = None;
+            }
+        }
+        match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            [] => {}
+            ["quit" | "exit"] => return false,
+            ["help"] => help(),
+            ["open", device] => {
+                println!("Commands use {device} unless given another camera");
+                self.device = Some(device.to_string());
+            }
+            ["open"] => match &self.device {
+                Some(device) => println!("Commands use {device}"),
+                None => println!("Commands use the first camera; open DEVICE picks another"),
+            },
+            ["close"] => self.device = None,
+            ["stop"] => self.stop(),
+            _ => self.command(words),
+        }
+        true
+    }
+
+    fn command(&mut self, mut words: Vec<String>) {
+        let own = std::iter::once("athletic".to_string()).chain(words.iter().cloned());
+        match Cli::try_parse_from(own) {
+            Ok(cli) => {
+                if let Some(option) = whole_session(&cli) {
+                    eprintln!("{option} holds for the whole session; give it to athletic repl");
+                    return;
+                }
+            }
+            Err(why) => {
+                let _ = why.print();
+                return;
+            }
+        }
+        let args = std::iter::once(OsString::from("athletic"))
+            .chain(self.options.iter().cloned())
+            .chain(words.iter().map(OsString::from));
+        let mut cli = match Cli::try_parse_from(args) {
+            Ok(cli) => cli,
+            Err(why) => {
+                let _ = why.print();
+                return;
+            }
+        };
+        let Some(mut cmd) = cli.command.take() else {
+            return;
+        };
+        match cmd {
+            Commands::Repl => {
+                eprintln!("this is the prompt already");
+                return;
+            }
+            Commands::Run { .. } => {
+                eprintln!("a script runs with athletic run, not at the prompt");
+                return;
+            }
+            _ => {}
+        }
+        if let (Some(device @ None), Some(open), false) =
+            (cmd.device_mut(), &self.device, cli.first_available)
+        {
+            *device = open.parse().ok();
+            // The window's athletic is told it as the camera after the
+            // command's name.
+            let name = Cli::command()
+                .try_get_matches_from(
+                    std::iter::once("athletic").chain(words.iter().map(String::as_str)),
+                )
+                .ok()
+                .and_then(|matches| matches.subcommand_name().map(str::to_string));
+            if let Some(at) = words.iter().position(|word| Some(word) == name.as_ref()) {
+                words.insert(at + 1, open.clone());
+            }
+        }
+        if cmd.opens_window() && !cli.dry_run {
+            self.open_window(words);
+            return;
+        }
+        match attempt(&cli, cmd) {
+            0 => {}
+            code => eprintln!("(exited with {code})"),
+        }
+    }
+
+    fn open_window(&mut self, words: Vec<String>) {
+        if let Some((_, name)) = &self.window {
+            eprintln!("the {name} window is open; stop closes it");
+            return;
+        }
+        let name = words.join(" ");
+        let started = std::env::current_exe().and_then(|athletic| {
+            Command::new(athletic)
+                .args(&self.options)
+                .args(&words)
+                .spawn()
+        });
+        match started {
+            Ok(child) => {
+                println!("Opening the {name} window; stop closes it");
+                self.window = Some((child, name));
+            }
+            Err(why) => eprintln!("can't start athletic for the window: {why}"),
+        }
+    }
+
+    /// Closes the window, if one is open.
+    fn stop(&mut self) {
+        if let Some((mut window, name)) = self.window.take() {
+            let _ = window.kill();
+            let _ = window.wait();
+            println!("Closed the {name} window");
+        }
+    }
+}
+
+/// The first of the options `cli` gives that hold for the whole process,
+/// if it gives any.
+pub fn whole_session(cli: &Cli) -> Option<&'static str> {
+    let defaults = Cli::parse_from(["athletic"]);
+    [
+        ("--shared", cli.shared),
+        ("--tune", cli.tune.is_some()),
+        ("--anti-flicker", cli.anti_flicker.is_some()),
+        ("--max-memory", cli.max_memory.is_some()),
+        ("--explain-format", cli.explain_format),
+        ("--trace", cli.trace.is_some()),
+        ("--color-space", cli.color_space != ColorSpace::Auto),
+        ("--pixel-map", cli.pixel_map.is_some()),
+        (
+            "--stall-timeout",
+            cli.stall_timeout != defaults.stall_timeout,
+        ),
+        (
+            "--stall-attempts",
+            cli.stall_attempts != defaults.stall_attempts,
+        ),
+    ]
+    .into_iter()
+    .find(|(_, given)| *given)
+    .map(|(option, _)| option)
+}
+
+fn help() {
+    println!("Commands are athletic's, as they'd follow athletic on the command line:");
+    for command in Cli::command().get_subcommands() {
+        if ["repl", "run"].contains(&command.get_name()) {
+            continue;
+        }
+        let about = command
+            .get_about()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        println!("  {:<18} {about}", command.get_name());
+    }
+    println!("and COMMAND --help says what one takes. At the prompt there's also:");
+    for (command, about) in [
+        (
+            "open DEVICE",
+            "Use DEVICE for the commands not given a camera",
+        ),
+        ("close", "Go back to the first camera"),
+        ("stop", "Close the window a command opened"),
+        ("quit", "Leave"),
+    ] {
+        println!("  {command:<18} {about}");
+    }
+}
@@ -0,0 +1,239 @@
+use crate::capture::Capture;
+use crate::defects;
+use crate::document::{self, Point};
+use crate::mask::{self, Mask};
+use crate::ocr;
+use crate::pdf::Pdf;
+use crate::pixel_format::RgbAFormat;
+use crate::window;
+use clap::Args;
+use color_eyre::Report;
+use ggez::{
+    event::EventHandler,
+    graphics::{Canvas, Color, DrawMode, DrawParam, Image, ImageFormat, Mesh, Text},
+    input::keyboard::{KeyCode, KeyInput},
+    Context, GameError,
+};
+use image::{DynamicImage, RgbImage};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a scanned page is cleaned up.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Black and white, thresholded against each pixel's surroundings.
+    Bilevel,
+    Gray,
+    Colour,
+}
+
+impl FromStr for Mode {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bilevel" | "BILEVEL" | "bw" => Ok(Mode::Bilevel),
+            "gray" | "GRAY" | "grey" => Ok(Mode::Gray),
+            "colour" | "COLOUR" | "color" => Ok(Mode::Colour),
+            _ => Err(Report::msg(format!("unknown scan mode: {s}"))),
+        }
+    }
+}
+
+/// What `scan-doc` saves and how.
+#[derive(Args, Clone)]
+pub struct ScanOptions {
+    /// Where to save each page as PNG, with {n} replaced by the page number,
+    /// or a PDF to add every page to when it ends in .pdf.
+    #[arg(short, long, default_value = "scan-{n}.png")]
+    pub output: String,
+    /// How to clean the page up: `bilevel` black and white, `gray` or
+    /// `colour`. In a PDF, bilevel pages are stored losslessly at one bit
+    /// per pixel and the others as JPEG.
+    #[arg(long, default_value = "bilevel")]
+    pub mode: Mode,
+    /// Resolution the page is taken to be scanned at, which sets its size
+    /// in a PDF.
+    #[arg(long, default_value_t = 150.0)]
+    pub dpi: f32,
+    /// Print the text on each page, read with tesseract, and lay it
+    /// invisibly over PDF pages so they can be searched. Needs athletic
+    /// built with the ocr feature.
+    #[arg(long)]
+    pub ocr: bool,
+}
+
+/// Shows the camera with the page it finds outlined, and saves the page,
+/// flattened and cleaned up, whenever Space is pressed. Return finishes the
+/// document and quits.
+pub struct ScanState {
+    capture: Capture,
+    options: ScanOptions,
+    masks: Vec<Mask>,
+    /// The newest frame, shown and scanned from, and the page found in it.
+    frame: Option<(RgbImage, Option<[Point; 4]>)>,
+    image: Option<Image>,
+    /// The pages so far, when they go in a PDF.
+    pdf: Option<Pdf>,
+    pages: u32,
+}
+
+impl ScanState {
+    pub fn new(capture: Capture, options: ScanOptions, masks: Vec<Mask>) -> Self {
+        let is_pdf = Path::new(&options.output)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+        ScanState {
+            pdf: is_pdf.then(|| Pdf::new(options.dpi)),
+            capture,
+            options,
+            masks,
+            frame: None,
+            image: None,
+            pages: 0,
+        }
+    }
+
+    fn receive(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        let frame = self
+            .capture
+            .frames
+            .recv()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let mut decoded = frame
+            .buffer
+            .decode_image::<RgbAFormat>()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let (width, height) = decoded.dimensions();
+        defects::correct(&mut decoded, width, height, 4);
+        mask::apply(&self.masks, &mut decoded, width, height, 4);
+        self.image = Some(Image::from_pixels(
+            ctx,
+            decoded.as_raw(),
+            ImageFormat::Rgba8UnormSrgb,
+            width,
+            height,
+        ));
+        let rgb = DynamicImage::ImageRgba8(decoded).to_rgb8();
+        let corners = document::find_page(&rgb);
+        self.frame = Some((rgb, corners));
+        Ok(())
+    }
+
+    /// Flattens and cleans up the page in the newest frame, or the whole
+    /// frame if no page was found, and saves it.
+    fn scan(&mut self) -> Result<(), Report> {
+        let Some((frame, corners)) = &self.frame else {
+            return Ok(());
+        };
+        let page = match corners {
+            Some(corners) => document::flatten(frame, corners),
+            None => frame.clone(),
+        };
+        let page = match self.options.mode {
+            Mode::Bilevel => {
+                let gray = DynamicImage::ImageRgb8(page).to_luma8();
+                DynamicImage::ImageLuma8(document::threshold(&gray))
+            }
+            Mode::Gray => DynamicImage::ImageLuma8(DynamicImage::ImageRgb8(page).to_luma8()),
+            Mode::Colour => DynamicImage::ImageRgb8(page),
+        };
+        let text = self
+            .options
+            .ocr
+            .then(|| ocr::recognize(&page))
+            .transpose()?;
+        if let Some(text) = &text {
+            println!("{}", text.text);
+        }
+
+        self.pages += 1;
+        match (&mut self.pdf, &page) {
+            (Some(pdf), DynamicImage::ImageLuma8(bilevel))
+                if self.options.mode == Mode::Bilevel =>
+            {
+                pdf.add_bilevel_page(bilevel)?
+            }
+            (Some(pdf), _) => pdf.add_page(&page)?,
+            (None, _) => {
+                let path = self.options.output.replace("{n}", &self.pages.to_string());
+                page.save(&path)?;
+                println!("Saved page to {path}");
+                return Ok(());
+            }
+        }
+        #[cfg(feature = "ocr")]
+        if let (Some(pdf), Some(text)) = (&mut self.pdf, text) {
+            pdf.add_text(text.words);
+        }
+        // The whole document is written again for every page, so what's been
+        // scanned so far is never lost.
+        if let Some(pdf) = &self.pdf {
+            pdf.write(BufWriter::new(File::create(&self.options.output)?))?;
+            println!("Added page {} to {}", self.pages, self.options.output);
+        }
+        Ok(())
+    }
+}
+
+impl EventHandler<GameError> for ScanState {
+    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.receive(ctx)?;
+
+        let size = ctx.gfx.drawable_size();
+        let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+        if let Some(image) = &self.image {
+            let (offset, scale) =
+                window::letterbox((image.width() as f32, image.height() as f32), size);
+            canvas.draw(image, DrawParam::new().dest(offset).scale([scale, scale]));
+            if let Some((_, Some(corners))) = &self.frame {
+                let points = corners.map(|(x, y)| [offset[0] + x * scale, offset[1] + y * scale]);
+                let outline = Mesh::new_polygon(ctx, DrawMode::stroke(3.0), &points, Color::GREEN)?;
+                canvas.draw(&outline, DrawParam::new());
+            }
+        }
+        let hint = match self.frame {
+            Some((_, Some(_))) => "Space to scan the outlined page",
+            _ => "No page found; Space scans the whole frame",
+        };
+        let finish = match self.pdf {
+            Some(_) => ", Return to finish",
+            None => "",
+        };
+        canvas.draw(
+            &Text::new(format!("{hint}{finish} ({} saved)", self.pages)),
+            DrawParam::new().dest([8.0, 8.0]).color(Color::WHITE),
+        );
+        canvas.finish(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        _repeated: bool,
+    ) -> Result<(), GameError> {
+        match input.keycode {
+            Some(KeyCode::Space) => {
+                if let Err(why) = self.scan() {
+                    eprintln!("failed to save page: {why}");
+                }
+            }
+            Some(KeyCode::Return) => {
+                if self.pdf.is_some() {
+                    println!("Finished {} with {} pages", self.options.output, self.pages);
+                }
+                ctx.request_quit();
+            }
+            Some(KeyCode::Escape) => ctx.request_quit(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+/// Scopes are computed on a grid of at most this many samples per axis, and
+/// drawn at this many levels.
+const SAMPLES: usize = 256;
+const LEVELS: usize = 256;
+
+/// Background of the scope panel where no samples landed.
+const BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Scope {
+    Waveform,
+    Parade,
+    Vectorscope,
+}
+
+impl Scope {
+    /// Cycles through the scopes and then back to none.
+    pub fn next(current: Option<Scope>) -> Option<Scope> {
+        match current {
+            None => Some(Scope::Waveform),
+            Some(Scope::Waveform) => Some(Scope::Parade),
+            Some(Scope::Parade) => Some(Scope::Vectorscope),
+            Some(Scope::Vectorscope) => None,
+        }
+    }
+}
+
+/// Where the scope panel sits in the window.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Dock {
+    Right,
+    Bottom,
+}
+
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// A grid of sample counts rendered into an RGBA image.
+struct Plot {
+    width: usize,
+    counts: Vec<u32>,
+}
+
+impl Plot {
+    fn new(width: usize) -> Self {
+        Plot {
+            width,
+            counts: vec![0; width * LEVELS],
+        }
+    }
+
+    /// Counts a sample at column `x`, `level` levels up from the bottom.
+    fn add(&mut self, x: usize, level: f32) {
+        let level = (level.clamp(0.0, 255.0) as usize).min(LEVELS - 1);
+        self.counts[(LEVELS - 1 - level) * self.width + x] += 1;
+    }
+
+    /// Renders the counts with `tint`, brighter where more samples fell,
+    /// and returns `(pixels, width, height)`.
+    fn render(&self, tint: impl Fn(usize) -> [f32; 3]) -> (Vec<u8>, u32, u32) {
+        let max = self.counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let mut pixels = Vec::with_capacity(self.counts.len() * 4);
+        for (n, &count) in self.counts.iter().enumerate() {
+            if count == 0 {
+                pixels.extend_from_slice(&BACKGROUND);
+                continue;
+            }
+            let brightness = (count as f32 / max).sqrt().max(0.25);
+            let [r, g, b] = tint(n % self.width);
+            pixels.extend_from_slice(&[
+                (r * brightness * 255.0) as u8,
+                (g * brightness * 255.0) as u8,
+                (b * brightness * 255.0) as u8,
+                255,
+            ]);
+        }
+        (pixels, self.width as u32, LEVELS as u32)
+    }
+}
+
+/// Computes `scope` for an RGBA frame and returns `(pixels, width, height)`
+/// of the rendered scope.
+pub fn compute(scope: Scope, rgba: &[u8], width: u32, height: u32) -> (Vec<u8>, u32, u32) {
+    let (width, height) = (width as usize, height as usize);
+    let step_x = (width / SAMPLES).max(1);
+    let step_y = (height / SAMPLES).max(1);
+    let columns = width.div_ceil(step_x);
+    let samples = (0..height).step_by(step_y).flat_map(|y| {
+        (0..width).step_by(step_x).filter_map(move |x| {
+            let at = (y * width + x) * 4;
+            let pixel = rgba.get(at..at + 3)?;
+            Some((x / step_x, [pixel[0], pixel[1], pixel[2]].map(f32::from)))
+        })
+    });
+
+    match scope {
+        Scope::Waveform => {
+            let mut plot = Plot::new(columns);
+            for (x, rgb) in samples {
+                plot.add(x, luma(rgb));
+            }
+            plot.render(|_| [0.6, 1.0, 0.6])
+        }
+        Scope::Parade => {
+            let mut plot = Plot::new(columns * 3);
+            for (x, rgb) in samples {
+                for (channel, value) in rgb.into_iter().enumerate() {
+                    plot.add(channel * columns + x, value);
+                }
+            }
+            plot.render(|x| match x / columns {
+                0 => [1.0, 0.3, 0.3],
+                1 => [0.3, 1.0, 0.3],
+                _ => [0.4, 0.4, 1.0],
+            })
+        }
+        Scope::Vectorscope => {
+            // Cb runs along x and Cr along y, both centred on neutral grey.
+            let mut plot = Plot::new(LEVELS);
+            for (_, [r, g, b]) in samples {
+                let cb = -0.1146 * r - 0.3854 * g + 0.5 * b + 128.0;
+                let cr = 0.5 * r - 0.4542 * g - 0.0458 * b + 128.0;
+                plot.add((cb.clamp(0.0, 255.0) as usize).min(LEVELS - 1), cr);
+            }
+            plot.render(|_| [1.0, 1.0, 1.0])
+        }
+    }
+}
+
+/// Overlays diagonal stripes on pixels whose luma is at or above
+/// `threshold`, marking areas that are close to clipping.
+pub fn zebra(rgba: &mut [u8], width: u32, threshold: u8) {
+    let width = width as usize;
+    for (n, pixel) in rgba.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (n % width, n / width);
+        if (x + y) / 8 % 2 == 0
+            && luma([pixel[0], pixel[1], pixel[2]].map(f32::from)) >= threshold as f32
+        {
+            pixel[..3].fill(0);
+        }
+    }
+}
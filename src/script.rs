@@ -0,0 +1,182 @@
+//! Scripts that react to what `serve` sees.
+//!
+//! Every `.rhai` file in the scripts directory is loaded along with the
+//! server, each on its own thread. The script runs once from the top, then
+//! its `on_event` function, if it has one, is called with every event as a
+//! map, the way WebSocket clients get them as JSON. Scripts act through:
+//!
+//! - `set_control(name, value)`, setting an integer camera control by the
+//!   name `list-properties` prints;
+//! - `switch_profile(name)`, switching to another profile in the config,
+//!   which needs --profile;
+//! - `start_recording(path)` and `stop_recording()`, which returns where
+//!   the recording went.
+//!
+//! ```rhai
+//! fn on_event(event) {
+//!     if event.type == "motion" {
+//!         if event.active {
+//!             start_recording("motion.mkv");
+//!         } else {
+//!             stop_recording();
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! An action that fails raises an error the script can catch. One it
+//! doesn't catch is reported, and the script is called again with the next
+//! event.
+
+use crate::bus;
+use crate::capture::{self, ControlRequest, Origin};
+use crate::record::Recordings;
+use color_eyre::Report;
+use flume::{Receiver, Sender};
+use nokhwa::utils::ControlValueSetter;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use std::path::{Path, PathBuf};
+
+/// What scripts act on.
+#[derive(Clone)]
+pub struct Actions {
+    pub controls: Sender<ControlRequest>,
+    pub recordings: Recordings,
+}
+
+/// Loads every script in `dir` and starts it on events as they are
+/// published. Returns where the names of the profiles they switch to
+/// arrive.
+pub fn start(dir: &Path, actions: &Actions) -> Result<Receiver<String>, Report> {
+    let (profiles, switches) = flume::unbounded();
+    for path in scripts(dir)? {
+        let (loaded, load) = flume::bounded(1);
+        let (actions, profiles) = (actions.clone(), profiles.clone());
+        // Engines and scripts stay on the thread they were made on, so
+        // the script is compiled there and only the outcome sent back.
+        let script = path.clone();
+        std::thread::spawn(move || {
+            let name = script.display().to_string();
+            let engine = engine(&name, actions, profiles);
+            let ast = match engine.compile_file(script) {
+                Ok(ast) => ast,
+                Err(why) => {
+                    let _ = loaded.send(Err(why.to_string()));
+                    return;
+                }
+            };
+            let _ = loaded.send(Ok(()));
+            run(&name, &engine, &ast);
+            eprintln!("script {name} finished");
+        });
+        load.recv()
+            .unwrap_or_else(|_| Err("it stopped while loading".to_string()))
+            .map_err(|why| Report::msg(format!("can't load {}: {why}", path.display())))?;
+        println!("Running script {}", path.display());
+    }
+    Ok(switches)
+}
+
+/// The `.rhai` files in `dir`, in name order.
+pub fn scripts(dir: &Path) -> Result<Vec<PathBuf>, Report> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|why| Report::msg(format!("can't read {}: {why}", dir.display())))?;
+    let mut scripts = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|extension| extension == "rhai")
+        {
+            scripts.push(path);
+        }
+    }
+    scripts.sort();
+    Ok(scripts)
+}
+
+/// An engine with the actions registered for the script called `name`.
+fn engine(name: &str, actions: Actions, profiles: Sender<String>) -> Engine {
+    let mut engine = Engine::new();
+    let script = name.to_string();
+    engine.on_print(move |text| println!("script {script}: {text}"));
+
+    let controls = actions.controls;
+    engine.register_fn(
+        "set_control",
+        move |control: &str, value: i64| -> Result<(), Box<EvalAltResult>> {
+            let id = capture::known_control(control)
+                .ok_or_else(|| format!("no camera control called {control}"))?;
+            let (reply, replied) = flume::bounded(1);
+            let setter = ControlValueSetter::Integer(value);
+            controls
+                .send(ControlRequest::Set(id, setter, Origin::Script, reply))
+                .map_err(|_| "the camera has stopped")?;
+            match replied.recv() {
+                Ok(Err(why)) => Err(format!("failed to set {control}: {why}").into()),
+                _ => Ok(()),
+            }
+        },
+    );
+    engine.register_fn(
+        "switch_profile",
+        move |profile: &str| -> Result<(), Box<EvalAltResult>> {
+            profiles
+                .send(profile.to_string())
+                .map_err(|_| "switching profiles needs --profile".into())
+        },
+    );
+    let recordings = actions.recordings.clone();
+    engine.register_fn(
+        "start_recording",
+        move |path: &str| -> Result<(), Box<EvalAltResult>> {
+            recordings
+                .start(Path::new(path))
+                .map_err(|why| why.to_string().into())
+        },
+    );
+    let recordings = actions.recordings;
+    engine.register_fn(
+        "stop_recording",
+        move || -> Result<String, Box<EvalAltResult>> {
+            let path = recordings.stop().map_err(|why| why.to_string())?;
+            Ok(path.display().to_string())
+        },
+    );
+    engine
+}
+
+/// Runs the script from the top, then hands it every event published
+/// from then on.
+fn run(name: &str, engine: &Engine, ast: &AST) {
+    let events = bus::subscribe();
+    let mut scope = Scope::new();
+    if let Err(why) = engine.run_ast_with_scope(&mut scope, ast) {
+        eprintln!("script {name}: {why}");
+        return;
+    }
+    let handles_events = ast
+        .iter_functions()
+        .any(|function| function.name == "on_event" && function.params.len() == 1);
+    if !handles_events {
+        return;
+    }
+    for event in events.iter() {
+        let event = match engine.parse_json(event.to_json(), true) {
+            Ok(event) => event,
+            Err(why) => {
+                eprintln!("script {name}: {why}");
+                continue;
+            }
+        };
+        // The top level has run already, and what it left in the scope
+        // stays for every call.
+        let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+        let called =
+            engine.call_fn_with_options::<Dynamic>(options, &mut scope, ast, "on_event", (event,));
+        if let Err(why) = called {
+            eprintln!("script {name}: {why}");
+        }
+    }
+}
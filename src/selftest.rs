@@ -0,0 +1,335 @@
+//! `selftest`: a round of checks that a camera works, for going through a
+//! batch of them. It opens the camera, streams in a few of the formats it
+//! offers, checks the picture isn't black or frozen, swings a couple of
+//! controls to see the picture follow, and says which checks passed.
+
+use crate::capture::{self, ControlRequest, Frame, Origin};
+use crate::negotiate::{self, Negotiation};
+use crate::pixel_format::RgbAFormat;
+use crate::{mono, pattern};
+use color_eyre::Report;
+use image::GrayImage;
+use nokhwa::utils::{
+    CameraFormat, CameraIndex, ControlValueDescription, ControlValueSetter, KnownCameraControl,
+    RequestedFormatType,
+};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// How long to leave the camera closed between formats, so it has time to
+/// reset.
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+/// How long a control gets to show in the picture, as cameras take a few
+/// frames to apply one.
+const SETTLE: Duration = Duration::from_millis(500);
+/// How far apart the frames compared for movement are.
+const APART: Duration = Duration::from_secs(1);
+/// A picture darker than this on average, out of 255, is black.
+const BLACK: f64 = 8.0;
+/// Frames closer than this on average, out of 255, are the same picture;
+/// a sensor's noise alone moves them further.
+const FROZEN: f64 = 0.05;
+/// How much swinging a control across its range has to move the picture,
+/// out of 255.
+const RESPONDS: f64 = 4.0;
+/// The controls swung, and what of the picture each moves.
+const CONTROLS: [(KnownCameraControl, Measure); 2] = [
+    (KnownCameraControl::Brightness, Measure::Mean),
+    (KnownCameraControl::Contrast, Measure::Spread),
+];
+
+#[derive(Copy, Clone)]
+enum Measure {
+    Mean,
+    /// The standard deviation.
+    Spread,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// How the checks went, printed as they're made.
+#[derive(Default)]
+struct Checks(Vec<Outcome>);
+
+impl Checks {
+    fn note(&mut self, outcome: Outcome, check: impl Display, detail: impl Display) {
+        let shown = match outcome {
+            Outcome::Pass => "pass",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "skip",
+        };
+        println!("  {shown}  {:<32} {detail}", check.to_string());
+        self.0.push(outcome);
+    }
+
+    fn count(&self, outcome: Outcome) -> usize {
+        self.0.iter().filter(|&&had| had == outcome).count()
+    }
+}
+
+/// Runs the checks on the camera at `index`, streaming in up to `formats`
+/// of its formats and waiting up to `timeout` for each one's first frame.
+/// Fails if any check does.
+pub fn run(index: CameraIndex, formats: usize, timeout: Duration) -> Result<(), Report> {
+    let capture = capture::spawn_capture(index.clone())?;
+    println!("Testing {}", capture.info.human_name());
+    let mut checks = Checks::default();
+    checks.note(
+        Outcome::Pass,
+        "opens",
+        format!("as {}", negotiate::spec(&capture.format)),
+    );
+
+    match capture.frames.recv_timeout(timeout) {
+        Ok(frame) => match levels(&frame) {
+            Ok(first) => {
+                let mean = measure(&first, Measure::Mean);
+                let outcome = if mean < BLACK {
+                    Outcome::Fail
+                } else {
+                    Outcome::Pass
+                };
+                checks.note(
+                    outcome,
+                    "picture isn't black",
+                    format!("level {mean:.0} of 255 on average"),
+                );
+                std::thread::sleep(APART);
+                capture.frames.drain();
+                match capture
+                    .frames
+                    .recv_timeout(timeout)
+                    .map_err(Report::from)
+                    .and_then(|frame| levels(&frame))
+                {
+                    Ok(later) => {
+                        let moved = difference(&first, &later);
+                        let outcome = if moved < FROZEN {
+                            Outcome::Fail
+                        } else {
+                            Outcome::Pass
+                        };
+                        checks.note(
+                            outcome,
+                            "picture changes",
+                            format!(
+                                "frames {APART:?} apart differ by {moved:.2} levels on average"
+                            ),
+                        );
+                    }
+                    Err(why) => checks.note(Outcome::Fail, "picture changes", why.to_string()),
+                }
+            }
+            Err(why) => checks.note(Outcome::Fail, "frames decode", why.to_string()),
+        },
+        Err(_) => checks.note(
+            Outcome::Fail,
+            "sends frames",
+            format!("none in {timeout:?}"),
+        ),
+    }
+
+    for (control, measure) in CONTROLS {
+        let check = format!("picture follows {control}");
+        if !capture.supported_controls.contains(&control) {
+            checks.note(
+                Outcome::Skip,
+                check,
+                format!("the camera has no {control} control"),
+            );
+            continue;
+        }
+        match swing(&capture, control, measure, timeout) {
+            Ok((low, high)) => {
+                let outcome = if (high - low).abs() < RESPONDS {
+                    Outcome::Fail
+                } else {
+                    Outcome::Pass
+                };
+                let what = match measure {
+                    Measure::Mean => "level",
+                    Measure::Spread => "spread",
+                };
+                checks.note(
+                    outcome,
+                    check,
+                    format!("{what} {low:.0} at its least, {high:.0} at its most"),
+                );
+            }
+            Err(why) => checks.note(Outcome::Fail, check, why.to_string()),
+        }
+    }
+    drop(capture);
+
+    for format in candidates(&index, formats) {
+        std::thread::sleep(REOPEN_DELAY);
+        let check = format!("streams {}", negotiate::spec(&format));
+        let started = Instant::now();
+        match capture::spawn_capture_with(index.clone(), RequestedFormatType::Exact(format)) {
+            Ok(capture) if capture.format != format && !pattern::is_pattern(&index) => checks.note(
+                Outcome::Fail,
+                check,
+                format!("opened as {} instead", negotiate::spec(&capture.format)),
+            ),
+            Ok(capture) => match capture.frames.recv_timeout(timeout) {
+                Ok(frame) if frame.buffer.resolution() != capture.format.resolution() => checks
+                    .note(
+                        Outcome::Fail,
+                        check,
+                        format!("frames are {}", frame.buffer.resolution()),
+                    ),
+                Ok(_) => checks.note(
+                    Outcome::Pass,
+                    check,
+                    format!("first frame in {:.2}s", started.elapsed().as_secs_f64()),
+                ),
+                Err(_) => checks.note(Outcome::Fail, check, format!("no frame in {timeout:?}")),
+            },
+            Err(why) => checks.note(Outcome::Fail, check, why.to_string()),
+        }
+    }
+
+    let (passed, failed, skipped) = (
+        checks.count(Outcome::Pass),
+        checks.count(Outcome::Fail),
+        checks.count(Outcome::Skip),
+    );
+    println!(
+        "{passed} of {} checks passed, {skipped} skipped",
+        passed + failed
+    );
+    match failed {
+        0 => Ok(()),
+        _ => Err(Report::msg(format!("{failed} checks failed"))),
+    }
+}
+
+/// Up to `count` of the formats the camera offers, as different as they
+/// come: the largest in each of its pixel formats, then the smallest.
+fn candidates(index: &CameraIndex, count: usize) -> Vec<CameraFormat> {
+    let mut offered = if pattern::is_pattern(index) {
+        vec![pattern::format()]
+    } else {
+        match Negotiation::probe(index, RequestedFormatType::None) {
+            Ok(negotiation) => negotiation.offered,
+            Err(why) => {
+                eprintln!("can't list the camera's formats: {why}");
+                Vec::new()
+            }
+        }
+    };
+    let area = |format: &CameraFormat| format.resolution().width() * format.resolution().height();
+    offered.sort_by_key(|format| std::cmp::Reverse((area(format), format.frame_rate())));
+    let mut picked: Vec<CameraFormat> = Vec::new();
+    for format in &offered {
+        if !picked.iter().any(|had| had.format() == format.format()) {
+            picked.push(*format);
+        }
+    }
+    if let Some(smallest) = offered.last() {
+        if !picked.contains(smallest) {
+            picked.push(*smallest);
+        }
+    }
+    picked.truncate(count);
+    picked
+}
+
+/// Sets `control` to the least and then the most it goes, and measures the
+/// picture at each, setting it back afterwards.
+fn swing(
+    capture: &capture::Capture,
+    control: KnownCameraControl,
+    measure: Measure,
+    timeout: Duration,
+) -> Result<(f64, f64), Report> {
+    let (reply, replied) = flume::bounded(1);
+    capture
+        .controls
+        .send(ControlRequest::Describe(reply))
+        .map_err(|_| Report::msg("the camera stopped"))?;
+    let controls = replied.recv()??;
+    let described = controls
+        .iter()
+        .find(|described| described.control() == control)
+        .ok_or_else(|| Report::msg(format!("the camera doesn't describe {control}")))?;
+    let ControlValueDescription::IntegerRange {
+        min, max, value, ..
+    } = *described.description()
+    else {
+        return Err(Report::msg(format!("{control} is not an integer range")));
+    };
+    let at = |level: i64| -> Result<f64, Report> {
+        let (reply, replied) = flume::bounded(1);
+        let setter = ControlValueSetter::Integer(level);
+        capture
+            .controls
+            .send(ControlRequest::Set(control, setter, Origin::Manual, reply))
+            .map_err(|_| Report::msg("the camera stopped"))?;
+        replied.recv()??;
+        std::thread::sleep(SETTLE);
+        capture.frames.drain();
+        let frame = capture
+            .frames
+            .recv_timeout(timeout)
+            .map_err(|_| Report::msg(format!("no frame in {timeout:?}")))?;
+        Ok(self::measure(&levels(&frame)?, measure))
+    };
+    let swung = at(min).and_then(|low| Ok((low, at(max)?)));
+    let (reply, replied) = flume::bounded(1);
+    let setter = ControlValueSetter::Integer(value);
+    if capture
+        .controls
+        .send(ControlRequest::Set(control, setter, Origin::Manual, reply))
+        .is_ok()
+    {
+        if let Ok(Err(why)) = replied.recv() {
+            eprintln!("failed to set {control} back to {value}: {why}");
+        }
+    }
+    swung
+}
+
+/// The frame's picture as grayscale.
+fn levels(frame: &Frame) -> Result<GrayImage, Report> {
+    Ok(match mono::Depth::of(frame) {
+        Some(depth) => mono::image(frame, depth)
+            .ok_or_else(|| Report::msg("grayscale frame is too short"))?
+            .to_luma8(),
+        None => image::DynamicImage::from(frame.buffer.decode_image::<RgbAFormat>()?).to_luma8(),
+    })
+}
+
+fn measure(image: &GrayImage, measure: Measure) -> f64 {
+    let count = image.len().max(1) as f64;
+    let mean = image.iter().map(|&level| f64::from(level)).sum::<f64>() / count;
+    match measure {
+        Measure::Mean => mean,
+        Measure::Spread => {
+            let variance = image
+                .iter()
+                .map(|&level| (f64::from(level) - mean).powi(2))
+                .sum::<f64>()
+                / count;
+            variance.sqrt()
+        }
+    }
+}
+
+/// How far apart `a` and `b` are on average, pixel by pixel.
+fn difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    if a.dimensions() != b.dimensions() {
+        return f64::INFINITY;
+    }
+    let total: u64 = a
+        .iter()
+        .zip(b.iter())
+        .map(|(&a, &b)| u64::from(a.abs_diff(b)))
+        .sum();
+    total as f64 / a.len().max(1) as f64
+}
@@ -0,0 +1,555 @@
+use crate::away::Away;
+use crate::bad_frames::BadFrames;
+use crate::bus::{self, Event, PublishOptions};
+use crate::capture::{self, Frame};
+use crate::config::{self, Reload};
+use crate::defects;
+use crate::grpc;
+use crate::interpolate::{InterpolateOptions, Interpolator};
+use crate::low_light::{LowLight, LowLightOptions};
+use crate::mask::{self, Mask};
+use crate::memory::{self, Held};
+use crate::motion::Motion;
+use crate::notify;
+use crate::pixel_format::RgbFormat;
+use crate::record::Recordings;
+use crate::script;
+use crate::shutdown;
+use crate::sink::{SinkOptions, Sinks, Stage};
+use crate::sink_frame::{PixelFormat, SinkFrame};
+use crate::summary;
+use crate::upscale::{Upscale, UpscaleOptions};
+use clap::Args;
+use color_eyre::Report;
+use flume::{Receiver, Sender, TrySendError};
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, RgbImage};
+use nokhwa::utils::{CameraIndex, RequestedFormatType};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+/// How many messages may wait for a slow client before its frames get dropped.
+pub const CLIENT_QUEUE_DEPTH: usize = 4;
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// How long clients get to hear that the stream has ended, once it has.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+const BOUNDARY: &str = "athletic-frame";
+
+/// Where and how `serve` streams.
+#[derive(Args, Clone)]
+pub struct ServeOptions {
+    /// `mjpeg` for plain HTTP, `ws` for a WebSocket that also carries JSON events,
+    /// or `grpc` for the service in proto/athletic.proto.
+    #[arg(long, default_value = "mjpeg")]
+    pub protocol: Protocol,
+    /// Serve over gRPC, as --protocol grpc.
+    #[arg(long, conflicts_with = "protocol")]
+    pub grpc: bool,
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub bind: SocketAddr,
+    /// JPEG quality, from 1 to 100.
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: u8,
+    /// Mean change in brightness, from 0 to 1, that counts as motion.
+    #[arg(long, default_value_t = 0.05)]
+    pub motion_threshold: f64,
+    #[command(flatten)]
+    pub upscale: UpscaleOptions,
+    #[command(flatten)]
+    pub low_light: LowLightOptions,
+    #[command(flatten)]
+    pub interpolate: InterpolateOptions,
+    /// Run every .rhai script in this directory, or in athletic/scripts in
+    /// the user's config directory, on the events serve sees. See
+    /// src/script.rs.
+    #[arg(long, value_name = "DIR")]
+    pub scripts: Option<Option<PathBuf>>,
+    #[command(flatten)]
+    pub publish: PublishOptions,
+}
+
+impl ServeOptions {
+    /// The protocol asked for, with --protocol or --grpc.
+    pub fn protocol(&self) -> Protocol {
+        match self.grpc {
+            true => Protocol::Grpc,
+            false => self.protocol,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Protocol {
+    /// `multipart/x-mixed-replace` over HTTP, viewable in any browser.
+    Mjpeg,
+    /// JPEG frames as binary messages and JSON events as text messages.
+    Ws,
+    /// The `athletic.v1.Camera` service in `proto/athletic.proto`.
+    Grpc,
+}
+
+impl FromStr for Protocol {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mjpeg" | "MJPEG" | "http" => Ok(Protocol::Mjpeg),
+            "ws" | "WS" | "websocket" => Ok(Protocol::Ws),
+            "grpc" | "gRPC" | "GRPC" => Ok(Protocol::Grpc),
+            _ => Err(Report::msg(format!("unknown protocol: {s}"))),
+        }
+    }
+}
+
+/// A frame as it goes out to clients.
+pub struct Encoded {
+    pub jpeg: Vec<u8>,
+    pub sequence: u64,
+    pub pts: Duration,
+    _held: Held,
+}
+
+/// What gets pushed to clients. Events, forwarded from the bus, only reach
+/// WebSocket clients and scripts.
+#[derive(Clone)]
+pub enum Outgoing {
+    Frame(Arc<Encoded>),
+    Event(Arc<String>),
+}
+
+pub type Clients = Arc<Mutex<Vec<Sender<Outgoing>>>>;
+
+/// Clients still connected, each counted by a [`Connected`].
+static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts a client as connected until it's dropped, so `serve` can wait
+/// for the stream to be closed properly before it returns.
+pub struct Connected(());
+
+impl Connected {
+    pub fn new() -> Self {
+        CONNECTED.fetch_add(1, Ordering::Relaxed);
+        Connected(())
+    }
+}
+
+impl Drop for Connected {
+    fn drop(&mut self) {
+        CONNECTED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Hands `outgoing` to every client, dropping it for clients that fall
+/// behind, or that have a frame waiting while frames hold more than the
+/// memory cap, and forgetting clients that went away.
+fn send(clients: &Clients, outgoing: Outgoing) {
+    let tight = matches!(outgoing, Outgoing::Frame(_)) && memory::over();
+    clients.lock().unwrap().retain(|client| {
+        if tight && !client.is_empty() {
+            return !client.is_disconnected();
+        }
+        match client.try_send(outgoing.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+/// Streams the camera to every client that connects to `options.bind`
+/// until the camera stops or the process is asked to, following changes
+/// to the profile `reload` watches.
+pub fn serve(
+    index: CameraIndex,
+    options: &ServeOptions,
+    sinks: &SinkOptions,
+    bad_frames: BadFrames,
+    masks: &[Mask],
+    away: Away,
+    mut reload: Option<Reload>,
+) -> Result<(), Report> {
+    let capture = capture::spawn_capture_queued(
+        index,
+        RequestedFormatType::None,
+        sinks.queue(Stage::Capture, capture::QUEUE_DEPTH),
+    )?;
+    summary::watch(&capture);
+    if let Some(reload) = &mut reload {
+        reload.attach(capture.controls.clone());
+    }
+    let sinks = Sinks::open(sinks, &capture)?;
+    let clients = Clients::default();
+    bus::start(&options.publish)?;
+    let events = bus::subscribe();
+    let forwarded = clients.clone();
+    std::thread::spawn(move || {
+        for event in events.iter() {
+            send(&forwarded, Outgoing::Event(Arc::new(event.to_json())));
+        }
+    });
+    let (protocol, bind) = (options.protocol(), options.bind);
+    let recordings = Recordings::new(&capture, masks);
+    match protocol {
+        Protocol::Mjpeg | Protocol::Ws => {
+            let listener = TcpListener::bind(bind)?;
+            println!(
+                "Serving {} on {}",
+                capture.info.human_name(),
+                listener.local_addr()?
+            );
+            let clients = clients.clone();
+            std::thread::spawn(move || accept(listener, protocol, clients));
+        }
+        Protocol::Grpc => {
+            println!("Serving {} over gRPC on {bind}", capture.info.human_name());
+            let service = grpc::CameraService::new(
+                &capture,
+                clients.clone(),
+                away.switch(),
+                recordings.clone(),
+            );
+            std::thread::spawn(move || {
+                if let Err(why) = grpc::run(bind, service) {
+                    eprintln!("gRPC server failed: {why}");
+                    std::process::exit(1);
+                }
+            });
+        }
+    }
+    if let Some(dir) = &options.scripts {
+        let dir = match dir {
+            Some(dir) => dir.clone(),
+            None => config::dir()
+                .ok_or_else(|| Report::msg("no config directory to find scripts in"))?
+                .join("scripts"),
+        };
+        let actions = script::Actions {
+            controls: capture.controls.clone(),
+            recordings: recordings.clone(),
+        };
+        let switches = script::start(&dir, &actions)?;
+        if let Some(reload) = &mut reload {
+            reload.switch_on(switches);
+        }
+    }
+    notify::ready();
+    notify::watch(&capture);
+    broadcast(
+        &capture.frames,
+        Outlets {
+            clients: &clients,
+            recordings: &recordings,
+        },
+        sinks,
+        bad_frames,
+        away,
+        options,
+        Filters::new(options, masks.to_vec(), reload),
+    )?;
+    if recordings.active().is_some() {
+        let path = recordings.stop()?;
+        println!("Finished the recording to {}", path.display());
+    }
+    if !shutdown::requested() {
+        bus::publish(Event::CameraLost {
+            camera: capture.info.human_name(),
+        });
+    }
+    // Without their queues, clients' streams end, and they're closed.
+    clients.lock().unwrap().clear();
+    let deadline = Instant::now() + CLOSE_TIMEOUT;
+    while CONNECTED.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+fn accept(listener: TcpListener, protocol: Protocol, clients: Clients) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(why) => {
+                eprintln!("failed to accept connection: {why}");
+                continue;
+            }
+        };
+        let (sender, receiver) = flume::bounded(CLIENT_QUEUE_DEPTH);
+        clients.lock().unwrap().push(sender);
+        std::thread::spawn(move || {
+            let _connected = Connected::new();
+            let peer = stream.peer_addr().ok();
+            let result = match protocol {
+                Protocol::Mjpeg => serve_mjpeg(stream, receiver),
+                Protocol::Ws => serve_ws(stream, receiver),
+                Protocol::Grpc => unreachable!("gRPC clients are accepted by tonic"),
+            };
+            if let (Err(why), Some(peer)) = (result, peer) {
+                eprintln!("client {peer} disconnected: {why}");
+            }
+        });
+    }
+}
+
+fn serve_mjpeg(stream: TcpStream, receiver: Receiver<Outgoing>) -> Result<(), Report> {
+    // Whatever was asked for, the answer is the stream; read the request
+    // head only so the client doesn't see its connection reset.
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    for outgoing in receiver.iter() {
+        if let Outgoing::Frame(frame) = outgoing {
+            write!(
+                stream,
+                "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                frame.jpeg.len()
+            )?;
+            stream.write_all(&frame.jpeg)?;
+            stream.write_all(b"\r\n")?;
+            stream.flush()?;
+        }
+    }
+    write!(stream, "--{BOUNDARY}--\r\n")?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn serve_ws(stream: TcpStream, receiver: Receiver<Outgoing>) -> Result<(), Report> {
+    let mut socket = tungstenite::accept(stream).map_err(|why| Report::msg(why.to_string()))?;
+    for outgoing in receiver.iter() {
+        let message = match outgoing {
+            Outgoing::Frame(frame) => Message::binary(frame.jpeg.clone()),
+            Outgoing::Event(event) => Message::text(event.as_str()),
+        };
+        socket.send(message)?;
+    }
+    // Close the connection as the protocol has it: say so, and wait for
+    // the client to agree.
+    socket.close(None)?;
+    socket.get_ref().set_read_timeout(Some(CLOSE_TIMEOUT))?;
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+/// What frames go through before they're sent, replaced whenever the
+/// profile being followed changes.
+struct Filters {
+    masks: Vec<Mask>,
+    low_light: Option<LowLight>,
+    upscale: Option<Upscale>,
+    interpolator: Option<Interpolator>,
+    reload: Option<Reload>,
+}
+
+impl Filters {
+    fn new(options: &ServeOptions, masks: Vec<Mask>, reload: Option<Reload>) -> Self {
+        Filters {
+            masks,
+            low_light: LowLight::new(&options.low_light),
+            upscale: options.upscale.upscale,
+            interpolator: Interpolator::new(&options.interpolate),
+            reload,
+        }
+    }
+
+    fn reload(&mut self) {
+        if let Some((options, masks)) = self.reload.as_mut().and_then(Reload::serve) {
+            let reload = self.reload.take();
+            *self = Filters::new(&options, masks, reload);
+        }
+    }
+}
+
+/// Where frames go besides the sinks: out to clients as JPEG, and as they
+/// came into the recording under way.
+struct Outlets<'a> {
+    clients: &'a Clients,
+    recordings: &'a Recordings,
+}
+
+/// Encodes frames and hands them to every client, publishing motion and
+/// stats as it goes.
+fn broadcast(
+    frames: &Receiver<Frame>,
+    Outlets {
+        clients,
+        recordings,
+    }: Outlets,
+    mut sinks: Sinks,
+    mut bad_frames: BadFrames,
+    mut away: Away,
+    options: &ServeOptions,
+    mut filters: Filters,
+) -> Result<(), Report> {
+    let (quality, motion_threshold) = (options.quality, options.motion_threshold);
+    let publishing = !options.publish.publishers.is_empty();
+
+    let mut last_sequence = None;
+    let (mut frames_sent, mut synthetic_sent, mut dropped) = (0u64, 0u64, 0u64);
+    let mut stats_since = Instant::now();
+    let mut corrupt_reported = 0;
+    let mut motion_score = Motion::default();
+    let mut motion = false;
+
+    for frame in frames.iter() {
+        if shutdown::requested() {
+            break;
+        }
+        if let Some(last) = last_sequence {
+            dropped += frame.sequence - last - 1;
+        }
+        let previous_sequence = last_sequence.replace(frame.sequence);
+        recordings.send(&frame);
+        filters.reload();
+        let Filters {
+            masks,
+            low_light,
+            upscale,
+            interpolator,
+            ..
+        } = &mut filters;
+
+        // The camera's own format can't be corrected, masked, blanked,
+        // brightened, scaled or interpolated, so any of them turns
+        // passthrough off.
+        let blanked = away.is_active();
+        let passthrough = sinks.native()
+            && defects::map().is_none()
+            && masks.is_empty()
+            && !blanked
+            && low_light.is_none()
+            && upscale.is_none()
+            && interpolator.is_none();
+        if passthrough {
+            sinks.write(&SinkFrame::native(&frame));
+        }
+        let idle = clients.lock().unwrap().is_empty();
+        let wanted = !idle || publishing || !(sinks.is_empty() || passthrough);
+        let image = if !wanted {
+            None
+        } else if blanked {
+            let resolution = frame.buffer.resolution();
+            let card = away.card(resolution.width(), resolution.height());
+            Some(DynamicImage::ImageRgba8(card.clone()).to_rgb8())
+        } else {
+            // Corrupt frames are counted and skipped rather than ending the stream.
+            summary::decode(|| frame.buffer.decode_image::<RgbFormat>())
+                .map_err(|why| bad_frames.record(&frame, why))
+                .ok()
+                .map(|mut image| {
+                    let (width, height) = image.dimensions();
+                    defects::correct(&mut image, width, height, 3);
+                    if let Some(low_light) = low_light {
+                        low_light.apply(&mut image, width, height, 3);
+                    }
+                    image
+                })
+        };
+        if let Some(mut image) = image {
+            let (width, height) = image.dimensions();
+            mask::apply(masks, &mut image, width, height, 3);
+            if let Some(upscale) = upscale {
+                image = upscale.apply(&image);
+            }
+            let (width, height) = image.dimensions();
+            let mut deliver = |image: &RgbImage, sequence, pts, conditions| {
+                if !passthrough {
+                    sinks.write(&SinkFrame {
+                        sequence,
+                        pts,
+                        width,
+                        height,
+                        format: PixelFormat::Rgb,
+                        data: image.as_raw(),
+                        conditions,
+                        planes: &frame.planes,
+                    });
+                }
+                if !idle {
+                    let mut jpeg = Vec::new();
+                    summary::render(|| {
+                        JpegEncoder::new_with_quality(&mut jpeg, quality).encode_image(image)
+                    })?;
+                    summary::sent("clients", jpeg.len());
+                    send(
+                        clients,
+                        Outgoing::Frame(Arc::new(Encoded {
+                            _held: Held::new(jpeg.len()),
+                            jpeg,
+                            sequence,
+                            pts,
+                        })),
+                    );
+                }
+                Ok::<_, Report>(())
+            };
+            // Made-up frames go out spread over the time until the next
+            // camera frame is due, with the sequence number of the frame
+            // before them, and this frame goes out after them.
+            let arrived = Instant::now();
+            let made_up = match interpolator {
+                Some(interpolator) => interpolator.push(frame.pts, &image),
+                None => Vec::new(),
+            };
+            if let Some(&(first, _)) = made_up.first() {
+                let wait_for = |pts: Duration| {
+                    let due = arrived + (pts - first);
+                    std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                };
+                let sequence = previous_sequence.unwrap_or(frame.sequence);
+                for (pts, made_up) in &made_up {
+                    wait_for(*pts);
+                    deliver(made_up, sequence, *pts, None)?;
+                }
+                wait_for(frame.pts);
+            }
+            deliver(&image, frame.sequence, frame.pts, frame.conditions)?;
+            if !idle {
+                frames_sent += 1 + made_up.len() as u64;
+                synthetic_sent += made_up.len() as u64;
+            }
+            if !idle || publishing {
+                if let Some(score) = motion_score.score(&image, width, height, 3) {
+                    if (score >= motion_threshold) != motion {
+                        motion = !motion;
+                        bus::publish(Event::Motion {
+                            active: motion,
+                            score,
+                        });
+                    }
+                }
+            }
+        }
+
+        let elapsed = stats_since.elapsed();
+        if elapsed >= STATS_INTERVAL {
+            bus::publish(Event::Stats {
+                frames: frames_sent,
+                synthetic: synthetic_sent,
+                dropped,
+                corrupt: bad_frames.count - corrupt_reported,
+                fps: frames_sent as f64 / elapsed.as_secs_f64(),
+                clients: clients.lock().unwrap().len(),
+                pts: frame.pts.as_secs_f64(),
+                memory: memory::held(),
+            });
+            frames_sent = 0;
+            synthetic_sent = 0;
+            dropped = 0;
+            corrupt_reported = bad_frames.count;
+            stats_since = Instant::now();
+        }
+    }
+    Ok(())
+}
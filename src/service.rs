@@ -0,0 +1,176 @@
+//! `install-service`: a systemd unit that runs an athletic command as a
+//! service, started at login or boot and restarted when it fails or stops
+//! getting frames through, which [`crate::notify`] tells systemd about.
+
+use crate::{config, Cli, Commands};
+use clap::{Args, Parser};
+use color_eyre::Report;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Args, Clone)]
+pub struct ServiceOptions {
+    /// The command the service runs, daemon, record or serve, with its
+    /// arguments, as it would be run here.
+    #[arg(
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        value_name = "COMMAND"
+    )]
+    pub command: Vec<String>,
+    /// Call the unit athletic-NAME.service; by default NAME is the
+    /// profile's, or else the command's.
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Install a system service, in /etc/systemd/system, rather than one
+    /// for this user.
+    #[arg(long)]
+    pub system: bool,
+    /// Write the unit to this file rather than where systemd looks.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+    /// Replace the unit if there is one already.
+    #[arg(long)]
+    pub force: bool,
+    /// Have systemd restart the service when no frames have got through
+    /// for this long.
+    #[arg(long, value_parser = crate::parse_duration, default_value = "30s")]
+    pub watchdog: Duration,
+}
+
+/// Where the unit `options` describe goes, and what's in it, for a
+/// service run with the config and profile given.
+pub fn unit(
+    options: &ServiceOptions,
+    config: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<(PathBuf, String), Report> {
+    let parsed = Cli::try_parse_from(
+        std::iter::once("athletic").chain(options.command.iter().map(String::as_str)),
+    )
+    .map_err(|why| Report::msg(format!("the service's command doesn't parse:\n{why}")))?;
+    let network = match parsed.command {
+        Some(Commands::Daemon) if options.system => {
+            return Err(Report::msg(
+                "the daemon needs the user's session bus, so install it without --system",
+            ))
+        }
+        Some(Commands::Daemon | Commands::Record { .. }) => false,
+        Some(Commands::Serve { .. }) => true,
+        _ => {
+            return Err(Report::msg(
+                "a service runs daemon, record or serve, not another command",
+            ))
+        }
+    };
+
+    let name = match (&options.name, profile) {
+        (Some(name), _) => name.clone(),
+        (None, Some(profile)) => profile.to_string(),
+        (None, None) => options.command[0].clone(),
+    };
+    let unit = format!("athletic-{name}.service");
+    let path = match &options.output {
+        Some(path) => path.clone(),
+        None if options.system => Path::new("/etc/systemd/system").join(&unit),
+        None => user_units()
+            .ok_or_else(|| Report::msg("no config directory to put the unit in"))?
+            .join(&unit),
+    };
+    if path.exists() && !options.force {
+        return Err(Report::msg(format!(
+            "{} exists already; give --force to replace it",
+            path.display()
+        )));
+    }
+
+    let here = std::env::current_dir()?;
+    let mut command = vec![std::env::current_exe()?.display().to_string()];
+    // The service may run as another user, or with another environment,
+    // so the config is named even where it's the default.
+    let config = config
+        .map(|config| here.join(config))
+        .or_else(|| config::default_path().filter(|path| path.exists()));
+    if let Some(config) = config {
+        command.extend(["--config".to_string(), config.display().to_string()]);
+    }
+    if let Some(profile) = profile {
+        command.extend(["--profile".to_string(), profile.to_string()]);
+    }
+    command.extend(options.command.iter().cloned());
+    Ok((path, unit_file(&command, &here, options, network)))
+}
+
+/// Writes the unit `options` describe, and says how to start it.
+pub fn install(
+    options: &ServiceOptions,
+    config: Option<&Path>,
+    profile: Option<&str>,
+) -> Result<(), Report> {
+    let (path, text) = unit(options, config, profile)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, text)
+        .map_err(|why| Report::msg(format!("can't write {}: {why}", path.display())))?;
+    println!("Wrote {}", path.display());
+    if options.output.is_none() {
+        let user = if options.system { "" } else { " --user" };
+        println!("Start it, and at every boot, with:");
+        println!("  systemctl{user} daemon-reload");
+        println!(
+            "  systemctl{user} enable --now {}",
+            path.file_name().unwrap().to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Where systemd looks for the user's own units.
+fn user_units() -> Option<PathBuf> {
+    config::home().map(|home| home.join("systemd").join("user"))
+}
+
+fn unit_file(command: &[String], dir: &Path, options: &ServiceOptions, network: bool) -> String {
+    let mut text = String::from("# Written by athletic install-service.\n[Unit]\n");
+    text += &format!(
+        "Description=athletic {}\n",
+        options.command.join(" ").replace('%', "%%")
+    );
+    if network {
+        text += "Wants=network-online.target\nAfter=network-online.target\n";
+    }
+    text += "\n[Service]\nType=notify\n";
+    let command: Vec<String> = command.iter().map(|arg| quote(arg)).collect();
+    text += &format!("ExecStart={}\n", command.join(" "));
+    text += &format!(
+        "WorkingDirectory={}\n",
+        dir.display().to_string().replace('%', "%%")
+    );
+    text += "Restart=on-failure\nRestartSec=5\n";
+    text += &match options.watchdog.subsec_millis() {
+        0 => format!("WatchdogSec={}s\n", options.watchdog.as_secs()),
+        _ => format!("WatchdogSec={}ms\n", options.watchdog.as_millis()),
+    };
+    let target = match options.system {
+        true => "multi-user.target",
+        false => "default.target",
+    };
+    text += &format!("\n[Install]\nWantedBy={target}\n");
+    text
+}
+
+/// `arg` as a word of a systemd command line: quoted if it has to be,
+/// and with the characters systemd would expand escaped.
+fn quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    match arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '\'' || c == ';') {
+        true => format!("\"{escaped}\""),
+        false => escaped,
+    }
+}
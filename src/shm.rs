@@ -0,0 +1,114 @@
+//! A ring of frames in a memory-mapped file, for local processes to read
+//! without sockets or copies. The layout is described in docs/shm-sink.md.
+
+use crate::sink_frame::{SinkFrame, FRAME_HEADER_SIZE};
+use color_eyre::Report;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::path::PathBuf;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: &[u8; 8] = b"ATHLSHM\0";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 64;
+const SLOTS: u32 = 4;
+
+/// Offsets into the file header.
+const SLOT_COUNT: usize = 12;
+const SLOT_SIZE: usize = 16;
+const FRAMES_WRITTEN: usize = 24;
+
+pub struct ShmSink {
+    path: PathBuf,
+    file: File,
+    map: MmapMut,
+    slot_size: usize,
+    frames_written: u64,
+}
+
+/// Where a sink called `name` lives: `name` itself if it is a path,
+/// otherwise a file of that name in shared memory.
+pub fn path(name: &str) -> PathBuf {
+    if name.contains(['/', '\\']) {
+        PathBuf::from(name)
+    } else if cfg!(target_os = "linux") {
+        PathBuf::from("/dev/shm").join(name)
+    } else {
+        std::env::temp_dir().join(name)
+    }
+}
+
+impl ShmSink {
+    pub fn create(name: &str) -> Result<Self, Report> {
+        let path = path(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        let slot_size = SLOT_HEADER_SIZE;
+        Ok(ShmSink {
+            map: map(&file, slot_size, 0)?,
+            path,
+            file,
+            slot_size,
+            frames_written: 0,
+        })
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        let pointer = self.map[offset..offset + 8].as_ptr() as *mut u64;
+        // Safety: offsets are multiples of 8 into a page-aligned mapping,
+        // and every access to these words from this process is atomic.
+        unsafe { AtomicU64::from_ptr(pointer) }
+    }
+
+    pub fn write(&mut self, frame: &SinkFrame) -> Result<(), Report> {
+        if SLOT_HEADER_SIZE + frame.data.len() > self.slot_size {
+            // Readers notice the new layout through the changed slot size.
+            self.slot_size = (SLOT_HEADER_SIZE + frame.data.len()).next_multiple_of(64);
+            self.map = map(&self.file, self.slot_size, self.frames_written)?;
+        }
+        let slot = HEADER_SIZE + (self.frames_written % SLOTS as u64) as usize * self.slot_size;
+
+        // A seqlock: odd while the slot is being written, so readers can
+        // tell when they raced the writer and should try again.
+        let lock = self.atomic(slot).load(Ordering::Relaxed);
+        self.atomic(slot).store(lock + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        self.map[slot + 8..slot + 8 + FRAME_HEADER_SIZE].copy_from_slice(&frame.header());
+        let data = slot + SLOT_HEADER_SIZE;
+        self.map[data..data + frame.data.len()].copy_from_slice(frame.data);
+
+        self.atomic(slot).store(lock + 2, Ordering::Release);
+        self.frames_written += 1;
+        self.atomic(FRAMES_WRITTEN)
+            .store(self.frames_written, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Sizes `file` for slots of `slot_size` bytes, maps it, clears any old
+/// slots and writes the header.
+fn map(file: &File, slot_size: usize, frames_written: u64) -> Result<MmapMut, Report> {
+    file.set_len((HEADER_SIZE + slot_size * SLOTS as usize) as u64)?;
+    // Safety: the file is ours and only ever grows while mapped; other
+    // processes only read it.
+    let mut map = unsafe { MmapMut::map_mut(file)? };
+    map.fill(0);
+    map[..8].copy_from_slice(MAGIC);
+    map[8..12].copy_from_slice(&VERSION.to_le_bytes());
+    map[SLOT_COUNT..SLOT_COUNT + 4].copy_from_slice(&SLOTS.to_le_bytes());
+    map[SLOT_SIZE..SLOT_SIZE + 8].copy_from_slice(&(slot_size as u64).to_le_bytes());
+    map[FRAMES_WRITTEN..FRAMES_WRITTEN + 8].copy_from_slice(&frames_written.to_le_bytes());
+    Ok(map)
+}
+
+impl Drop for ShmSink {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
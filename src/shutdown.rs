@@ -0,0 +1,73 @@
+//! Stopping on Ctrl-C, or when systemd stops the service, the way a
+//! command stops at its end: `record` finishes the file and writes its
+//! manifest, `serve` says goodbye to its clients, and the summary is
+//! printed, rather than the process dying wherever it is and leaving a
+//! recording that won't seek.
+//!
+//! A second signal, or a command that hasn't stopped within [`GRACE`],
+//! ends the process at once.
+
+use crate::notify;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long a command gets to stop once asked to.
+const GRACE: Duration = Duration::from_secs(10);
+
+/// Set once a signal has asked the process to stop.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the process has been asked to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// From now on, has SIGINT and SIGTERM, or Ctrl-C on Windows, ask the
+/// command to stop rather than end the process. Only for commands that
+/// look at [`requested`].
+pub fn catch() {
+    #[cfg(unix)]
+    unsafe {
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    #[cfg(windows)]
+    unsafe {
+        windows_sys::Win32::System::Console::SetConsoleCtrlHandler(Some(on_ctrl), 1);
+    }
+    std::thread::spawn(|| {
+        while !requested() {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        eprintln!("Stopping; interrupt again to stop at once");
+        notify::stopping();
+        std::thread::sleep(GRACE);
+        eprintln!("didn't stop within {}s, so stopping now", GRACE.as_secs());
+        std::process::exit(130);
+    });
+}
+
+/// Asks the command to stop the first time, and ends the process the next.
+fn request() {
+    if REQUESTED.swap(true, Ordering::Relaxed) {
+        #[cfg(unix)]
+        unsafe {
+            // Not exit(): nothing else is safe in a signal handler.
+            libc::_exit(130);
+        }
+        #[cfg(not(unix))]
+        std::process::exit(130);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_signal: libc::c_int) {
+    request();
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn on_ctrl(_event: u32) -> windows_sys::Win32::Foundation::BOOL {
+    request();
+    1
+}
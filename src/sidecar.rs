@@ -0,0 +1,140 @@
+//! One line of JSON per frame, written beside a recording or the sinks so
+//! analysis can line frames up with what the camera was doing, and one per
+//! change to the camera's controls, so `controls replay` can do it again.
+//! The format is described in docs/metadata-sidecar.md.
+
+use crate::capture::{self, Capture, Conditions, ControlChange, ControlRequest};
+use color_eyre::Report;
+use flume::Receiver;
+use nokhwa::utils::ControlValueSetter;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+pub struct Sidecar {
+    out: BufWriter<File>,
+    changes: Receiver<ControlChange>,
+}
+
+impl Sidecar {
+    /// Creates the sidecar at `path` and asks `capture` for the exposure
+    /// and gain of every frame, and for every change to its controls.
+    pub fn create(path: &Path, capture: &Capture) -> Result<Self, Report> {
+        let out = BufWriter::new(File::create(path)?);
+        let (watcher, changes) = flume::unbounded();
+        let _ = capture.controls.send(ControlRequest::WatchConditions);
+        let _ = capture.controls.send(ControlRequest::WatchChanges(watcher));
+        Ok(Sidecar { out, changes })
+    }
+
+    /// Logs a frame, with any `events` that arrived while it was captured.
+    /// Each line is flushed as it's written, so the sidecar keeps up with a
+    /// recording that gets cut short.
+    pub fn write(
+        &mut self,
+        sequence: u64,
+        pts: Duration,
+        conditions: Option<Conditions>,
+        motion: Option<f64>,
+        events: &[String],
+    ) -> Result<(), Report> {
+        // The changes made while the frame was captured come before it.
+        for change in self.changes.try_iter() {
+            // Only values JSON has a type for are logged.
+            let Some(value) = to_json(&change.value) else {
+                continue;
+            };
+            let line = json!({
+                "control": change.control.to_string(),
+                "pts": change.pts.as_secs_f64(),
+                "time": now().as_secs_f64(),
+                "value": value,
+                "origin": change.origin.to_string(),
+            });
+            writeln!(self.out, "{line}")?;
+        }
+        let conditions = conditions.unwrap_or_default();
+        let time = now();
+        let mut line = json!({
+            "sequence": sequence,
+            "pts": pts.as_secs_f64(),
+            "time": time.as_secs_f64(),
+            "exposure": conditions.exposure,
+            "gain": conditions.gain,
+            "motion": motion,
+        });
+        if !events.is_empty() {
+            line["events"] = json!(events);
+        }
+        writeln!(self.out, "{line}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn now() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// `value` as JSON, if JSON has a type for it.
+pub fn to_json(value: &ControlValueSetter) -> Option<Value> {
+    match value {
+        ControlValueSetter::Integer(n) => Some(json!(n)),
+        ControlValueSetter::Float(n) => Some(json!(n)),
+        ControlValueSetter::Boolean(b) => Some(json!(b)),
+        ControlValueSetter::String(s) => Some(json!(s)),
+        _ => None,
+    }
+}
+
+fn from_json(value: &Value) -> Option<ControlValueSetter> {
+    match value {
+        Value::Number(n) => match n.as_i64() {
+            Some(n) => Some(ControlValueSetter::Integer(n)),
+            None => n.as_f64().map(ControlValueSetter::Float),
+        },
+        Value::Bool(b) => Some(ControlValueSetter::Boolean(*b)),
+        Value::String(s) => Some(ControlValueSetter::String(s.clone())),
+        _ => None,
+    }
+}
+
+/// The control changes logged in the sidecar at `path`, in the order
+/// they were made.
+pub fn changes(path: &Path) -> Result<Vec<ControlChange>, Report> {
+    let file = File::open(path)
+        .map_err(|why| Report::msg(format!("can't read {}: {why}", path.display())))?;
+    let mut changes = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line: Value = serde_json::from_str(&line?)
+            .map_err(|why| Report::msg(format!("{}:{}: {why}", path.display(), number + 1)))?;
+        // Frames' lines have no control.
+        let Some(name) = line["control"].as_str() else {
+            continue;
+        };
+        let bad = |what: &str| Report::msg(format!("{}:{}: {what}", path.display(), number + 1));
+        let control = capture::known_control(name)
+            .ok_or_else(|| bad(&format!("no camera control called {name}")))?;
+        let pts = line["pts"]
+            .as_f64()
+            .filter(|pts| *pts >= 0.0)
+            .ok_or_else(|| bad("the change has no pts"))?;
+        let value = from_json(&line["value"]).ok_or_else(|| bad("the change has no value"))?;
+        let origin = line["origin"]
+            .as_str()
+            .unwrap_or("manual")
+            .parse()
+            .map_err(|why| bad(&format!("{why}")))?;
+        changes.push(ControlChange {
+            pts: Duration::from_secs_f64(pts),
+            control,
+            value,
+            origin,
+        });
+    }
+    Ok(changes)
+}
@@ -0,0 +1,417 @@
+use crate::capture::{self, Capture, Conditions, Queue, Sent};
+use crate::memory::Held;
+use crate::motion::Motion;
+use crate::pipe::PipeSink;
+use crate::shm::ShmSink;
+use crate::sidecar::Sidecar;
+use crate::sink_frame::{PixelFormat, SinkFrame};
+use crate::stream::{Plane, Planes, Stream};
+use crate::summary;
+use crate::trace;
+use clap::Args;
+use color_eyre::Report;
+use flume::Sender;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where to send frames besides the main output.
+#[derive(Args, Clone)]
+pub struct SinkOptions {
+    /// Also write frames to a shared-memory ring buffer with this name, or
+    /// at this path if it contains a slash. See docs/shm-sink.md.
+    #[arg(long, value_name = "NAME")]
+    pub shm_sink: Option<String>,
+    /// Also write frames to a named pipe (a FIFO outside Windows) with this
+    /// name, or at this path if it contains a slash. See docs/pipe-sink.md.
+    #[arg(long, value_name = "NAME")]
+    pub pipe_sink: Option<String>,
+    /// Pixel format for the sinks: rgb, rgba, bgra, or native to pass on
+    /// whatever the camera sends (YUYV, NV12, GREY or MJPEG) undecoded.
+    #[arg(long, alias = "pipe-format", default_value = "bgra")]
+    pub pixel_format: SinkFormat,
+    /// Also log every frame sent to the sinks to this file, one JSON object
+    /// per line. See docs/metadata-sidecar.md.
+    #[arg(long, value_name = "FILE")]
+    pub metadata_sidecar: Option<PathBuf>,
+    /// The one stage that may hold up the ones before it, down to the
+    /// camera, rather than lose frames when it falls behind: capture, for
+    /// the command itself, shm, pipe or sidecar. By default every stage
+    /// drops the frames it can't keep up with.
+    #[arg(long, value_name = "STAGE")]
+    pub block_on: Option<Stage>,
+    /// How many frames may wait for a stage, as STAGE=FRAMES; can be given
+    /// once for each stage. By default the preview drops a frame as soon
+    /// as one is waiting, and every other stage lets 4 wait.
+    #[arg(long = "queue", value_name = "STAGE=FRAMES", value_parser = parse_queue)]
+    pub queues: Vec<(Stage, usize)>,
+    /// Which of the camera's streams a sink writes, as STAGE=STREAM, for
+    /// cameras with more than one: depth from a depth camera, say. Can be
+    /// given once for each sink; each writes the main stream otherwise.
+    #[arg(long = "sink-stream", value_name = "STAGE=STREAM", value_parser = parse_sink_stream)]
+    pub streams: Vec<(Stage, Stream)>,
+}
+
+impl SinkOptions {
+    /// How frames wait for `stage`: in a queue `depth` deep unless the
+    /// options say otherwise.
+    pub fn queue(&self, stage: Stage, depth: usize) -> Queue {
+        Queue {
+            depth: self
+                .queues
+                .iter()
+                .rev()
+                .find(|(queued, _)| *queued == stage)
+                .map_or(depth, |(_, depth)| *depth),
+            block: self.block_on == Some(stage),
+        }
+    }
+
+    /// The stream the sink at `stage` writes.
+    pub fn stream(&self, stage: Stage) -> Stream {
+        self.streams
+            .iter()
+            .rev()
+            .find(|(sink, _)| *sink == stage)
+            .map_or(Stream::Main, |(_, stream)| *stream)
+    }
+}
+
+/// A stage of the pipeline with a queue of its own.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    /// The command's own: the preview window, or `serve`'s clients.
+    Capture,
+    Shm,
+    Pipe,
+    Sidecar,
+}
+
+impl FromStr for Stage {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "capture" => Ok(Stage::Capture),
+            "shm" => Ok(Stage::Shm),
+            "pipe" => Ok(Stage::Pipe),
+            "sidecar" => Ok(Stage::Sidecar),
+            _ => Err(Report::msg(format!(
+                "no stage called {s}; there's capture, shm, pipe and sidecar"
+            ))),
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Capture => "capture",
+            Stage::Shm => "shm",
+            Stage::Pipe => "pipe",
+            Stage::Sidecar => "sidecar",
+        })
+    }
+}
+
+/// Parses `STAGE=FRAMES` for --queue.
+pub fn parse_queue(s: &str) -> Result<(Stage, usize), Report> {
+    let (stage, depth) = s
+        .split_once('=')
+        .ok_or_else(|| Report::msg(format!("expected STAGE=FRAMES, not {s}")))?;
+    let depth = depth
+        .parse()
+        .ok()
+        .filter(|depth| *depth > 0)
+        .ok_or_else(|| Report::msg(format!("a queue holds 1 frame or more, not {depth}")))?;
+    Ok((stage.parse()?, depth))
+}
+
+/// Parses `STAGE=STREAM` for --sink-stream.
+pub fn parse_sink_stream(s: &str) -> Result<(Stage, Stream), Report> {
+    let (stage, stream) = s
+        .split_once('=')
+        .ok_or_else(|| Report::msg(format!("expected STAGE=STREAM, not {s}")))?;
+    match stage.parse()? {
+        Stage::Capture => Err(Report::msg(
+            "capture isn't a sink; there's shm, pipe and sidecar",
+        )),
+        stage => Ok((stage, stream.parse()?)),
+    }
+}
+
+/// What the sinks write.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum SinkFormat {
+    /// The camera's own format, without decoding.
+    Native,
+    Convert(PixelFormat),
+}
+
+impl FromStr for SinkFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "native" | "NATIVE" => Ok(SinkFormat::Native),
+            _ => Ok(SinkFormat::Convert(s.parse()?)),
+        }
+    }
+}
+
+/// What a queued frame has besides its one stream.
+static NO_PLANES: Planes = Planes::new();
+
+/// A frame as it waits for a sink on the sink's thread, sharing its data
+/// with the other sinks.
+struct Queued {
+    sequence: u64,
+    pts: Duration,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    data: Arc<Vec<u8>>,
+    conditions: Option<Conditions>,
+    _held: Held,
+}
+
+impl Queued {
+    fn new(frame: &SinkFrame) -> Self {
+        Queued {
+            sequence: frame.sequence,
+            pts: frame.pts,
+            width: frame.width,
+            height: frame.height,
+            format: frame.format,
+            data: Arc::new(frame.data.to_vec()),
+            conditions: frame.conditions,
+            _held: Held::new(frame.data.len()),
+        }
+    }
+
+    /// `plane`, of one of the streams that came with `frame`.
+    fn plane(frame: &SinkFrame, plane: &Plane) -> Self {
+        let (width, height, format, data) = match plane {
+            Plane::Image(buffer) => (
+                buffer.resolution().width(),
+                buffer.resolution().height(),
+                PixelFormat::of(buffer.source_frame_format()),
+                buffer.buffer().to_vec(),
+            ),
+            Plane::Depth(depth) => (
+                depth.width,
+                depth.height,
+                PixelFormat::Z16,
+                depth.samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+            ),
+        };
+        Queued {
+            sequence: frame.sequence,
+            pts: frame.pts,
+            width,
+            height,
+            format,
+            _held: Held::new(data.len()),
+            data: Arc::new(data),
+            conditions: frame.conditions,
+        }
+    }
+
+    fn frame(&self) -> SinkFrame<'_> {
+        SinkFrame {
+            sequence: self.sequence,
+            pts: self.pts,
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            data: &self.data,
+            conditions: self.conditions,
+            planes: &NO_PLANES,
+        }
+    }
+}
+
+/// A sink writing on a thread of its own, so a slow one holds up no one
+/// unless its queue says to.
+struct Writer {
+    frames: Sender<Arc<Queued>>,
+    queue: Queue,
+    stream: Stream,
+}
+
+impl Writer {
+    /// Has `write` take every frame queued for it, until it fails, when
+    /// the sink called `what` is closed.
+    fn spawn(
+        what: &'static str,
+        queue: Queue,
+        stream: Stream,
+        mut write: impl FnMut(&SinkFrame) -> Result<(), Report> + Send + 'static,
+    ) -> Self {
+        let (frames, queued) = flume::bounded::<Arc<Queued>>(queue.depth);
+        std::thread::spawn(move || {
+            for frame in queued.iter() {
+                if let Err(why) = write(&frame.frame()) {
+                    eprintln!("failed to write to {what}, closing it: {why}");
+                    return;
+                }
+            }
+        });
+        Writer {
+            frames,
+            queue,
+            stream,
+        }
+    }
+
+    /// False once the sink has closed.
+    fn send(&self, frame: &Arc<Queued>) -> bool {
+        self.queue.send(&self.frames, frame.clone()) != Sent::Gone
+    }
+}
+
+/// The sinks asked for on the command line.
+pub struct Sinks {
+    format: SinkFormat,
+    shm: Option<Writer>,
+    pipe: Option<PipeSink>,
+    pipe_stream: Stream,
+    sidecar: Option<Writer>,
+}
+
+impl Sinks {
+    pub fn open(options: &SinkOptions, capture: &Capture) -> Result<Self, Report> {
+        let queue = |stage| options.queue(stage, capture::QUEUE_DEPTH);
+        for &(stage, stream) in &options.streams {
+            if !capture.streams.contains(&stream) {
+                let has: Vec<_> = capture.streams.iter().map(Stream::to_string).collect();
+                return Err(Report::msg(format!(
+                    "{} has no {stream} stream for the {stage} sink; it has {}",
+                    capture.info.human_name(),
+                    has.join(", ")
+                )));
+            }
+        }
+        let shm = match options.shm_sink.as_deref() {
+            Some(name) => {
+                let mut shm = ShmSink::create(name)?;
+                Some(Writer::spawn(
+                    "shared memory",
+                    queue(Stage::Shm),
+                    options.stream(Stage::Shm),
+                    move |frame| {
+                        shm.write(frame)?;
+                        summary::sent("shm", frame.data.len());
+                        Ok(())
+                    },
+                ))
+            }
+            None => None,
+        };
+        let sidecar = match options.metadata_sidecar.as_deref() {
+            Some(path) => {
+                let mut sidecar = Sidecar::create(path, capture)?;
+                let mut motion = Motion::default();
+                Some(Writer::spawn(
+                    "the metadata sidecar",
+                    queue(Stage::Sidecar),
+                    options.stream(Stage::Sidecar),
+                    move |frame| {
+                        let channels = match frame.format {
+                            PixelFormat::Rgb => Some(3),
+                            PixelFormat::Rgba | PixelFormat::Bgra => Some(4),
+                            _ => None,
+                        };
+                        let score = channels.and_then(|channels| {
+                            motion.score(frame.data, frame.width, frame.height, channels)
+                        });
+                        sidecar.write(frame.sequence, frame.pts, frame.conditions, score, &[])
+                    },
+                ))
+            }
+            None => None,
+        };
+        Ok(Sinks {
+            format: options.pixel_format,
+            shm,
+            pipe: options
+                .pipe_sink
+                .as_deref()
+                .map(|name| PipeSink::create(name, queue(Stage::Pipe)))
+                .transpose()?,
+            pipe_stream: options.stream(Stage::Pipe),
+            sidecar,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shm.is_none() && self.pipe.is_none() && self.sidecar.is_none()
+    }
+
+    /// Whether the sinks want frames as the camera sent them, rather than
+    /// decoded ones.
+    pub fn native(&self) -> bool {
+        self.format == SinkFormat::Native
+    }
+
+    /// Hands `frame` to every sink, converted to the sinks' pixel format,
+    /// closing the sinks that fail.
+    pub fn write(&mut self, frame: &SinkFrame) {
+        if self.is_empty() {
+            return;
+        }
+        let _zone = trace::zone("sinks");
+        let converted;
+        let frame = match self.format {
+            SinkFormat::Convert(to) if to != frame.format => {
+                let format;
+                (format, converted) = frame.format.convert(frame.data, to);
+                &SinkFrame {
+                    format,
+                    data: &converted,
+                    ..*frame
+                }
+            }
+            _ => frame,
+        };
+        // Each stream is queued once, however many sinks write it, and not
+        // at all for frames that came without it.
+        let mut queued: Vec<(Stream, Arc<Queued>)> = Vec::new();
+        let mut queue = |stream: Stream| {
+            if let Some((_, frame)) = queued.iter().find(|(has, _)| *has == stream) {
+                return Some(frame.clone());
+            }
+            let frame = Arc::new(match stream {
+                Stream::Main => Queued::new(frame),
+                _ => Queued::plane(frame, frame.planes.get(stream)?),
+            });
+            queued.push((stream, frame.clone()));
+            Some(frame)
+        };
+        for writer in [&mut self.shm, &mut self.sidecar] {
+            let Some(queued) = writer.as_ref().and_then(|writer| queue(writer.stream)) else {
+                continue;
+            };
+            if writer.as_ref().is_some_and(|writer| !writer.send(&queued)) {
+                *writer = None;
+            }
+        }
+        if let Some(pipe) = &mut self.pipe {
+            let written = match self.pipe_stream {
+                Stream::Main => Some(pipe.write(frame).map(|()| frame.data.len())),
+                stream => queue(stream)
+                    .map(|queued| pipe.write(&queued.frame()).map(|()| queued.data.len())),
+            };
+            match written {
+                None => {}
+                Some(Ok(bytes)) => summary::sent("pipe", bytes),
+                Some(Err(why)) => {
+                    eprintln!("failed to write to the pipe, closing it: {why}");
+                    self.pipe = None;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Frames as the shared-memory and pipe sinks write them: the pixel
+//! formats they come in and the header each one is sent with. The layout
+//! is described in docs/shm-sink.md and docs/pipe-sink.md.
+
+use crate::capture::{Conditions, Frame};
+use crate::stream::Planes;
+use color_eyre::Report;
+use nokhwa::utils::FrameFormat;
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PixelFormat {
+    Rgb,
+    Rgba,
+    /// What DirectShow and most Windows video APIs call RGB32.
+    Bgra,
+    /// Packed 4:2:2, as Y0 U Y1 V.
+    Yuyv,
+    /// A full-size Y plane followed by a half-size interleaved UV plane.
+    Nv12,
+    Grey,
+    /// One JPEG image per frame.
+    Mjpeg,
+    /// 16-bit depth, in the camera's units, as a depth camera's depth
+    /// stream comes.
+    Z16,
+}
+
+impl FromStr for PixelFormat {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb" | "RGB" | "rgb24" | "RGB24" => Ok(PixelFormat::Rgb),
+            "rgba" | "RGBA" => Ok(PixelFormat::Rgba),
+            "bgra" | "BGRA" | "rgb32" | "RGB32" => Ok(PixelFormat::Bgra),
+            _ => Err(Report::msg(format!("unknown pixel format: {s}"))),
+        }
+    }
+}
+
+impl PixelFormat {
+    /// Bytes per row of the first plane, or 0 for compressed formats.
+    pub fn stride(self, width: u32) -> u32 {
+        match self {
+            PixelFormat::Rgb => width * 3,
+            PixelFormat::Rgba | PixelFormat::Bgra => width * 4,
+            PixelFormat::Yuyv | PixelFormat::Z16 => width * 2,
+            PixelFormat::Nv12 | PixelFormat::Grey => width,
+            PixelFormat::Mjpeg => 0,
+        }
+    }
+
+    /// The V4L2 fourcc for the format.
+    pub fn fourcc(self) -> [u8; 4] {
+        match self {
+            PixelFormat::Rgb => *b"RGB3",
+            PixelFormat::Rgba => *b"AB24",
+            PixelFormat::Bgra => *b"AR24",
+            PixelFormat::Yuyv => *b"YUYV",
+            PixelFormat::Nv12 => *b"NV12",
+            PixelFormat::Grey => *b"GREY",
+            PixelFormat::Mjpeg => *b"MJPG",
+            PixelFormat::Z16 => *b"Z16 ",
+        }
+    }
+
+    /// What a camera sending `format` sends.
+    pub fn of(format: FrameFormat) -> Self {
+        match format {
+            FrameFormat::MJPEG => PixelFormat::Mjpeg,
+            FrameFormat::YUYV => PixelFormat::Yuyv,
+            FrameFormat::NV12 => PixelFormat::Nv12,
+            FrameFormat::GRAY => PixelFormat::Grey,
+            FrameFormat::RAWRGB => PixelFormat::Rgb,
+        }
+    }
+
+    fn is_rgb(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Rgb | PixelFormat::Rgba | PixelFormat::Bgra
+        )
+    }
+
+    /// Converts `data` from this format to `to`, with opaque alpha where
+    /// there was none, returning the format it ends up in. Only converts
+    /// between the RGB formats; anything else comes back unchanged, still
+    /// in this format.
+    pub fn convert(self, data: &[u8], to: PixelFormat) -> (PixelFormat, Cow<'_, [u8]>) {
+        if self == to || !self.is_rgb() || !to.is_rgb() {
+            return (self, Cow::Borrowed(data));
+        }
+        let pixels = data.chunks_exact(self.stride(1) as usize);
+        let mut converted = Vec::with_capacity(pixels.len() * to.stride(1) as usize);
+        for pixel in pixels {
+            let [r, g, b, a] = match self {
+                PixelFormat::Rgb => [pixel[0], pixel[1], pixel[2], 255],
+                PixelFormat::Bgra => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+            match to {
+                PixelFormat::Rgb => converted.extend_from_slice(&[r, g, b]),
+                PixelFormat::Rgba => converted.extend_from_slice(&[r, g, b, a]),
+                _ => converted.extend_from_slice(&[b, g, r, a]),
+            }
+        }
+        (to, Cow::Owned(converted))
+    }
+}
+
+/// A frame on its way to the sinks, either decoded or as the camera sent it.
+pub struct SinkFrame<'a> {
+    pub sequence: u64,
+    pub pts: Duration,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: &'a [u8],
+    pub conditions: Option<Conditions>,
+    /// The camera's other streams, for the sinks that write one of them.
+    pub planes: &'a Planes,
+}
+
+/// Size of the header written by [`SinkFrame::header`].
+pub const FRAME_HEADER_SIZE: usize = 40;
+
+impl<'a> SinkFrame<'a> {
+    /// The frame as the camera sent it.
+    pub fn native(frame: &'a Frame) -> Self {
+        let resolution = frame.buffer.resolution();
+        SinkFrame {
+            sequence: frame.sequence,
+            pts: frame.pts,
+            width: resolution.width(),
+            height: resolution.height(),
+            format: PixelFormat::of(frame.buffer.source_frame_format()),
+            data: frame.buffer.buffer(),
+            conditions: frame.conditions,
+            planes: &frame.planes,
+        }
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.format.stride(self.width)
+    }
+
+    /// The little-endian frame header shared by the shared-memory and pipe
+    /// sinks: sequence, PTS in nanoseconds, width, height, stride, fourcc
+    /// and data length.
+    pub fn header(&self) -> [u8; FRAME_HEADER_SIZE] {
+        let mut header = [0; FRAME_HEADER_SIZE];
+        header[0..8].copy_from_slice(&self.sequence.to_le_bytes());
+        header[8..16].copy_from_slice(&(self.pts.as_nanos() as u64).to_le_bytes());
+        header[16..20].copy_from_slice(&self.width.to_le_bytes());
+        header[20..24].copy_from_slice(&self.height.to_le_bytes());
+        header[24..28].copy_from_slice(&self.stride().to_le_bytes());
+        header[28..32].copy_from_slice(&self.format.fourcc());
+        header[32..40].copy_from_slice(&(self.data.len() as u64).to_le_bytes());
+        header
+    }
+}
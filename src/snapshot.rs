@@ -0,0 +1,142 @@
+use crate::bayer::{self, BayerOptions};
+use crate::burst::{self, BurstOptions};
+use crate::capture::Frame;
+use crate::mask::{self, Mask};
+use crate::pixel_format::RgbAFormat;
+use crate::stack::{Stack, StackOptions};
+use crate::timer::{self, TimerOptions};
+use crate::{capture, clipboard, defects, dng, mono, ocr, summary};
+use color_eyre::Report;
+use image::{DynamicImage, RgbaImage};
+use nokhwa::utils::CameraIndex;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// Where a snapshot goes.
+#[derive(Default)]
+pub struct Outputs<'a> {
+    pub image: Option<&'a Path>,
+    pub clipboard: bool,
+    /// The undemosaiced frame, as DNG.
+    pub raw: Option<&'a Path>,
+    /// Print the text in the frame.
+    pub text: bool,
+}
+
+/// Grabs a frame from the camera, several stacked into one or a burst of
+/// them, once the timer runs out if there is one, and sends it to `outputs`.
+pub fn snapshot(
+    index: CameraIndex,
+    outputs: &Outputs,
+    options: &BayerOptions,
+    masks: &[Mask],
+    timer: &TimerOptions,
+    stacking: &StackOptions,
+    burst: &BurstOptions,
+) -> Result<(), Report> {
+    if outputs.text {
+        ocr::available()?;
+    }
+    let capture = capture::spawn_capture(index)?;
+    summary::watch(&capture);
+    timer::wait(timer);
+    // Frames queued during the countdown are from before it ended.
+    capture.frames.drain();
+    let frame = capture.frames.recv()?;
+    let depth = mono::Depth::of(&frame);
+    let resolution = frame.buffer.resolution();
+
+    let mut images = vec![decode(&frame, options)?];
+    if let Some(count) = stacking.stack {
+        let mut stack = Stack::new(stacking.mode);
+        stack.add(&images[0])?;
+        for _ in 1..count {
+            stack.add(&decode(&capture.frames.recv()?, options)?)?;
+        }
+        images = vec![stack.finish()];
+        println!("Stacked {count} frames");
+    }
+    if let Some(count) = burst.burst {
+        for _ in 1..count {
+            images.push(decode(&capture.frames.recv()?, options)?);
+        }
+        if let Some(keep) = burst.pick_sharpest {
+            images = burst::pick_sharpest(images, keep);
+        }
+    }
+    if !masks.is_empty() || defects::map().is_some() {
+        images = images
+            .into_iter()
+            .map(|image| correct_and_mask(image, masks))
+            .collect();
+    }
+    // Whatever only takes one image gets the first, the sharpest if picked.
+    let image = &images[0];
+
+    if let Some(path) = outputs.image {
+        for (n, image) in images.iter().enumerate() {
+            let path = burst::numbered(path, n, images.len());
+            image.save(&path)?;
+            println!("Saved frame to {}", path.display());
+        }
+    }
+    if let Some(path) = outputs.raw {
+        let (Some(depth), Some(pattern)) = (depth, options.bayer) else {
+            return Err(Report::msg(format!(
+                "the camera sends {} frames, not raw Bayer data",
+                frame.buffer.source_frame_format()
+            )));
+        };
+        let mut file = BufWriter::new(File::create(path)?);
+        dng::write(
+            &mut file,
+            frame.buffer.buffer(),
+            resolution.width(),
+            resolution.height(),
+            depth,
+            pattern,
+            &capture.info.human_name(),
+        )?;
+        println!("Saved raw frame to {}", path.display());
+    }
+    if outputs.text {
+        println!("{}", ocr::recognize(image)?.text);
+    }
+    if outputs.clipboard {
+        clipboard::copy_image_and_wait(&image.to_rgba8())?;
+        println!("Copied frame to the clipboard");
+    }
+    Ok(())
+}
+
+fn correct_and_mask(image: DynamicImage, masks: &[Mask]) -> DynamicImage {
+    // Masks are drawn in 8-bit colour, which costs 16-bit grayscale its depth.
+    let mut masked = image.to_rgba8();
+    let (width, height) = masked.dimensions();
+    defects::correct(&mut masked, width, height, 4);
+    mask::apply(masks, &mut masked, width, height, 4);
+    match image {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageLuma16(_) => {
+            DynamicImage::ImageLuma8(DynamicImage::ImageRgba8(masked).to_luma8())
+        }
+        _ => DynamicImage::ImageRgba8(masked),
+    }
+}
+
+/// Turns `frame` into an image. Grayscale frames keep their own depth rather
+/// than being expanded to RGBA, so 16-bit sensors keep their full range.
+fn decode(frame: &Frame, options: &BayerOptions) -> Result<DynamicImage, Report> {
+    let resolution = frame.buffer.resolution();
+    Ok(match (mono::Depth::of(frame), options.bayer) {
+        (Some(depth), Some(pattern)) => {
+            let mut image = RgbaImage::new(resolution.width(), resolution.height());
+            bayer::demosaic(frame, depth, pattern, options.demosaic, &mut image);
+            DynamicImage::ImageRgba8(image)
+        }
+        (Some(depth), None) => {
+            mono::image(frame, depth).ok_or_else(|| Report::msg("grayscale frame is too short"))?
+        }
+        (None, _) => frame.buffer.decode_image::<RgbAFormat>()?.into(),
+    })
+}
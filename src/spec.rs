@@ -0,0 +1,113 @@
+//! The strings the command line takes for devices, resolutions, formats,
+//! durations, sizes and what to list.
+
+use clap::ValueEnum;
+use color_eyre::Report;
+use nokhwa::utils::{CameraFormat, FrameFormat, Resolution};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A device as given on the command line: its number, or otherwise its
+/// name.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum IndexKind {
+    String(String),
+    Index(u32),
+}
+
+impl FromStr for IndexKind {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u32>() {
+            Ok(p) => Ok(IndexKind::Index(p)),
+            Err(_) => Ok(IndexKind::String(s.to_string())),
+        }
+    }
+}
+
+/// What `list-properties` lists.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, ValueEnum)]
+pub enum PropertyKind {
+    /// Both the controls and the formats.
+    All,
+    /// The camera's controls and their values.
+    #[value(alias = "ctrls")]
+    Controls,
+    /// The formats the camera can stream in.
+    #[value(aliases = ["compatibleformats", "cf", "compatfmts"])]
+    CompatibleFormats,
+}
+
+/// Parses resolutions written as `WIDTHxHEIGHT`, such as `1280x720`.
+pub fn parse_resolution(s: &str) -> Result<Resolution, Report> {
+    let (width, height) = s
+        .split_once(['x', 'X'])
+        .ok_or_else(|| Report::msg(format!("expected WIDTHxHEIGHT, got: {s}")))?;
+    match (width.parse(), height.parse()) {
+        (Ok(width), Ok(height)) => Ok(Resolution::new(width, height)),
+        _ => Err(Report::msg(format!("invalid resolution: {s}"))),
+    }
+}
+
+/// Parses camera formats written as `WIDTHxHEIGHT@FPS:FORMAT`, such as
+/// `1280x720@60:YUYV`.
+pub fn parse_camera_format(s: &str) -> Result<CameraFormat, Report> {
+    let invalid = || Report::msg(format!("expected WIDTHxHEIGHT@FPS:FORMAT, got: {s}"));
+    let (resolution, rest) = s.split_once('@').ok_or_else(invalid)?;
+    let (fps, format) = rest.split_once(':').ok_or_else(invalid)?;
+    let format = FrameFormat::from_str(&format.to_uppercase())
+        .map_err(|_| Report::msg(format!("unknown frame format: {format}")))?;
+    Ok(CameraFormat::new(
+        parse_resolution(resolution)?,
+        format,
+        fps.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Parses durations such as `5s`, `500ms` or `2m`; a bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, Report> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(at) => s.split_at(at),
+        None => (s, "s"),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| Report::msg(format!("invalid duration: {s}")))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(Report::msg(format!("unknown duration unit: {unit}"))),
+    };
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| Report::msg(format!("duration out of range: {s}")))
+}
+
+/// Parses sizes in bytes such as `256M`, `1.5G` or `512KiB`, where K is
+/// 1024 bytes; a bare number is bytes.
+pub fn parse_size(s: &str) -> Result<u64, Report> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(at) => s.split_at(at),
+        None => (s, ""),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| Report::msg(format!("invalid size: {s}")))?;
+    let unit = unit.to_ascii_uppercase();
+    let prefix = unit.strip_suffix("IB").or(unit.strip_suffix('B'));
+    let scale = match prefix.unwrap_or(&unit) {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(Report::msg(format!("unknown size unit: {unit}"))),
+    };
+    let bytes = number * scale as f64;
+    match bytes.is_finite() && bytes < u64::MAX as f64 {
+        true => Ok(bytes as u64),
+        false => Err(Report::msg(format!("size out of range: {s}"))),
+    }
+}
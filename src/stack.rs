@@ -0,0 +1,143 @@
+use clap::Args;
+use color_eyre::Report;
+use image::{DynamicImage, ImageBuffer, Luma, Rgba};
+use std::str::FromStr;
+
+/// How frames stacked into one are combined.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Mode {
+    /// Averages them, which cuts noise and blurs whatever moved.
+    #[default]
+    Mean,
+    /// Keeps the brightest of each pixel, leaving trails behind lights.
+    Max,
+    /// Keeps the darkest of each pixel.
+    Min,
+}
+
+impl FromStr for Mode {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean" | "MEAN" | "average" => Ok(Mode::Mean),
+            "max" | "MAX" | "lighten" => Ok(Mode::Max),
+            "min" | "MIN" | "darken" => Ok(Mode::Min),
+            _ => Err(Report::msg(format!("unknown stacking mode: {s}"))),
+        }
+    }
+}
+
+/// Combining several frames into one, like a long exposure.
+#[derive(Args, Clone, Default)]
+pub struct StackOptions {
+    /// Combine this many frames into the snapshot.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..), conflicts_with = "save_raw")]
+    pub stack: Option<u32>,
+    /// How stacked frames are combined: `mean`, `max` or `min`. A mean is
+    /// saved at 16 bits, keeping the precision averaging gains.
+    #[arg(long, default_value = "mean", requires = "stack")]
+    pub mode: Mode,
+}
+
+/// Frames added up so far, one sample at a time.
+pub struct Stack {
+    mode: Mode,
+    width: u32,
+    height: u32,
+    /// 1 for grayscale or 4 for RGBA.
+    channels: usize,
+    sixteen: bool,
+    /// Per sample, the running sum for a mean or the extreme otherwise,
+    /// wide enough for tens of thousands of 16-bit frames.
+    samples: Vec<u32>,
+    count: u32,
+}
+
+impl Stack {
+    pub fn new(mode: Mode) -> Self {
+        Stack {
+            mode,
+            width: 0,
+            height: 0,
+            channels: 0,
+            sixteen: false,
+            samples: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Adds `image`, which has to be the same size and kind as the ones
+    /// before it.
+    pub fn add(&mut self, image: &DynamicImage) -> Result<(), Report> {
+        let (channels, sixteen, samples): (usize, bool, Vec<u32>) = match image {
+            DynamicImage::ImageLuma8(image) => (1, false, widen(image.as_raw())),
+            DynamicImage::ImageLuma16(image) => (1, true, widen(image.as_raw())),
+            DynamicImage::ImageRgba16(image) => (4, true, widen(image.as_raw())),
+            _ => (4, false, widen(image.to_rgba8().as_raw())),
+        };
+        if self.count == 0 {
+            (self.width, self.height) = (image.width(), image.height());
+            (self.channels, self.sixteen, self.samples) = (channels, sixteen, samples);
+            self.count = 1;
+            return Ok(());
+        }
+        if (image.width(), image.height(), channels, sixteen)
+            != (self.width, self.height, self.channels, self.sixteen)
+        {
+            return Err(Report::msg("the camera changed format while stacking"));
+        }
+        let combine: fn(u32, u32) -> u32 = match self.mode {
+            Mode::Mean => |total, sample| total + sample,
+            Mode::Max => u32::max,
+            Mode::Min => u32::min,
+        };
+        for (total, sample) in self.samples.iter_mut().zip(samples) {
+            *total = combine(*total, sample);
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// The combined image: a mean at 16 bits, otherwise at the frames' depth.
+    pub fn finish(self) -> DynamicImage {
+        let (width, height) = (self.width, self.height);
+        let sixteen = self.sixteen || self.mode == Mode::Mean;
+        let samples: Vec<u16> = match self.mode {
+            Mode::Mean => {
+                // Eight-bit frames are scaled up to fill the 16-bit range.
+                let scale = if self.sixteen { 1 } else { 257 };
+                let count = self.count as u64;
+                self.samples
+                    .iter()
+                    .map(|&total| ((total as u64 * scale + count / 2) / count) as u16)
+                    .collect()
+            }
+            Mode::Max | Mode::Min => self.samples.iter().map(|&sample| sample as u16).collect(),
+        };
+        let narrow = || {
+            samples
+                .iter()
+                .map(|&sample| sample as u8)
+                .collect::<Vec<_>>()
+        };
+        match (self.channels, sixteen) {
+            (1, false) => DynamicImage::ImageLuma8(
+                ImageBuffer::<Luma<u8>, _>::from_raw(width, height, narrow()).unwrap(),
+            ),
+            (1, true) => DynamicImage::ImageLuma16(
+                ImageBuffer::<Luma<u16>, _>::from_raw(width, height, samples).unwrap(),
+            ),
+            (_, false) => DynamicImage::ImageRgba8(
+                ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, narrow()).unwrap(),
+            ),
+            (_, true) => DynamicImage::ImageRgba16(
+                ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples).unwrap(),
+            ),
+        }
+    }
+}
+
+fn widen<T: Copy + Into<u32>>(samples: &[T]) -> Vec<u32> {
+    samples.iter().map(|&sample| sample.into()).collect()
+}
@@ -0,0 +1,66 @@
+//! What a command does when its camera stalls: says so, as events too,
+//! and exits with [`EXIT`] once the camera can't be got going again, so
+//! whatever runs it, like the units `install-service` writes, runs it
+//! again.
+
+use crate::bus::{self, Event};
+use athletic::capture::{Recovery, Stall};
+use nokhwa::utils::CameraIndex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The exit code of a command whose camera stalled for good: EX_TEMPFAIL,
+/// as running it again may well work.
+pub const EXIT: i32 = 75;
+
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+
+/// Whether a capture gave up on its camera.
+pub fn gave_up() -> bool {
+    GAVE_UP.load(Ordering::Relaxed)
+}
+
+/// For [`athletic::capture::StallPolicy`].
+pub fn report(index: &CameraIndex, stall: &Stall) {
+    let camera = index.to_string();
+    match stall {
+        Stall::Stalled {
+            attempt,
+            recovery,
+            why,
+        } => {
+            let doing = match recovery {
+                Recovery::RestartStream => "restarting its stream",
+                Recovery::Reopen => "opening it again",
+                Recovery::GiveUp => "giving up on it",
+            };
+            eprintln!("camera {camera} stalled ({why}); {doing}");
+            if *recovery == Recovery::GiveUp {
+                GAVE_UP.store(true, Ordering::Relaxed);
+            }
+            bus::publish(Event::CameraStalled {
+                camera,
+                attempt: *attempt,
+                recovery: recovery.to_string(),
+            });
+        }
+        Stall::Recovered { attempts } => {
+            eprintln!("camera {camera} is sending frames again");
+            bus::publish(Event::CameraRecovered {
+                camera,
+                attempts: *attempts,
+            });
+        }
+        Stall::Hung { waited } => {
+            eprintln!(
+                "camera {camera} hasn't sent a frame in {}s and can't be stopped, so stopping now",
+                waited.as_secs()
+            );
+            bus::publish(Event::CameraStalled {
+                camera,
+                attempt: 0,
+                recovery: Recovery::GiveUp.to_string(),
+            });
+            std::process::exit(EXIT);
+        }
+    }
+}
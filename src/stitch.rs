@@ -0,0 +1,293 @@
+//! Stitching overlapping frames into a panorama: corners are found in each,
+//! matched between neighbours by the patches around them, and the
+//! homography between neighbours is fitted to the matches with RANSAC.
+
+use crate::warp::{apply, fit, invert, multiply, sample, Matrix, Point, IDENTITY};
+use color_eyre::Report;
+use image::{imageops, imageops::FilterType, GrayImage, RgbImage};
+
+/// Width frames are shrunk to before looking for features.
+const WORK_WIDTH: u32 = 480;
+/// Most corners kept per frame, strongest first.
+const MAX_CORNERS: usize = 500;
+/// Half the side of the patch describing each corner.
+const PATCH_RADIUS: i64 = 5;
+/// A match is only trusted if it's clearly better than the runner-up: its
+/// distance has to be under this share of the second best's.
+const RATIO: f32 = 0.8;
+const RANSAC_ROUNDS: usize = 1000;
+/// How far, in working pixels, a match may land from where the homography
+/// puts it and still count as agreeing with it.
+const INLIER_DISTANCE: f64 = 3.0;
+const MIN_INLIERS: usize = 12;
+/// Largest panorama, relative to one frame, before the fit is taken to
+/// have gone wrong.
+const MAX_GROWTH: f64 = 12.0;
+
+/// A corner and the normalised patch around it.
+struct Feature {
+    point: Point,
+    patch: Vec<f32>,
+}
+
+/// Stitches `frames`, each overlapping the one before it, into one image
+/// seen from the middle frame.
+pub fn stitch(frames: &[RgbImage]) -> Result<RgbImage, Report> {
+    let Some(first) = frames.first() else {
+        return Err(Report::msg("no frames to stitch"));
+    };
+    let (width, height) = first.dimensions();
+    if frames
+        .iter()
+        .any(|frame| frame.dimensions() != (width, height))
+    {
+        return Err(Report::msg("the frames aren't all the same size"));
+    }
+    let scale = width as f64 / WORK_WIDTH.min(width) as f64;
+    let features: Vec<Vec<Feature>> = frames
+        .iter()
+        .map(|frame| {
+            let work = imageops::resize(
+                frame,
+                (width as f64 / scale) as u32,
+                ((height as f64 / scale) as u32).max(1),
+                FilterType::Triangle,
+            );
+            find_features(&image::DynamicImage::ImageRgb8(work).to_luma8())
+        })
+        .collect();
+
+    // Each frame's homography onto the first, then onto the middle one.
+    let mut onto_first = vec![IDENTITY];
+    for n in 1..frames.len() {
+        let step = align(&features[n], &features[n - 1], scale).ok_or_else(|| {
+            Report::msg(format!(
+                "couldn't line frame {} up with frame {n}; they need to overlap more",
+                n + 1
+            ))
+        })?;
+        onto_first.push(multiply(&onto_first[n - 1], &step));
+    }
+    let middle = invert(&onto_first[frames.len() / 2]).ok_or_else(|| Report::msg("bad fit"))?;
+    let homographies: Vec<Matrix> = onto_first.iter().map(|h| multiply(&middle, h)).collect();
+
+    let (mut left, mut top, mut right, mut bottom) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for homography in &homographies {
+        for corner in [
+            (0.0, 0.0),
+            (width as f64, 0.0),
+            (0.0, height as f64),
+            (width as f64, height as f64),
+        ] {
+            let (x, y) = apply(homography, corner);
+            (left, top) = (left.min(x), top.min(y));
+            (right, bottom) = (right.max(x), bottom.max(y));
+        }
+    }
+    let (out_width, out_height) = (right - left, bottom - top);
+    if !(out_width.is_finite() && out_height.is_finite())
+        || out_width * out_height > MAX_GROWTH * (width * height) as f64
+    {
+        return Err(Report::msg(
+            "the frames don't line up into a panorama; sweep more slowly, keeping the camera level",
+        ));
+    }
+    let inverses: Vec<Matrix> = homographies
+        .iter()
+        .map(|h| invert(h).ok_or_else(|| Report::msg("bad fit")))
+        .collect::<Result<_, _>>()?;
+    Ok(compose(
+        frames,
+        &inverses,
+        (left, top),
+        (out_width.ceil() as u32, out_height.ceil() as u32),
+    ))
+}
+
+/// Draws every frame into a canvas of `size` whose top left is at `origin`
+/// in the middle frame's coordinates, with `inverses` taking canvas points
+/// back into each frame. Where frames overlap they're blended, each
+/// weighted by how far the pixel is from its edges so seams fade out.
+fn compose(frames: &[RgbImage], inverses: &[Matrix], origin: Point, size: (u32, u32)) -> RgbImage {
+    let (width, height) = frames[0].dimensions();
+    let (w, h) = (width as f64, height as f64);
+    RgbImage::from_fn(size.0, size.1, |x, y| {
+        let point = (x as f64 + 0.5 + origin.0, y as f64 + 0.5 + origin.1);
+        let (mut total, mut weights) = ([0.0f64; 3], 0.0);
+        for (frame, inverse) in frames.iter().zip(inverses) {
+            let (u, v) = apply(inverse, point);
+            if !(0.0..w).contains(&u) || !(0.0..h).contains(&v) {
+                continue;
+            }
+            let weight = u.min(w - u).min(v).min(h - v).max(1e-3);
+            let pixel = sample(frame, (u - 0.5) as f32, (v - 0.5) as f32);
+            for (total, channel) in total.iter_mut().zip(pixel) {
+                *total += channel as f64 * weight;
+            }
+            weights += weight;
+        }
+        if weights == 0.0 {
+            return image::Rgb([0, 0, 0]);
+        }
+        image::Rgb(total.map(|total| (total / weights).round() as u8))
+    })
+}
+
+/// Harris corners of `image`, strongest first, each with its patch.
+fn find_features(image: &GrayImage) -> Vec<Feature> {
+    let image = imageops::blur(image, 1.0);
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let at = |x: i64, y: i64| image.get_pixel(x as u32, y as u32)[0] as f32;
+    let border = PATCH_RADIUS + 2;
+    if width <= 2 * border || height <= 2 * border {
+        return Vec::new();
+    }
+
+    let index = |x: i64, y: i64| (y * width + x) as usize;
+    let mut gradients = vec![(0.0f32, 0.0f32); (width * height) as usize];
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            gradients[index(x, y)] = (
+                (at(x + 1, y) - at(x - 1, y)) / 2.0,
+                (at(x, y + 1) - at(x, y - 1)) / 2.0,
+            );
+        }
+    }
+    let mut response = vec![0.0f32; (width * height) as usize];
+    for y in border..height - border {
+        for x in border..width - border {
+            let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+            for dy in -2..=2 {
+                for dx in -2..=2 {
+                    let (gx, gy) = gradients[index(x + dx, y + dy)];
+                    (xx, yy, xy) = (xx + gx * gx, yy + gy * gy, xy + gx * gy);
+                }
+            }
+            response[index(x, y)] = xx * yy - xy * xy - 0.04 * (xx + yy) * (xx + yy);
+        }
+    }
+
+    // Only the strongest corner in each neighbourhood is kept.
+    let mut corners = Vec::new();
+    for y in border..height - border {
+        for x in border..width - border {
+            let value = response[index(x, y)];
+            if value <= 0.0 {
+                continue;
+            }
+            let strongest = (-3..=3).all(|dy| {
+                (-3..=3).all(|dx| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    (nx, ny) == (x, y)
+                        || !(0..width).contains(&nx)
+                        || !(0..height).contains(&ny)
+                        || response[index(nx, ny)] < value
+                })
+            });
+            if strongest {
+                corners.push((value, x, y));
+            }
+        }
+    }
+    corners.sort_by(|a, b| b.0.total_cmp(&a.0));
+    corners.truncate(MAX_CORNERS);
+
+    corners
+        .into_iter()
+        .map(|(_, x, y)| {
+            let mut patch = Vec::new();
+            for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+                for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+                    patch.push(at(x + dx, y + dy));
+                }
+            }
+            // Normalised, so patches compare the same under different
+            // exposure.
+            let mean = patch.iter().sum::<f32>() / patch.len() as f32;
+            let norm = patch
+                .iter()
+                .map(|v| (v - mean).powi(2))
+                .sum::<f32>()
+                .sqrt()
+                .max(1e-3);
+            Feature {
+                point: (x as f64, y as f64),
+                patch: patch.iter().map(|v| (v - mean) / norm).collect(),
+            }
+        })
+        .collect()
+}
+
+/// The homography taking `from`'s frame onto `to`'s, in full-size pixels
+/// given the frames were shrunk by `scale`, if enough features agree on
+/// one.
+fn align(from: &[Feature], to: &[Feature], scale: f64) -> Option<Matrix> {
+    let distance = |a: &Feature, b: &Feature| -> f32 {
+        a.patch
+            .iter()
+            .zip(&b.patch)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum()
+    };
+    let best = |feature: &Feature, among: &[Feature]| -> Option<(usize, f32, f32)> {
+        let (mut best, mut first, mut second) = (None, f32::MAX, f32::MAX);
+        for (n, other) in among.iter().enumerate() {
+            let d = distance(feature, other);
+            if d < first {
+                (best, first, second) = (Some(n), d, first);
+            } else if d < second {
+                second = d;
+            }
+        }
+        best.map(|best| (best, first, second))
+    };
+    // Matches have to pass the ratio test, and each has to be the other's
+    // best match too.
+    let matches: Vec<(Point, Point)> = from
+        .iter()
+        .enumerate()
+        .filter_map(|(n, feature)| {
+            let (m, first, second) = best(feature, to)?;
+            if first > RATIO * RATIO * second || best(&to[m], from)?.0 != n {
+                return None;
+            }
+            Some((feature.point, to[m].point))
+        })
+        .collect();
+    if matches.len() < MIN_INLIERS {
+        return None;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut best_inliers: Vec<usize> = Vec::new();
+    for _ in 0..RANSAC_ROUNDS {
+        let picked: Vec<(Point, Point)> = rand::seq::index::sample(&mut rng, matches.len(), 4)
+            .into_iter()
+            .map(|n| matches[n])
+            .collect();
+        let Some(homography) = fit(&picked) else {
+            continue;
+        };
+        let inliers: Vec<usize> = (0..matches.len())
+            .filter(|&n| {
+                let ((x, y), (u, v)) = matches[n];
+                let (px, py) = apply(&homography, (x, y));
+                (px - u).hypot(py - v) < INLIER_DISTANCE
+            })
+            .collect();
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+        }
+    }
+    if best_inliers.len() < MIN_INLIERS {
+        return None;
+    }
+    let inliers: Vec<(Point, Point)> = best_inliers
+        .into_iter()
+        .map(|n| {
+            let ((x, y), (u, v)) = matches[n];
+            ((x * scale, y * scale), (u * scale, v * scale))
+        })
+        .collect();
+    fit(&inliers)
+}
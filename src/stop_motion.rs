@@ -0,0 +1,220 @@
+use crate::capture::Capture;
+use crate::defects;
+use crate::mask::{self, Mask};
+use crate::pixel_format::RgbAFormat;
+use crate::window;
+use clap::Args;
+use color_eyre::Report;
+use ggez::{
+    event::EventHandler,
+    graphics::{Canvas, Color, DrawParam, Image, ImageFormat, Text},
+    input::keyboard::{KeyCode, KeyInput},
+    Context, GameError,
+};
+use image::RgbaImage;
+use std::path::PathBuf;
+
+/// How much Up and Down change the onion skin's opacity by.
+const OPACITY_STEP: f32 = 0.1;
+
+/// Where `stop-motion` keeps its frames and how the last one is shown.
+#[derive(Args, Clone)]
+pub struct StopMotionOptions {
+    /// Directory the frames are saved in, as frame-0001.png and onwards.
+    /// Frames already there are kept and numbering carries on after them.
+    #[arg(short, long, default_value = ".")]
+    pub directory: PathBuf,
+    /// Opacity, from 0 to 1, of the last frame shown over the live view.
+    #[arg(long, default_value_t = 0.4)]
+    pub onion_skin: f32,
+}
+
+/// Shows the camera with the last frame taken faintly over it. Space takes
+/// a frame, Backspace deletes the last one, and Up and Down change how
+/// strongly it shows.
+pub struct StopMotionState {
+    capture: Capture,
+    options: StopMotionOptions,
+    masks: Vec<Mask>,
+    /// The newest camera frame, as taken and as shown.
+    live: Option<(RgbaImage, Image)>,
+    /// The last frame taken.
+    onion: Option<Image>,
+    /// Number of the frame Space takes next.
+    next: u32,
+}
+
+impl StopMotionState {
+    pub fn new(
+        ctx: &mut Context,
+        capture: Capture,
+        options: StopMotionOptions,
+        masks: Vec<Mask>,
+    ) -> Result<Self, Report> {
+        std::fs::create_dir_all(&options.directory)?;
+        let mut last = 0;
+        for entry in std::fs::read_dir(&options.directory)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("frame-")?.strip_suffix(".png"))
+                .and_then(|number| number.parse().ok());
+            last = last.max(number.unwrap_or(0));
+        }
+        let mut state = StopMotionState {
+            capture,
+            options,
+            masks,
+            live: None,
+            onion: None,
+            next: last + 1,
+        };
+        if last > 0 {
+            println!(
+                "Carrying on after frame {last} in {}",
+                state.options.directory.display()
+            );
+            state.load_onion(ctx)?;
+        }
+        Ok(state)
+    }
+
+    fn path(&self, number: u32) -> PathBuf {
+        self.options
+            .directory
+            .join(format!("frame-{number:04}.png"))
+    }
+
+    /// Shows the last frame taken, if there is one, as the onion skin.
+    fn load_onion(&mut self, ctx: &mut Context) -> Result<(), Report> {
+        self.onion = None;
+        if self.next > 1 {
+            let image = image::open(self.path(self.next - 1))?.to_rgba8();
+            self.onion = Some(to_image(ctx, &image));
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        let frame = self
+            .capture
+            .frames
+            .recv()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let mut decoded = frame
+            .buffer
+            .decode_image::<RgbAFormat>()
+            .map_err(|why| GameError::RenderError(why.to_string()))?;
+        let (width, height) = decoded.dimensions();
+        defects::correct(&mut decoded, width, height, 4);
+        mask::apply(&self.masks, &mut decoded, width, height, 4);
+        let image = to_image(ctx, &decoded);
+        self.live = Some((decoded, image));
+        Ok(())
+    }
+
+    /// Saves the newest camera frame as the next frame and shows it as the
+    /// onion skin.
+    fn take(&mut self) -> Result<(), Report> {
+        let Some((frame, image)) = &self.live else {
+            return Ok(());
+        };
+        let path = self.path(self.next);
+        frame.save(&path)?;
+        println!("Saved {}", path.display());
+        self.onion = Some(image.clone());
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Deletes the last frame taken, so it can be taken again.
+    fn undo(&mut self, ctx: &mut Context) -> Result<(), Report> {
+        if self.next == 1 {
+            return Ok(());
+        }
+        let path = self.path(self.next - 1);
+        std::fs::remove_file(&path)?;
+        println!("Deleted {}", path.display());
+        self.next -= 1;
+        self.load_onion(ctx)
+    }
+}
+
+fn to_image(ctx: &mut Context, image: &RgbaImage) -> Image {
+    Image::from_pixels(
+        ctx,
+        image.as_raw(),
+        ImageFormat::Rgba8UnormSrgb,
+        image.width(),
+        image.height(),
+    )
+}
+
+impl EventHandler<GameError> for StopMotionState {
+    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.receive(ctx)?;
+
+        let size = ctx.gfx.drawable_size();
+        let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+        let layers = [
+            self.live.as_ref().map(|(_, image)| (image, 1.0)),
+            self.onion
+                .as_ref()
+                .map(|image| (image, self.options.onion_skin)),
+        ];
+        for (image, opacity) in layers.into_iter().flatten() {
+            let (offset, scale) =
+                window::letterbox((image.width() as f32, image.height() as f32), size);
+            canvas.draw(
+                image,
+                DrawParam::new()
+                    .dest(offset)
+                    .scale([scale, scale])
+                    .color(Color::new(1.0, 1.0, 1.0, opacity)),
+            );
+        }
+        canvas.draw(
+            &Text::new(format!(
+                "Frame {}  onion skin {:.0}%\nSpace to take, Backspace to delete the last",
+                self.next,
+                self.options.onion_skin * 100.0
+            )),
+            DrawParam::new().dest([8.0, 8.0]).color(Color::WHITE),
+        );
+        canvas.finish(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        repeated: bool,
+    ) -> Result<(), GameError> {
+        let result = match input.keycode {
+            // Holding Space down shouldn't take a burst.
+            Some(KeyCode::Space) if !repeated => self.take(),
+            Some(KeyCode::Back) if !repeated => self.undo(ctx),
+            Some(KeyCode::Up) => {
+                self.options.onion_skin = (self.options.onion_skin + OPACITY_STEP).min(1.0);
+                Ok(())
+            }
+            Some(KeyCode::Down) => {
+                self.options.onion_skin = (self.options.onion_skin - OPACITY_STEP).max(0.0);
+                Ok(())
+            }
+            Some(KeyCode::Escape) => {
+                ctx.request_quit();
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(why) = result {
+            eprintln!("failed to update the frames: {why}");
+        }
+        Ok(())
+    }
+}
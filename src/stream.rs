@@ -0,0 +1,114 @@
+//! Sources that capture more than one picture at a time: a colour camera
+//! with depth, a stereo pair, or a camera with the screen. Each picture is
+//! a [`Stream`]; a [`Frame`](crate::capture::Frame) carries the main one
+//! in its buffer and the rest, taken at the same moment, as [`Planes`].
+
+use crate::depth::DepthPlane;
+use color_eyre::Report;
+use nokhwa::Buffer;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// One of the pictures a source captures.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Stream {
+    /// The picture every source has: the camera's, a depth camera's
+    /// colour, or the left of a stereo pair.
+    #[default]
+    Main,
+    /// The distance to each pixel of the main picture.
+    Depth,
+    /// The right of a stereo pair.
+    Right,
+    /// The screen, captured alongside the camera.
+    Screen,
+}
+
+impl FromStr for Stream {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" | "colour" | "color" | "left" => Ok(Stream::Main),
+            "depth" => Ok(Stream::Depth),
+            "right" => Ok(Stream::Right),
+            "screen" => Ok(Stream::Screen),
+            _ => Err(Report::msg(format!(
+                "no stream called {s}; there's main, depth, right and screen"
+            ))),
+        }
+    }
+}
+
+impl Display for Stream {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stream::Main => "main",
+            Stream::Depth => "depth",
+            Stream::Right => "right",
+            Stream::Screen => "screen",
+        })
+    }
+}
+
+/// A stream's picture from one moment.
+#[derive(Debug)]
+pub enum Plane {
+    /// A picture in one of the formats cameras send.
+    Image(Buffer),
+    Depth(DepthPlane),
+}
+
+impl Plane {
+    /// How many bytes it holds.
+    pub fn bytes(&self) -> usize {
+        match self {
+            Plane::Image(buffer) => buffer.buffer().len(),
+            Plane::Depth(depth) => depth.samples.len() * 2,
+        }
+    }
+}
+
+/// The pictures of a frame's streams other than its main one, shared
+/// between the frame's clones.
+#[derive(Clone, Debug, Default)]
+pub struct Planes(Vec<(Stream, Arc<Plane>)>);
+
+impl Planes {
+    pub const fn new() -> Self {
+        Planes(Vec::new())
+    }
+
+    /// `stream`'s picture, if the frame has one.
+    pub fn get(&self, stream: Stream) -> Option<&Plane> {
+        self.0
+            .iter()
+            .find(|(has, _)| *has == stream)
+            .map(|(_, plane)| &**plane)
+    }
+
+    /// Adds `stream`'s picture, or replaces it.
+    pub fn insert(&mut self, stream: Stream, plane: Plane) {
+        self.0.retain(|(has, _)| *has != stream);
+        self.0.push((stream, Arc::new(plane)));
+    }
+
+    /// The depth plane, if the frame came from a depth camera.
+    pub fn depth(&self) -> Option<&DepthPlane> {
+        match self.get(Stream::Depth)? {
+            Plane::Depth(depth) => Some(depth),
+            Plane::Image(_) => None,
+        }
+    }
+
+    /// How many bytes they hold together.
+    pub fn bytes(&self) -> usize {
+        self.0.iter().map(|(_, plane)| plane.bytes()).sum()
+    }
+
+    /// The streams they're pictures of.
+    pub fn streams(&self) -> impl Iterator<Item = Stream> + '_ {
+        self.0.iter().map(|(stream, _)| *stream)
+    }
+}
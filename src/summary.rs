@@ -0,0 +1,401 @@
+//! A summary of what a session did, printed when a command that captures
+//! ends, so a camera that drops frames or a slow stage shows up without a
+//! profiler.
+
+use crate::capture::{Capture, Counters};
+use crate::drift::Drift;
+use crate::memory;
+use crate::trace;
+use color_eyre::Report;
+use ggez::event::{EventHandler, MouseButton};
+use ggez::input::keyboard::KeyInput;
+use ggez::{Context, GameError};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Latencies are counted in buckets this wide...
+const BUCKET: Duration = Duration::from_micros(10);
+/// ...up to this long, and anything longer in one more.
+const BUCKETS: usize = 10_000;
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+struct Session {
+    started: Instant,
+    cameras: Vec<Arc<Counters>>,
+    decoded: u64,
+    decode: Latency,
+    render: Latency,
+    sinks: BTreeMap<&'static str, Sink>,
+    drift: Option<AvDrift>,
+}
+
+/// How long a stage took, as a histogram, so long sessions don't grow it.
+struct Latency {
+    buckets: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+#[derive(Serialize, Default, Clone, Copy)]
+struct Sink {
+    frames: u64,
+    bytes: u64,
+}
+
+/// The summary as `--summary` writes it. Times are in seconds.
+#[derive(Serialize)]
+struct Written {
+    wall_time: f64,
+    received: u64,
+    decoded: u64,
+    dropped: u64,
+    /// Times a stalled camera's stream was restarted, and times it was
+    /// opened again.
+    restarts: u64,
+    reopens: u64,
+    decode: Option<Percentiles>,
+    render: Option<Percentiles>,
+    sinks: BTreeMap<&'static str, Sink>,
+    bytes: u64,
+    /// The most the frames in the pipeline held at once.
+    peak_memory: u64,
+    /// How far a recording's picture and sound drifted apart.
+    av_drift: Option<AvDrift>,
+}
+
+/// Drift between the picture and sound of a recording, in seconds,
+/// positive with the sound ahead, and the frames that made up for it.
+#[derive(Serialize, Clone, Copy)]
+struct AvDrift {
+    last: f64,
+    widest: f64,
+    repeated: u64,
+    dropped: u64,
+}
+
+#[derive(Serialize)]
+struct Percentiles {
+    mean: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl Latency {
+    fn new() -> Self {
+        Latency {
+            buckets: vec![0; BUCKETS + 1],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    fn add(&mut self, took: Duration) {
+        let bucket = (took.as_nanos() / BUCKET.as_nanos()) as usize;
+        self.buckets[bucket.min(BUCKETS)] += 1;
+        self.count += 1;
+        self.total += took;
+        self.max = self.max.max(took);
+    }
+
+    /// The time `fraction` of the samples took at most, to the bucket.
+    fn percentile(&self, fraction: f64) -> Duration {
+        let wanted = (self.count as f64 * fraction).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= wanted {
+                return (BUCKET * (bucket as u32 + 1)).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    fn percentiles(&self) -> Option<Percentiles> {
+        (self.count > 0).then(|| Percentiles {
+            mean: (self.total / self.count as u32).as_secs_f64(),
+            p50: self.percentile(0.5).as_secs_f64(),
+            p95: self.percentile(0.95).as_secs_f64(),
+            p99: self.percentile(0.99).as_secs_f64(),
+            max: self.max.as_secs_f64(),
+        })
+    }
+}
+
+fn with_session(f: impl FnOnce(&mut Session)) {
+    if let Some(session) = SESSION.lock().unwrap().as_mut() {
+        f(session);
+    }
+}
+
+/// Starts counting, until the returned guard is dropped, which prints the
+/// summary and writes it as JSON to `json` if given.
+pub fn begin(json: Option<PathBuf>) -> Summary {
+    *SESSION.lock().unwrap() = Some(Session {
+        started: Instant::now(),
+        cameras: Vec::new(),
+        decoded: 0,
+        decode: Latency::new(),
+        render: Latency::new(),
+        sinks: BTreeMap::new(),
+        drift: None,
+    });
+    Summary { json }
+}
+
+/// Counts the frames `capture` receives and drops.
+pub fn watch(capture: &Capture) {
+    with_session(|session| session.cameras.push(capture.counters.clone()));
+}
+
+/// Decodes a frame with `decode`, timing it.
+pub fn decode<T, E>(decode: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let start = Instant::now();
+    let result = {
+        let _zone = trace::zone("decode");
+        decode()
+    };
+    with_session(|session| {
+        session.decode.add(start.elapsed());
+        session.decoded += result.is_ok() as u64;
+    });
+    result
+}
+
+/// Draws or encodes a frame with `render`, timing it.
+pub fn render<T>(render: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = {
+        let _zone = trace::zone("render");
+        render()
+    };
+    with_session(|session| session.render.add(start.elapsed()));
+    result
+}
+
+/// Notes that a frame of `bytes` bytes went to `sink`.
+pub fn sent(sink: &'static str, bytes: usize) {
+    with_session(|session| {
+        let sink = session.sinks.entry(sink).or_default();
+        sink.frames += 1;
+        sink.bytes += bytes as u64;
+    });
+}
+
+/// Notes how far a recording's picture and sound drifted apart.
+pub fn drift(drift: &Drift) {
+    with_session(|session| {
+        session.drift = Some(AvDrift {
+            last: drift.last,
+            widest: drift.widest,
+            repeated: drift.repeated,
+            dropped: drift.dropped,
+        })
+    });
+}
+
+/// Prints the summary when dropped.
+pub struct Summary {
+    json: Option<PathBuf>,
+}
+
+impl Summary {
+    fn finish(&self) -> Result<(), Report> {
+        let Some(session) = SESSION.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let count = |counter: fn(&Counters) -> &AtomicU64| {
+            session
+                .cameras
+                .iter()
+                .map(|counters| counter(counters).load(Ordering::Relaxed))
+                .sum()
+        };
+        let written = Written {
+            wall_time: session.started.elapsed().as_secs_f64(),
+            received: count(|counters| &counters.received),
+            decoded: session.decoded,
+            dropped: count(|counters| &counters.dropped),
+            restarts: count(|counters| &counters.restarts),
+            reopens: count(|counters| &counters.reopens),
+            decode: session.decode.percentiles(),
+            render: session.render.percentiles(),
+            bytes: session.sinks.values().map(|sink| sink.bytes).sum(),
+            sinks: session.sinks,
+            peak_memory: memory::peak(),
+            av_drift: session.drift,
+        };
+
+        println!("Session summary:");
+        line("Wall time", format!("{:.1}s", written.wall_time));
+        line(
+            "Frames",
+            format!(
+                "{} received, {} decoded, {} dropped",
+                written.received, written.decoded, written.dropped
+            ),
+        );
+        line(
+            "Stalls",
+            format!(
+                "{} stream restarts, {} reopens",
+                written.restarts, written.reopens
+            ),
+        );
+        for (label, percentiles) in [("Decode", &written.decode), ("Render", &written.render)] {
+            if let Some(Percentiles {
+                mean,
+                p50,
+                p95,
+                p99,
+                max,
+            }) = percentiles
+            {
+                let ms = |seconds: &f64| seconds * 1000.0;
+                line(
+                    label,
+                    format!(
+                        "mean {:.2}ms, p50 {:.2}ms, p95 {:.2}ms, p99 {:.2}ms, max {:.2}ms",
+                        ms(mean),
+                        ms(p50),
+                        ms(p95),
+                        ms(p99),
+                        ms(max)
+                    ),
+                );
+            }
+        }
+        for (name, sink) in &written.sinks {
+            match sink.bytes {
+                0 => line(name, format!("{} frames", sink.frames)),
+                bytes => line(name, format!("{} frames, {}", sink.frames, size(bytes))),
+            }
+        }
+        if let Some(drift) = &written.av_drift {
+            line(
+                "A/V drift",
+                format!(
+                    "{:+.1}ms at the end, {:+.1}ms at most, {} frames repeated, {} dropped",
+                    drift.last * 1000.0,
+                    drift.widest * 1000.0,
+                    drift.repeated,
+                    drift.dropped
+                ),
+            );
+        }
+        line("Written", size(written.bytes));
+        line("Memory", format!("{} at most", size(written.peak_memory)));
+
+        if let Some(path) = &self.json {
+            std::fs::write(path, serde_json::to_string_pretty(&written)? + "\n")?;
+            println!("Wrote the summary to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Summary {
+    fn drop(&mut self) {
+        if let Err(why) = self.finish() {
+            eprintln!("failed to write the session summary: {why}");
+        }
+    }
+}
+
+fn line(label: &str, value: impl std::fmt::Display) {
+    println!("{label:>10}: {value}");
+}
+
+fn size(bytes: u64) -> String {
+    match bytes as f64 {
+        bytes if bytes >= 1e9 => format!("{:.1} GB", bytes / 1e9),
+        bytes if bytes >= 1e6 => format!("{:.1} MB", bytes / 1e6),
+        bytes => format!("{:.0} kB", bytes / 1e3),
+    }
+}
+
+/// A window's state along with the summary, which ggez only drops, and so
+/// prints, once the window has closed.
+pub struct Summarized<S> {
+    state: S,
+    _summary: Option<Summary>,
+}
+
+impl<S> Summarized<S> {
+    pub fn new(state: S, summary: Option<Summary>) -> Self {
+        Summarized {
+            state,
+            _summary: summary,
+        }
+    }
+}
+
+impl<S: EventHandler<GameError>> EventHandler<GameError> for Summarized<S> {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.state.update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.state.draw(ctx)
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        repeated: bool,
+    ) -> Result<(), GameError> {
+        self.state.key_down_event(ctx, input, repeated)
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, input: KeyInput) -> Result<(), GameError> {
+        self.state.key_up_event(ctx, input)
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> Result<(), GameError> {
+        self.state.text_input_event(ctx, character)
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        self.state.mouse_button_down_event(ctx, button, x, y)
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        self.state.mouse_button_up_event(ctx, button, x, y)
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> Result<(), GameError> {
+        self.state.mouse_motion_event(ctx, x, y, dx, dy)
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> Result<bool, GameError> {
+        self.state.quit_event(ctx)
+    }
+}
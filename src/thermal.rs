@@ -0,0 +1,101 @@
+use crate::capture::Frame;
+use crate::mono::Depth;
+use clap::Args;
+use color_eyre::Report;
+use std::str::FromStr;
+
+/// Kelvin at 0 °C.
+const ZERO_CELSIUS: f32 = 273.15;
+
+/// How frames from thermal cameras and IR sensors are shown.
+///
+/// They arrive as 16-bit grayscale frames whose samples fill a narrow part
+/// of the range, so shown over all of it they look black.
+#[derive(Args, Clone, Default)]
+pub struct ThermalOptions {
+    /// Colour grayscale frames with white-hot, black-hot or ironbow.
+    #[arg(long)]
+    pub palette: Option<Palette>,
+    /// Stretch each grayscale frame from its coldest sample to its hottest,
+    /// rather than over the whole range. R turns it on and off.
+    #[arg(long)]
+    pub auto_range: bool,
+    /// Kelvin per count of a radiometric camera's 16-bit samples, as 0.01
+    /// for most Lepton-based ones, to show the temperature at the centre.
+    #[arg(long, value_name = "KELVIN")]
+    pub radiometric: Option<f32>,
+}
+
+/// How a grayscale level is coloured.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Palette {
+    /// Hot is white and cold is black, as the gray it arrives as.
+    #[default]
+    WhiteHot,
+    BlackHot,
+    /// Black through purple, red and orange to white, as thermal cameras
+    /// have shown heat for decades.
+    Ironbow,
+}
+
+/// The ironbow palette's stops, spread evenly from cold to hot.
+const IRONBOW: [[u8; 3]; 7] = [
+    [0, 0, 0],
+    [40, 0, 120],
+    [150, 0, 150],
+    [230, 60, 40],
+    [255, 160, 0],
+    [255, 230, 80],
+    [255, 255, 255],
+];
+
+impl Palette {
+    /// The colour of `level`, from 0 for the coldest shown to 255 for the
+    /// hottest.
+    pub fn colour(self, level: u8) -> [u8; 3] {
+        match self {
+            Palette::WhiteHot => [level; 3],
+            Palette::BlackHot => [255 - level; 3],
+            Palette::Ironbow => {
+                let at = level as f32 / 255.0 * (IRONBOW.len() - 1) as f32;
+                let (below, t) = (at.floor() as usize, at.fract());
+                let above = (below + 1).min(IRONBOW.len() - 1);
+                std::array::from_fn(|channel| {
+                    let (from, to) = (
+                        IRONBOW[below][channel] as f32,
+                        IRONBOW[above][channel] as f32,
+                    );
+                    (from + (to - from) * t).round() as u8
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for Palette {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "white-hot" | "gray" | "grey" => Ok(Palette::WhiteHot),
+            "black-hot" => Ok(Palette::BlackHot),
+            "ironbow" | "iron" => Ok(Palette::Ironbow),
+            _ => Err(Report::msg(format!(
+                "unknown palette: {s}; there's white-hot, black-hot and ironbow"
+            ))),
+        }
+    }
+}
+
+/// The temperature in °C of the sample at the centre of a 16-bit `frame`,
+/// each count of which is `kelvin` kelvin.
+pub fn spot(frame: &Frame, depth: Depth, kelvin: f32) -> Option<f32> {
+    if depth != Depth::Sixteen {
+        return None;
+    }
+    let resolution = frame.buffer.resolution();
+    let (width, height) = (resolution.width() as usize, resolution.height() as usize);
+    let at = (height / 2 * width + width / 2) * 2;
+    let sample = frame.buffer.buffer().get(at..at + 2)?;
+    Some(u16::from_le_bytes([sample[0], sample[1]]) as f32 * kelvin - ZERO_CELSIUS)
+}
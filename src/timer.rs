@@ -0,0 +1,76 @@
+use crate::audio;
+use clap::Args;
+use std::time::{Duration, Instant};
+
+/// Pitch of the beeps counting down, and of the last one.
+const TICK: f32 = 880.0;
+const SHUTTER: f32 = 1760.0;
+
+/// Waiting before a shot is taken.
+#[derive(Args, Clone, Default)]
+pub struct TimerOptions {
+    /// Count down this long before taking the shot, such as 5 or 10s.
+    #[arg(long, value_parser = crate::parse_duration)]
+    pub timer: Option<Duration>,
+    /// Beep every second of the countdown, and once more for the shot.
+    #[arg(long)]
+    pub beep: bool,
+}
+
+/// A countdown under way.
+pub struct Countdown {
+    end: Instant,
+    beep: bool,
+    /// Seconds left as of the last [`Countdown::tick`].
+    shown: Option<u64>,
+}
+
+impl Countdown {
+    pub fn start(length: Duration, beep: bool) -> Self {
+        Countdown {
+            end: Instant::now() + length,
+            beep,
+            shown: None,
+        }
+    }
+
+    /// Whole seconds left, rounded up, so the countdown shows 3, 2, 1.
+    pub fn remaining(&self) -> u64 {
+        let left = self.end.saturating_duration_since(Instant::now());
+        left.as_millis().div_ceil(1000) as u64
+    }
+
+    /// Beeps if a new second has begun. Returns whether the countdown is
+    /// over.
+    pub fn tick(&mut self) -> bool {
+        let remaining = self.remaining();
+        if self.shown != Some(remaining) {
+            self.shown = Some(remaining);
+            if self.beep {
+                match remaining {
+                    0 => audio::beep(SHUTTER, Duration::from_millis(300)),
+                    _ => audio::beep(TICK, Duration::from_millis(100)),
+                }
+            }
+        }
+        remaining == 0
+    }
+}
+
+/// Counts down on the terminal, returning once `options.timer` has passed.
+pub fn wait(options: &TimerOptions) {
+    let Some(length) = options.timer else {
+        return;
+    };
+    let mut countdown = Countdown::start(length, options.beep);
+    let mut shown = None;
+    while !countdown.tick() {
+        let remaining = countdown.remaining();
+        if shown != Some(remaining) {
+            eprint!("{remaining}… ");
+            shown = Some(remaining);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    eprintln!();
+}
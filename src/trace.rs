@@ -0,0 +1,199 @@
+//! Profiler zones around the pipeline's stages, so it shows where frame
+//! time goes on each thread.
+//!
+//! Built with the `profiling` feature, every command that runs lets the
+//! Tracy profiler connect and shows it the zones live; until one connects,
+//! nothing is collected. `--trace FILE` also writes every zone to FILE in
+//! the Chrome trace event format, which Perfetto and chrome://tracing open
+//! as it is. Without the feature, zones cost nothing.
+
+use color_eyre::Report;
+use std::path::Path;
+
+/// A stage running on this thread, timed until it's dropped.
+#[must_use]
+pub struct Zone {
+    #[cfg(feature = "profiling")]
+    _span: Option<tracy_client::Span>,
+    #[cfg(feature = "profiling")]
+    open: Option<(&'static str, std::time::Instant)>,
+}
+
+/// Opens a zone called `name` on this thread.
+#[inline]
+#[cfg_attr(feature = "profiling", track_caller)]
+pub fn zone(name: &'static str) -> Zone {
+    #[cfg(not(feature = "profiling"))]
+    let _ = name;
+    Zone {
+        #[cfg(feature = "profiling")]
+        _span: tracy_client::Client::running().map(|client| {
+            let at = std::panic::Location::caller();
+            client.span_alloc(Some(name), "", at.file(), at.line(), 0)
+        }),
+        #[cfg(feature = "profiling")]
+        open: enabled::TRACE
+            .get()
+            .map(|_| (name, std::time::Instant::now())),
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Drop for Zone {
+    fn drop(&mut self) {
+        if let Some((name, start)) = self.open.take() {
+            enabled::record(name, start);
+        }
+    }
+}
+
+/// Lets Tracy connect, and see the zones opened from then on.
+pub fn listen() {
+    #[cfg(feature = "profiling")]
+    tracy_client::Client::start();
+}
+
+/// Names this thread in Tracy and the trace.
+pub fn name_thread(name: &str) {
+    #[cfg(feature = "profiling")]
+    {
+        if let Some(client) = tracy_client::Client::running() {
+            client.set_thread_name(name);
+        }
+        enabled::name_thread(name);
+    }
+    #[cfg(not(feature = "profiling"))]
+    let _ = name;
+}
+
+/// Starts writing zones to `path`.
+#[cfg(feature = "profiling")]
+pub fn start(path: &Path) -> Result<(), Report> {
+    enabled::start(path)
+}
+
+#[cfg(not(feature = "profiling"))]
+pub fn start(_path: &Path) -> Result<(), Report> {
+    Err(Report::msg(
+        "tracing needs athletic built with --features profiling",
+    ))
+}
+
+/// Waits for the zones so far to be written, before the process exits.
+pub fn finish() {
+    #[cfg(feature = "profiling")]
+    enabled::finish();
+}
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use color_eyre::Report;
+    use flume::Sender;
+    use once_cell::sync::OnceCell;
+    use serde_json::json;
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::io::{BufWriter, Write};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Instant;
+
+    pub struct Trace {
+        start: Instant,
+        events: Sender<Event>,
+    }
+
+    pub enum Event {
+        Write(serde_json::Value),
+        /// Flush what's written and say so.
+        Flush(Sender<()>),
+    }
+
+    pub static TRACE: OnceCell<Trace> = OnceCell::new();
+    static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+    thread_local! {
+        static THREAD: Cell<u64> = const { Cell::new(0) };
+    }
+
+    /// This thread's number in the trace, given out as threads first
+    /// open a zone.
+    fn thread() -> u64 {
+        THREAD.with(|thread| {
+            if thread.get() == 0 {
+                thread.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+            }
+            thread.get()
+        })
+    }
+
+    pub fn start(path: &Path) -> Result<(), Report> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let (events, received) = flume::unbounded();
+        // The closing bracket is optional in this format, which matters
+        // because windowed commands exit without unwinding.
+        std::thread::spawn(move || {
+            let _ = file.write_all(b"[\n");
+            while let Ok(event) = received.recv() {
+                match event {
+                    Event::Write(event) => {
+                        let _ = writeln!(file, "{event},");
+                        if received.is_empty() {
+                            let _ = file.flush();
+                        }
+                    }
+                    Event::Flush(done) => {
+                        let _ = file.flush();
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        TRACE
+            .set(Trace {
+                start: Instant::now(),
+                events,
+            })
+            .map_err(|_| Report::msg("already tracing"))?;
+        println!("Tracing to {}", path.display());
+        Ok(())
+    }
+
+    pub fn record(name: &'static str, start: Instant) {
+        let Some(trace) = TRACE.get() else {
+            return;
+        };
+        let micros = |at: Instant| at.saturating_duration_since(trace.start).as_secs_f64() * 1e6;
+        let _ = trace.events.send(Event::Write(json!({
+            "name": name,
+            "ph": "X",
+            "ts": micros(start),
+            "dur": start.elapsed().as_secs_f64() * 1e6,
+            "pid": 1,
+            "tid": thread(),
+        })));
+    }
+
+    pub fn name_thread(name: &str) {
+        let Some(trace) = TRACE.get() else {
+            return;
+        };
+        let _ = trace.events.send(Event::Write(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": thread(),
+            "args": { "name": name },
+        })));
+    }
+
+    pub fn finish() {
+        let Some(trace) = TRACE.get() else {
+            return;
+        };
+        let (done, flushed) = flume::bounded(1);
+        if trace.events.send(Event::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Tunes: named sets of camera controls for a kind of shot, so `--tune
+//! low-light` does what would otherwise take knowing which of a dozen
+//! controls to change and to what.
+//!
+//! Cameras differ in the ranges their controls take, so a tune gives
+//! most levels as a share of the range, as `75%`, rather than a number
+//! that suits only one camera.
+
+use crate::capture;
+use color_eyre::Report;
+use nokhwa::utils::KnownCameraControl;
+use serde::{Deserialize, Deserializer};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// The tunes athletic comes with, which one in the config may replace.
+pub const BUILTIN: &[&str] = &["low-light", "document", "greenscreen"];
+
+/// Controls V4L2 has and nokhwa doesn't know by name, as
+/// `list-properties` doesn't print them either.
+const AUTO_CONTROLS: [(&str, KnownCameraControl); 3] = [
+    // V4L2_CID_EXPOSURE_AUTO: 1 is manual, 3 lets the camera decide.
+    ("ExposureAuto", KnownCameraControl::Other(0x009a_0901)),
+    // V4L2_CID_AUTO_WHITE_BALANCE: 0 holds the white balance it has.
+    ("WhiteBalanceAuto", KnownCameraControl::Other(0x0098_090c)),
+    // V4L2_CID_FOCUS_AUTO.
+    ("FocusAuto", KnownCameraControl::Other(0x009a_090c)),
+];
+
+/// What a control is set to.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Level {
+    Value(i64),
+    /// A share of the control's range, from its minimum at 0 to its maximum
+    /// at 1.
+    Share(f64),
+}
+
+impl Level {
+    /// The value to set a control taking `min` to `max` in steps of `step`
+    /// to.
+    pub fn within(self, min: i64, max: i64, step: i64) -> i64 {
+        match self {
+            Level::Value(value) => value,
+            Level::Share(share) => {
+                let step = step.max(1);
+                let steps = (share * (max - min) as f64 / step as f64).round() as i64;
+                // Rounding up may go a step past the last one in range.
+                min + steps.clamp(0, (max - min) / step) * step
+            }
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f64>() {
+                Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                    Ok(Level::Share(percent / 100.0))
+                }
+                _ => Err(Report::msg(format!(
+                    "a share of a control's range is between 0% and 100%, not {s}"
+                ))),
+            },
+            None => {
+                s.trim().parse().map(Level::Value).map_err(|_| {
+                    Report::msg(format!("expected a number or a percentage, got: {s}"))
+                })
+            }
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Level::Value(value) => write!(f, "{value}"),
+            Level::Share(share) => write!(f, "{}%", share * 100.0),
+        }
+    }
+}
+
+/// Taken as either a number or a percentage in a string, as in
+/// `Gain = 40` and `Gain = "40%"`.
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Value(i64),
+            Text(String),
+        }
+        match Written::deserialize(deserializer)? {
+            Written::Value(value) => Ok(Level::Value(value)),
+            Written::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A named set of controls, set in order, so turning an automatic control
+/// off comes before setting what it used to.
+#[derive(Clone, Debug)]
+pub struct Tune {
+    pub name: String,
+    pub settings: Vec<(KnownCameraControl, Level)>,
+}
+
+impl Tune {
+    /// The built-in tune `name`.
+    pub fn builtin(name: &str) -> Option<Tune> {
+        use KnownCameraControl::{Contrast, Exposure, Gain, Saturation, Sharpness};
+        let [exposure_auto, white_balance_auto, focus_auto] =
+            AUTO_CONTROLS.map(|(_, control)| control);
+        let settings = match name {
+            // A longer exposure first, then gain for what it can't make up,
+            // with the white balance held so it doesn't wander in the noise.
+            "low-light" => vec![
+                (exposure_auto, Level::Value(1)),
+                (Exposure, Level::Share(0.75)),
+                (Gain, Level::Share(0.6)),
+                (white_balance_auto, Level::Value(0)),
+            ],
+            // Crisp, high contrast text, with colour turned down and the
+            // camera left to focus on the page.
+            "document" => vec![
+                (focus_auto, Level::Value(1)),
+                (Contrast, Level::Share(0.7)),
+                (Sharpness, Level::Share(0.8)),
+                (Saturation, Level::Share(0.3)),
+            ],
+            // A key that stays the same colour: nothing automatic, with a
+            // little extra saturation and soft edges against the screen.
+            "greenscreen" => vec![
+                (exposure_auto, Level::Value(1)),
+                (white_balance_auto, Level::Value(0)),
+                (Saturation, Level::Share(0.6)),
+                (Sharpness, Level::Share(0.3)),
+            ],
+            _ => return None,
+        };
+        Some(Tune {
+            name: name.to_string(),
+            settings,
+        })
+    }
+}
+
+/// Looks up a control by the name `list-properties` prints for it, or by
+/// the name of one of the automatic controls only V4L2 has.
+pub fn control(name: &str) -> Option<KnownCameraControl> {
+    capture::known_control(name).or_else(|| {
+        AUTO_CONTROLS
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(name))
+            .map(|&(_, control)| control)
+    })
+}
+
+/// The name [`control`] looks `control` up by.
+pub fn control_name(control: KnownCameraControl) -> String {
+    AUTO_CONTROLS
+        .iter()
+        .find(|&&(_, known)| known == control)
+        .map_or_else(|| control.to_string(), |(name, _)| name.to_string())
+}
@@ -0,0 +1,92 @@
+use crate::trace;
+use clap::Args;
+use color_eyre::Report;
+use image::{imageops::FilterType, RgbImage};
+use std::str::FromStr;
+
+/// Scaling frames up before they're sent or recorded.
+#[derive(Args, Clone, Default)]
+pub struct UpscaleOptions {
+    /// Scale frames up by this factor before they go out, as FACTORx
+    /// optionally followed by :nearest, :bilinear, :bicubic or :lanczos
+    /// (the default), e.g. 1.5x to turn 720p into 1080p. Masks are applied
+    /// first, at the camera's resolution.
+    #[arg(long, value_name = "FACTORx[:METHOD]")]
+    pub upscale: Option<Upscale>,
+}
+
+/// How the new pixels are made up.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Method {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    /// Lanczos with three lobes: the sharpest, and the slowest.
+    Lanczos,
+}
+
+impl FromStr for Method {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" | "NEAREST" => Ok(Method::Nearest),
+            "bilinear" | "BILINEAR" | "linear" => Ok(Method::Bilinear),
+            "bicubic" | "BICUBIC" | "cubic" => Ok(Method::Bicubic),
+            "lanczos" | "LANCZOS" | "lanczos3" => Ok(Method::Lanczos),
+            "esrgan" | "ESRGAN" => Err(Report::msg(
+                "esrgan super-resolution needs an ONNX runtime, which athletic isn't built with",
+            )),
+            _ => Err(Report::msg(format!("unknown upscaling method: {s}"))),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Upscale {
+    pub factor: f32,
+    pub method: Method,
+}
+
+impl FromStr for Upscale {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (factor, method) = match s.split_once(':') {
+            Some((factor, method)) => (factor, method.parse()?),
+            None => (s, Method::Lanczos),
+        };
+        let factor: f32 = factor
+            .strip_suffix(['x', 'X'])
+            .unwrap_or(factor)
+            .parse()
+            .map_err(|_| Report::msg(format!("invalid upscaling factor: {factor}")))?;
+        if !(factor > 1.0 && factor <= 8.0) {
+            return Err(Report::msg(format!(
+                "upscaling factor has to be above 1 and at most 8, not {factor}"
+            )));
+        }
+        Ok(Upscale { factor, method })
+    }
+}
+
+impl Upscale {
+    /// The size a `width` by `height` frame comes out at, rounded to even
+    /// numbers so it still fits 4:2:0 formats.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |length: u32| ((length as f32 * self.factor / 2.0).round() as u32 * 2).max(2);
+        (scale(width), scale(height))
+    }
+
+    pub fn apply(&self, image: &RgbImage) -> RgbImage {
+        let _zone = trace::zone("upscale");
+        let (width, height) = self.size(image.width(), image.height());
+        let filter = match self.method {
+            Method::Nearest => FilterType::Nearest,
+            Method::Bilinear => FilterType::Triangle,
+            Method::Bicubic => FilterType::CatmullRom,
+            Method::Lanczos => FilterType::Lanczos3,
+        };
+        image::imageops::resize(image, width, height, filter)
+    }
+}
@@ -0,0 +1,143 @@
+//! Resampling frames and the projective transforms between them, shared
+//! by flattening pages, stitching panoramas and interpolating frames.
+
+use image::RgbImage;
+
+/// A 3×3 matrix, row by row.
+pub type Matrix = [f64; 9];
+pub type Point = (f64, f64);
+
+pub const IDENTITY: Matrix = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+/// The colour at `(x, y)` by bilinear interpolation, clamped to the edges.
+pub fn sample(image: &RgbImage, x: f32, y: f32) -> [f32; 3] {
+    let (width, height) = image.dimensions();
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (left, top) = (x as usize, y as usize);
+    let right = if left + 1 < width as usize { 3 } else { 0 };
+    let below = if top + 1 < height as usize {
+        width as usize * 3
+    } else {
+        0
+    };
+    let (fx, fy) = (x.fract(), y.fract());
+    let raw = image.as_raw();
+    let at = (top * width as usize + left) * 3;
+    let mut out = [0.0; 3];
+    for (n, out) in out.iter_mut().enumerate() {
+        let value = |offset: usize| raw[at + offset + n] as f32;
+        let upper = value(0) + (value(right) - value(0)) * fx;
+        let lower = value(below) + (value(below + right) - value(below)) * fx;
+        *out = upper + (lower - upper) * fy;
+    }
+    out
+}
+
+/// The homography best taking each first point of `pairs` onto its second,
+/// by least squares, or exactly for four. Both sets are first moved to
+/// have their centroid at the origin and their mean distance from it √2,
+/// which keeps the equations well conditioned.
+pub fn fit(pairs: &[(Point, Point)]) -> Option<Matrix> {
+    let normaliser = |points: &mut dyn Iterator<Item = Point>| -> Matrix {
+        let points: Vec<Point> = points.collect();
+        let count = points.len() as f64;
+        let (cx, cy) = points
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (x + p.0, y + p.1));
+        let (cx, cy) = (cx / count, cy / count);
+        let spread = points
+            .iter()
+            .map(|p| (p.0 - cx).hypot(p.1 - cy))
+            .sum::<f64>()
+            / count;
+        let s = std::f64::consts::SQRT_2 / spread.max(1e-9);
+        [s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0]
+    };
+    let from_norm = normaliser(&mut pairs.iter().map(|pair| pair.0));
+    let to_norm = normaliser(&mut pairs.iter().map(|pair| pair.1));
+
+    // The normal equations of the usual eight-unknown linear system.
+    let mut normal = [[0.0f64; 9]; 8];
+    for &(from, to) in pairs {
+        let (x, y) = apply(&from_norm, from);
+        let (u, v) = apply(&to_norm, to);
+        for row in [
+            [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u],
+            [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v],
+        ] {
+            for i in 0..8 {
+                for j in 0..9 {
+                    normal[i][j] += row[i] * row[j];
+                }
+            }
+        }
+    }
+    // Gaussian elimination with partial pivoting.
+    for column in 0..8 {
+        let pivot = (column..8)
+            .max_by(|&a, &b| normal[a][column].abs().total_cmp(&normal[b][column].abs()))?;
+        if normal[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        normal.swap(column, pivot);
+        for row in 0..8 {
+            if row != column {
+                let pivot = normal[column];
+                let factor = normal[row][column] / pivot[column];
+                for (value, pivot) in normal[row].iter_mut().zip(pivot).skip(column) {
+                    *value -= factor * pivot;
+                }
+            }
+        }
+    }
+    let mut normalised = [1.0; 9];
+    for (n, value) in normalised.iter_mut().take(8).enumerate() {
+        *value = normal[n][8] / normal[n][n];
+    }
+    Some(multiply(
+        &invert(&to_norm)?,
+        &multiply(&normalised, &from_norm),
+    ))
+}
+
+/// Where `matrix` takes `(x, y)`.
+pub fn apply(matrix: &Matrix, (x, y): Point) -> Point {
+    let w = matrix[6] * x + matrix[7] * y + matrix[8];
+    (
+        (matrix[0] * x + matrix[1] * y + matrix[2]) / w,
+        (matrix[3] * x + matrix[4] * y + matrix[5]) / w,
+    )
+}
+
+/// `a` after `b`.
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [0.0; 9];
+    for row in 0..3 {
+        for column in 0..3 {
+            out[row * 3 + column] = (0..3).map(|k| a[row * 3 + k] * b[k * 3 + column]).sum();
+        }
+    }
+    out
+}
+
+/// The transform undoing `m`, unless it squashes the plane flat.
+pub fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactor = |a: usize, b: usize, c: usize, d: usize| m[a] * m[d] - m[b] * m[c];
+    let adjugate = [
+        cofactor(4, 5, 7, 8),
+        -cofactor(1, 2, 7, 8),
+        cofactor(1, 2, 4, 5),
+        -cofactor(3, 5, 6, 8),
+        cofactor(0, 2, 6, 8),
+        -cofactor(0, 2, 3, 5),
+        cofactor(3, 4, 6, 7),
+        -cofactor(0, 1, 6, 7),
+        cofactor(0, 1, 3, 4),
+    ];
+    let determinant = m[0] * adjugate[0] + m[1] * adjugate[3] + m[2] * adjugate[6];
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    Some(adjugate.map(|value| value / determinant))
+}
@@ -0,0 +1,151 @@
+use clap::Args;
+use color_eyre::Report;
+use ggez::{
+    conf::{WindowMode, WindowSetup},
+    event::EventLoop,
+    winit::window::Fullscreen,
+    Context, ContextBuilder,
+};
+use nokhwa::utils::{CameraFormat, CameraInfo, Resolution};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DEFAULT_TITLE: &str = "athletic — {name} {width}x{height}@{fps} ({format})";
+
+/// How the preview window is placed and decorated.
+#[derive(Args, Clone)]
+pub struct WindowOptions {
+    /// Cover the whole monitor.
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Keep the window above all others.
+    #[arg(long)]
+    pub always_on_top: bool,
+    /// Open the window without decorations.
+    #[arg(long)]
+    pub borderless: bool,
+    /// Initial window size as WIDTHxHEIGHT; defaults to the camera resolution.
+    #[arg(long, value_parser = crate::spec::parse_resolution)]
+    pub window_size: Option<Resolution>,
+    /// Open the window on this monitor, counting from 0.
+    #[arg(long)]
+    pub monitor: Option<usize>,
+    /// Window title; {name}, {index}, {width}, {height}, {fps} and {format}
+    /// are replaced with the camera's.
+    #[arg(long, default_value = DEFAULT_TITLE)]
+    pub title: String,
+    /// When to draw: vsync, once per display refresh; immediate, as often
+    /// as possible; or rate:N, N times a second. The last frame is drawn
+    /// again when the camera hasn't sent a new one.
+    #[arg(long, value_name = "WHEN", default_value_t = Present::Vsync)]
+    pub present: Present,
+}
+
+/// How often the window is drawn, whether or not a new frame has arrived.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Present {
+    Vsync,
+    Immediate,
+    Rate(u32),
+}
+
+impl Present {
+    /// How long to leave between draws, for a fixed rate.
+    pub fn interval(self) -> Option<Duration> {
+        match self {
+            Present::Rate(rate) => Some(Duration::from_secs(1) / rate),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Present {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vsync" => Ok(Present::Vsync),
+            "immediate" => Ok(Present::Immediate),
+            _ => match s.strip_prefix("rate:").map(str::parse) {
+                Some(Ok(rate)) if rate > 0 => Ok(Present::Rate(rate)),
+                _ => Err(Report::msg(format!(
+                    "can't present {s}; there's vsync, immediate and rate:N with N above 0"
+                ))),
+            },
+        }
+    }
+}
+
+impl Display for Present {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Present::Vsync => f.write_str("vsync"),
+            Present::Immediate => f.write_str("immediate"),
+            Present::Rate(rate) => write!(f, "rate:{rate}"),
+        }
+    }
+}
+
+/// Fills in the placeholders of a `--title` template.
+pub fn title(template: &str, info: &CameraInfo, format: &CameraFormat) -> String {
+    template
+        .replace("{name}", &info.human_name())
+        .replace("{index}", &info.index().to_string())
+        .replace("{width}", &format.width().to_string())
+        .replace("{height}", &format.height().to_string())
+        .replace("{fps}", &format.frame_rate().to_string())
+        .replace("{format}", &format.format().to_string())
+}
+
+/// Creates the ggez context for a window of `size`, unless overridden by `options`.
+pub fn build(
+    title: &str,
+    size: Resolution,
+    options: &WindowOptions,
+) -> Result<(Context, EventLoop<()>), Report> {
+    let size = options.window_size.unwrap_or(size);
+    let (ctx, event_loop) = ContextBuilder::new("athletic", "athletic")
+        .window_setup(
+            WindowSetup::default()
+                .title(title)
+                .vsync(options.present == Present::Vsync),
+        )
+        .window_mode(
+            WindowMode::default()
+                .dimensions(size.width() as f32, size.height() as f32)
+                .borderless(options.borderless)
+                .resizable(true),
+        )
+        .build()?;
+
+    let window = ctx.gfx.window();
+    window.set_always_on_top(options.always_on_top);
+    let monitor = match options.monitor {
+        Some(n) => Some(window.available_monitors().nth(n).ok_or_else(|| {
+            Report::msg(format!(
+                "no monitor {n}, there are {}",
+                window.available_monitors().count()
+            ))
+        })?),
+        None => None,
+    };
+    if let Some(monitor) = &monitor {
+        window.set_outer_position(monitor.position());
+    }
+    if options.fullscreen {
+        window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+    }
+    Ok((ctx, event_loop))
+}
+
+/// Where to draw an image of `image` size so it fills `window` without
+/// distorting, leaving black bars on the sides that don't fit.
+pub fn letterbox(image: (f32, f32), window: (f32, f32)) -> ([f32; 2], f32) {
+    let scale = (window.0 / image.0).min(window.1 / image.1);
+    let offset = [
+        (window.0 - image.0 * scale) / 2.0,
+        (window.1 - image.1 * scale) / 2.0,
+    ];
+    (offset, scale)
+}
@@ -0,0 +1,48 @@
+use athletic::bad_frames::BadFrames;
+use athletic::bus::{self, Event};
+use athletic::capture::{Clock, Frame};
+use nokhwa::{
+    utils::{FrameFormat, Resolution},
+    Buffer,
+};
+
+fn truncated_jpeg(clock: &mut Clock) -> Frame {
+    let buffer = Buffer::new(
+        Resolution::new(4, 4),
+        &[0xFF, 0xD8, 0xFF],
+        FrameFormat::MJPEG,
+    );
+    clock.stamp(buffer)
+}
+
+#[test]
+fn bad_frames_are_counted_reported_and_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let dumps = dir.path().join("bad");
+    let events = bus::subscribe();
+    let mut bad = BadFrames::new(Some(dumps.clone()));
+    let mut clock = Clock::default();
+    let first = truncated_jpeg(&mut clock);
+    let second = truncated_jpeg(&mut clock);
+    bad.record(&first, "unexpected end of file");
+    bad.record(&second, "unexpected end of file");
+
+    assert_eq!(bad.count, 2);
+    for sequence in [0, 1] {
+        let kept = std::fs::read(dumps.join(format!("frame-{sequence}.mjpeg"))).unwrap();
+        assert_eq!(kept, [0xFF, 0xD8, 0xFF]);
+    }
+    // Other tests publish too, so look for this one's among them.
+    let reported = events.drain().any(|event| {
+        matches!(&*event, Event::Error { message }
+            if message == "skipping corrupt frame 0: unexpected end of file")
+    });
+    assert!(reported, "no error event for the first frame");
+}
+
+#[test]
+fn without_a_directory_bad_frames_are_only_counted() {
+    let mut bad = BadFrames::new(None);
+    bad.record(&truncated_jpeg(&mut Clock::default()), "bad");
+    assert_eq!(bad.count, 1);
+}
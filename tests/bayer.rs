@@ -0,0 +1,146 @@
+use athletic::bayer::{self, Channel, Demosaic, Pattern};
+use athletic::capture::{Clock, Frame};
+use athletic::dng;
+use athletic::mono::Depth;
+use nokhwa::{
+    utils::{FrameFormat, Resolution},
+    Buffer,
+};
+
+const WIDTH: usize = 6;
+const HEIGHT: usize = 4;
+
+/// A mosaic of a flat colour, each pixel holding the channel `pattern`
+/// puts over it.
+fn flat(pattern: Pattern, [r, g, b]: [u16; 3], depth: Depth) -> Vec<u8> {
+    (0..WIDTH * HEIGHT)
+        .flat_map(|n| {
+            let sample = match pattern.at(n % WIDTH, n / WIDTH) {
+                Channel::Red => r,
+                Channel::Green => g,
+                Channel::Blue => b,
+            };
+            match depth {
+                Depth::Eight => vec![sample as u8],
+                Depth::Sixteen => sample.to_le_bytes().to_vec(),
+            }
+        })
+        .collect()
+}
+
+fn frame(data: &[u8]) -> Frame {
+    let resolution = Resolution::new(WIDTH as u32, HEIGHT as u32);
+    Clock::default().stamp(Buffer::new(resolution, data, FrameFormat::GRAY))
+}
+
+#[test]
+fn patterns_parse_with_the_greens_on_a_diagonal() {
+    let rggb: Pattern = "rggb".parse().unwrap();
+    assert_eq!(rggb.at(0, 0), Channel::Red);
+    assert_eq!(rggb.at(3, 0), Channel::Green);
+    assert_eq!(rggb.at(1, 1), Channel::Blue);
+    for pattern in ["BGGR", "GRBG", "GBRG"] {
+        assert!(pattern.parse::<Pattern>().is_ok(), "{pattern}");
+    }
+    for pattern in ["RGBG", "RRGB", "RGB", "RGGBX", "CMYK"] {
+        assert!(pattern.parse::<Pattern>().is_err(), "{pattern}");
+    }
+}
+
+#[test]
+fn every_algorithm_keeps_a_flat_colour() {
+    for pattern in ["RGGB", "BGGR", "GRBG", "GBRG"] {
+        let pattern: Pattern = pattern.parse().unwrap();
+        for demosaic in [Demosaic::Nearest, Demosaic::Bilinear, Demosaic::Malvar] {
+            let frame = frame(&flat(pattern, [200, 100, 50], Depth::Eight));
+            let mut rgba = vec![0; WIDTH * HEIGHT * 4];
+            bayer::demosaic(&frame, Depth::Eight, pattern, demosaic, &mut rgba);
+            for pixel in rgba.chunks_exact(4) {
+                assert_eq!(pixel, [200, 100, 50, 255], "{pattern:?} {demosaic:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn sixteen_bit_samples_are_scaled_down() {
+    let pattern: Pattern = "RGGB".parse().unwrap();
+    let frame = frame(&flat(pattern, [65_535, 257 * 100, 0], Depth::Sixteen));
+    let mut rgba = vec![0; WIDTH * HEIGHT * 4];
+    bayer::demosaic(
+        &frame,
+        Depth::Sixteen,
+        pattern,
+        Demosaic::Bilinear,
+        &mut rgba,
+    );
+    assert_eq!(rgba[..4], [255, 100, 0, 255]);
+}
+
+#[test]
+fn short_frames_are_left_alone() {
+    let pattern: Pattern = "RGGB".parse().unwrap();
+    let frame = frame(&[0; 10]);
+    let mut rgba = vec![7; WIDTH * HEIGHT * 4];
+    bayer::demosaic(&frame, Depth::Eight, pattern, Demosaic::Malvar, &mut rgba);
+    assert!(rgba.iter().all(|&byte| byte == 7));
+}
+
+/// The type, count and value (or where the value is) of IFD entry `tag`.
+fn entry(dng: &[u8], tag: u16) -> Option<(u16, u32, u32)> {
+    let u16_at = |at: usize| u16::from_le_bytes([dng[at], dng[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes(dng[at..at + 4].try_into().unwrap());
+    let ifd = u32_at(4) as usize;
+    (0..u16_at(ifd) as usize)
+        .map(|n| ifd + 2 + n * 12)
+        .find(|&at| u16_at(at) == tag)
+        .map(|at| (u16_at(at + 2), u32_at(at + 4), u32_at(at + 8)))
+}
+
+#[test]
+fn dngs_hold_the_mosaic_and_its_layout() {
+    let pattern: Pattern = "GRBG".parse().unwrap();
+    let data = flat(pattern, [1000, 2000, 3000], Depth::Sixteen);
+    let mut out = Vec::new();
+    dng::write(
+        &mut out,
+        &data,
+        WIDTH as u32,
+        HEIGHT as u32,
+        Depth::Sixteen,
+        pattern,
+        "bench camera",
+    )
+    .unwrap();
+
+    assert_eq!(&out[..4], b"II*\0");
+    let ifd = u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize;
+    let count = u16::from_le_bytes([out[ifd], out[ifd + 1]]) as usize;
+    let tags: Vec<u16> = (0..count)
+        .map(|n| u16::from_le_bytes([out[ifd + 2 + n * 12], out[ifd + 3 + n * 12]]))
+        .collect();
+    assert!(tags.is_sorted(), "TIFF tags must be in order: {tags:?}");
+
+    assert_eq!(entry(&out, 256), Some((4, 1, WIDTH as u32)));
+    assert_eq!(entry(&out, 257), Some((4, 1, HEIGHT as u32)));
+    assert_eq!(entry(&out, 258), Some((3, 1, 16)));
+    // The CFA pattern as TIFF numbers colours: 0 red, 1 green, 2 blue.
+    assert_eq!(
+        entry(&out, 33422),
+        Some((1, 4, u32::from_le_bytes([1, 0, 2, 1])))
+    );
+    let (_, _, offset) = entry(&out, 273).unwrap();
+    let (_, _, length) = entry(&out, 279).unwrap();
+    assert_eq!(length as usize, data.len());
+    assert_eq!(&out[offset as usize..], data);
+    let (_, length, model) = entry(&out, 272).unwrap();
+    assert_eq!(&out[model as usize..][..length as usize], b"bench camera\0");
+}
+
+#[test]
+fn short_raw_frames_are_refused() {
+    let pattern: Pattern = "RGGB".parse().unwrap();
+    let mut out = Vec::new();
+    let written = dng::write(&mut out, &[0; 10], 6, 4, Depth::Eight, pattern, "camera");
+    assert!(written.is_err());
+}
@@ -0,0 +1,63 @@
+use athletic::capture::{self, Clock, Recovery};
+use athletic::pattern::DEVICE;
+use nokhwa::utils::{CameraIndex, FrameFormat, Resolution};
+use nokhwa::Buffer;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+fn buffer() -> Buffer {
+    Buffer::new(
+        Resolution::new(2, 2),
+        &[16, 128, 16, 128].repeat(2),
+        FrameFormat::YUYV,
+    )
+}
+
+#[test]
+fn frames_are_stamped_from_the_first_in_order() {
+    let mut clock = Clock::default();
+    assert_eq!(clock.elapsed(), Duration::ZERO);
+    let first = clock.stamp(buffer());
+    std::thread::sleep(Duration::from_millis(20));
+    let second = clock.stamp(buffer());
+    assert_eq!((first.sequence, second.sequence), (0, 1));
+    assert_eq!(first.pts, Duration::ZERO);
+    assert!(second.pts >= Duration::from_millis(20), "{:?}", second.pts);
+    assert!(clock.elapsed() >= second.pts);
+}
+
+#[test]
+fn frames_dropped_for_a_slow_reader_leave_gaps_in_the_sequence() {
+    let capture = capture::spawn_capture(CameraIndex::String(DEVICE.to_string())).unwrap();
+    // Long enough for the queue to fill and frames to be dropped.
+    std::thread::sleep(Duration::from_millis(500));
+    let queued: Vec<u64> = capture.frames.drain().map(|frame| frame.sequence).collect();
+    assert_eq!(queued, (0..capture::QUEUE_DEPTH as u64).collect::<Vec<_>>());
+    let next = capture.frames.recv().unwrap();
+    assert!(
+        next.sequence > queued.len() as u64,
+        "no gap before {}",
+        next.sequence
+    );
+    assert!(capture.counters.dropped.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn a_stalled_camera_has_its_stream_restarted_then_is_reopened_until_the_tries_run_out() {
+    let tries: Vec<Recovery> = (1..=5).map(|attempt| Recovery::after(attempt, 3)).collect();
+    assert_eq!(
+        tries,
+        [
+            Recovery::RestartStream,
+            Recovery::Reopen,
+            Recovery::Reopen,
+            Recovery::GiveUp,
+            Recovery::GiveUp,
+        ]
+    );
+}
+
+#[test]
+fn no_tries_gives_up_at_once() {
+    assert_eq!(Recovery::after(1, 0), Recovery::GiveUp);
+}
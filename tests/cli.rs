@@ -0,0 +1,751 @@
+//! The commands, run as a user would run them, on the test pattern.
+
+mod support;
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use support::{athletic, first_part, free_address, run, scratch, Running, DEVICE};
+
+#[test]
+fn snapshot_saves_the_pattern() {
+    let dir = scratch();
+    let output = dir.path().join("shot.png");
+    run(
+        dir.path(),
+        &["snapshot", DEVICE, "-o", output.to_str().unwrap()],
+    );
+
+    let image = image::open(&output).unwrap().into_rgb8();
+    assert_eq!(image.dimensions(), (640, 480));
+    let colours: HashSet<_> = (0..640).map(|x| image.get_pixel(x, 240).0).collect();
+    assert!(colours.len() >= 8, "only {} colours", colours.len());
+}
+
+#[test]
+fn record_writes_a_recording_that_verifies() {
+    let dir = scratch();
+    let output = dir.path().join("out.mkv");
+    let output = output.to_str().unwrap();
+    run(
+        dir.path(),
+        &[
+            "record",
+            DEVICE,
+            "-o",
+            output,
+            "--duration",
+            "1s",
+            "--progress",
+            "off",
+        ],
+    );
+
+    assert!(std::fs::metadata(output).unwrap().len() > 0);
+    assert!(dir.path().join("out.mkv.manifest.json").exists());
+    let verified = run(dir.path(), &["verify", output]);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("OK"));
+}
+
+#[test]
+fn verify_finalize_finishes_a_recording_cut_short() {
+    let dir = scratch();
+    let output = dir.path().join("out.mkv");
+    let output = output.to_str().unwrap();
+    run(
+        dir.path(),
+        &[
+            "record",
+            DEVICE,
+            "-o",
+            output,
+            "--duration",
+            "2s",
+            "--progress",
+            "off",
+        ],
+    );
+    // As a power cut could leave it: no manifest, and the last cluster
+    // only partly on disk, ending in zeros.
+    std::fs::remove_file(dir.path().join("out.mkv.manifest.json")).unwrap();
+    let mut recording = std::fs::read(output).unwrap();
+    recording.truncate(recording.len() - 1000);
+    recording.extend([0; 100]);
+    std::fs::write(output, recording).unwrap();
+
+    let finalized = run(dir.path(), &["verify", "--finalize", output]);
+    let finalized = String::from_utf8_lossy(&finalized.stdout);
+    assert!(finalized.contains("cut off"), "{finalized}");
+    assert!(finalized.contains("out.mkv: OK"), "{finalized}");
+    let again = run(dir.path(), &["verify", "--finalize", output]);
+    assert!(String::from_utf8_lossy(&again.stdout).contains("finished already"));
+}
+
+#[test]
+fn verify_finalize_leaves_encrypted_recordings_to_be_decrypted() {
+    let dir = scratch();
+    let recipients = dir.path().join("recipients.txt");
+    let recipient = age::x25519::Identity::generate().to_public();
+    std::fs::write(&recipients, format!("{recipient}\n")).unwrap();
+    let output = dir.path().join("out.mkv.age");
+    let output = output.to_str().unwrap();
+    let encrypt = format!("age:{}", recipients.display());
+    run(
+        dir.path(),
+        &[
+            "record",
+            DEVICE,
+            "-o",
+            output,
+            "--duration",
+            "1s",
+            "--progress",
+            "off",
+            "--encrypt",
+            &encrypt,
+        ],
+    );
+    std::fs::remove_file(dir.path().join("out.mkv.age.manifest.json")).unwrap();
+    let before = std::fs::read(output).unwrap();
+
+    let finalized = athletic(dir.path(), &["verify", "--finalize", output])
+        .output()
+        .unwrap();
+    assert!(!finalized.status.success(), "finalized ciphertext");
+    let why = String::from_utf8_lossy(&finalized.stderr);
+    assert!(why.contains("it's encrypted"), "{why}");
+    assert_eq!(
+        std::fs::read(output).unwrap(),
+        before,
+        "touched the recording"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn record_finishes_the_recording_when_stopped() {
+    let dir = scratch();
+    let output = dir.path().join("out.mkv");
+    let output = output.to_str().unwrap();
+    let mut record = athletic(
+        dir.path(),
+        &["record", DEVICE, "-o", output, "--progress", "off"],
+    )
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null())
+    .spawn()
+    .unwrap();
+    let start = Instant::now();
+    while std::fs::metadata(output).map_or(0, |file| file.len()) < 100_000 {
+        assert!(
+            start.elapsed() < Duration::from_secs(20),
+            "nothing was recorded"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    unsafe { libc::kill(record.id() as libc::pid_t, libc::SIGTERM) };
+
+    assert!(record.wait().unwrap().success());
+    let verified = run(dir.path(), &["verify", output]);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("OK"));
+}
+
+#[test]
+fn serve_streams_jpeg_frames() {
+    let dir = scratch();
+    let address = free_address();
+    let mut server = Running::start(
+        dir.path(),
+        &["serve", DEVICE, "--bind", &address.to_string()],
+    );
+
+    let (content_type, jpeg) = first_part(server.connect(address), "/");
+    assert_eq!(content_type, "image/jpeg");
+    let frame = image::load_from_memory(&jpeg).unwrap();
+    assert_eq!((frame.width(), frame.height()), (640, 480));
+}
+
+mod proto {
+    tonic::include_proto!("athletic.v1");
+}
+
+#[test]
+fn serve_records_when_asked_over_grpc() {
+    use proto::camera_client::CameraClient;
+    let dir = scratch();
+    let address = free_address();
+    let mut server = Running::start(
+        dir.path(),
+        &["serve", DEVICE, "--grpc", "--bind", &address.to_string()],
+    );
+    drop(server.connect(address));
+    let output = dir.path().join("asked.mkv");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut camera = CameraClient::connect(format!("http://{address}"))
+            .await
+            .unwrap();
+        let properties = camera
+            .get_properties(proto::GetPropertiesRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(properties.format.unwrap().width, 640);
+        camera
+            .start_recording(proto::StartRecordingRequest {
+                path: output.to_str().unwrap().to_string(),
+            })
+            .await
+            .unwrap();
+        let again = camera
+            .start_recording(proto::StartRecordingRequest {
+                path: output.to_str().unwrap().to_string(),
+            })
+            .await;
+        assert_eq!(again.unwrap_err().code(), tonic::Code::FailedPrecondition);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let stopped = camera
+            .stop_recording(proto::StopRecordingRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(stopped.path, output.to_str().unwrap());
+    });
+
+    let verified = run(dir.path(), &["verify", output.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("OK"));
+}
+
+#[test]
+fn a_profile_picks_the_stage_that_blocks() {
+    let dir = scratch();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[profiles.obs]\ndevice = \"{DEVICE}\"\n\n[profiles.obs.pipeline]\n\
+             block-on = \"pipe\"\nqueues = {{ pipe = 16 }}\n"
+        ),
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    let planned = run(
+        dir.path(),
+        &[
+            "--config",
+            config,
+            "--profile",
+            "obs",
+            "--dry-run",
+            "serve",
+            "--pipe-sink",
+            "obs",
+        ],
+    );
+    let planned = String::from_utf8_lossy(&planned.stdout);
+    assert!(planned.contains("Blocks: on pipe"), "{planned}");
+
+    std::fs::write(
+        dir.path().join("config.toml"),
+        "[profiles.obs.pipeline]\nqueues = { capture = 0 }\n",
+    )
+    .unwrap();
+    let checked = athletic(dir.path(), &["--config", config, "config", "check"])
+        .output()
+        .unwrap();
+    assert!(!checked.status.success(), "took an empty queue");
+    assert!(String::from_utf8_lossy(&checked.stdout).contains("config.toml:2: profile obs"));
+}
+
+#[test]
+fn a_profile_picks_the_stream_a_sink_writes() {
+    let dir = scratch();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[profiles.obs]\ndevice = \"{DEVICE}\"\n\n[profiles.obs.pipeline]\n\
+             streams = {{ pipe = \"depth\" }}\n"
+        ),
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    let planned = run(
+        dir.path(),
+        &[
+            "--config",
+            config,
+            "--profile",
+            "obs",
+            "--dry-run",
+            "serve",
+            "--pipe-sink",
+            "obs",
+        ],
+    );
+    let planned = String::from_utf8_lossy(&planned.stdout);
+    assert!(
+        planned.contains("as a pipe of the depth stream"),
+        "{planned}"
+    );
+
+    std::fs::write(
+        dir.path().join("config.toml"),
+        "[profiles.obs.pipeline]\nstreams = { capture = \"depth\" }\n",
+    )
+    .unwrap();
+    let checked = athletic(dir.path(), &["--config", config, "config", "check"])
+        .output()
+        .unwrap();
+    assert!(!checked.status.success(), "took a stream for capture");
+    assert!(String::from_utf8_lossy(&checked.stdout).contains("config.toml:2: profile obs"));
+}
+
+#[test]
+fn tunes_in_the_config_are_checked_and_offered() {
+    let dir = scratch();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[tunes.stage]\nExposureAuto = 1\nGlow = \"40%\"\n\n\
+         [profiles.desk]\ntune = \"studio\"\n",
+    )
+    .unwrap();
+    let config = config.to_str().unwrap();
+    let checked = athletic(dir.path(), &["--config", config, "config", "check"])
+        .output()
+        .unwrap();
+    assert!(
+        !checked.status.success(),
+        "took an unknown control and tune"
+    );
+    let checked = String::from_utf8_lossy(&checked.stdout);
+    assert!(checked.contains("config.toml:3: tune stage: no camera control called Glow"));
+    assert!(checked.contains("config.toml:6: profile desk: no tune called studio"));
+
+    let tuned = athletic(
+        dir.path(),
+        &["--config", config, "--tune", "sepia", "snapshot", DEVICE],
+    )
+    .output()
+    .unwrap();
+    assert!(!tuned.status.success(), "took an unknown tune");
+    let tuned = String::from_utf8_lossy(&tuned.stderr);
+    assert!(
+        tuned.contains("no tune called sepia; there's stage, low-light, document, greenscreen"),
+        "{tuned}"
+    );
+}
+
+#[test]
+fn quirks_in_the_config_are_checked() {
+    let dir = scratch();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        "[quirks.\"0fd9:0066\"]\nresolution = \"3840x2160\"\nmax-frame-rate = 0\n",
+    )
+    .unwrap();
+    let checked = athletic(
+        dir.path(),
+        &["--config", config.to_str().unwrap(), "config", "check"],
+    )
+    .output()
+    .unwrap();
+    assert!(!checked.status.success(), "took a frame rate of 0");
+    let checked = String::from_utf8_lossy(&checked.stdout);
+    assert!(
+        checked.contains(
+            "config.toml:3: quirks 0fd9:0066: max-frame-rate has to be between 1 and 240, not 0"
+        ),
+        "{checked}"
+    );
+}
+
+#[test]
+fn controls_replay_sets_what_the_sidecar_logged() {
+    let dir = scratch();
+    let sidecar = dir.path().join("session.jsonl");
+    std::fs::write(
+        &sidecar,
+        "{\"control\":\"Brightness\",\"pts\":0,\"time\":0,\"value\":140,\"origin\":\"preset\"}\n\
+         {\"sequence\":0,\"pts\":0.0,\"time\":0,\"exposure\":156,\"gain\":0,\"motion\":null}\n\
+         {\"control\":\"Exposure\",\"pts\":0.1,\"time\":0,\"value\":200,\"origin\":\"auto\"}\n",
+    )
+    .unwrap();
+    let sidecar = sidecar.to_str().unwrap();
+    let replayed = run(
+        dir.path(),
+        &["controls", "replay", sidecar, DEVICE, "--at-once"],
+    );
+    let stdout = String::from_utf8_lossy(&replayed.stdout);
+    assert!(stdout.contains("Replaying 1 control changes"), "{stdout}");
+    assert!(stdout.contains("Left out 1 changes"), "{stdout}");
+    // The test pattern has no controls to set.
+    let stderr = String::from_utf8_lossy(&replayed.stderr);
+    assert!(
+        stderr.contains("failed to set Brightness to 140"),
+        "{stderr}"
+    );
+
+    std::fs::write(dir.path().join("empty.jsonl"), "").unwrap();
+    let empty = dir.path().join("empty.jsonl");
+    let refused = athletic(
+        dir.path(),
+        &["controls", "replay", empty.to_str().unwrap(), DEVICE],
+    )
+    .output()
+    .unwrap();
+    assert!(!refused.status.success(), "replayed nothing");
+}
+
+#[test]
+fn repl_runs_one_command_after_another() {
+    let dir = scratch();
+    let mut repl = athletic(dir.path(), &["repl"])
+        .current_dir(dir.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let lines = format!(
+        "open {DEVICE}\n\
+         snapshot -o first.png\n\
+         snapshot --tune document -o tuned.png\n\
+         snapshot 'no such camera' -o missing.png\n\
+         snapshot -o second.png\n\
+         quit\n"
+    );
+    std::io::Write::write_all(repl.stdin.as_mut().unwrap(), lines.as_bytes()).unwrap();
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    assert!(dir.path().join("first.png").exists());
+    assert!(
+        dir.path().join("second.png").exists(),
+        "a failed command ended the prompt"
+    );
+    assert!(!dir.path().join("tuned.png").exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--tune holds for the whole session"),
+        "{stderr}"
+    );
+    // A command that fails says why and ends, rather than panicking.
+    assert!(stderr.contains("(exited with 1)"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn run_goes_through_a_script() {
+    let dir = scratch();
+    let script = dir.path().join("check.athletic");
+    std::fs::write(
+        &script,
+        format!(
+            "# Two shots and a clip.\n\
+             let camera = {DEVICE}\n\
+             let name = first\n\
+             snapshot $camera -o ${{name}}.png\n\
+             wait 100ms\n\
+             echo halfway\n\
+             record $camera -o clip.mkv --duration 1s\n"
+        ),
+    )
+    .unwrap();
+    let script = script.to_str().unwrap();
+    let output = athletic(dir.path(), &["run", script, "name=given"])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("halfway"));
+    assert!(dir.path().join("given.png").exists());
+    assert!(!dir.path().join("first.png").exists());
+    run(
+        dir.path(),
+        &["verify", dir.path().join("clip.mkv").to_str().unwrap()],
+    );
+
+    // The test pattern has no controls, so the script stops there.
+    let stops = dir.path().join("stops.athletic");
+    std::fs::write(
+        &stops,
+        format!(
+            "controls set {DEVICE} -c Brightness=140\n\
+             snapshot {DEVICE} -o after.png\n"
+        ),
+    )
+    .unwrap();
+    let stopped = athletic(dir.path(), &["run", stops.to_str().unwrap()])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(!stopped.status.success());
+    let stderr = String::from_utf8_lossy(&stopped.stderr);
+    assert!(stderr.contains("stops.athletic:1:"), "{stderr}");
+    assert!(!dir.path().join("after.png").exists());
+
+    // A mistake anywhere stops it before it starts.
+    let mistaken = dir.path().join("mistaken.athletic");
+    std::fs::write(
+        &mistaken,
+        format!(
+            "snapshot {DEVICE} -o before.png\n\
+             snapshot $nowhere -o never.png\n"
+        ),
+    )
+    .unwrap();
+    let refused = athletic(dir.path(), &["run", mistaken.to_str().unwrap()])
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+    assert!(!refused.status.success());
+    assert!(!dir.path().join("before.png").exists());
+}
+
+#[test]
+fn selftest_passes_the_test_pattern() {
+    let dir = scratch();
+    let output = run(dir.path(), &["selftest", DEVICE]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for check in [
+        "opens",
+        "picture isn't black",
+        "picture changes",
+        "streams 640x480@30:YUYV",
+    ] {
+        assert!(stdout.contains(&format!("pass  {check}")), "{stdout}");
+    }
+    // It has no controls to swing.
+    assert!(
+        stdout.contains("skip  picture follows Brightness"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn analyze_dead_pixels_writes_a_map_for_pixel_map() {
+    let dir = scratch();
+    let map = dir.path().join("map.json");
+    let map = map.to_str().unwrap();
+    let output = run(
+        dir.path(),
+        &["analyze", "dead-pixels", DEVICE, "--frames", "2", "-o", map],
+    );
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Found 0 pixels"),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let written: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(map).unwrap()).unwrap();
+    assert_eq!(written["width"], 640);
+    assert_eq!(written["height"], 480);
+
+    let shot = dir.path().join("shot.png");
+    run(
+        dir.path(),
+        &[
+            "--pixel-map",
+            map,
+            "snapshot",
+            DEVICE,
+            "-o",
+            shot.to_str().unwrap(),
+        ],
+    );
+    assert!(shot.exists());
+}
+
+#[test]
+fn install_service_writes_a_unit_for_the_profile() {
+    let dir = scratch();
+    std::fs::create_dir(dir.path().join("athletic")).unwrap();
+    std::fs::write(
+        dir.path().join("athletic").join("config.toml"),
+        format!("[profiles.cctv]\ndevice = \"{DEVICE}\"\n"),
+    )
+    .unwrap();
+    let args = [
+        "--profile",
+        "cctv",
+        "install-service",
+        "record",
+        "-o",
+        "front door.mkv",
+    ];
+    run(dir.path(), &args);
+
+    let unit = dir.path().join("systemd/user/athletic-cctv.service");
+    let unit = std::fs::read_to_string(unit).unwrap();
+    assert!(unit.contains("\nType=notify\n"), "{unit}");
+    assert!(unit.contains("\nWatchdogSec=30s\n"), "{unit}");
+    assert!(
+        unit.contains(r#" --profile cctv record -o "front door.mkv""#),
+        "{unit}"
+    );
+    let again = athletic(dir.path(), &args).output().unwrap();
+    assert!(!again.status.success(), "replaced the unit without --force");
+
+    let preview = athletic(dir.path(), &["install-service", "preview"])
+        .output()
+        .unwrap();
+    assert!(!preview.status.success(), "installed preview as a service");
+}
+
+#[cfg(unix)]
+#[test]
+fn record_tells_systemd_it_is_ready_and_alive() {
+    use std::os::unix::net::UnixDatagram;
+
+    let dir = scratch();
+    let socket = dir.path().join("notify");
+    let systemd = UnixDatagram::bind(&socket).unwrap();
+    let output = dir.path().join("out.mkv");
+    let recorded = athletic(
+        dir.path(),
+        &[
+            "record",
+            DEVICE,
+            "-o",
+            output.to_str().unwrap(),
+            "--duration",
+            "2s",
+            "--progress",
+            "off",
+        ],
+    )
+    .env("NOTIFY_SOCKET", &socket)
+    .env("WATCHDOG_USEC", "200000")
+    .output()
+    .unwrap();
+    assert!(recorded.status.success());
+
+    systemd.set_nonblocking(true).unwrap();
+    let mut said = Vec::new();
+    let mut message = [0; 64];
+    while let Ok(length) = systemd.recv(&mut message) {
+        said.push(String::from_utf8_lossy(&message[..length]).into_owned());
+    }
+    assert_eq!(
+        said.first().map(String::as_str),
+        Some("READY=1"),
+        "{said:?}"
+    );
+    assert!(said.iter().any(|state| state == "WATCHDOG=1"), "{said:?}");
+}
+
+#[test]
+fn serve_follows_changes_to_its_profile() {
+    let dir = scratch();
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, "[profiles.desk]\n").unwrap();
+    let address = free_address();
+    let mut server = Running::start(
+        dir.path(),
+        &[
+            "--config",
+            config.to_str().unwrap(),
+            "--profile",
+            "desk",
+            "serve",
+            DEVICE,
+            "--bind",
+            &address.to_string(),
+        ],
+    );
+    let brightest = |jpeg: &[u8]| {
+        let frame = image::load_from_memory(jpeg).unwrap().to_luma8();
+        frame.pixels().map(|pixel| pixel[0]).max().unwrap()
+    };
+    let (_, jpeg) = first_part(server.connect(address), "/");
+    assert!(brightest(&jpeg) > 200);
+
+    std::fs::write(&config, "[profiles.desk]\nmasks = [\"0,0,640,480\"]\n").unwrap();
+    let start = Instant::now();
+    loop {
+        let (_, jpeg) = first_part(server.connect(address), "/");
+        if brightest(&jpeg) < 16 {
+            break;
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "the mask never applied"
+        );
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn serve_scripts_record_on_events() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = scratch();
+    let scripts = dir.path().join("athletic").join("scripts");
+    std::fs::create_dir_all(&scripts).unwrap();
+    let output = dir.path().join("scripted.mkv");
+    std::fs::write(
+        scripts.join("record.rhai"),
+        format!(
+            r#"
+            fn on_event(event) {{
+                if event.type != "stats" {{
+                    return;
+                }}
+                if event.pts < 2.0 {{
+                    start_recording("{}");
+                }} else if event.pts > 3.0 {{
+                    stop_recording();
+                }}
+            }}
+            "#,
+            output.display()
+        ),
+    )
+    .unwrap();
+    // Not a script, so it's left alone.
+    std::fs::write(scripts.join("notes.txt"), "not rhai").unwrap();
+
+    let address = free_address();
+    let mut server = athletic(
+        dir.path(),
+        &[
+            "serve",
+            DEVICE,
+            "--bind",
+            &address.to_string(),
+            "--scripts",
+            "--publish",
+            "stdout",
+        ],
+    )
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+    let start = Instant::now();
+    let lines = BufReader::new(server.stdout.take().unwrap()).lines();
+    let mut finished = false;
+    for line in lines {
+        let line = line.unwrap();
+        if line.contains(r#""type":"recording","active":false"#) {
+            finished = true;
+            break;
+        }
+        assert!(start.elapsed() < Duration::from_secs(20), "never stopped");
+    }
+    assert!(finished, "serve exited before the recording finished");
+    // The manifest is written once the recording has been reported done.
+    let manifest = dir.path().join("scripted.mkv.manifest.json");
+    while !manifest.exists() {
+        assert!(start.elapsed() < Duration::from_secs(20), "no manifest");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let _ = server.kill();
+    let _ = server.wait();
+    let verified = run(dir.path(), &["verify", output.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("OK"));
+}
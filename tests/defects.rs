@@ -0,0 +1,82 @@
+use athletic::defects::{find, Defect, Kind, Levels, PixelMap};
+
+const WIDTH: u32 = 24;
+const HEIGHT: u32 = 16;
+
+/// Averaged frames of `level`, but with `set` pixels at their own.
+fn levels(level: impl Fn(u32, u32) -> u8, set: &[(u32, u32, u8)]) -> Levels {
+    let mut rgb = Vec::new();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let level = set
+                .iter()
+                .find(|&&(sx, sy, _)| (sx, sy) == (x, y))
+                .map_or(level(x, y), |&(_, _, level)| level);
+            rgb.extend([level; 3]);
+        }
+    }
+    let mut levels = Levels::new(WIDTH, HEIGHT);
+    levels.add(&rgb).unwrap();
+    levels
+}
+
+#[test]
+fn strays_are_found_but_not_edges() {
+    let dark = levels(
+        |_, _| 12,
+        &[(5, 5, 200), (10, 10, 180), (11, 10, 190), (6, 12, 90)],
+    );
+    // Something lit on the left and in shadow on the right, with a darker
+    // edge down the right-hand side of the frame.
+    let lit = |x: u32, _| match x {
+        0..=11 => 210,
+        x if x == WIDTH - 1 => 60,
+        _ => 120,
+    };
+    let bright = levels(lit, &[(3, 7, 20), (6, 12, 90)]);
+
+    let found = find(&dark, &bright, 48.0).unwrap();
+    let at = |x, y, kind| Defect { x, y, kind };
+    assert_eq!(
+        found,
+        [
+            at(5, 5, Kind::Hot),
+            at(3, 7, Kind::Dead),
+            at(10, 10, Kind::Hot),
+            at(11, 10, Kind::Hot),
+            at(6, 12, Kind::Stuck),
+        ]
+    );
+}
+
+#[test]
+fn a_map_fills_its_pixels_in_from_those_around_them() {
+    let map = PixelMap {
+        width: 3,
+        height: 3,
+        defects: vec![
+            Defect {
+                x: 1,
+                y: 1,
+                kind: Kind::Hot,
+            },
+            Defect {
+                x: 2,
+                y: 1,
+                kind: Kind::Hot,
+            },
+        ],
+    };
+    // Gray, with a fourth channel left alone.
+    #[rustfmt::skip]
+    let mut pixels = vec![
+        10, 10, 10, 1,   20, 20, 20, 1,   30, 30, 30, 1,
+        40, 40, 40, 1,  255, 255, 255, 1, 255, 255, 255, 1,
+        50, 50, 50, 1,   60, 60, 60, 1,   70, 70, 70, 1,
+    ];
+    map.correct(&mut pixels, 3, 3, 4);
+    // Each from the six around it that aren't on the map.
+    assert_eq!(pixels[16..20], [40, 40, 40, 1]);
+    // And this from the four.
+    assert_eq!(pixels[20..24], [45, 45, 45, 1]);
+}
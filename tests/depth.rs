@@ -0,0 +1,26 @@
+use athletic::depth::{self, DepthPlane};
+use nokhwa::utils::CameraIndex;
+
+#[test]
+fn depth_is_coloured_from_near_to_far() {
+    let plane = DepthPlane {
+        width: 3,
+        height: 1,
+        samples: vec![0, 1, 4000],
+        scale: 0.001,
+    };
+    assert_eq!(plane.metres(0, 0), None);
+    assert_eq!(plane.metres(2, 0), Some(4.0));
+    let mut rgba = vec![0; 12];
+    plane.colorize(4.0, &mut rgba);
+    assert_eq!(&rgba[0..4], &[0, 0, 0, 255]);
+    assert_eq!(&rgba[4..8], &[255, 0, 0, 255]);
+    assert_eq!(&rgba[8..12], &[0, 0, 255, 255]);
+    assert_eq!(plane.image().unwrap().get_pixel(2, 0).0, [4000]);
+}
+
+#[test]
+fn realsense_is_a_depth_device() {
+    assert!(depth::is_depth(&CameraIndex::String("realsense".into())));
+    assert!(!depth::is_depth(&CameraIndex::Index(0)));
+}
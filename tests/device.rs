@@ -0,0 +1,82 @@
+use athletic::device::find;
+use nokhwa::utils::{CameraIndex, CameraInfo};
+
+fn cameras() -> Vec<CameraInfo> {
+    [
+        "HD Pro Webcam C920",
+        "Integrated Camera",
+        "Integrated IR Camera",
+    ]
+    .iter()
+    .enumerate()
+    .map(|(index, name)| CameraInfo::new(name, "", "", CameraIndex::Index(index as u32)))
+    .collect()
+}
+
+#[test]
+fn a_part_of_one_name_finds_that_camera() {
+    for name in ["c920", "HD Pro", "webcam c9"] {
+        assert_eq!(find(name, &cameras()).unwrap(), CameraIndex::Index(0));
+    }
+}
+
+#[test]
+fn a_whole_name_wins_over_parts_of_others() {
+    let mut cameras = cameras();
+    cameras.push(CameraInfo::new("Integrated", "", "", CameraIndex::Index(3)));
+    assert_eq!(find("Integrated", &cameras).unwrap(), CameraIndex::Index(3));
+}
+
+#[test]
+fn a_part_of_several_names_lists_them() {
+    let why = find("integrated", &cameras()).unwrap_err().to_string();
+    assert!(why.contains("1: Integrated Camera"), "{why}");
+    assert!(why.contains("2: Integrated IR Camera"), "{why}");
+    assert!(!why.contains("C920"), "{why}");
+}
+
+#[test]
+fn a_name_nothing_has_lists_every_camera() {
+    let why = find("Brio", &cameras()).unwrap_err().to_string();
+    for camera in cameras() {
+        assert!(why.contains(&camera.human_name()), "{why}");
+    }
+    assert!(find("Brio", &[]).is_err());
+}
+
+#[test]
+fn string_indices_are_tried_as_they_are() {
+    let cameras = [CameraInfo::new(
+        "FaceTime HD Camera",
+        "",
+        "",
+        CameraIndex::String("0x8020000005ac8514".to_string()),
+    )];
+    for (name, index) in [
+        ("facetime", "0x8020000005ac8514"),
+        ("0x8020000005ac8514", "0x8020000005ac8514"),
+        ("rtsp://camera.local/stream", "rtsp://camera.local/stream"),
+    ] {
+        assert_eq!(
+            find(name, &cameras).unwrap(),
+            CameraIndex::String(index.to_string())
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn links_lead_to_their_video_node() {
+    use athletic::device::video_node;
+
+    let dir = tempfile::tempdir().unwrap();
+    let node = dir.path().join("video7");
+    std::fs::write(&node, "").unwrap();
+    let by_id = dir.path().join("usb-046d_HD_Pro_Webcam_C920-video-index0");
+    std::os::unix::fs::symlink(&node, &by_id).unwrap();
+
+    assert_eq!(video_node(&node).unwrap(), 7);
+    assert_eq!(video_node(&by_id).unwrap(), 7);
+    assert!(video_node(dir.path()).is_err());
+    assert!(video_node(&dir.path().join("video8")).is_err());
+}
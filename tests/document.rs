@@ -0,0 +1,68 @@
+use athletic::document;
+use image::{GrayImage, Luma, RgbImage};
+
+/// A white page with its corners at `corners`, on a dark desk.
+fn page_on_desk(corners: [(f32, f32); 4]) -> RgbImage {
+    let inside = |x: f32, y: f32| {
+        (0..4).all(|n| {
+            let (a, b) = (corners[n], corners[(n + 1) % 4]);
+            (b.0 - a.0) * (y - a.1) - (b.1 - a.1) * (x - a.0) >= 0.0
+        })
+    };
+    RgbImage::from_fn(640, 480, |x, y| match inside(x as f32, y as f32) {
+        true => image::Rgb([235, 235, 228]),
+        false => image::Rgb([50, 40, 35]),
+    })
+}
+
+#[test]
+fn a_page_is_found_by_its_corners() {
+    let corners = [(140.0, 60.0), (520.0, 90.0), (490.0, 430.0), (110.0, 400.0)];
+    let found = document::find_page(&page_on_desk(corners)).unwrap();
+    for (found, expected) in found.iter().zip(corners) {
+        let distance = ((found.0 - expected.0).powi(2) + (found.1 - expected.1).powi(2)).sqrt();
+        assert!(distance < 8.0, "found {found:?} for {expected:?}");
+    }
+}
+
+#[test]
+fn scraps_are_too_small_to_be_pages() {
+    let scrap = [
+        (300.0, 200.0),
+        (360.0, 200.0),
+        (360.0, 250.0),
+        (300.0, 250.0),
+    ];
+    assert_eq!(document::find_page(&page_on_desk(scrap)), None);
+    assert_eq!(document::find_page(&RgbImage::new(0, 0)), None);
+}
+
+#[test]
+fn flattening_straightens_the_page() {
+    let corners = [(140.0, 60.0), (520.0, 90.0), (490.0, 430.0), (110.0, 400.0)];
+    let page = document::flatten(&page_on_desk(corners), &corners);
+    let (width, height) = page.dimensions();
+    assert!((380..=384).contains(&width), "{width} wide");
+    assert!((340..=344).contains(&height), "{height} high");
+    // All page, no desk, short of the edges' antialiasing.
+    for (x, y, pixel) in page.enumerate_pixels() {
+        if x > 2 && y > 2 && x < width - 3 && y < height - 3 {
+            assert!(pixel[0] > 200, "desk at {x},{y}");
+        }
+    }
+}
+
+#[test]
+fn thresholding_keeps_text_through_a_shadow() {
+    // Grey text on a page that falls from bright to shadowed.
+    let page = GrayImage::from_fn(200, 100, |x, y| {
+        let paper = 240 - x as u8 / 2;
+        let text = (20..80).contains(&y) && x % 20 < 4;
+        Luma([if text { paper / 2 } else { paper }])
+    });
+    let clean = document::threshold(&page);
+    for (x, y, pixel) in clean.enumerate_pixels() {
+        let text = (20..80).contains(&y) && x % 20 < 4;
+        assert_eq!(pixel[0], if text { 0 } else { 255 }, "at {x},{y}");
+    }
+}
@@ -0,0 +1,66 @@
+use athletic::capture;
+use athletic::drift::Drift;
+use athletic::pattern::DEVICE;
+use nokhwa::utils::CameraIndex;
+use std::time::Duration;
+
+/// Where a microphone whose clock runs `rate` times as fast as the
+/// camera's, and that lags it by 20ms, has got to by `pts`, in the 10ms
+/// blocks it delivers in.
+fn sound(pts: Duration, rate: f64) -> Duration {
+    let heard = (pts.as_secs_f64() * rate - 0.02).max(0.0);
+    Duration::from_millis((heard * 100.0).floor() as u64 * 10)
+}
+
+/// The times every frame went into the file at.
+fn corrected(drift: &mut Drift, frames: &[Duration], rate: f64) -> Vec<Duration> {
+    frames
+        .iter()
+        .flat_map(|&pts| drift.correct(pts, sound(pts, rate)))
+        .collect()
+}
+
+#[test]
+fn frames_are_repeated_to_keep_up_with_sound_that_runs_fast() {
+    let capture = capture::spawn_capture(CameraIndex::String(DEVICE.to_string())).unwrap();
+    let frames: Vec<Duration> = capture
+        .frames
+        .iter()
+        .map(|frame| frame.pts)
+        .take_while(|&pts| pts < Duration::from_secs(3))
+        .collect();
+    let fps = capture.format.frame_rate() as f64;
+    let mut drift = Drift::new(fps);
+    let written = corrected(&mut drift, &frames, 1.06);
+
+    // Two seconds after the clocks settle, the sound is 120ms ahead: three
+    // frames' worth and a bit.
+    assert!((drift.last - 0.12).abs() < 1.0 / fps, "{}", drift.last);
+    assert_eq!(drift.widest, drift.last);
+    assert_eq!((drift.repeated, drift.dropped), (3, 0));
+    assert_eq!(written.len(), frames.len() + 3);
+    assert!(written.windows(2).all(|pair| pair[0] < pair[1]));
+    let behind = drift.last - (*written.last().unwrap() - *frames.last().unwrap()).as_secs_f64();
+    assert!(behind.abs() < 1.0 / fps, "{behind}");
+}
+
+#[test]
+fn frames_are_dropped_to_wait_for_sound_that_runs_slow() {
+    let frames: Vec<Duration> = (0..300).map(|n| Duration::from_millis(n * 40)).collect();
+    let mut drift = Drift::new(25.0);
+    let written = corrected(&mut drift, &frames, 0.99);
+
+    // 110ms behind by the end, 11 seconds after settling.
+    assert!((drift.last + 0.11).abs() < 0.04, "{}", drift.last);
+    assert_eq!((drift.repeated, drift.dropped), (0, 2));
+    assert_eq!(written.len(), frames.len() - 2);
+    assert!(written.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn sound_that_keeps_time_changes_nothing() {
+    let frames: Vec<Duration> = (0..300).map(|n| Duration::from_millis(n * 40)).collect();
+    let mut drift = Drift::new(25.0);
+    assert_eq!(corrected(&mut drift, &frames, 1.0), frames);
+    assert!(drift.widest.abs() < 0.01, "{}", drift.widest);
+}
@@ -0,0 +1,81 @@
+use age::secrecy::ExposeSecret;
+use athletic::encrypt::{self, Encryption};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[test]
+fn recordings_decrypt_with_the_recipients_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let identity = age::x25519::Identity::generate();
+    let identity_file = dir.path().join("key.txt");
+    std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+    let recipients = dir.path().join("recipients.txt");
+    let recipient = identity.to_public();
+    std::fs::write(&recipients, format!("# the lab\n\n{recipient}\n")).unwrap();
+
+    let recording: Vec<u8> = (0..200_000u32).map(|n| (n * 7 % 251) as u8).collect();
+    let encrypted = dir.path().join("take.mkv.age");
+    let encryption: Encryption = format!("age:{}", recipients.display()).parse().unwrap();
+    let mut out = encryption
+        .wrap(BufWriter::new(File::create(&encrypted).unwrap()))
+        .unwrap();
+    for chunk in recording.chunks(4096) {
+        out.write_all(chunk).unwrap();
+    }
+    out.finish().unwrap();
+    assert_ne!(std::fs::read(&encrypted).unwrap()[..32], recording[..32]);
+
+    let decrypted = dir.path().join("take.mkv");
+    encrypt::decrypt(&encrypted, &identity_file, &decrypted).unwrap();
+    assert_eq!(std::fs::read(&decrypted).unwrap(), recording);
+
+    let stranger = dir.path().join("stranger.txt");
+    let other = age::x25519::Identity::generate();
+    std::fs::write(&stranger, other.to_string().expose_secret()).unwrap();
+    assert!(encrypt::decrypt(&encrypted, &stranger, &decrypted).is_err());
+}
+
+#[test]
+fn an_unfinished_recording_decrypts_up_to_its_last_whole_chunk() {
+    let dir = tempfile::tempdir().unwrap();
+    let identity = age::x25519::Identity::generate();
+    let identity_file = dir.path().join("key.txt");
+    std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+    let recipients = dir.path().join("recipients.txt");
+    std::fs::write(&recipients, format!("{}\n", identity.to_public())).unwrap();
+
+    let recording: Vec<u8> = (0..300_000u32).map(|n| (n * 7 % 251) as u8).collect();
+    let encrypted = dir.path().join("take.mkv.age");
+    let mut out = Encryption::Age(recipients)
+        .wrap(BufWriter::new(File::create(&encrypted).unwrap()))
+        .unwrap();
+    out.write_all(&recording).unwrap();
+    out.flush().unwrap();
+    // As a crash would leave it, without the end of the stream.
+    drop(out);
+    assert!(encrypt::is_encrypted(&encrypted).unwrap());
+
+    let decrypted = dir.path().join("take.mkv");
+    assert!(encrypt::decrypt(&encrypted, &identity_file, &decrypted).is_err());
+    assert_eq!(
+        std::fs::read(&decrypted).unwrap(),
+        recording[..4 * 64 * 1024]
+    );
+    assert!(!encrypt::is_encrypted(&decrypted).unwrap());
+}
+
+#[test]
+fn recipients_files_must_name_someone() {
+    let dir = tempfile::tempdir().unwrap();
+    let recipients = dir.path().join("recipients.txt");
+    std::fs::write(&recipients, "# nobody yet\n").unwrap();
+    let encryption = Encryption::Age(recipients.clone());
+    let out = || BufWriter::new(File::create(dir.path().join("out")).unwrap());
+    assert!(encryption.wrap(out()).is_err());
+
+    std::fs::write(&recipients, "age1notakey\n").unwrap();
+    assert!(encryption.wrap(out()).is_err());
+
+    assert!("age:".parse::<Encryption>().is_err());
+    assert!("gpg:keys.txt".parse::<Encryption>().is_err());
+}
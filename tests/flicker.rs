@@ -0,0 +1,24 @@
+use athletic::flicker::AntiFlicker;
+
+#[test]
+fn regions_give_their_mains_frequency() {
+    assert_eq!(AntiFlicker::of_region("US"), Some(AntiFlicker::Hz60));
+    assert_eq!(AntiFlicker::of_region("gb"), Some(AntiFlicker::Hz50));
+    assert_eq!(AntiFlicker::of_region("JP"), Some(AntiFlicker::Auto));
+    assert_eq!(AntiFlicker::of_region("USA"), None);
+    assert_eq!(AntiFlicker::of_region("4K"), None);
+}
+
+#[test]
+fn settings_read_back_as_written() {
+    for setting in [
+        AntiFlicker::Off,
+        AntiFlicker::Hz50,
+        AntiFlicker::Hz60,
+        AntiFlicker::Auto,
+    ] {
+        assert_eq!(setting.to_string().parse::<AntiFlicker>().unwrap(), setting);
+    }
+    assert_eq!("60Hz".parse::<AntiFlicker>().unwrap(), AntiFlicker::Hz60);
+    assert!("55hz".parse::<AntiFlicker>().is_err());
+}
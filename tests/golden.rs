@@ -0,0 +1,168 @@
+//! Decodes and filters the sample frames in tests/golden and checks the
+//! results against the reference PNGs beside them, so a colour conversion
+//! that swaps U and V or misreads a stride fails a test rather than
+//! someone's eyes.
+//!
+//! The samples are one small scene, of a hue sweep, colour bars and a grey
+//! ramp, in each format, plus the same YUYV frame with padded rows and a
+//! dim copy for the low light filter.
+//!
+//! When a change to the output is meant, run the suite with
+//! `ATHLETIC_BLESS=1` to write new references, and look at them before
+//! checking them in.
+
+use athletic::layout;
+use athletic::low_light::{LowLight, LowLightOptions};
+use athletic::mask::{self, Mask, Style};
+use athletic::pixel_format::RgbFormat;
+use athletic::upscale::{Method, Upscale};
+use image::RgbImage;
+use nokhwa::utils::{FrameFormat, Resolution};
+use nokhwa::Buffer;
+use std::path::PathBuf;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// How far a channel may stray from the reference. Decoding YUV is exact
+/// arithmetic give or take rounding; JPEG decoders are allowed to differ
+/// a little more, by the IDCT they use.
+const RAW_TOLERANCE: u8 = 2;
+const JPEG_TOLERANCE: u8 = 6;
+
+fn path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect()
+}
+
+/// Decodes the sample frame `name` as the capture pipeline does: packed
+/// first, then converted to RGB.
+fn decode(name: &str, format: FrameFormat) -> RgbImage {
+    let data = std::fs::read(path(name)).unwrap();
+    let buffer = layout::pack(Buffer::new(Resolution::new(WIDTH, HEIGHT), &data, format));
+    buffer.decode_image::<RgbFormat>().unwrap()
+}
+
+/// Checks `image` against the reference `name`, or writes it there when
+/// blessing.
+fn check(image: &RgbImage, name: &str, tolerance: u8) {
+    let reference = path(name);
+    if std::env::var_os("ATHLETIC_BLESS").is_some() {
+        image.save(&reference).unwrap();
+        return;
+    }
+    let expected = image::open(&reference)
+        .unwrap_or_else(|why| panic!("can't open {}: {why}", reference.display()))
+        .into_rgb8();
+    assert_eq!(image.dimensions(), expected.dimensions(), "{name}");
+    let mut worst = (0, 0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let off = pixel
+            .0
+            .iter()
+            .zip(expected.get_pixel(x, y).0)
+            .map(|(&got, wanted)| got.abs_diff(wanted))
+            .max()
+            .unwrap();
+        if off > worst.2 {
+            worst = (x, y, off);
+        }
+    }
+    let (x, y, off) = worst;
+    assert!(
+        off <= tolerance,
+        "{name}: pixel {x},{y} is {:?}, the reference has {:?}",
+        image.get_pixel(x, y).0,
+        expected.get_pixel(x, y).0
+    );
+}
+
+#[test]
+fn yuyv_decodes() {
+    check(
+        &decode("scene.yuyv", FrameFormat::YUYV),
+        "decode-yuyv.png",
+        RAW_TOLERANCE,
+    );
+}
+
+#[test]
+fn padded_yuyv_decodes_like_packed() {
+    check(
+        &decode("scene-padded.yuyv", FrameFormat::YUYV),
+        "decode-yuyv.png",
+        RAW_TOLERANCE,
+    );
+}
+
+#[test]
+fn nv12_decodes() {
+    check(
+        &decode("scene.nv12", FrameFormat::NV12),
+        "decode-nv12.png",
+        RAW_TOLERANCE,
+    );
+}
+
+#[test]
+fn mjpeg_decodes() {
+    check(
+        &decode("scene.jpg", FrameFormat::MJPEG),
+        "decode-mjpeg.png",
+        JPEG_TOLERANCE,
+    );
+}
+
+#[test]
+fn masks_black_out_and_pixelate() {
+    let mut image = decode("scene.yuyv", FrameFormat::YUYV);
+    let masks = [
+        Mask {
+            x: 4,
+            y: 4,
+            width: 16,
+            height: 12,
+            style: Style::Black,
+        },
+        Mask {
+            x: 24,
+            y: 8,
+            width: 36,
+            height: 32,
+            style: Style::Pixelate,
+        },
+    ];
+    mask::apply(&masks, &mut image, WIDTH, HEIGHT, 3);
+    check(&image, "mask.png", RAW_TOLERANCE);
+}
+
+#[test]
+fn low_light_brightens() {
+    let frame = decode("dark.yuyv", FrameFormat::YUYV);
+    let mut low_light = LowLight::new(&LowLightOptions {
+        low_light: true,
+        max_gain: 4.0,
+    })
+    .unwrap();
+    // The gain eases in over frames, so it's given a couple of seconds'
+    // worth of the same one to settle.
+    let mut image = frame.clone();
+    for _ in 0..60 {
+        image = frame.clone();
+        low_light.apply(&mut image, WIDTH, HEIGHT, 3);
+    }
+    let sum = |image: &RgbImage| image.iter().map(|&value| value as u64).sum::<u64>();
+    assert!(sum(&image) > sum(&frame) * 3 / 2, "barely brightened");
+    check(&image, "low-light.png", RAW_TOLERANCE);
+}
+
+#[test]
+fn upscale_doubles() {
+    let frame = decode("scene.yuyv", FrameFormat::YUYV);
+    let upscale = Upscale {
+        factor: 2.0,
+        method: Method::Bilinear,
+    };
+    check(&upscale.apply(&frame), "upscale.png", RAW_TOLERANCE);
+}
@@ -0,0 +1,129 @@
+use athletic::layout::{self, FrameLayout, Layout};
+use athletic::pixel_format::RgbAFormat;
+use nokhwa::{
+    pixel_format::RgbFormat,
+    utils::{CameraFormat, FrameFormat, Resolution},
+    Buffer,
+};
+
+/// Builds a frame of `rows` rows of `row` bytes each, padded out to
+/// `stride` with bytes that would show up as garbage if they were read as
+/// pixels.
+fn padded(row: &[u8], rows: usize, stride: usize) -> Vec<u8> {
+    (0..rows)
+        .flat_map(|_| {
+            let mut line = row.to_vec();
+            line.resize(stride, 0xAA);
+            line
+        })
+        .collect()
+}
+
+#[test]
+fn padded_yuyv_is_packed() {
+    let resolution = Resolution::new(6, 4);
+    let row = [16, 128, 16, 128].repeat(3);
+    let buffer = Buffer::new(resolution, &padded(&row, 4, 16), FrameFormat::YUYV);
+    let packed = layout::pack(buffer);
+    assert_eq!(packed.buffer(), row.repeat(4).as_slice());
+}
+
+#[test]
+fn padded_yuyv_decodes_like_packed() {
+    let resolution = Resolution::new(6, 4);
+    let row = [81, 90, 81, 240].repeat(3);
+    let tight = Buffer::new(resolution, &row.repeat(4), FrameFormat::YUYV);
+    let loose = Buffer::new(resolution, &padded(&row, 4, 32), FrameFormat::YUYV);
+    assert_eq!(
+        layout::pack(loose).decode_image::<RgbFormat>().unwrap(),
+        tight.decode_image::<RgbFormat>().unwrap()
+    );
+}
+
+#[test]
+fn padded_nv12_keeps_both_planes() {
+    let resolution = Resolution::new(4, 4);
+    let luma = [1, 2, 3, 4];
+    let chroma = [5, 6, 7, 8];
+    let mut data = padded(&luma, 4, 8);
+    data.extend(padded(&chroma, 2, 8));
+    let packed = layout::pack(Buffer::new(resolution, &data, FrameFormat::NV12));
+    let mut expected = luma.repeat(4);
+    expected.extend(chroma.repeat(2));
+    assert_eq!(packed.buffer(), expected.as_slice());
+}
+
+#[test]
+fn grayscale_depth_survives_packing() {
+    let resolution = Resolution::new(5, 3);
+    let narrow = Layout::of(FrameFormat::GRAY, resolution, 8 * 3).unwrap();
+    assert_eq!((narrow.row, narrow.stride), (5, 8));
+    let wide = Layout::of(FrameFormat::GRAY, resolution, 12 * 3).unwrap();
+    assert_eq!((wide.row, wide.stride), (10, 12));
+}
+
+#[test]
+fn packed_and_compressed_buffers_are_untouched() {
+    let resolution = Resolution::new(2, 2);
+    let rgb = [9; 12];
+    let packed = layout::pack(Buffer::new(resolution, &rgb, FrameFormat::RAWRGB));
+    assert_eq!(packed.buffer(), &rgb);
+    assert!(Layout::of(FrameFormat::RAWRGB, resolution, 12)
+        .unwrap()
+        .is_packed());
+
+    let jpeg = [0xFF, 0xD8, 0xFF, 0xD9, 0x00];
+    let mjpeg = layout::pack(Buffer::new(resolution, &jpeg, FrameFormat::MJPEG));
+    assert_eq!(mjpeg.buffer(), &jpeg);
+}
+
+#[test]
+fn buffers_that_are_not_whole_rows_are_left_alone() {
+    let resolution = Resolution::new(4, 3);
+    assert_eq!(Layout::of(FrameFormat::YUYV, resolution, 25), None);
+    let odd = [0; 25];
+    let buffer = layout::pack(Buffer::new(resolution, &odd, FrameFormat::YUYV));
+    assert_eq!(buffer.buffer().len(), 25);
+}
+
+#[test]
+fn decoded_frames_fit_their_layout_in_every_format() {
+    for (width, height) in [(2, 2), (6, 4), (64, 48), (320, 240), (1280, 720)] {
+        let resolution = Resolution::new(width, height);
+        let pixels = (width * height) as usize;
+        for (format, length) in [
+            (FrameFormat::YUYV, pixels * 2),
+            (FrameFormat::NV12, pixels * 3 / 2),
+            (FrameFormat::GRAY, pixels),
+            (FrameFormat::RAWRGB, pixels * 3),
+        ] {
+            let buffer = Buffer::new(resolution, &vec![100; length], format);
+            let rgb = FrameLayout::decoded::<RgbFormat>(resolution);
+            let rgba = FrameLayout::decoded::<RgbAFormat>(resolution);
+            assert_eq!((rgb.bytes(), rgba.bytes()), (pixels * 3, pixels * 4));
+            let mut out = vec![0; rgba.bytes()];
+            buffer
+                .decode_image_to_buffer::<RgbAFormat>(&mut out)
+                .unwrap_or_else(|why| panic!("{format} at {resolution}: {why}"));
+        }
+    }
+}
+
+// An MJPEG frame is far smaller than what it decodes to, so its size says
+// nothing about the buffer it needs.
+#[test]
+fn mjpeg_fits_the_layout_of_its_format() {
+    let jpeg = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/golden/scene.jpg"
+    ))
+    .unwrap();
+    let format = CameraFormat::new(Resolution::new(64, 48), FrameFormat::MJPEG, 30);
+    let layout = FrameLayout::of::<RgbAFormat>(&format);
+    assert_eq!((layout.row(), layout.bytes()), (64 * 4, 64 * 48 * 4));
+    let buffer = Buffer::new(format.resolution(), &jpeg, FrameFormat::MJPEG);
+    let mut out = vec![0; layout.bytes()];
+    buffer
+        .decode_image_to_buffer::<RgbAFormat>(&mut out)
+        .unwrap();
+}
@@ -0,0 +1,47 @@
+use athletic::mask::{self, Mask, Style};
+
+#[test]
+fn masks_parse_with_an_optional_style() {
+    let mask: Mask = "10, 20, 30, 40".parse().unwrap();
+    assert_eq!(
+        mask,
+        Mask {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+            style: Style::Black,
+        }
+    );
+    let mask: Mask = "0,0,8,8,Pixelate".parse().unwrap();
+    assert_eq!(mask.style, Style::Pixelate);
+    assert_eq!("0,0,8,8,BLACK".parse::<Mask>().unwrap().style, Style::Black);
+}
+
+#[test]
+fn malformed_masks_are_refused() {
+    for mask in [
+        "",
+        "1,2,3",
+        "1,2,3,4,5,6",
+        "1,2,three,4",
+        "-1,2,3,4",
+        "1,2,3,4,blur",
+    ] {
+        assert!(mask.parse::<Mask>().is_err(), "{mask:?}");
+    }
+    let why = "1,2,3,4,blur".parse::<Mask>().unwrap_err();
+    assert_eq!(why.to_string(), "unknown mask style: blur");
+}
+
+#[test]
+fn masks_past_the_edge_are_clipped() {
+    let mut pixels = vec![200; 4 * 4 * 4];
+    let mask: Mask = "2,2,100,100".parse().unwrap();
+    mask::apply(&[mask], &mut pixels, 4, 4, 4);
+    for (n, pixel) in pixels.chunks_exact(4).enumerate() {
+        let masked = n % 4 >= 2 && n / 4 >= 2;
+        let expected = if masked { [0, 0, 0, 200] } else { [200; 4] };
+        assert_eq!(pixel, expected, "pixel {n}");
+    }
+}
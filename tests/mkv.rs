@@ -0,0 +1,74 @@
+use athletic::mkv::{self, AudioTrack, Block, MatroskaWriter, VideoTrack};
+use std::time::Duration;
+
+const VIDEO: VideoTrack = VideoTrack {
+    codec: "V_MJPEG",
+    width: 64,
+    height: 48,
+};
+
+/// A recording of `frames` frames at 25 fps, each holding its own number.
+fn recording(frames: u8, audio: bool) -> Vec<u8> {
+    let audio = audio.then_some(AudioTrack {
+        sample_rate: 8000,
+        channels: 1,
+    });
+    let mut writer = MatroskaWriter::new(Vec::new(), Some(VIDEO), audio).unwrap();
+    for n in 0..frames {
+        let pts = Duration::from_millis(n as u64 * 40);
+        writer.write_frame(pts, &[n; 100]).unwrap();
+        if audio.is_some() {
+            writer.write_audio(pts, &[0x10, n]).unwrap();
+        }
+    }
+    writer.finish().unwrap()
+}
+
+#[test]
+fn frames_read_back_as_written() {
+    // Long enough to take more than one cluster.
+    let file = recording(200, false);
+    let mut read = Vec::new();
+    let scan = mkv::read_frames(file.as_slice(), |pts, frame| {
+        read.push((pts, frame.to_vec()));
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(scan.frames, 200);
+    assert_eq!(scan.complete, file.len() as u64);
+    assert_eq!(scan.trailing, 0);
+    for (n, (pts, frame)) in read.iter().enumerate() {
+        assert_eq!(*pts, Duration::from_millis(n as u64 * 40));
+        assert_eq!(*frame, [n as u8; 100]);
+    }
+}
+
+#[test]
+fn sound_reads_back_on_its_own_track() {
+    let file = recording(10, true);
+    let (mut video, mut audio) = (0, Vec::new());
+    let scan = mkv::read_blocks(file.as_slice(), |block| {
+        match block {
+            Block::Video(..) => video += 1,
+            Block::Audio(pts, samples) => audio.push((pts, samples.to_vec())),
+        }
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!((scan.frames, video), (10, 10));
+    assert_eq!(audio.len(), 10);
+    assert_eq!(audio[3], (Duration::from_millis(120), vec![0x10, 3]));
+}
+
+#[test]
+fn a_recording_cut_short_is_read_up_to_the_cut() {
+    let file = recording(200, false);
+    let whole = mkv::scan(file.as_slice()).unwrap();
+    let cut = &file[..file.len() - 1000];
+    let scan = mkv::scan(cut).unwrap();
+    assert!(scan.frames > 0 && scan.frames < whole.frames);
+    assert_eq!(scan.complete + scan.trailing, cut.len() as u64);
+    assert!(scan.trailing > 0);
+
+    assert!(mkv::scan(&b"not a matroska file"[..]).is_err());
+}
@@ -0,0 +1,88 @@
+use athletic::capture::{Clock, Frame};
+use athletic::mono::{self, Depth, Windowing};
+use athletic::thermal::Palette;
+use image::DynamicImage;
+use nokhwa::{
+    utils::{FrameFormat, Resolution},
+    Buffer,
+};
+
+fn grey(width: u32, height: u32, data: &[u8]) -> Frame {
+    let buffer = Buffer::new(Resolution::new(width, height), data, FrameFormat::GRAY);
+    Clock::default().stamp(buffer)
+}
+
+fn sixteen(samples: &[u16]) -> Frame {
+    let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+    grey(samples.len() as u32, 1, &data)
+}
+
+#[test]
+fn depth_is_told_by_bytes_per_pixel() {
+    assert_eq!(Depth::of(&grey(4, 1, &[0; 4])), Some(Depth::Eight));
+    assert_eq!(Depth::of(&grey(4, 1, &[0; 8])), Some(Depth::Sixteen));
+    let yuyv = Buffer::new(Resolution::new(2, 1), &[0; 4], FrameFormat::YUYV);
+    assert_eq!(Depth::of(&Clock::default().stamp(yuyv)), None);
+}
+
+#[test]
+fn the_full_window_shows_samples_as_they_are() {
+    let frame = grey(3, 1, &[0, 128, 255]);
+    let mut rgba = [0; 12];
+    Windowing::default().render(&frame, Depth::Eight, Palette::WhiteHot, &mut rgba);
+    assert_eq!(rgba, [0, 0, 0, 255, 128, 128, 128, 255, 255, 255, 255, 255]);
+
+    Windowing::default().render(&frame, Depth::Eight, Palette::BlackHot, &mut rgba);
+    assert_eq!(rgba[..4], [255, 255, 255, 255]);
+}
+
+#[test]
+fn a_narrow_window_stretches_its_part_of_the_range() {
+    // Samples filling a sliver of the 16-bit range, as thermal cameras send.
+    let frame = sixteen(&[29_000, 29_500, 30_000]);
+    let window = Windowing::spanning(&frame, Depth::Sixteen);
+    let mut rgba = [0; 12];
+    window.render(&frame, Depth::Sixteen, Palette::WhiteHot, &mut rgba);
+    assert_eq!([rgba[0], rgba[4], rgba[8]], [0, 128, 255]);
+
+    // Samples outside the window clip.
+    let window = Windowing {
+        level: 0.25,
+        width: 0.5,
+    };
+    let frame = grey(3, 1, &[0, 64, 200]);
+    window.render(&frame, Depth::Eight, Palette::WhiteHot, &mut rgba);
+    assert_eq!([rgba[0], rgba[4], rgba[8]], [0, 128, 255]);
+}
+
+#[test]
+fn steps_stay_in_range() {
+    let mut window = Windowing::default();
+    for _ in 0..100 {
+        window.shift(true);
+        window.widen(false);
+    }
+    assert_eq!(window.level, 1.0);
+    assert!(window.width > 0.0 && window.width < 0.01);
+    for _ in 0..100 {
+        window.shift(false);
+        window.widen(true);
+    }
+    assert_eq!(
+        window,
+        Windowing {
+            level: 0.0,
+            width: 1.0
+        }
+    );
+}
+
+#[test]
+fn images_keep_the_frames_depth() {
+    let frame = sixteen(&[1, 2, 65_535]);
+    let Some(DynamicImage::ImageLuma16(image)) = mono::image(&frame, Depth::Sixteen) else {
+        panic!("not a 16-bit image");
+    };
+    assert_eq!(image.into_raw(), [1, 2, 65_535]);
+    assert!(mono::image(&grey(4, 4, &[0; 3]), Depth::Eight).is_none());
+}
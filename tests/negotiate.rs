@@ -0,0 +1,32 @@
+use athletic::negotiate::Negotiation;
+use nokhwa::utils::{CameraFormat, FrameFormat, RequestedFormatType, Resolution};
+
+fn format(width: u32, height: u32, fps: u32, format: FrameFormat) -> CameraFormat {
+    CameraFormat::new(Resolution::new(width, height), format, fps)
+}
+
+#[test]
+fn a_driver_that_sets_something_else_is_called_out() {
+    let picked = format(1280, 720, 30, FrameFormat::MJPEG);
+    let negotiation = Negotiation {
+        camera: "Webcam (0)".to_string(),
+        requested: RequestedFormatType::Closest(format(1280, 720, 60, FrameFormat::MJPEG)),
+        decodable: &[FrameFormat::MJPEG, FrameFormat::YUYV],
+        offered: vec![
+            format(640, 480, 30, FrameFormat::YUYV),
+            format(640, 480, 30, FrameFormat::MJPEG),
+            picked,
+        ],
+        picked: Some(picked),
+        configured: Some(Ok(format(640, 480, 30, FrameFormat::YUYV))),
+    };
+    let explained = negotiation.to_string();
+    for line in [
+        "640x480@30:YUYV    another format",
+        "640x480@30:MJPEG   684 pixels and 30 fps off",
+        "1280x720@30:MJPEG  picked",
+        "Configured: 640x480@30:YUYV, as the driver set that instead",
+    ] {
+        assert!(explained.contains(line), "no {line:?} in:\n{explained}");
+    }
+}
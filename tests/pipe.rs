@@ -0,0 +1,87 @@
+//! The frame stream as docs/pipe-sink.md gives it to readers.
+
+use athletic::pipe;
+use athletic::sink_frame::FRAME_HEADER_SIZE;
+
+#[test]
+fn the_stream_header_names_the_frame_header_size() {
+    let header = pipe::stream_header();
+    assert_eq!(header.len(), 16);
+    assert_eq!(&header[..8], b"ATHLPIPE");
+    assert_eq!(header[8..12], 1u32.to_le_bytes());
+    assert_eq!(header[12..16], (FRAME_HEADER_SIZE as u32).to_le_bytes());
+    #[cfg(target_os = "linux")]
+    {
+        let header: [u8; 16] = header.try_into().unwrap();
+        assert_eq!(pipe::read_stream_header(&header).unwrap(), 40);
+        let mut newer = header;
+        newer[8] = 2;
+        assert!(pipe::read_stream_header(&newer).is_err());
+        let mut other = header;
+        other[..8].copy_from_slice(b"ATHLSHM\0");
+        assert!(pipe::read_stream_header(&other).is_err());
+    }
+}
+
+#[test]
+fn names_with_either_slash_are_paths() {
+    for name in [
+        "/tmp/frames",
+        "pipes/frames",
+        "C:/pipes/frames",
+        r"pipes\frames",
+    ] {
+        assert_eq!(pipe::path(name), std::path::Path::new(name), "{name}");
+    }
+    assert!(pipe::path("frames").ends_with("frames"));
+}
+
+#[cfg(unix)]
+#[test]
+fn a_reader_gets_the_header_then_frames() {
+    use athletic::capture::Queue;
+    use athletic::pipe::PipeSink;
+    use athletic::sink_frame::{PixelFormat, SinkFrame};
+    use athletic::stream::Planes;
+    use std::io::Read;
+    use std::time::Duration;
+
+    static PLANES: Planes = Planes::new();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("frames");
+    let mut sink = PipeSink::create(path.to_str().unwrap(), Queue::default()).unwrap();
+    let reader = {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let mut pipe = std::fs::File::open(path).unwrap();
+            let mut stream = vec![0; 16 + FRAME_HEADER_SIZE + 12];
+            pipe.read_exact(&mut stream).unwrap();
+            stream
+        })
+    };
+    let data: Vec<u8> = (0..12).collect();
+    let frame = SinkFrame {
+        sequence: 7,
+        pts: Duration::from_millis(280),
+        width: 2,
+        height: 2,
+        format: PixelFormat::Rgb,
+        data: &data,
+        conditions: None,
+        planes: &PLANES,
+    };
+    // Frames sent before the reader connects are dropped, so keep
+    // sending until it has one.
+    while !reader.is_finished() {
+        sink.write(&frame).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let stream = reader.join().unwrap();
+    assert_eq!(stream[..16], pipe::stream_header());
+    assert_eq!(stream[16..56], frame.header());
+    assert_eq!(stream[56..], data);
+    drop(sink);
+    assert!(!path.exists(), "the FIFO outlived its sink");
+}
@@ -0,0 +1,102 @@
+use athletic::pixel_format::{ColorSpace, RgbAFormat, RgbFormat};
+use nokhwa::{
+    utils::{FrameFormat, Resolution},
+    Buffer,
+};
+
+fn jpeg() -> Vec<u8> {
+    std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/golden/scene.jpg"
+    ))
+    .unwrap()
+}
+
+fn mjpeg(data: &[u8]) -> Buffer {
+    Buffer::new(Resolution::new(64, 48), data, FrameFormat::MJPEG)
+}
+
+#[test]
+fn mjpeg_matches_both_ways() {
+    let buffer = mjpeg(&jpeg());
+    let rgb = buffer.decode_image::<RgbFormat>().unwrap();
+    let mut rgba = vec![0; 64 * 48 * 4];
+    buffer
+        .decode_image_to_buffer::<RgbAFormat>(&mut rgba)
+        .unwrap();
+    let opaque: Vec<u8> = rgb.pixels().flat_map(|p| [p[0], p[1], p[2], 255]).collect();
+    assert_eq!(rgba, opaque);
+}
+
+// Each of these used to abort the process from inside mozjpeg.
+#[test]
+fn broken_mjpeg_is_an_error() {
+    let whole = jpeg();
+    let mut flipped = whole.clone();
+    for byte in flipped[whole.len() / 2..whole.len() - 2]
+        .iter_mut()
+        .step_by(7)
+    {
+        *byte ^= 0x55;
+    }
+    for data in [
+        &[][..],
+        &[1, 2, 3, 4, 5, 6, 7, 8],
+        &whole[..whole.len() / 2],
+        &flipped,
+    ] {
+        assert!(mjpeg(data).decode_image::<RgbFormat>().is_err());
+    }
+}
+
+#[test]
+fn mjpeg_of_another_size_is_an_error() {
+    let buffer = Buffer::new(Resolution::new(32, 24), &jpeg(), FrameFormat::MJPEG);
+    assert!(buffer.decode_image::<RgbAFormat>().is_err());
+}
+
+#[test]
+fn frames_that_do_not_fit_are_errors() {
+    let empty = Buffer::new(Resolution::new(0, 4), &[], FrameFormat::NV12);
+    assert!(empty.decode_image::<RgbFormat>().is_err());
+
+    let rgb = Buffer::new(Resolution::new(2, 2), &[7; 12], FrameFormat::RAWRGB);
+    assert!(rgb
+        .decode_image_to_buffer::<RgbFormat>(&mut [0; 6])
+        .is_err());
+    assert!(rgb
+        .decode_image_to_buffer::<RgbAFormat>(&mut [0; 8])
+        .is_err());
+}
+
+#[test]
+fn yuv_past_the_gamut_clamps() {
+    // nokhwa's conversion wrapped this red round to 225.
+    let buffer = Buffer::new(
+        Resolution::new(2, 1),
+        &[255, 128, 255, 255],
+        FrameFormat::YUYV,
+    );
+    let rgb = buffer.decode_image::<RgbFormat>().unwrap();
+    assert_eq!(rgb.as_raw(), &[255, 175, 255, 255, 175, 255]);
+}
+
+#[test]
+fn hd_frames_are_read_as_bt709() {
+    assert_eq!(
+        ColorSpace::Auto.of(Resolution::new(1280, 720)),
+        ColorSpace::Bt709
+    );
+    assert_eq!(
+        ColorSpace::Auto.of(Resolution::new(640, 480)),
+        ColorSpace::Bt601
+    );
+    assert_eq!(
+        ColorSpace::Bt601.of(Resolution::new(1920, 1080)),
+        ColorSpace::Bt601
+    );
+    assert_ne!(
+        ColorSpace::Bt601.rgb(120, 90, 200),
+        ColorSpace::Bt709.rgb(120, 90, 200)
+    );
+}
@@ -0,0 +1,11 @@
+use athletic::privacy::app_name;
+
+#[test]
+fn apps_in_the_consent_store_go_by_their_names() {
+    assert_eq!(app_name(r"C:#Program Files#Zoom#bin#Zoom.exe"), "Zoom.exe");
+    assert_eq!(
+        app_name("Microsoft.WindowsCamera_8wekyb3d8bbwe"),
+        "Microsoft.WindowsCamera"
+    );
+    assert_eq!(app_name("Some_App"), "Some_App");
+}
@@ -0,0 +1,54 @@
+use athletic::capture::ControlRequest;
+use athletic::ptz::Ptz;
+use ggez::input::gamepad::gilrs::Axis;
+use nokhwa::utils::KnownCameraControl;
+use std::time::Duration;
+
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn without_the_controls_zoom_crops_and_pan_stays_in_the_frame() {
+    let (controls, requests) = flume::unbounded();
+    let mut ptz = Ptz::new(controls, &[]);
+    assert_eq!(ptz.view().w, 1.0);
+
+    ptz.axis(Axis::RightStickY, 1.0);
+    ptz.update(SECOND);
+    assert_eq!(ptz.view().w, 0.5);
+    ptz.axis(Axis::RightStickY, 0.0);
+
+    ptz.axis(Axis::LeftStickX, 1.0);
+    for _ in 0..10 {
+        ptz.update(SECOND);
+    }
+    let view = ptz.view();
+    assert_eq!((view.x, view.w), (0.5, 0.5));
+    assert!(requests.is_empty());
+}
+
+#[test]
+fn with_the_controls_sticks_nudge_the_camera() {
+    let (controls, requests) = flume::unbounded();
+    let mut ptz = Ptz::new(controls, &[KnownCameraControl::Pan]);
+    ptz.axis(Axis::LeftStickX, 1.0);
+    ptz.update(Duration::from_millis(40));
+    assert!(requests.is_empty(), "nudged before the interval");
+    ptz.update(Duration::from_millis(60));
+    match requests.try_recv() {
+        Ok(ControlRequest::Nudge(KnownCameraControl::Pan, fraction)) => {
+            assert!((fraction - 0.025).abs() < 1e-6, "{fraction}")
+        }
+        _ => panic!("no nudge to pan"),
+    }
+    // The stick did the camera's panning, not the crop's.
+    assert_eq!(ptz.view().x, 0.0);
+}
+
+#[test]
+fn a_stick_barely_off_centre_is_centred() {
+    let (controls, requests) = flume::unbounded();
+    let mut ptz = Ptz::new(controls, &[KnownCameraControl::Pan]);
+    ptz.axis(Axis::LeftStickX, 0.1);
+    ptz.update(SECOND);
+    assert!(requests.is_empty());
+}
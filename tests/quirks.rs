@@ -0,0 +1,47 @@
+use athletic::quirks::{Quirk, UsbId};
+use nokhwa::utils::{CameraFormat, FrameFormat, Resolution};
+
+#[test]
+fn usb_ids_are_found_in_device_names() {
+    let cam_link = UsbId {
+        vendor: 0x0fd9,
+        product: 0x0066,
+    };
+    assert_eq!("0fd9:0066".parse::<UsbId>().unwrap(), cam_link);
+    assert_eq!(cam_link.to_string(), "0fd9:0066");
+    assert!("fd9:66".parse::<UsbId>().is_err());
+    assert_eq!(
+        UsbId::in_name(r"\\?\usb#vid_0FD9&pid_0066&mi_00#7&1a2b3c4d&0&0000#{e5323777}"),
+        Some(cam_link)
+    );
+    assert_eq!(UsbId::in_name("0x141000000fd90066"), Some(cam_link));
+    assert_eq!(UsbId::in_name("FaceTime HD Camera"), None);
+}
+
+#[test]
+fn quirks_keep_cards_to_what_they_stream() {
+    let offered = [
+        CameraFormat::new(Resolution::new(3840, 2160), FrameFormat::NV12, 30),
+        CameraFormat::new(Resolution::new(1920, 1080), FrameFormat::NV12, 60),
+        CameraFormat::new(Resolution::new(1920, 1080), FrameFormat::NV12, 30),
+        CameraFormat::new(Resolution::new(1280, 720), FrameFormat::NV12, 60),
+    ];
+    let quirk = |resolution, max_frame_rate| Quirk {
+        card: "card".to_string(),
+        id: UsbId {
+            vendor: 0x0fd9,
+            product: 0x0066,
+        },
+        resolution,
+        max_frame_rate,
+    };
+    assert_eq!(
+        quirk(Some(Resolution::new(1920, 1080)), None).restrict(&offered),
+        &offered[1..3]
+    );
+    assert_eq!(
+        quirk(None, Some(30)).restrict(&offered),
+        [offered[0], offered[2]]
+    );
+    assert_eq!(quirk(None, None).restrict(&offered), offered);
+}
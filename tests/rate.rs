@@ -0,0 +1,44 @@
+use athletic::rate::{self, parse_bitrate};
+use image::RgbImage;
+
+#[test]
+fn bitrates_take_a_suffix() {
+    assert_eq!(parse_bitrate("250000").unwrap(), 250_000);
+    assert_eq!(parse_bitrate("800k").unwrap(), 800_000);
+    assert_eq!(parse_bitrate("4M").unwrap(), 4_000_000);
+    assert_eq!(parse_bitrate("1.5m").unwrap(), 1_500_000);
+    assert_eq!(parse_bitrate("2G").unwrap(), 2_000_000_000);
+    for bitrate in ["", "k", "fast", "4Mb"] {
+        assert!(parse_bitrate(bitrate).is_err(), "{bitrate:?}");
+    }
+}
+
+/// A frame busy enough that the JPEG quality matters to its size.
+fn busy() -> RgbImage {
+    RgbImage::from_fn(160, 120, |x, y| {
+        image::Rgb([((x * 7) ^ (y * 3)) as u8, (x * y) as u8, (x + y * 5) as u8])
+    })
+}
+
+#[test]
+fn frames_are_encoded_to_fit() {
+    let image = busy();
+    let small = rate::encode(&image, 10).unwrap().len();
+    let large = rate::encode(&image, 90).unwrap().len();
+    assert!(small < large);
+
+    let bits = (small + large) as f64 / 2.0 * 8.0;
+    let (jpeg, quality) = rate::encode_to_size(&image, bits, 50).unwrap();
+    assert!((jpeg.len() * 8) as f64 <= bits);
+    assert!(quality > 10 && quality < 90, "quality {quality}");
+    // The next quality up would have gone over.
+    let over = rate::encode(&image, quality + 1).unwrap();
+    assert!((over.len() * 8) as f64 > bits);
+}
+
+#[test]
+fn a_size_nothing_fits_gets_the_lowest_quality() {
+    let (jpeg, quality) = rate::encode_to_size(&busy(), 8.0, 50).unwrap();
+    assert_eq!(quality, 5);
+    assert_eq!(jpeg, rate::encode(&busy(), 5).unwrap());
+}
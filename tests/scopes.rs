@@ -0,0 +1,59 @@
+use athletic::scopes::{compute, zebra, Scope};
+
+/// A `width` by 2 RGBA frame, grey at `level` throughout.
+fn grey(width: u32, level: u8) -> Vec<u8> {
+    [level, level, level, 255].repeat(width as usize * 2)
+}
+
+/// The rows of a rendered scope with samples in them, counting from the
+/// top.
+fn lit_rows(pixels: &[u8], width: u32) -> Vec<usize> {
+    pixels
+        .chunks_exact(width as usize * 4)
+        .enumerate()
+        .filter(|(_, row)| row.chunks_exact(4).any(|pixel| pixel[3] == 255))
+        .map(|(y, _)| y)
+        .collect()
+}
+
+#[test]
+fn a_flat_grey_frame_is_one_line_on_the_waveform() {
+    let (pixels, width, height) = compute(Scope::Waveform, &grey(16, 200), 16, 2);
+    assert_eq!((width, height), (16, 256));
+    assert_eq!(lit_rows(&pixels, width), [255 - 200]);
+}
+
+#[test]
+fn the_parade_has_a_column_for_each_channel() {
+    let frame = [10, 100, 250, 255].repeat(8 * 2);
+    let (pixels, width, _) = compute(Scope::Parade, &frame, 8, 2);
+    assert_eq!(width, 24);
+    assert_eq!(lit_rows(&pixels, width), [255 - 250, 255 - 100, 255 - 10]);
+}
+
+#[test]
+fn grey_sits_in_the_middle_of_the_vectorscope() {
+    let (pixels, width, _) = compute(Scope::Vectorscope, &grey(16, 90), 16, 2);
+    let lit: Vec<usize> = pixels
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(_, pixel)| pixel[3] == 255)
+        .map(|(n, _)| n)
+        .collect();
+    // Drawn with Cr rising up the plot.
+    assert_eq!(lit, [(255 - 128) * width as usize + 128]);
+}
+
+#[test]
+fn zebra_stripes_only_what_is_bright() {
+    let mut frame = grey(32, 250);
+    frame[32 * 4..].copy_from_slice(&grey(16, 100));
+    zebra(&mut frame, 32, 235);
+    let (bright, dark) = frame.split_at(32 * 4);
+    // Stripes 8 pixels wide, starting with one.
+    for (x, pixel) in bright.chunks_exact(4).enumerate() {
+        let striped = x / 8 % 2 == 0;
+        assert_eq!(pixel[0] == 0, striped, "pixel {x}");
+    }
+    assert!(dark.chunks_exact(4).all(|pixel| pixel[0] == 100));
+}
@@ -0,0 +1,39 @@
+use athletic::capture::{self, Capture, Queue};
+use athletic::pattern::{self, DEVICE};
+use color_eyre::Report;
+use nokhwa::utils::{CameraFormat, CameraIndex, FrameFormat, RequestedFormatType, Resolution};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many times the daemon has been asked for a camera.
+static ASKED: AtomicUsize = AtomicUsize::new(0);
+
+fn relayed() -> CameraFormat {
+    CameraFormat::new(Resolution::new(320, 240), FrameFormat::MJPEG, 15)
+}
+
+/// Stands in for the daemon, which settles on a format of its own
+/// whatever is asked for.
+fn daemon(_: CameraIndex, _: RequestedFormatType, queue: Queue) -> Result<Capture, Report> {
+    ASKED.fetch_add(1, Ordering::SeqCst);
+    let mut capture = pattern::spawn(queue);
+    capture.format = relayed();
+    Ok(capture)
+}
+
+#[test]
+fn a_dry_run_settles_the_format_a_capture_gets() {
+    capture::relay_through(daemon).unwrap();
+    let asked = RequestedFormatType::Closest(relayed());
+    let (_, settled) = capture::settle(CameraIndex::Index(3), asked).unwrap();
+    let capture =
+        capture::spawn_capture_queued(CameraIndex::Index(3), asked, Queue::default()).unwrap();
+    assert_eq!(settled, capture.format);
+    assert_eq!(settled, relayed());
+    assert_eq!(ASKED.load(Ordering::SeqCst), 2, "the daemon wasn't asked");
+
+    // The test pattern never goes through the relay.
+    let pattern = CameraIndex::String(DEVICE.to_string());
+    let (_, settled) = capture::settle(pattern, asked).unwrap();
+    assert_eq!(settled, pattern::format());
+    assert_eq!(ASKED.load(Ordering::SeqCst), 2);
+}
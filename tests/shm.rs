@@ -0,0 +1,100 @@
+//! The ring's layout as docs/shm-sink.md gives it to readers.
+
+use athletic::shm::{self, ShmSink};
+use athletic::sink_frame::{PixelFormat, SinkFrame};
+use athletic::stream::Planes;
+use std::time::Duration;
+
+static PLANES: Planes = Planes::new();
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// A `width` by 2 BGRA frame.
+fn frame(sequence: u64, width: u32, data: &[u8]) -> SinkFrame<'_> {
+    SinkFrame {
+        sequence,
+        pts: Duration::from_millis(sequence * 40),
+        width,
+        height: 2,
+        format: PixelFormat::Bgra,
+        data,
+        conditions: None,
+        planes: &PLANES,
+    }
+}
+
+#[test]
+fn frames_go_in_slots_after_the_header() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mut sink = ShmSink::create(path.to_str().unwrap()).unwrap();
+    let data: Vec<u8> = (0..32).collect();
+    for sequence in 0..5 {
+        sink.write(&frame(sequence, 4, &data)).unwrap();
+    }
+
+    let ring = std::fs::read(&path).unwrap();
+    assert_eq!(&ring[..8], b"ATHLSHM\0");
+    assert_eq!(u32_at(&ring, 8), 1);
+    let slots = u32_at(&ring, 12) as usize;
+    let slot_size = u64_at(&ring, 16) as usize;
+    assert_eq!(slots, 4);
+    assert_eq!(slot_size % 64, 0);
+    assert!(slot_size >= 64 + data.len());
+    assert_eq!(ring.len(), 64 + slots * slot_size);
+    assert_eq!(u64_at(&ring, 24), 5);
+
+    // The fifth frame went round into the first slot, which has been
+    // written twice, and the second slot once.
+    let newest = 64;
+    assert_eq!(u64_at(&ring, newest), 4);
+    assert_eq!(u64_at(&ring, newest + slot_size), 2);
+    let header = &ring[newest + 8..newest + 48];
+    assert_eq!(header, frame(4, 4, &data).header());
+    assert_eq!(u64_at(header, 0), 4);
+    assert_eq!(u64_at(header, 8), 160_000_000);
+    assert_eq!((u32_at(header, 16), u32_at(header, 20)), (4, 2));
+    assert_eq!(u32_at(header, 24), 16);
+    assert_eq!(&header[28..32], b"AR24");
+    assert_eq!(u64_at(header, 32), 32);
+    assert_eq!(&ring[newest + 64..newest + 64 + data.len()], data);
+
+    drop(sink);
+    assert!(!path.exists(), "the ring outlived its sink");
+}
+
+#[test]
+fn a_bigger_frame_grows_the_slots() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ring");
+    let mut sink = ShmSink::create(path.to_str().unwrap()).unwrap();
+    sink.write(&frame(0, 4, &[1; 32])).unwrap();
+    let small = u64_at(&std::fs::read(&path).unwrap(), 16);
+    sink.write(&frame(1, 400, &[2; 3200])).unwrap();
+
+    let ring = std::fs::read(&path).unwrap();
+    let slot_size = u64_at(&ring, 16) as usize;
+    assert!(slot_size > small as usize && slot_size >= 64 + 3200);
+    // The count carries on through the new layout, in the slot after.
+    assert_eq!(u64_at(&ring, 24), 2);
+    let slot = 64 + slot_size;
+    assert_eq!(u64_at(&ring, slot + 8), 1);
+    assert!(ring[slot + 64..slot + 64 + 3200]
+        .iter()
+        .all(|&byte| byte == 2));
+}
+
+#[test]
+fn names_with_either_slash_are_paths() {
+    for name in ["/tmp/ring", "rings/ring", "C:/rings/ring", r"rings\ring"] {
+        assert_eq!(shm::path(name), std::path::Path::new(name), "{name}");
+    }
+    assert_ne!(shm::path("ring"), std::path::Path::new("ring"));
+    assert!(shm::path("ring").ends_with("ring"));
+}
@@ -0,0 +1,41 @@
+use athletic::sink_frame::PixelFormat;
+
+#[test]
+fn rgb_formats_convert_between_each_other() {
+    let rgb = [10, 20, 30, 40, 50, 60];
+    let (format, rgba) = PixelFormat::Rgb.convert(&rgb, PixelFormat::Rgba);
+    assert_eq!(format, PixelFormat::Rgba);
+    assert_eq!(*rgba, [10, 20, 30, 255, 40, 50, 60, 255]);
+
+    let (format, bgra) = PixelFormat::Rgba.convert(&[10, 20, 30, 128], PixelFormat::Bgra);
+    assert_eq!(format, PixelFormat::Bgra);
+    assert_eq!(*bgra, [30, 20, 10, 128]);
+
+    let (format, back) = PixelFormat::Bgra.convert(&bgra, PixelFormat::Rgb);
+    assert_eq!(format, PixelFormat::Rgb);
+    assert_eq!(*back, [10, 20, 30]);
+}
+
+#[test]
+fn other_formats_stay_as_they_are() {
+    let yuyv = [16, 128, 16, 128];
+    let (format, data) = PixelFormat::Yuyv.convert(&yuyv, PixelFormat::Bgra);
+    assert_eq!(format, PixelFormat::Yuyv);
+    assert_eq!(*data, yuyv);
+
+    let (format, _) = PixelFormat::Z16.convert(&yuyv, PixelFormat::Rgb);
+    assert_eq!(format, PixelFormat::Z16);
+}
+
+#[test]
+fn pixel_formats_parse_by_their_usual_names() {
+    for (name, format) in [
+        ("rgb24", PixelFormat::Rgb),
+        ("RGBA", PixelFormat::Rgba),
+        ("RGB32", PixelFormat::Bgra),
+        ("bgra", PixelFormat::Bgra),
+    ] {
+        assert_eq!(name.parse::<PixelFormat>().unwrap(), format, "{name}");
+    }
+    assert!("yuyv".parse::<PixelFormat>().is_err());
+}
@@ -0,0 +1,138 @@
+//! Pins what scripts and users read from athletic: the help of every
+//! command, and the shape of the JSON it writes. A change to either shows
+//! up as a failing test rather than someone's broken script.
+//!
+//! The snapshots are in tests/snapshots, kept by insta. When a change is
+//! meant, run `cargo insta test --review` (or the suite with
+//! `INSTA_UPDATE=always`) and read the diff before checking them in; a
+//! renamed flag or a JSON field that went away is a breaking change.
+
+mod support;
+
+use insta::{assert_json_snapshot, assert_snapshot, Settings};
+use serde_json::Value;
+use std::path::Path;
+use support::{run, scratch, DEVICE};
+
+/// `command --help`, with the binary named as it is everywhere.
+fn help(command: &[&str]) -> String {
+    let dir = scratch();
+    let args: Vec<&str> = command.iter().copied().chain(["--help"]).collect();
+    let output = run(dir.path(), &args);
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .replace("athletic.exe", "athletic")
+}
+
+/// The subcommands a help lists, but help itself.
+fn subcommands(help: &str) -> Vec<String> {
+    help.lines()
+        .skip_while(|line| *line != "Commands:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.strip_prefix("  ")?.split_whitespace().next())
+        .filter(|name| *name != "help")
+        .map(str::to_string)
+        .collect()
+}
+
+/// Help snapshots go in a directory of their own, named after the command
+/// alone.
+fn help_settings() -> Settings {
+    let mut settings = Settings::clone_current();
+    settings.set_snapshot_path("snapshots/help");
+    settings.set_prepend_module_to_snapshot(false);
+    settings.set_omit_expression(true);
+    settings
+}
+
+#[test]
+fn help_of_every_command() {
+    let mut pending = vec![Vec::<String>::new()];
+    let mut seen = 0;
+    while let Some(command) = pending.pop() {
+        let args: Vec<&str> = command.iter().map(String::as_str).collect();
+        let text = help(&args);
+        for subcommand in subcommands(&text) {
+            let mut nested = command.clone();
+            nested.push(subcommand);
+            pending.push(nested);
+        }
+        let name = match command.is_empty() {
+            true => "athletic".to_string(),
+            false => command.join("-"),
+        };
+        help_settings().bind(|| assert_snapshot!(name, text));
+        seen += 1;
+    }
+    let help = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/help");
+    let snapshots = std::fs::read_dir(help)
+        .unwrap()
+        .filter(|entry| {
+            let path = entry.as_ref().unwrap().path();
+            path.extension()
+                .is_some_and(|extension| extension == "snap")
+        })
+        .count();
+    assert_eq!(
+        seen, snapshots,
+        "there are help snapshots of commands that no longer exist"
+    );
+}
+
+fn read(file: &Path) -> Value {
+    serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap()
+}
+
+#[test]
+fn summary_and_manifest() {
+    let dir = scratch();
+    let output = dir.path().join("out.mkv");
+    let summary = dir.path().join("summary.json");
+    run(
+        dir.path(),
+        &[
+            "record",
+            DEVICE,
+            "-o",
+            output.to_str().unwrap(),
+            "--duration",
+            "1s",
+            "--progress",
+            "off",
+            "--summary",
+            summary.to_str().unwrap(),
+        ],
+    );
+
+    // What changes from run to run is redacted, leaving the fields and
+    // what's the same every time.
+    assert_json_snapshot!("summary", read(&summary), {
+        ".wall_time" => "[seconds]",
+        ".received" => "[frames]",
+        ".decoded" => "[frames]",
+        ".dropped" => "[frames]",
+        ".decode.mean" => "[seconds]",
+        ".decode.p50" => "[seconds]",
+        ".decode.p95" => "[seconds]",
+        ".decode.p99" => "[seconds]",
+        ".decode.max" => "[seconds]",
+        ".render.mean" => "[seconds]",
+        ".render.p50" => "[seconds]",
+        ".render.p95" => "[seconds]",
+        ".render.p99" => "[seconds]",
+        ".render.max" => "[seconds]",
+        ".sinks.*.frames" => "[frames]",
+        ".sinks.*.bytes" => "[bytes]",
+        ".bytes" => "[bytes]",
+        ".peak_memory" => "[bytes]",
+    });
+    assert_json_snapshot!("manifest", read(&dir.path().join("out.mkv.manifest.json")), {
+        ".segments[].bytes" => "[bytes]",
+        ".segments[].frames" => "[frames]",
+        ".segments[].end" => "[seconds]",
+        ".segments[].started" => "[time]",
+        ".segments[].ended" => "[time]",
+        ".segments[].sha256" => "[sha256]",
+    });
+}
@@ -0,0 +1,207 @@
+//! Properties of the strings the command line takes, each checked over
+//! many generated values. A failure is shrunk to the simplest value that
+//! still fails, and saved so the next run tries it first.
+
+use athletic::spec::{
+    parse_camera_format, parse_duration, parse_resolution, parse_size, IndexKind, PropertyKind,
+};
+use clap::ValueEnum;
+use nokhwa::utils::{frame_formats, CameraFormat, Resolution};
+use proptest::prelude::*;
+use proptest::sample::select;
+
+/// Up to 24 characters of the kind specs are made of, and some that they
+/// aren't.
+const GARBAGE: &str = "[0179xX@:.,+ mshJé\\x00-]{0,24}";
+
+/// `s` with each letter's case flipped at random.
+fn recased(s: String) -> impl Strategy<Value = String> {
+    proptest::collection::vec(any::<bool>(), s.chars().count()).prop_map(move |upper| {
+        s.chars()
+            .zip(upper)
+            .map(|(c, upper)| match upper {
+                true => c.to_ascii_uppercase(),
+                false => c.to_ascii_lowercase(),
+            })
+            .collect()
+    })
+}
+
+fn resolution() -> impl Strategy<Value = Resolution> {
+    (any::<u32>(), any::<u32>()).prop_map(|(width, height)| Resolution::new(width, height))
+}
+
+/// Every name and alias of every property kind.
+fn property_names() -> Vec<(PropertyKind, String)> {
+    PropertyKind::value_variants()
+        .iter()
+        .flat_map(|&kind| {
+            let value = kind.to_possible_value().unwrap();
+            value
+                .get_name_and_aliases()
+                .map(|name| (kind, name.to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn numbers_past_u32_are_device_names() {
+    let too_big = (u32::MAX as u64 + 1).to_string();
+    assert_eq!(
+        too_big.parse::<IndexKind>().unwrap(),
+        IndexKind::String(too_big)
+    );
+}
+
+#[test]
+fn malformed_durations_and_sizes_are_rejected() {
+    for s in [".", "", "-1s", "1.2.3s", "1e3s", &"9".repeat(400)] {
+        assert!(parse_duration(s).is_err(), "{s:?}");
+    }
+    for s in [
+        ".",
+        "",
+        "-1M",
+        "1.2.3M",
+        "1MM",
+        "1 M",
+        "1E",
+        &"9".repeat(400),
+    ] {
+        assert!(parse_size(s).is_err(), "{s:?}");
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn numbers_are_indices(index: u32) {
+        prop_assert_eq!(index.to_string().parse::<IndexKind>().unwrap(), IndexKind::Index(index));
+    }
+
+    #[test]
+    fn anything_else_is_a_device_name(name in GARBAGE) {
+        prop_assume!(name.parse::<u32>().is_err());
+        prop_assert_eq!(name.parse::<IndexKind>().unwrap(), IndexKind::String(name));
+    }
+
+    #[test]
+    fn resolutions_round_trip(resolution in resolution()) {
+        let written = resolution.to_string();
+        prop_assert_eq!(parse_resolution(&written).unwrap(), resolution);
+        let shouted = written.replace('x', "X");
+        prop_assert_eq!(parse_resolution(&shouted).unwrap(), resolution);
+    }
+
+    #[test]
+    fn resolutions_without_an_x_are_rejected(s in "[0179@:.,+ mshJé\\x00-]{0,24}") {
+        prop_assert!(parse_resolution(&s).is_err());
+    }
+
+    #[test]
+    fn resolutions_without_two_numbers_are_rejected(width: u32) {
+        for s in [
+            format!("{width}x"),
+            format!("x{width}"),
+            format!("{width}x{width}x{width}"),
+            format!("-{width}x{width}"),
+        ] {
+            prop_assert!(parse_resolution(&s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn camera_formats_round_trip_in_any_case(
+        (format, written) in (resolution(), select(frame_formats()), any::<u32>())
+            .prop_flat_map(|(resolution, format, fps)| {
+                let format = CameraFormat::new(resolution, format, fps);
+                (Just(format), recased(format.format().to_string()))
+            })
+            .prop_map(|(format, fourcc)| {
+                let written = format!("{}@{}:{fourcc}", format.resolution(), format.frame_rate());
+                (format, written)
+            })
+    ) {
+        prop_assert_eq!(parse_camera_format(&written).unwrap(), format);
+    }
+
+    #[test]
+    fn camera_formats_without_a_rate_and_format_are_rejected(s in GARBAGE) {
+        prop_assume!(!(s.contains('@') && s.contains(':')));
+        prop_assert!(parse_camera_format(&s).is_err());
+    }
+
+    #[test]
+    fn camera_formats_missing_a_part_are_rejected(width: u32, height: u32, fps: u32) {
+        for s in [
+            format!("{width}x{height}@{fps}"),
+            format!("{width}x{height}:YUYV"),
+            format!("{width}x{height}@{fps}:"),
+            format!("{width}x{height}@{fps}:H264"),
+            format!("{width}x{height}@-{fps}:YUYV"),
+            format!("{width}@{fps}:YUYV"),
+        ] {
+            prop_assert!(parse_camera_format(&s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn durations_follow_their_unit(thousandths in 0..100_000_000u64) {
+        let number = format!("{}.{:03}", thousandths / 1000, thousandths % 1000);
+        let value = thousandths as f64 / 1000.0;
+        for (unit, seconds) in [
+            ("", value),
+            ("ms", value / 1000.0),
+            ("s", value),
+            ("m", value * 60.0),
+            ("h", value * 3600.0),
+        ] {
+            let written = format!("{number}{unit}");
+            let parsed = parse_duration(&written).unwrap().as_secs_f64();
+            prop_assert!(
+                (parsed - seconds).abs() <= seconds * 1e-12 + 1e-9,
+                "{} came out as {}s",
+                written,
+                parsed
+            );
+        }
+    }
+
+    #[test]
+    fn durations_in_other_units_are_rejected(number: u32, unit in "[mshdMSx -]{0,24}") {
+        prop_assume!(!matches!(unit.as_str(), "" | "ms" | "s" | "m" | "h"));
+        let written = format!("{number}{unit}");
+        prop_assert!(parse_duration(&written).is_err(), "{:?}", written);
+    }
+
+    #[test]
+    fn sizes_follow_their_unit_in_any_case(
+        (number, scale, written) in (
+            0..1u64 << 20,
+            select(vec![("", 1), ("k", 1 << 10), ("m", 1 << 20), ("g", 1 << 30)]),
+            select(vec!["", "b", "ib"]),
+        )
+            .prop_flat_map(|(number, (unit, scale), suffix)| {
+                let suffix = if unit.is_empty() { "" } else { suffix };
+                (Just(number), Just(scale), recased(format!("{number}{unit}{suffix}")))
+            })
+    ) {
+        prop_assert_eq!(parse_size(&written).unwrap(), number * scale, "{}", written);
+    }
+
+    #[test]
+    fn property_kinds_parse_by_any_of_their_names_in_any_case(
+        (kind, written) in select(property_names())
+            .prop_flat_map(|(kind, name)| (Just(kind), recased(name)))
+    ) {
+        prop_assert_eq!(PropertyKind::from_str(&written, true).unwrap(), kind, "{}", written);
+    }
+
+    #[test]
+    fn unknown_property_kinds_are_rejected(s in "[a-z]{0,12}") {
+        prop_assume!(property_names().iter().all(|(_, name)| *name != s));
+        prop_assert!(PropertyKind::from_str(&s, true).is_err(), "{:?}", s);
+    }
+}
@@ -0,0 +1,56 @@
+use athletic::stitch;
+use image::{imageops, RgbImage};
+
+/// A scene of scattered blocks, busy with corners to match.
+fn scene(width: u32, height: u32) -> RgbImage {
+    let mut hash = 0x9E37_79B9u32;
+    let mut next = move || {
+        hash ^= hash << 13;
+        hash ^= hash >> 17;
+        hash ^= hash << 5;
+        hash
+    };
+    let mut scene = RgbImage::from_pixel(width, height, image::Rgb([90, 90, 90]));
+    for _ in 0..400 {
+        let (x, y) = (next() % width, next() % height);
+        let (w, h) = (4 + next() % 20, 4 + next() % 20);
+        let colour = image::Rgb([next() as u8, next() as u8, next() as u8]);
+        for y in y..(y + h).min(height) {
+            for x in x..(x + w).min(width) {
+                scene.put_pixel(x, y, colour);
+            }
+        }
+    }
+    scene
+}
+
+#[test]
+fn overlapping_frames_make_a_wider_picture() {
+    let scene = scene(720, 240);
+    let frames: Vec<RgbImage> = [0, 180, 360]
+        .map(|x| imageops::crop_imm(&scene, x, 0, 360, 240).to_image())
+        .to_vec();
+    let panorama = stitch::stitch(&frames).unwrap();
+    let (width, height) = panorama.dimensions();
+    assert!((710..=730).contains(&width), "{width} wide");
+    assert!((236..=244).contains(&height), "{height} high");
+
+    // The middle frame is drawn where it was.
+    let left = (width - 360) / 2;
+    let differing = (0..240)
+        .flat_map(|y| (0..360).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            let (a, b) = (panorama.get_pixel(left + x, y), frames[1].get_pixel(x, y));
+            a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > 40)
+        })
+        .count();
+    assert!(differing < 360 * 240 / 20, "{differing} pixels differ");
+}
+
+#[test]
+fn frames_that_dont_overlap_are_refused() {
+    let frames = [scene(300, 200), RgbImage::new(300, 200)];
+    assert!(stitch::stitch(&frames).is_err());
+    assert!(stitch::stitch(&[]).is_err());
+    assert!(stitch::stitch(&[scene(300, 200), scene(200, 200)]).is_err());
+}
@@ -0,0 +1,31 @@
+use athletic::depth::DepthPlane;
+use athletic::stream::{Plane, Planes, Stream};
+
+#[test]
+fn streams_are_named() {
+    for stream in [Stream::Main, Stream::Depth, Stream::Right, Stream::Screen] {
+        assert_eq!(stream.to_string().parse::<Stream>().unwrap(), stream);
+    }
+    assert_eq!("colour".parse::<Stream>().unwrap(), Stream::Main);
+    assert!("infrared".parse::<Stream>().is_err());
+}
+
+#[test]
+fn planes_hold_one_picture_for_each_stream() {
+    let depth = |sample| {
+        Plane::Depth(DepthPlane {
+            width: 2,
+            height: 1,
+            samples: vec![sample; 2],
+            scale: 0.001,
+        })
+    };
+    let mut planes = Planes::new();
+    assert!(planes.depth().is_none());
+    planes.insert(Stream::Depth, depth(1));
+    planes.insert(Stream::Depth, depth(2));
+    assert_eq!(planes.streams().collect::<Vec<_>>(), [Stream::Depth]);
+    assert_eq!(planes.depth().unwrap().samples, [2, 2]);
+    assert_eq!(planes.bytes(), 4);
+    assert!(planes.get(Stream::Right).is_none());
+}
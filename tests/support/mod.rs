@@ -0,0 +1,128 @@
+//! Runs the athletic binary against the test pattern, so commands can be
+//! tested end to end without a camera.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+pub use athletic::pattern::DEVICE;
+
+/// How long a server gets to start listening.
+const STARTUP: Duration = Duration::from_secs(20);
+
+/// A directory for one test's files, which also stands in for the config
+/// directory, so no one's profiles get in the way.
+pub fn scratch() -> TempDir {
+    tempfile::tempdir().unwrap()
+}
+
+/// `athletic` with `args`, reading no config but what's in `dir`.
+pub fn athletic(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_athletic"));
+    command
+        .args(args)
+        .env("XDG_CONFIG_HOME", dir)
+        .env("APPDATA", dir)
+        .stdin(Stdio::null());
+    command
+}
+
+/// Runs `athletic` with `args` to the end, failing the test unless it
+/// succeeds.
+pub fn run(dir: &Path, args: &[&str]) -> Output {
+    let output = athletic(dir, args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "athletic {} failed with {}:\n{}{}",
+        args.join(" "),
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// A loopback address nothing is listening on.
+pub fn free_address() -> SocketAddr {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+}
+
+/// A running `athletic`, killed when dropped.
+pub struct Running(Child);
+
+impl Running {
+    pub fn start(dir: &Path, args: &[&str]) -> Self {
+        let child = athletic(dir, args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        Running(child)
+    }
+
+    /// Connects to `address` once something listens there.
+    pub fn connect(&mut self, address: SocketAddr) -> TcpStream {
+        let start = Instant::now();
+        loop {
+            if let Ok(stream) = TcpStream::connect(address) {
+                stream.set_read_timeout(Some(STARTUP)).unwrap();
+                return stream;
+            }
+            if let Some(status) = self.0.try_wait().unwrap() {
+                panic!("athletic exited with {status} before listening on {address}");
+            }
+            assert!(
+                start.elapsed() < STARTUP,
+                "nothing listened on {address} within {STARTUP:?}"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Asks for `path` over HTTP and reads the first part of the
+/// multipart response, returning its content type and body.
+pub fn first_part(mut stream: TcpStream, path: &str) -> (String, Vec<u8>) {
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert!(line.starts_with("HTTP/1.1 200"), "got {line:?}");
+
+    // The response's headers, then the part's, up to the blank line
+    // after the one giving the part's length.
+    let (mut content_type, mut length) = (String::new(), None);
+    loop {
+        line.clear();
+        assert!(reader.read_line(&mut line).unwrap() > 0, "the stream ended");
+        let line = line.trim_end();
+        if let Some((name, value)) = line.split_once(':') {
+            match name.to_ascii_lowercase().as_str() {
+                "content-type" => content_type = value.trim().to_string(),
+                "content-length" => length = Some(value.trim().parse().unwrap()),
+                _ => {}
+            }
+        } else if line.is_empty() {
+            if let Some(length) = length {
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                return (content_type, body);
+            }
+        }
+    }
+}
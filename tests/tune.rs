@@ -0,0 +1,29 @@
+use athletic::tune::{self, Level, Tune};
+
+#[test]
+fn levels_are_numbers_or_shares_of_the_range() {
+    assert_eq!("40".parse::<Level>().unwrap(), Level::Value(40));
+    assert_eq!("75%".parse::<Level>().unwrap(), Level::Share(0.75));
+    assert!("150%".parse::<Level>().is_err());
+    assert!("bright".parse::<Level>().is_err());
+}
+
+#[test]
+fn a_share_lands_on_a_step_inside_the_range() {
+    assert_eq!(Level::Share(0.5).within(0, 255, 1), 128);
+    assert_eq!(Level::Share(0.75).within(3, 2047, 10), 1533);
+    assert_eq!(Level::Share(1.0).within(-64, 64, 5), 61);
+    assert_eq!(Level::Value(9).within(0, 4, 1), 9);
+}
+
+#[test]
+fn builtin_tunes_use_controls_that_can_be_named() {
+    for name in tune::BUILTIN {
+        let tune = Tune::builtin(name).unwrap();
+        for (control, _) in tune.settings {
+            let name = tune::control_name(control);
+            assert_eq!(tune::control(&name), Some(control), "{name}");
+        }
+    }
+    assert!(Tune::builtin("sepia").is_none());
+}
@@ -0,0 +1,42 @@
+use athletic::warp::{self, Point, IDENTITY};
+use image::RgbImage;
+
+fn close(a: Point, b: Point) -> bool {
+    (a.0 - b.0).hypot(a.1 - b.1) < 1e-6
+}
+
+#[test]
+fn four_points_are_fitted_exactly_and_undone_by_the_inverse() {
+    let from = [(0.0, 0.0), (400.0, 0.0), (400.0, 300.0), (0.0, 300.0)];
+    let to = [(140.0, 60.0), (520.0, 90.0), (490.0, 430.0), (110.0, 400.0)];
+    let pairs: Vec<(Point, Point)> = from.into_iter().zip(to).collect();
+    let homography = warp::fit(&pairs).unwrap();
+    let inverse = warp::invert(&homography).unwrap();
+    for (from, to) in pairs {
+        assert!(close(warp::apply(&homography, from), to), "{from:?}");
+        assert!(close(warp::apply(&inverse, to), from), "{to:?}");
+    }
+    let round_trip = warp::multiply(&inverse, &homography);
+    let scale = round_trip[8];
+    for (got, identity) in round_trip.iter().zip(IDENTITY) {
+        assert!((got / scale - identity).abs() < 1e-9, "{round_trip:?}");
+    }
+}
+
+#[test]
+fn points_in_a_line_have_no_homography() {
+    let line = [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (3.0, 3.0)];
+    let pairs: Vec<(Point, Point)> = line.into_iter().zip(line).collect();
+    assert_eq!(warp::fit(&pairs), None);
+    assert_eq!(warp::invert(&[0.0; 9]), None);
+}
+
+#[test]
+fn samples_blend_the_pixels_around_them_and_clamp_to_the_edges() {
+    let image = RgbImage::from_fn(2, 2, |x, y| {
+        image::Rgb([(x * 100) as u8, (y * 200) as u8, 50])
+    });
+    assert_eq!(warp::sample(&image, 0.5, 0.5), [50.0, 100.0, 50.0]);
+    assert_eq!(warp::sample(&image, 0.25, 0.0), [25.0, 0.0, 50.0]);
+    assert_eq!(warp::sample(&image, -3.0, 9.0), [0.0, 200.0, 50.0]);
+}
@@ -0,0 +1,35 @@
+use athletic::pattern;
+use athletic::window::{letterbox, title, DEFAULT_TITLE};
+
+#[test]
+fn a_wider_window_gets_bars_at_the_sides() {
+    let (offset, scale) = letterbox((640.0, 480.0), (1280.0, 720.0));
+    assert_eq!(scale, 1.5);
+    assert_eq!(offset, [160.0, 0.0]);
+}
+
+#[test]
+fn a_taller_window_gets_bars_above_and_below() {
+    let (offset, scale) = letterbox((640.0, 480.0), (320.0, 480.0));
+    assert_eq!(scale, 0.5);
+    assert_eq!(offset, [0.0, 120.0]);
+}
+
+#[test]
+fn a_window_the_shape_of_the_image_is_filled() {
+    let (offset, scale) = letterbox((640.0, 480.0), (320.0, 240.0));
+    assert_eq!(scale, 0.5);
+    assert_eq!(offset, [0.0, 0.0]);
+}
+
+#[test]
+fn titles_are_filled_in_with_the_camera() {
+    let shown = title(DEFAULT_TITLE, &pattern::info(), &pattern::format());
+    assert_eq!(shown, "athletic — athletic test pattern 640x480@30 (YUYV)");
+    let shown = title(
+        "{index}: {name}, {nothing}",
+        &pattern::info(),
+        &pattern::format(),
+    );
+    assert_eq!(shown, "test-pattern: athletic test pattern, {nothing}");
+}